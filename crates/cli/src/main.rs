@@ -5,23 +5,63 @@ use clap::{Arg, ArgAction, Command, arg, command, value_parser};
 use duckdb::Connection;
 use tracing::{self, error, info, trace};
 
-fn main() -> Result<()> {
-    let matches = command!() // requires `cargo` feature
+fn main() -> std::process::ExitCode {
+    match run() {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => {
+            // Alternate format includes the chain of causes in one line
+            eprintln!("error: {e:#}");
+            std::process::ExitCode::FAILURE
+        }
+    }
+}
+
+/// The command line interface, with all its subcommands
+fn cli() -> Command {
+    command!() // requires `cargo` feature
         .arg(
             arg!(--db <DATABASE>)
-                .required(true)
                 .help("Path to the database file. Ex.: ./ordinance.db"),
         )
+        .arg(
+            Arg::new("OPEN_TIMEOUT")
+                .long("open-timeout")
+                .value_name("SECONDS")
+                .value_parser(value_parser!(u64))
+                .help("Give up opening the database after this many seconds"),
+        )
         .arg(
             Arg::new("verbose")
                 .short('v')
                 .action(ArgAction::Count)
                 .help("Set the verbosity level, ex.: -vvv"),
         )
-        .subcommand(Command::new("init").about("Initialize a new empty database"))
+        .arg(
+            Arg::new("JSON_LOGS")
+                .long("json-logs")
+                .action(ArgAction::SetTrue)
+                .help("Emit the logs as JSON lines, one event per line"),
+        )
+        .subcommand(
+            Command::new("init")
+                .about("Initialize a new empty database")
+                .arg(
+                    Arg::new("OVERWRITE")
+                        .long("overwrite")
+                        .action(ArgAction::SetTrue)
+                        .help("Replace an existing database, losing all its content"),
+                ),
+        )
+        .subcommand(
+            Command::new("migrate").about("Upgrade the database to the current data model"),
+        )
         .subcommand(
             Command::new("load")
                 .about("Load ordinance raw data")
+                .after_help(
+                    "Foreign keys are checked on every insert, since DuckDB can't \
+                    defer them to the commit.",
+                )
                 .arg(
                     Arg::new("username")
                         .short('u')
@@ -32,9 +72,104 @@ fn main() -> Result<()> {
                     Arg::new("path")
                         .value_parser(value_parser!(PathBuf))
                         .required(true)
-                        .help("Path to directory with scraper output"),
+                        .num_args(1..)
+                        .help(
+                            "Path to directory with scraper output, or a .tar.gz of it, \
+                            or '-' to read a quantitative ordinance CSV from stdin. \
+                            Several outputs are loaded as a commit each",
+                        ),
+                )
+                .arg(
+                    Arg::new("JOBS")
+                        .short('j')
+                        .long("jobs")
+                        .value_parser(value_parser!(usize))
+                        .help("Number of outputs loaded at once [default: 1]"),
+                )
+                .arg(
+                    Arg::new("VALIDATE_SOURCES")
+                        .long("validate-sources")
+                        .action(ArgAction::SetTrue)
+                        .help("Flag sources that look like URLs but are malformed"),
+                )
+                .arg(
+                    Arg::new("ALLOW_MISSING")
+                        .long("allow-missing")
+                        .action(ArgAction::SetTrue)
+                        .help("Skip missing optional files, such as usage.json"),
+                )
+                .arg(
+                    Arg::new("TOLERANT")
+                        .long("tolerant")
+                        .action(ArgAction::SetTrue)
+                        .help("Accept comments and trailing commas in meta.json and usage.json"),
+                )
+                .arg(
+                    Arg::new("STORE_RAW")
+                        .long("store-raw")
+                        .action(ArgAction::SetTrue)
+                        .help("Also store the original quantitative ordinance CSV"),
+                )
+                .arg(
+                    Arg::new("DEDUP_LOGS")
+                        .long("dedup-logs")
+                        .action(ArgAction::SetTrue)
+                        .help("Skip consecutive duplicated log records"),
+                )
+                .arg(
+                    Arg::new("QUANTITATIVE_FILE")
+                        .long("quantitative-file")
+                        .help("Name of the quantitative ordinance file [default: any known name]"),
+                )
+                .arg(
+                    Arg::new("LIMIT_JURISDICTIONS")
+                        .long("limit-jurisdictions")
+                        .value_parser(value_parser!(usize))
+                        .hide(true)
+                        .help("Debug: load only the first N jurisdictions, marked as partial"),
+                )
+                .args(load_args())
+                .arg(
+                    Arg::new("LOG_PATTERN")
+                        .long("log-pattern")
+                        .help("Regex to parse the runtime logs, with the named groups ts, level, subject, and message"),
+                )
+                .arg(
+                    Arg::new("TECHNOLOGY")
+                        .short('t')
+                        .long("technology")
+                        .required_if_eq("path", "-")
+                        .value_parser(["wind", "solar"])
+                        .help("Technology of a CSV read from stdin, ex.: 'wind'"),
+                ),
+        )
+        .subcommand(
+            Command::new("load-boundaries")
+                .about("Load jurisdiction geometries from a GeoJSON boundary file")
+                .arg(
+                    Arg::new("path")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true)
+                        .help("GeoJSON FeatureCollection of boundaries keyed by FIPS"),
                 ),
         )
+        .subcommand(
+            Command::new("add-sources")
+                .about("Add the documents of a scraper output to an existing commit")
+                .arg(
+                    Arg::new("COMMIT")
+                        .long("commit")
+                        .required(true)
+                        .help("Commit to add to, by hash or id"),
+                )
+                .arg(
+                    Arg::new("path")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true)
+                        .help("Path to directory with scraper output, or a .tar.gz of it"),
+                )
+                .args(load_args()),
+        )
         .subcommand(
             Command::new("export")
                 .about("Export the database")
@@ -58,14 +193,424 @@ fn main() -> Result<()> {
                     Arg::new("FORMAT")
                         .short('f')
                         .long("format")
-                        .help("Format to export, ex.: 'csv' or 'json'")
-                        .value_parser(["csv", "revx", "json", "gpkg", "gpq"])
+                        .help("Format to export, ex.: 'csv', 'table', 'bincode', or 'sqlite'")
+                        .value_parser([
+                            "csv", "revx", "json", "gpkg", "gpq", "bincode", "table", "sqlite",
+                        ])
                         .default_value("revx")
                         .default_missing_value("revx"),
+                )
+                .arg(
+                    Arg::new("COLUMNS")
+                        .long("columns")
+                        .value_delimiter(',')
+                        .help("Columns to export, ex.: 'state,feature,quantitative'"),
+                )
+                .arg(
+                    Arg::new("GROUP_BY")
+                        .long("group-by")
+                        .value_delimiter(',')
+                        .conflicts_with("COLUMNS")
+                        .help("Count and mean value by these columns, ex.: 'state,feature'"),
+                )
+                .arg(
+                    Arg::new("COMMIT")
+                        .long("commit")
+                        .value_parser(value_parser!(usize))
+                        .help("Only export the ordinances of this commit (bookkeeper id)"),
+                )
+                .arg(
+                    Arg::new("FEATURE")
+                        .long("feature")
+                        .action(ArgAction::Append)
+                        .help("Only export this feature, can be repeated"),
+                )
+                .arg(
+                    Arg::new("INCLUDE_EMPTY")
+                        .long("include-empty")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("EXCLUDE_EMPTY")
+                        .help("Export the ordinances without any value (default)"),
+                )
+                .arg(
+                    Arg::new("EXCLUDE_EMPTY")
+                        .long("exclude-empty")
+                        .action(ArgAction::SetTrue)
+                        .help("Skip the ordinances without any value"),
+                )
+                .arg(
+                    Arg::new("OUTPUT_PRECISION")
+                        .long("output-precision")
+                        .value_parser(value_parser!(u32))
+                        .help("Round the quantitative values to this many decimal places"),
+                )
+                .arg(
+                    Arg::new("FIPS_PADDED")
+                        .long("fips-padded")
+                        .action(ArgAction::SetTrue)
+                        .help("Export FIPS codes zero-padded, ex.: '08059'"),
+                )
+                .arg(
+                    Arg::new("WITH_JURISDICTIONS")
+                        .long("with-jurisdictions")
+                        .action(ArgAction::SetTrue)
+                        .help("With the sqlite format, also export the jurisdictions"),
+                )
+                .arg(
+                    Arg::new("APPEND")
+                        .long("append")
+                        .action(ArgAction::SetTrue)
+                        .help("Append to an existing csv output instead of failing"),
                 ),
         )
         .subcommand(Command::new("log").about("Show the history of the database"))
-        .get_matches();
+        .subcommand(
+            Command::new("schema")
+                .about("Show the schema of the database")
+                .arg(
+                    Arg::new("FORMAT")
+                        .short('f')
+                        .long("format")
+                        .help("Output format")
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                ),
+        )
+        .subcommand(
+            Command::new("counts")
+                .about("Count ordinances in the database")
+                .arg(
+                    Arg::new("BY")
+                        .long("by")
+                        .help("Group the counts by")
+                        .value_parser(["jurisdiction-type"])
+                        .default_value("jurisdiction-type"),
+                ),
+        )
+        .subcommand(
+            Command::new("feature-stats")
+                .about("Count, min, max, and mean of the quantitative values by feature"),
+        )
+        .subcommand(
+            Command::new("status")
+                .about("Show how fresh the data of the database is")
+                .arg(
+                    Arg::new("STALE_AFTER")
+                        .long("stale-after")
+                        .value_name("DAYS")
+                        .value_parser(value_parser!(f64))
+                        .help("Warn if the newest data is older than this many days"),
+                ),
+        )
+        .subcommand(
+            Command::new("completeness")
+                .about("Count and percentage of missing values by ordinance column"),
+        )
+        .subcommand(
+            Command::new("recheck")
+                .about("Verify archived documents against their stored checksums")
+                .arg(
+                    Arg::new("FILES")
+                        .long("files")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true)
+                        .help("Directory with the archived documents"),
+                )
+                .arg(
+                    Arg::new("FIX")
+                        .long("fix")
+                        .action(ArgAction::SetTrue)
+                        .help("Update the stored checksums of mismatched documents"),
+                ),
+        )
+        .subcommand(
+            Command::new("resolve-fips")
+                .about("Fill in the missing FIPS codes from the county and state")
+                .arg(
+                    Arg::new("LOOKUP")
+                        .long("lookup")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("CSV with the columns county, state, and FIPS, to extend the sources"),
+                ),
+        )
+        .subcommand(
+            Command::new("rename-feature")
+                .about("Rename a feature in all the ordinances")
+                .arg(
+                    Arg::new("OLD")
+                        .required(true)
+                        .help("Current name of the feature"),
+                )
+                .arg(
+                    Arg::new("NEW")
+                        .required(true)
+                        .help("New name of the feature"),
+                ),
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Verify the consistency of the loaded outputs")
+                .arg(
+                    Arg::new("CONSISTENCY")
+                        .long("consistency")
+                        .action(ArgAction::SetTrue)
+                        .help("Compare the jurisdictions of the sources and of the ordinances"),
+                ),
+        )
+        .subcommand(
+            Command::new("compact")
+                .about("Copy the database into a new, smaller, file to distribute")
+                .arg(
+                    Arg::new("OUTPUT")
+                        .long("output")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true)
+                        .help("Path of the compacted database, which must not exist"),
+                ),
+        )
+        .subcommand(
+            Command::new("inspect")
+                .about("Summarize a scraper output directory, without a database")
+                .arg(
+                    Arg::new("PATH")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                        .help("Path to directory with scraper output"),
+                ),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Diagnose a scraper output directory before loading it")
+                .arg(
+                    Arg::new("PATH")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                        .help("Path to directory with scraper output"),
+                ),
+        )
+        .subcommand(
+            Command::new("validate-json")
+                .about("Validate a JSON file of a scraper output, without a database")
+                .arg(
+                    Arg::new("KIND")
+                        .long("kind")
+                        .required(true)
+                        .value_parser(["meta", "usage", "jurisdictions"])
+                        .help("Which file of the scraper output"),
+                )
+                .arg(
+                    Arg::new("TOLERANT")
+                        .long("tolerant")
+                        .action(ArgAction::SetTrue)
+                        .help("Accept comments and trailing commas, except in jurisdictions"),
+                )
+                .arg(
+                    Arg::new("PATH")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                        .help("Path to the JSON file"),
+                ),
+        )
+        .subcommand(
+            Command::new("errors")
+                .about("Show the errors logged by the scraper")
+                .arg(
+                    Arg::new("COMMIT")
+                        .long("commit")
+                        .value_parser(value_parser!(usize))
+                        .help("Only errors of this commit (bookkeeper id)"),
+                ),
+        )
+        .subcommand(
+            Command::new("logs")
+                .about("Export the runtime logs of the scraper")
+                .arg(
+                    Arg::new("COMMIT")
+                        .long("commit")
+                        .value_parser(value_parser!(usize))
+                        .help("Only the logs of this commit (bookkeeper id)"),
+                )
+                .arg(
+                    Arg::new("FORMAT")
+                        .short('f')
+                        .long("format")
+                        .help("Output format")
+                        .value_parser(["csv", "json"])
+                        .default_value("csv"),
+                ),
+        )
+        .subcommand(
+            Command::new("export-usage")
+                .about("Export the usage of the scraper, one record per step")
+                .arg(
+                    Arg::new("FORMAT")
+                        .short('f')
+                        .long("format")
+                        .help("Output format")
+                        .value_parser(["csv", "json"])
+                        .default_value("csv"),
+                ),
+        )
+        .subcommand(
+            Command::new("provenance")
+                .about("Export each ordinance with its commit and source document")
+                .arg(
+                    Arg::new("FIPS")
+                        .long("fips")
+                        .value_parser(value_parser!(u64))
+                        .help("Only the ordinances of this jurisdiction"),
+                )
+                .arg(
+                    Arg::new("FORMAT")
+                        .short('f')
+                        .long("format")
+                        .help("Output format")
+                        .value_parser(["csv", "json"])
+                        .default_value("csv"),
+                ),
+        )
+        .subcommand(
+            Command::new("search")
+                .about("Search the qualitative ordinances, most relevant first")
+                .arg(
+                    Arg::new("QUERY")
+                        .required(true)
+                        .help("Words to search in the summaries, ex.: 'noise'"),
+                )
+                .arg(
+                    Arg::new("FORMAT")
+                        .short('f')
+                        .long("format")
+                        .help("Output format")
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                ),
+        )
+        .subcommand(
+            Command::new("completions")
+                .about("Print the shell completions, ex.: to source in ~/.bashrc")
+                .arg(
+                    Arg::new("SHELL")
+                        .required(true)
+                        .value_parser(value_parser!(clap_complete::Shell))
+                        .help("Shell to complete"),
+                ),
+        )
+        .subcommand(
+            Command::new("find")
+                .about("Show everything about a jurisdiction")
+                .arg(
+                    Arg::new("COUNTY")
+                        .required(true)
+                        .help("County, ex.: 'Jefferson County'"),
+                )
+                .arg(
+                    Arg::new("STATE")
+                        .required(true)
+                        .help("State, ex.: 'Colorado'"),
+                )
+                .arg(
+                    Arg::new("FORMAT")
+                        .short('f')
+                        .long("format")
+                        .help("Output format")
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                ),
+        )
+}
+
+/// Options of every command that reads a scraper output, see `load_options()`
+fn load_args() -> [Arg; 4] {
+    [
+        Arg::new("STRICT")
+            .long("strict")
+            .visible_alias("fail-on-warning")
+            .action(ArgAction::SetTrue)
+            .help("Abort the load on any warning, such as a checksum mismatch"),
+        Arg::new("IO_BUFFER_SIZE")
+            .long("io-buffer-size")
+            .value_parser(value_parser!(u64).range(1..))
+            .help("Size, in bytes, of the buffer to read the documents [default: 65536]"),
+        Arg::new("PARALLEL_HASH")
+            .long("parallel-hash")
+            .value_name("N")
+            .value_parser(value_parser!(u64).range(1..))
+            .help("Number of documents checksummed at once [default: 8]"),
+        Arg::new("TIMEOUT")
+            .long("timeout")
+            .value_name("SECONDS")
+            .value_parser(value_parser!(u64).range(1..))
+            .help("Give up the load after this many seconds, writing nothing"),
+    ]
+}
+
+/// Options to load a scraper output, from the arguments of a command
+///
+/// The same for every command that reads a scraper output, such as
+/// `load` and `add-sources`. An argument that the command doesn't
+/// accept keeps its default.
+fn load_options(matches: &clap::ArgMatches) -> infra_compass_db::LoadOptions {
+    let flag = |id| {
+        matches
+            .try_get_one::<bool>(id)
+            .ok()
+            .flatten()
+            .copied()
+            .unwrap_or_default()
+    };
+    let count = |id| {
+        matches
+            .try_get_one::<u64>(id)
+            .ok()
+            .flatten()
+            .map(|&n| n as usize)
+    };
+    let text = |id| matches.try_get_one::<String>(id).ok().flatten().cloned();
+
+    let options = infra_compass_db::LoadOptions {
+        validate_sources: flag("VALIDATE_SOURCES"),
+        allow_missing: flag("ALLOW_MISSING"),
+        log_pattern: text("LOG_PATTERN"),
+        strict: flag("STRICT"),
+        io_buffer_size: count("IO_BUFFER_SIZE"),
+        parallel_hash: count("PARALLEL_HASH"),
+        tolerant: flag("TOLERANT"),
+        store_raw: flag("STORE_RAW"),
+        dedup_logs: flag("DEDUP_LOGS"),
+        limit_jurisdictions: matches
+            .try_get_one::<usize>("LIMIT_JURISDICTIONS")
+            .ok()
+            .flatten()
+            .copied(),
+        quantitative_file: text("QUANTITATIVE_FILE"),
+        timeout: matches
+            .try_get_one::<u64>("TIMEOUT")
+            .ok()
+            .flatten()
+            .map(|&s| std::time::Duration::from_secs(s)),
+    };
+    trace!("Load options: {:?}", options);
+    options
+}
+
+/// The subscriber of the logs, as JSON lines with `json`
+fn subscriber<W>(level: tracing::Level, json: bool, writer: W) -> tracing::Dispatch
+where
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(writer);
+    if json {
+        builder.json().into()
+    } else {
+        builder.into()
+    }
+}
+
+fn run() -> Result<()> {
+    let matches = cli().get_matches();
 
     let verbose = matches.get_count("verbose");
     let tracing_level = match verbose {
@@ -74,21 +619,115 @@ fn main() -> Result<()> {
         2 => tracing::Level::DEBUG,
         _ => tracing::Level::TRACE,
     };
-    tracing_subscriber::fmt()
-        .with_max_level(tracing_level)
-        .init();
+    // Diagnostics go to stderr, keeping stdout for the output itself,
+    // such as the JSON of `inspect`
+    tracing::dispatcher::set_global_default(subscriber(
+        tracing_level,
+        matches.get_flag("JSON_LOGS"),
+        std::io::stderr,
+    ))?;
     info!("Verbosity level: {:?}", verbose);
 
     //       Command::new("log")
     //          .about("Show the history of the database")
-    let db = matches.get_one::<String>("db").expect("required");
+    // Commands that don't need a database
+    if let Some(completions) = matches.subcommand_matches("completions") {
+        let shell = *completions
+            .get_one::<clap_complete::Shell>("SHELL")
+            .unwrap();
+        trace!("Generating completions for {:?}", shell);
+
+        // Into a buffer first, since generate() panics on a write error,
+        // such as a closed pipe
+        let mut cli = cli();
+        let name = cli.get_name().to_string();
+        let mut script = Vec::new();
+        clap_complete::generate(shell, &mut cli, name, &mut script);
+        std::io::Write::write_all(&mut std::io::stdout(), &script)?;
+        return Ok(());
+    }
+    if let Some(inspect) = matches.subcommand_matches("inspect") {
+        let path = inspect.get_one::<PathBuf>("PATH").unwrap();
+        trace!("Inspecting {:?}", path);
+
+        let inspection = infra_compass_db::inspect(path)
+            .with_context(|| format!("Failed to inspect {}", path.display()))?;
+        println!("{}", serde_json::to_string_pretty(&inspection)?);
+        return Ok(());
+    }
+    if let Some(doctor) = matches.subcommand_matches("doctor") {
+        let path = doctor.get_one::<PathBuf>("PATH").unwrap();
+        trace!("Diagnosing {:?}", path);
+
+        let diagnosis = infra_compass_db::doctor(path)
+            .with_context(|| format!("Failed to diagnose {}", path.display()))?;
+        for check in &diagnosis.checks {
+            println!("{check}");
+        }
+        let failures = diagnosis.failures().count();
+        if failures > 0 {
+            anyhow::bail!("{failures} critical checks failed");
+        }
+        return Ok(());
+    }
+    if let Some(validate) = matches.subcommand_matches("validate-json") {
+        let path = validate.get_one::<PathBuf>("PATH").unwrap();
+        let kind = match validate.get_one::<String>("KIND").unwrap().as_str() {
+            "meta" => infra_compass_db::JsonKind::Meta,
+            "usage" => infra_compass_db::JsonKind::Usage,
+            "jurisdictions" => infra_compass_db::JsonKind::Jurisdictions,
+            _ => unreachable!(),
+        };
+        let tolerant = validate.get_flag("TOLERANT");
+        trace!(
+            "Validating {:?} as {:?}, tolerant: {}",
+            path, kind, tolerant
+        );
+
+        infra_compass_db::validate_json(kind, path, tolerant)
+            .with_context(|| format!("{} is not valid", path.display()))?;
+        println!("valid");
+        return Ok(());
+    }
+
+    let db = matches
+        .get_one::<String>("db")
+        .context("the argument '--db <DATABASE>' is required")?;
+    let open_timeout = matches
+        .get_one::<u64>("OPEN_TIMEOUT")
+        .map(|s| std::time::Duration::from_secs(*s));
+    // Every command opens the database through these, thus within the
+    // timeout, if any. The queries only read, thus read-only.
+    let open = || -> Result<Connection> {
+        match open_timeout {
+            Some(timeout) => infra_compass_db::open_with_timeout(db, timeout),
+            None => Connection::open(db).map_err(Into::into),
+        }
+        .with_context(|| format!("Failed to open database {db}"))
+    };
+    let readonly = || -> Result<Connection> {
+        match open_timeout {
+            Some(timeout) => infra_compass_db::open_readonly_with_timeout(db, timeout),
+            None => infra_compass_db::open_readonly(db),
+        }
+        .with_context(|| format!("Failed to open database {db}"))
+    };
 
     match matches.subcommand_name() {
         Some("init") => {
-            trace!("Creating database at {:?}", &db);
-            infra_compass_db::init_db(db)
+            let overwrite = matches
+                .subcommand_matches("init")
+                .unwrap()
+                .get_flag("OVERWRITE");
+            trace!("Creating database at {:?}, overwrite: {}", &db, overwrite);
+            infra_compass_db::init_db_with(db, overwrite)
                 .with_context(|| format!("Failed to initialize database as {db}"))?;
         }
+        Some("migrate") => {
+            trace!("Migrating database at {:?}", &db);
+            infra_compass_db::migrate(&mut open()?)
+                .with_context(|| format!("Failed to migrate database {db}"))?;
+        }
         Some("export") => {
             trace!("Exporting database {:?}", &db);
 
@@ -112,7 +751,22 @@ fn main() -> Result<()> {
                 .get_one::<PathBuf>("OUTPUT")
                 .unwrap();
             trace!("Output to: {:?}", &output);
-            if output.exists() {
+
+            let append = matches
+                .subcommand_matches("export")
+                .unwrap()
+                .get_flag("APPEND");
+            trace!("Append mode: {:?}", &append);
+
+            if infra_compass_db::UNIMPLEMENTED_FORMATS.contains(&format.as_str()) {
+                anyhow::bail!("The {format} export is not implemented yet");
+            }
+            // Any other format would give a second table, document, or
+            // payload after the first one, thus an invalid output
+            if append && format != "csv" {
+                anyhow::bail!("Can only append to a csv export, not {format}");
+            }
+            if output.exists() && !append {
                 error!(
                     "Output {:?} already exists. Please remove it before exporting.",
                     output
@@ -120,16 +774,107 @@ fn main() -> Result<()> {
                 anyhow::bail!("Output already exists");
             }
 
+            // Only the first batch written to a file should carry the header
+            let has_headers = !append || output.metadata().map_or(true, |m| m.len() == 0);
+            trace!("Writing header: {:?}", &has_headers);
+
+            let columns = matches
+                .subcommand_matches("export")
+                .unwrap()
+                .get_many::<String>("COLUMNS")
+                .map(|c| c.cloned().collect::<Vec<_>>());
+            trace!("Columns: {:?}", &columns);
+            let group_by = matches
+                .subcommand_matches("export")
+                .unwrap()
+                .get_many::<String>("GROUP_BY")
+                .map(|c| c.cloned().collect::<Vec<_>>());
+            trace!("Group by: {:?}", &group_by);
+
+            let commit_id = matches
+                .subcommand_matches("export")
+                .unwrap()
+                .get_one::<usize>("COMMIT")
+                .copied();
+            trace!("Commit: {:?}", &commit_id);
+
+            let features = matches
+                .subcommand_matches("export")
+                .unwrap()
+                .get_many::<String>("FEATURE")
+                .map(|f| f.cloned().collect::<Vec<_>>())
+                .unwrap_or_default();
+            trace!("Features: {:?}", &features);
+
+            let exclude_empty = matches
+                .subcommand_matches("export")
+                .unwrap()
+                .get_flag("EXCLUDE_EMPTY");
+            trace!("Exclude empty: {:?}", &exclude_empty);
+
+            let precision = matches
+                .subcommand_matches("export")
+                .unwrap()
+                .get_one::<u32>("OUTPUT_PRECISION")
+                .copied();
+            trace!("Precision: {:?}", &precision);
+            let fips_padded = matches
+                .subcommand_matches("export")
+                .unwrap()
+                .get_flag("FIPS_PADDED");
+            trace!("FIPS padded: {:?}", fips_padded);
+
+            let mut filter = infra_compass_db::OrdinanceFilter::builder()
+                .features(features)
+                .exclude_empty(exclude_empty);
+            if let Some(commit_id) = commit_id {
+                filter = filter.commit(commit_id);
+            }
+
+            let options = infra_compass_db::ExportOptions {
+                has_headers,
+                columns,
+                filter: filter.build(),
+                precision,
+                fips_padded,
+                group_by,
+            };
+
+            if format == "sqlite" {
+                let with_jurisdictions = matches
+                    .subcommand_matches("export")
+                    .unwrap()
+                    .get_flag("WITH_JURISDICTIONS");
+                let n = infra_compass_db::export_sqlite(
+                    &readonly()?,
+                    output,
+                    technology,
+                    &options,
+                    with_jurisdictions,
+                )
+                .with_context(|| format!("Failed to export to {}", output.display()))?;
+                info!("Exported {} ordinances to {:?}", n, output);
+                return Ok(());
+            }
+
             let mut wrt = std::io::BufWriter::new(
                 std::fs::OpenOptions::new()
-                    .create_new(true)
+                    .create_new(!append)
+                    .create(append)
+                    .append(append)
                     .write(true)
                     .open(output)
-                    .expect("Failed to open output file"),
+                    .with_context(|| format!("Failed to open output file {}", output.display()))?,
             );
             trace!("Output file created: {:?}", &wrt);
 
-            infra_compass_db::export(&mut wrt, db, format, technology)?;
+            infra_compass_db::export(&mut wrt, &readonly()?, format, technology, &options)?;
+            // Errors of a flush on drop are lost, thus explicitly flush
+            // and sync, such as to report a full disk
+            wrt.into_inner()
+                .map_err(|e| e.into_error())
+                .and_then(|file| file.sync_all())
+                .with_context(|| format!("Failed to write output file {}", output.display()))?;
         }
         Some("load") => {
             trace!("Subcommand load");
@@ -140,25 +885,364 @@ fn main() -> Result<()> {
                 .get_one::<String>("username")
                 .unwrap();
             trace!("Username: {:?}", &username);
-            let path = matches
+            let paths: Vec<&PathBuf> = matches
                 .subcommand_matches("load")
                 .unwrap()
-                .get_one::<PathBuf>("path")
-                .unwrap();
-            trace!("Loading data from: {:?}", &path,);
+                .get_many::<PathBuf>("path")
+                .unwrap()
+                .collect();
+            trace!("Loading data from: {:?}", &paths,);
+            let jobs = matches
+                .subcommand_matches("load")
+                .unwrap()
+                .get_one::<usize>("JOBS")
+                .copied();
+            let path = paths[0];
+            if (paths.len() > 1 || jobs.is_some()) && paths.iter().any(|p| p.as_os_str() == "-") {
+                anyhow::bail!("Can only load several scraper outputs at once, not stdin");
+            }
 
             // In the future, replace this Connection with a custom one
             // that already creates a session with the username, and hance
             // handle ahead permissions/authorization.
-            let conn: Connection = Connection::open(db).expect("Failed to open database");
-            infra_compass_db::load_ordinance(conn, username, path).with_context(|| {
-                format!("Failed to load ordinance data from {}", path.display(),)
-            })?;
+            if !std::path::Path::new(db).exists() {
+                anyhow::bail!("database {db} does not exist, create it with the `init` subcommand");
+            }
+            let options = load_options(matches.subcommand_matches("load").unwrap());
+            let conn = open()?;
+            if let Some(warning) = infra_compass_db::version_warning(&conn)
+                .with_context(|| format!("Failed to check the version of {db}"))?
+            {
+                eprintln!("WARNING: {warning}, with the `migrate` subcommand");
+            }
+            if paths.len() > 1 || jobs.is_some() {
+                let commits = infra_compass_db::load_parallel(
+                    conn,
+                    username,
+                    &paths,
+                    &options,
+                    jobs.unwrap_or(1),
+                )
+                .context("Failed to load ordinance data")?;
+                info!("Loaded {} outputs", commits.len());
+                for commit in commits {
+                    println!("{commit}");
+                }
+                return Ok(());
+            }
+            let summary = if path.as_os_str() == "-" {
+                let technology = matches
+                    .subcommand_matches("load")
+                    .unwrap()
+                    .get_one::<String>("TECHNOLOGY")
+                    .unwrap();
+                infra_compass_db::load_quantitative_csv(
+                    conn,
+                    username,
+                    technology,
+                    std::io::stdin().lock(),
+                    &options,
+                )
+                .context("Failed to load ordinance data from stdin")?
+            } else {
+                infra_compass_db::load_ordinance(conn, username, path, &options).with_context(
+                    || format!("Failed to load ordinance data from {}", path.display(),),
+                )?
+            };
+            info!(
+                "Loaded {} ordinances as commit {}",
+                summary.ordinances, summary.commit_id
+            );
+            println!("{}", summary.commit_id);
+            for warning in &summary.warnings {
+                eprintln!("WARNING: {warning}");
+            }
+            if let Some(usage) = summary.usage {
+                eprintln!("Loaded commit {}: {}", summary.commit_id, usage);
+            }
+        }
+
+        Some("load-boundaries") => {
+            let path = matches
+                .subcommand_matches("load-boundaries")
+                .unwrap()
+                .get_one::<PathBuf>("path")
+                .unwrap();
+            trace!("Loading boundaries from {:?} into {:?}", &path, &db);
+
+            let n = infra_compass_db::load_boundaries(&mut open()?, path)
+                .with_context(|| format!("Failed to load boundaries from {}", path.display()))?;
+            info!("Loaded {} boundaries", n);
         }
+        Some("add-sources") => {
+            let add_sources = matches.subcommand_matches("add-sources").unwrap();
+            let commit = add_sources.get_one::<String>("COMMIT").unwrap();
+            let path = add_sources.get_one::<PathBuf>("path").unwrap();
+            trace!("Adding sources from {:?} to commit {:?}", &path, &commit);
 
+            let mut conn = open()?;
+            let added =
+                infra_compass_db::add_sources(&mut conn, commit, path, &load_options(add_sources))
+                    .with_context(|| format!("Failed to add sources from {}", path.display()))?;
+            info!("Added {} documents to commit {}", added, commit);
+            println!("{added}");
+        }
         Some("log") => {
             trace!("Showing log for database at {:?}", &db);
         }
+        Some("schema") => {
+            trace!("Showing schema for database at {:?}", &db);
+            let format = matches
+                .subcommand_matches("schema")
+                .unwrap()
+                .get_one::<String>("FORMAT")
+                .unwrap();
+            trace!("Output format: {:?}", &format);
+
+            let schema = infra_compass_db::schema(&readonly()?)
+                .with_context(|| format!("Failed to inspect schema of {db}"))?;
+            match format.as_str() {
+                "json" => println!("{}", serde_json::to_string_pretty(&schema)?),
+                _ => print!("{schema}"),
+            }
+        }
+        Some("find") => {
+            let find = matches.subcommand_matches("find").unwrap();
+            let county = find.get_one::<String>("COUNTY").unwrap();
+            let state = find.get_one::<String>("STATE").unwrap();
+            let format = find.get_one::<String>("FORMAT").unwrap();
+            trace!("Finding {:?}, {:?} in database at {:?}", county, state, &db);
+
+            let report = infra_compass_db::find_jurisdiction(&readonly()?, county, state)
+                .with_context(|| format!("Failed to find {county}, {state}"))?;
+            match format.as_str() {
+                "json" => println!("{}", serde_json::to_string_pretty(&report)?),
+                _ => print!("{report}"),
+            }
+        }
+        Some("search") => {
+            let search = matches.subcommand_matches("search").unwrap();
+            let query = search.get_one::<String>("QUERY").unwrap();
+            let format = search.get_one::<String>("FORMAT").unwrap();
+            trace!("Searching {:?} in database at {:?}", query, &db);
+
+            let found = infra_compass_db::search(&readonly()?, query)
+                .with_context(|| format!("Failed to search {query:?} in {db}"))?;
+            match format.as_str() {
+                "json" => println!("{}", serde_json::to_string_pretty(&found)?),
+                _ => {
+                    for r in found {
+                        println!(
+                            "{}, {} ({}): {}",
+                            r.county.unwrap_or_default(),
+                            r.state.unwrap_or_default(),
+                            r.feature.unwrap_or_default(),
+                            r.summary.unwrap_or_default()
+                        );
+                    }
+                }
+            }
+        }
+        Some("counts") => {
+            trace!("Counting ordinances in database at {:?}", &db);
+            let by = matches
+                .subcommand_matches("counts")
+                .unwrap()
+                .get_one::<String>("BY")
+                .unwrap();
+            trace!("Grouping by: {:?}", &by);
+
+            let counts = infra_compass_db::counts_by_jurisdiction_type(&readonly()?)
+                .with_context(|| format!("Failed to count ordinances in {db}"))?;
+            for (kind, count) in counts {
+                println!("{kind}\t{count}");
+            }
+        }
+        Some("feature-stats") => {
+            trace!("Computing feature statistics in {:?}", &db);
+
+            let stats = infra_compass_db::feature_stats(&readonly()?)
+                .with_context(|| format!("Failed to compute feature statistics in {db}"))?;
+            println!("feature\tunits\tvalue_type\tcount\tmin\tmax\tmean");
+            for s in stats {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    s.feature,
+                    s.units.unwrap_or_default(),
+                    s.value_type.unwrap_or_default(),
+                    s.count,
+                    s.min,
+                    s.max,
+                    s.mean
+                );
+            }
+        }
+        Some("status") => {
+            let stale_after = matches
+                .subcommand_matches("status")
+                .unwrap()
+                .get_one::<f64>("STALE_AFTER")
+                .copied();
+            trace!(
+                "Checking freshness of {:?}, stale after {:?}",
+                &db, stale_after
+            );
+
+            let status = infra_compass_db::status(&readonly()?)
+                .with_context(|| format!("Failed to check the status of {db}"))?;
+            print!("{status}");
+            if let Some(days) = stale_after
+                && status.is_stale(days)
+            {
+                eprintln!("WARNING: the newest data is older than {days} days");
+            }
+        }
+        Some("completeness") => {
+            trace!("Counting missing values in {:?}", &db);
+
+            let completeness = infra_compass_db::completeness(&readonly()?)
+                .with_context(|| format!("Failed to count missing values in {db}"))?;
+            println!("column\tnulls\tpercent");
+            for c in completeness {
+                println!("{}\t{}\t{:.1}", c.column, c.nulls, c.percent);
+            }
+        }
+        Some("recheck") => {
+            let recheck = matches.subcommand_matches("recheck").unwrap();
+            let files = recheck.get_one::<PathBuf>("FILES").unwrap();
+            let fix = recheck.get_flag("FIX");
+            trace!("Rechecking {:?} against {:?}, fix: {:?}", files, &db, fix);
+
+            let mismatches = infra_compass_db::recheck(&mut open()?, files, fix)
+                .with_context(|| format!("Failed to recheck documents in {}", files.display()))?;
+            for m in &mismatches {
+                println!("{m}");
+            }
+            if fix {
+                eprintln!("Fixed {} checksums", mismatches.len());
+            }
+        }
+        Some("resolve-fips") => {
+            let lookup = matches
+                .subcommand_matches("resolve-fips")
+                .unwrap()
+                .get_one::<PathBuf>("LOOKUP");
+            trace!("Resolving FIPS codes in {:?} with lookup {:?}", &db, lookup);
+
+            let resolution =
+                infra_compass_db::resolve_fips(&mut open()?, lookup.map(PathBuf::as_path))
+                    .with_context(|| format!("Failed to resolve the FIPS codes in {db}"))?;
+            println!("Resolved {} FIPS codes", resolution.resolved);
+            for u in &resolution.unresolved {
+                eprintln!("WARNING: unresolved {u}");
+            }
+        }
+        Some("rename-feature") => {
+            let rename = matches.subcommand_matches("rename-feature").unwrap();
+            let old = rename.get_one::<String>("OLD").unwrap();
+            let new = rename.get_one::<String>("NEW").unwrap();
+            trace!("Renaming feature {:?} to {:?} in {:?}", old, new, &db);
+
+            let renamed = infra_compass_db::rename_feature(&mut open()?, old, new)
+                .with_context(|| format!("Failed to rename feature {old:?} in {db}"))?;
+            println!("Renamed {renamed} rows");
+        }
+        Some("verify") => {
+            let verify = matches.subcommand_matches("verify").unwrap();
+            if !verify.get_flag("CONSISTENCY") {
+                anyhow::bail!("No verification selected, such as --consistency");
+            }
+            trace!("Checking the consistency of {:?}", &db);
+
+            let inconsistencies = infra_compass_db::consistency_check(&readonly()?)
+                .with_context(|| format!("Failed to check the consistency of {db}"))?;
+            for i in &inconsistencies {
+                println!("{i}");
+            }
+            if !inconsistencies.is_empty() {
+                anyhow::bail!("Found {} inconsistencies", inconsistencies.len());
+            }
+        }
+        Some("compact") => {
+            let output = matches
+                .subcommand_matches("compact")
+                .unwrap()
+                .get_one::<PathBuf>("OUTPUT")
+                .unwrap();
+            trace!("Compacting {:?} into {:?}", &db, output);
+
+            // Read-write, to merge any pending write-ahead log first
+            let conn = open()?;
+            let compaction = infra_compass_db::compact(&conn, output)
+                .with_context(|| format!("Failed to compact {db} into {}", output.display()))?;
+            println!("Compacted: {compaction}");
+        }
+        Some("errors") => {
+            trace!("Showing logged errors in database at {:?}", &db);
+            let commit_id = matches
+                .subcommand_matches("errors")
+                .unwrap()
+                .get_one::<usize>("COMMIT")
+                .copied();
+            trace!("Commit: {:?}", &commit_id);
+
+            let errors = infra_compass_db::errors(&readonly()?, commit_id)
+                .with_context(|| format!("Failed to query logged errors in {db}"))?;
+            for e in errors {
+                println!(
+                    "[{}] commit {} {}: {}",
+                    e.timestamp.unwrap_or_default(),
+                    e.commit_id,
+                    e.subject.unwrap_or_default(),
+                    e.message.unwrap_or_default()
+                );
+            }
+        }
+        Some("logs") => {
+            trace!("Exporting runtime logs in database at {:?}", &db);
+            let commit_id = matches
+                .subcommand_matches("logs")
+                .unwrap()
+                .get_one::<usize>("COMMIT")
+                .copied();
+            trace!("Commit: {:?}", &commit_id);
+            let format = matches
+                .subcommand_matches("logs")
+                .unwrap()
+                .get_one::<String>("FORMAT")
+                .unwrap();
+            trace!("Output format: {:?}", &format);
+
+            let mut stdout = std::io::stdout().lock();
+            infra_compass_db::export_logs(&readonly()?, &mut stdout, format, commit_id)
+                .with_context(|| format!("Failed to export the logs in {db}"))?;
+        }
+        Some("export-usage") => {
+            trace!("Exporting usage in database at {:?}", &db);
+            let format = matches
+                .subcommand_matches("export-usage")
+                .unwrap()
+                .get_one::<String>("FORMAT")
+                .unwrap();
+            trace!("Output format: {:?}", &format);
+
+            let mut stdout = std::io::stdout().lock();
+            infra_compass_db::export_usage(&readonly()?, &mut stdout, format)
+                .with_context(|| format!("Failed to export the usage in {db}"))?;
+        }
+        Some("provenance") => {
+            let provenance = matches.subcommand_matches("provenance").unwrap();
+            let fips = provenance.get_one::<u64>("FIPS").copied();
+            let format = provenance.get_one::<String>("FORMAT").unwrap();
+            trace!(
+                "Exporting provenance in {:?} as {:?}, FIPS: {:?}",
+                &db, format, fips
+            );
+
+            let mut stdout = std::io::stdout().lock();
+            infra_compass_db::export_provenance(&readonly()?, &mut stdout, format, fips)
+                .with_context(|| format!("Failed to export the provenance in {db}"))?;
+        }
         _ => {
             println!("No subcommand was used");
         }
@@ -166,3 +1250,76 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test_cli {
+    use super::*;
+
+    #[test]
+    fn verify_cli() {
+        cli().debug_assert();
+    }
+
+    #[test]
+    /// Every path that reads a scraper output honors the same options
+    fn load_options_shared() {
+        for args in [
+            &["compass", "load", "-u", "test", "-", "-t", "wind"][..],
+            &["compass", "load", "-u", "test", "output"],
+            &["compass", "add-sources", "--commit", "1", "output"],
+        ] {
+            let matches = cli().get_matches_from(args.iter().chain(&[
+                "--strict",
+                "--timeout",
+                "5",
+                "--parallel-hash",
+                "2",
+            ]));
+            let (_, matches) = matches.subcommand().unwrap();
+            let options = load_options(matches);
+            assert!(options.strict, "{args:?}");
+            assert_eq!(
+                options.timeout,
+                Some(std::time::Duration::from_secs(5)),
+                "{args:?}"
+            );
+            assert_eq!(options.parallel_hash, Some(2), "{args:?}");
+            assert!(!options.store_raw, "{args:?}");
+        }
+    }
+
+    #[test]
+    /// Each event is a JSON line with its level, target, and fields
+    fn json_logs() {
+        #[derive(Clone, Default)]
+        struct Buffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let dispatch = subscriber(tracing::Level::INFO, true, move || writer.clone());
+        tracing::dispatcher::with_default(&dispatch, || {
+            info!(commit = 3, "Loaded");
+            trace!("Too verbose");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1);
+        let event: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(event["level"], "INFO");
+        assert_eq!(event["fields"]["message"], "Loaded");
+        assert_eq!(event["fields"]["commit"], 3);
+        assert!(event["target"].as_str().unwrap().contains("compass"));
+        assert!(event["timestamp"].is_string());
+    }
+}
@@ -0,0 +1,213 @@
+//! Run the command line application as a user would
+
+use std::process::Command;
+
+fn compass() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_infra-compass-cli"))
+}
+
+#[test]
+/// Loading into a missing database fails cleanly instead of panicking
+fn load_missing_database() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = tmp.path().join("missing.db");
+
+    let output = compass()
+        .arg("--db")
+        .arg(&db)
+        .args(["load", "-u", "test"])
+        .arg(tmp.path())
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("error: database "), "{stderr}");
+    assert!(stderr.contains("does not exist"), "{stderr}");
+    assert!(!stderr.contains("panicked"), "{stderr}");
+    assert!(!db.exists());
+}
+
+#[test]
+/// Several outputs can be loaded at once, but not from stdin
+fn load_several_not_stdin() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = tmp.path().join("missing.db");
+
+    for args in [vec!["-", "--jobs", "2"], vec!["output", "-"]] {
+        let output = compass()
+            .arg("--db")
+            .arg(&db)
+            .args(["load", "-u", "test", "-t", "wind"])
+            .args(&args)
+            .output()
+            .unwrap();
+
+        assert!(!output.status.success(), "{args:?}");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("not stdin"), "{args:?}: {stderr}");
+    }
+}
+
+#[test]
+/// Only a CSV export can be appended to, leaving any other untouched
+fn export_append_only_csv() {
+    let tmp = tempfile::tempdir().unwrap();
+    let output = tmp.path().join("export.txt");
+    std::fs::write(&output, "previous export\n").unwrap();
+
+    for format in ["table", "bincode", "json", "revx", "sqlite"] {
+        let result = compass()
+            .arg("--db")
+            .arg(tmp.path().join("ordinance.db"))
+            .args(["export", "-t", "wind", "--append", "-f", format, "-o"])
+            .arg(&output)
+            .output()
+            .unwrap();
+
+        assert!(!result.status.success(), "{format}");
+        let stderr = String::from_utf8(result.stderr).unwrap();
+        assert!(
+            stderr.contains(&format!("Can only append to a csv export, not {format}")),
+            "{stderr}"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            "previous export\n"
+        );
+    }
+}
+
+#[test]
+/// A format without a writer yet fails clearly, creating nothing
+fn export_unimplemented() {
+    let tmp = tempfile::tempdir().unwrap();
+
+    for format in ["gpkg", "gpq"] {
+        let output = tmp.path().join(format!("export.{format}"));
+        let result = compass()
+            .arg("--db")
+            .arg(tmp.path().join("ordinance.db"))
+            .args(["export", "-t", "wind", "-f", format, "-o"])
+            .arg(&output)
+            .output()
+            .unwrap();
+
+        assert!(!result.status.success(), "{format}");
+        let stderr = String::from_utf8(result.stderr).unwrap();
+        assert!(
+            stderr.contains(&format!("The {format} export is not implemented yet")),
+            "{stderr}"
+        );
+        assert!(!output.exists());
+    }
+}
+
+#[test]
+/// A valid and a malformed file of each kind
+fn validate_json() {
+    let tmp = tempfile::tempdir().unwrap();
+    for (kind, valid) in [
+        (
+            "meta",
+            r#"{"username": "test", "versions": {}, "technology": "wind", "models": [],
+                "time_start_utc": "2025-03-04T05:10:52+00:00",
+                "time_end_utc": "2025-03-04T05:19:49+00:00",
+                "total_time": 537.5, "total_time_string": "0:08:57.5",
+                "num_jurisdictions_searched": 1, "num_jurisdictions_found": 1,
+                "cost": null, "manifest": {}}"#,
+        ),
+        ("usage", r#"{}"#),
+        ("jurisdictions", r#"{"jurisdictions": []}"#),
+    ] {
+        let path = tmp.path().join(format!("{kind}.json"));
+        std::fs::write(&path, valid).unwrap();
+        let output = compass()
+            .args(["validate-json", "--kind", kind])
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{kind}: {output:?}");
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "valid\n");
+
+        std::fs::write(&path, "{\n  \"jurisdictions\": [,\n}").unwrap();
+        let output = compass()
+            .args(["validate-json", "--kind", kind])
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert!(!output.status.success(), "{kind}");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("line 2 column"), "{kind}: {stderr}");
+    }
+}
+
+#[test]
+/// A scraper output is summarized without any database
+fn inspect_without_database() {
+    let tmp = tempfile::tempdir().unwrap();
+    std::fs::create_dir(tmp.path().join("ordinance_files")).unwrap();
+    std::fs::write(
+        tmp.path().join("jurisdictions.json"),
+        r#"{"jurisdictions": []}"#,
+    )
+    .unwrap();
+    std::fs::write(
+        tmp.path().join("quantitative_ordinances.csv"),
+        "county,state,subdivison,jurisdiction_type,FIPS,feature,value,units,offset,min_dist,max_dist,summary,ord_year,section,source\n",
+    )
+    .unwrap();
+    std::fs::write(
+        tmp.path().join("qualitative_ordinances.csv"),
+        "county,state,subdivison,jurisdiction_type,FIPS,feature,summary,ord_year,section,source\n",
+    )
+    .unwrap();
+
+    let output = compass().arg("inspect").arg(tmp.path()).output().unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let inspection: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(inspection["jurisdictions"], 0);
+    assert_eq!(inspection["ordinances"], 0);
+    assert_eq!(
+        inspection["skipped"],
+        serde_json::json!(["meta.json", "usage.json", "logs/all.log"])
+    );
+}
+
+#[test]
+/// A missing file is reported, and the diagnosis fails
+fn doctor_missing_file() {
+    let tmp = tempfile::tempdir().unwrap();
+    std::fs::create_dir(tmp.path().join("ordinance_files")).unwrap();
+    std::fs::write(
+        tmp.path().join("jurisdictions.json"),
+        r#"{"jurisdictions": []}"#,
+    )
+    .unwrap();
+    std::fs::write(
+        tmp.path().join("qualitative_ordinances.csv"),
+        "county,state,subdivison,jurisdiction_type,FIPS,feature,summary,ord_year,section,source\n",
+    )
+    .unwrap();
+
+    let output = compass().arg("doctor").arg(tmp.path()).output().unwrap();
+    assert!(!output.status.success(), "{output:?}");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("[FAIL] layout: missing quantitative_ordinances.csv"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("[PASS] jurisdictions.json"), "{stdout}");
+}
+
+#[test]
+/// The bash completions cover the subcommands
+fn completions_bash() {
+    let output = compass().args(["completions", "bash"]).output().unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("complete -F"), "{stdout}");
+    for subcommand in ["init", "load", "export", "search", "completions"] {
+        assert!(stdout.contains(subcommand), "{subcommand} missing");
+    }
+}
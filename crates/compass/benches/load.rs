@@ -0,0 +1,78 @@
+//! Throughput of loading quantitative ordinances
+//!
+//! A synthetic quantitative ordinance CSV, with `rows` records for each
+//! of `jurisdictions` counties, is parsed and inserted into an in-memory
+//! database, so that disk I/O doesn't blur the parse and insert cost.
+//!
+//! The sizes can be changed with the environment variables
+//! `COMPASS_BENCH_JURISDICTIONS`, a comma separated list of jurisdiction
+//! counts, and `COMPASS_BENCH_ROWS`, the records per jurisdiction. For
+//! instance:
+//!
+//! ```text
+//! COMPASS_BENCH_JURISDICTIONS=10,5000 COMPASS_BENCH_ROWS=20 cargo bench
+//! ```
+
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+
+/// Features cycled through the synthetic records
+const FEATURES: [&str; 4] = ["structures", "property line", "roads", "noise"];
+
+/// Read a list of sizes from the environment, or use the default
+fn sizes(name: &str, default: &[usize]) -> Vec<usize> {
+    match std::env::var(name) {
+        Ok(v) => v
+            .split(',')
+            .map(|s| s.trim().parse().expect("invalid benchmark size"))
+            .collect(),
+        Err(_) => default.to_vec(),
+    }
+}
+
+/// A quantitative ordinance CSV with `rows` records per jurisdiction
+fn synthetic_csv(jurisdictions: usize, rows: usize) -> String {
+    let mut csv = String::from(
+        "county,state,subdivison,jurisdiction_type,FIPS,feature,value,units,offset,min_dist,max_dist,summary,ord_year,section,source\n",
+    );
+    for j in 0..jurisdictions {
+        for r in 0..rows {
+            csv.push_str(&format!(
+                "county-{j},state-{},,county,{},{},{}.5,feet,,,,A summary of the ordinance,2020,Section {r},https://example.com/{j}.pdf\n",
+                j % 50,
+                10000 + j,
+                FEATURES[r % FEATURES.len()],
+                100 + r,
+            ));
+        }
+    }
+    csv
+}
+
+fn load_quantitative_csv(c: &mut Criterion) {
+    let rows = sizes("COMPASS_BENCH_ROWS", &[10])[0];
+    let mut group = c.benchmark_group("load_quantitative_csv");
+    for jurisdictions in sizes("COMPASS_BENCH_JURISDICTIONS", &[10, 100, 1000]) {
+        let csv = synthetic_csv(jurisdictions, rows);
+        group.throughput(Throughput::Elements((jurisdictions * rows) as u64));
+        group.bench_function(format!("{jurisdictions}x{rows}"), |b| {
+            b.iter_batched(
+                || infra_compass_db::open_in_memory().unwrap(),
+                |conn| {
+                    infra_compass_db::load_quantitative_csv(
+                        conn,
+                        "bench",
+                        "wind",
+                        csv.as_bytes(),
+                        &infra_compass_db::LoadOptions::default(),
+                    )
+                    .unwrap()
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, load_quantitative_csv);
+criterion_main!(benches);
@@ -0,0 +1,140 @@
+//! Scraper outputs distributed as a single archive
+//!
+//! A scraper output is often shared as an `output.tar.gz`. Instead of
+//! requiring a manual extraction, it is extracted into a temporary
+//! directory and loaded from there as any other output.
+
+use std::path::{Component, Path, PathBuf};
+
+use tracing::{debug, trace};
+
+use crate::error::{Error, Result};
+
+/// File that identifies the root of a scraper output
+const ROOT_MARKER: &str = "jurisdictions.json";
+
+/// Check if a path is a gzipped tarball, by its extension
+pub(crate) fn is_archive<P: AsRef<Path>>(path: P) -> bool {
+    let name = path.as_ref().to_string_lossy().to_lowercase();
+    name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
+
+/// Extract a gzipped tarball and locate the scraper output inside it
+///
+/// Any entry that would be extracted outside `dest`, such as an absolute
+/// path or one with `..`, aborts the extraction. The archive might have
+/// the output at its top level, or inside a single directory, such as
+/// `output/`, and the effective root is returned.
+pub(crate) fn extract<P: AsRef<Path>, Q: AsRef<Path>>(archive: P, dest: Q) -> Result<PathBuf> {
+    let (archive, dest) = (archive.as_ref(), dest.as_ref());
+    debug!("Extracting {:?} into {:?}", archive, dest);
+
+    let file = std::fs::File::open(archive)?;
+    let mut tarball = tar::Archive::new(flate2::read::GzDecoder::new(file));
+    for entry in tarball.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if !path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(Error::UnsafeArchivePath(path));
+        }
+        trace!("Extracting {:?}", path);
+        entry.unpack_in(dest)?;
+    }
+
+    locate_root(dest)
+}
+
+/// Find the directory holding the scraper output
+fn locate_root(dir: &Path) -> Result<PathBuf> {
+    if dir.join(ROOT_MARKER).exists() {
+        return Ok(dir.to_path_buf());
+    }
+
+    let subdirs = std::fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect::<Vec<_>>();
+    match subdirs.as_slice() {
+        [root] if root.join(ROOT_MARKER).exists() => Ok(root.clone()),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Missing {ROOT_MARKER} in archive"),
+        )
+        .into()),
+    }
+}
+
+#[cfg(test)]
+/// Sample archives to support tests
+pub(crate) mod sample {
+    use crate::error::Result;
+
+    /// Pack a complete scraper output inside an `output/` directory
+    pub(crate) fn as_file<P: AsRef<std::path::Path>>(path: P) -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        crate::scraper::sample::as_dir(tmp.path())?;
+
+        let file = std::fs::File::create(path)?;
+        let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        builder.append_dir_all("output", tmp.path())?;
+        builder.into_inner()?.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_archive {
+    use super::*;
+
+    #[test]
+    fn archive_extension() {
+        assert!(is_archive("output.tar.gz"));
+        assert!(is_archive("output.TGZ"));
+        assert!(!is_archive("output"));
+        assert!(!is_archive("output.tar"));
+    }
+
+    #[test]
+    fn extract_nested_root() {
+        let tmp = tempfile::tempdir().unwrap();
+        let archive = tmp.path().join("output.tar.gz");
+        sample::as_file(&archive).unwrap();
+
+        let dest = tmp.path().join("extracted");
+        std::fs::create_dir(&dest).unwrap();
+        let root = extract(&archive, &dest).unwrap();
+        assert_eq!(root, dest.join("output"));
+        assert!(root.join("usage.json").exists());
+    }
+
+    #[test]
+    fn refuse_path_traversal() {
+        let tmp = tempfile::tempdir().unwrap();
+        let archive = tmp.path().join("evil.tar.gz");
+
+        let file = std::fs::File::create(&archive).unwrap();
+        let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        let content = b"evil";
+        let mut header = tar::Header::new_gnu();
+        // Bypass the path validation of set_path(), as a crafted archive would
+        let name = b"../evil.txt";
+        header.as_old_mut().name[..name.len()].copy_from_slice(name);
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, &content[..]).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let dest = tmp.path().join("extracted");
+        std::fs::create_dir(&dest).unwrap();
+        let err = extract(&archive, &dest).unwrap_err();
+        assert!(matches!(err, Error::UnsafeArchivePath(_)));
+        assert!(!tmp.path().join("evil.txt").exists());
+    }
+}
@@ -0,0 +1,231 @@
+//! Jurisdiction boundaries from a standard US boundary file
+//!
+//! The scraper outputs don't carry geometries, so national boundaries are
+//! loaded once from a GeoJSON FeatureCollection, such as the Census
+//! cartographic boundary files converted to GeoJSON. Each feature is
+//! keyed by its FIPS code, either as the feature `id` or as one of the
+//! `GEOID`, `FIPS`, or `fips` properties.
+
+use duckdb::Connection;
+use sha2::Digest;
+use tracing::{debug, trace, warn};
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, PartialEq)]
+/// A single jurisdiction boundary
+struct Boundary {
+    /// FIPS code as in the file, such as "08059"
+    fips: String,
+    /// Name of the jurisdiction, such as "Jefferson"
+    name: String,
+    /// GeoJSON geometry
+    geometry: String,
+}
+
+impl Boundary {
+    /// Rank of the jurisdiction, inferred from the FIPS code length
+    ///
+    /// Two digits identify a state, and five a county.
+    fn rank(&self) -> &'static str {
+        match self.fips.len() {
+            2 => "state",
+            5 => "county",
+            _ => "other",
+        }
+    }
+}
+
+/// Parse the boundaries of a GeoJSON FeatureCollection
+///
+/// Features without a FIPS code or a geometry are skipped with a warning.
+fn parse_boundaries(content: &str) -> Result<Vec<Boundary>> {
+    let collection: serde_json::Value =
+        serde_json::from_str(content).map_err(|e| Error::InvalidGeoJSON(e.to_string()))?;
+    if collection["type"] != "FeatureCollection" {
+        return Err(Error::InvalidGeoJSON(
+            "expected a FeatureCollection".to_string(),
+        ));
+    }
+    let features = collection["features"]
+        .as_array()
+        .ok_or_else(|| Error::InvalidGeoJSON("missing features".to_string()))?;
+
+    let mut boundaries = Vec::with_capacity(features.len());
+    for (i, feature) in features.iter().enumerate() {
+        let properties = &feature["properties"];
+        let fips = [
+            &feature["id"],
+            &properties["GEOID"],
+            &properties["FIPS"],
+            &properties["fips"],
+        ]
+        .into_iter()
+        .find_map(|v| match v {
+            serde_json::Value::String(s) => Some(s.clone()),
+            serde_json::Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        });
+        let Some(fips) = fips else {
+            warn!("Skipping feature {} without a FIPS code", i);
+            continue;
+        };
+        if feature["geometry"].is_null() {
+            warn!("Skipping feature {} (FIPS {}) without a geometry", i, fips);
+            continue;
+        }
+        let name = properties["NAME"]
+            .as_str()
+            .or_else(|| properties["name"].as_str())
+            .unwrap_or(&fips)
+            .to_string();
+
+        boundaries.push(Boundary {
+            fips,
+            name,
+            geometry: feature["geometry"].to_string(),
+        });
+    }
+
+    trace!("Parsed {} boundaries", boundaries.len());
+    Ok(boundaries)
+}
+
+/// Load jurisdiction geometries from a GeoJSON boundary file
+///
+/// Fill the geometry of the jurisdictions matching each feature's FIPS
+/// code, inserting the jurisdictions not yet in the database. The load is
+/// registered in the bookkeeper, with the checksum of the file as its
+/// hash.
+///
+/// Requires the spatial extension, installed by `init_db`.
+///
+/// # Returns
+///
+/// The number of jurisdictions updated or inserted.
+pub fn load_boundaries<P: AsRef<std::path::Path>>(conn: &mut Connection, path: P) -> Result<usize> {
+    trace!("Loading boundaries from {:?}", path.as_ref());
+
+    let content = std::fs::read(path.as_ref())?;
+    let checksum = format!("sha256:{:x}", sha2::Sha256::digest(&content));
+    let boundaries = parse_boundaries(&String::from_utf8_lossy(&content))?;
+
+    conn.execute_batch("LOAD spatial;")?;
+    let tx = conn.transaction()?;
+
+    let commit_id: usize = tx.query_row(
+        "INSERT INTO bookkeeper (hash, comment) VALUES (?, ?) RETURNING id",
+        [
+            checksum,
+            format!("Boundaries from {}", path.as_ref().display()),
+        ],
+        |row| row.get(0),
+    )?;
+    debug!("Boundaries commit id: {}", commit_id);
+
+    let mut update = tx.prepare(
+        "UPDATE jurisdiction SET geometry = ST_GeomFromGeoJSON(?) WHERE FIPS = ?::UBIGINT",
+    )?;
+    let mut insert = tx.prepare(
+        "INSERT INTO jurisdiction (bookkeeper_lnk, name, FIPS, geometry, rank, src)
+          VALUES (?, ?, ?::UBIGINT, ST_GeomFromGeoJSON(?), ?, ?)",
+    )?;
+    let src = path.as_ref().display().to_string();
+    for b in &boundaries {
+        let updated = update.execute([&b.geometry, &b.fips])?;
+        if updated == 0 {
+            insert.execute(duckdb::params![
+                commit_id,
+                b.name,
+                b.fips,
+                b.geometry,
+                b.rank(),
+                src
+            ])?;
+        }
+    }
+    drop((update, insert));
+    tx.commit()?;
+
+    trace!("Loaded {} boundaries", boundaries.len());
+    Ok(boundaries.len())
+}
+
+#[cfg(test)]
+mod test_boundaries {
+    use super::*;
+
+    const SAMPLE: &str = r#"{
+      "type": "FeatureCollection",
+      "features": [
+        {
+          "type": "Feature",
+          "id": "08059",
+          "properties": {"NAME": "Jefferson"},
+          "geometry": {"type": "Polygon", "coordinates": [[[-105.0, 39.5], [-105.0, 39.9], [-105.4, 39.9], [-105.0, 39.5]]]}
+        },
+        {
+          "type": "Feature",
+          "properties": {"GEOID": "08", "NAME": "Colorado"},
+          "geometry": {"type": "Polygon", "coordinates": [[[-109.0, 37.0], [-102.0, 37.0], [-102.0, 41.0], [-109.0, 37.0]]]}
+        },
+        {
+          "type": "Feature",
+          "properties": {"NAME": "Nowhere"},
+          "geometry": null
+        }
+      ]
+    }"#;
+
+    #[test]
+    fn parse_sample() {
+        let boundaries = parse_boundaries(SAMPLE).unwrap();
+        assert_eq!(boundaries.len(), 2);
+        assert_eq!(boundaries[0].fips, "08059");
+        assert_eq!(boundaries[0].name, "Jefferson");
+        assert_eq!(boundaries[0].rank(), "county");
+        assert!(boundaries[0].geometry.contains("Polygon"));
+        assert_eq!(boundaries[1].fips, "08");
+        assert_eq!(boundaries[1].rank(), "state");
+    }
+
+    #[test]
+    fn not_a_collection() {
+        let err = parse_boundaries(r#"{"type": "Feature"}"#).unwrap_err();
+        assert!(matches!(err, Error::InvalidGeoJSON(_)));
+    }
+
+    #[test]
+    #[ignore = "requires the spatial extension (network access)"]
+    fn load_sample() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        let db = db.to_str().unwrap();
+        crate::init_db(db).unwrap();
+        let path = tmp.path().join("counties.geojson");
+        std::fs::write(&path, SAMPLE).unwrap();
+
+        assert_eq!(
+            load_boundaries(&mut Connection::open(db).unwrap(), &path).unwrap(),
+            2
+        );
+        // Loading again updates instead of duplicating
+        assert_eq!(
+            load_boundaries(&mut Connection::open(db).unwrap(), &path).unwrap(),
+            2
+        );
+
+        let conn = Connection::open(db).unwrap();
+        conn.execute_batch("LOAD spatial;").unwrap();
+        let (count, wkt): (usize, String) = conn
+            .query_row(
+                "SELECT COUNT(*) OVER (), ST_AsText(geometry) FROM jurisdiction
+                  WHERE FIPS = 8059",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(count, 1);
+        assert!(wkt.starts_with("POLYGON"));
+    }
+}
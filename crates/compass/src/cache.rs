@@ -0,0 +1,215 @@
+//! Cache the ordinance records of repeated queries
+//!
+//! A server embedding this crate might query the same popular filters
+//! over and over. An `OrdinanceDb` keeps its connection, and optionally
+//! the records of the most recent queries, so that repeating one is
+//! served from memory instead of the database.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use duckdb::Connection;
+use tracing::trace;
+
+use crate::error::Result;
+use crate::{OrdinanceFilter, OrdinanceRecord, Technology};
+
+/// What identifies a query of ordinance records
+type CacheKey = (String, OrdinanceFilter, Option<u32>);
+
+/// Handle on an ordinance database, with an optional cache of records
+///
+/// The cache is opt-in, see `with_cache()`. It is invalidated on any
+/// write through `connection_mut()`, but can't notice writes from any
+/// other connection, such as another process loading a new commit.
+/// Call `invalidate()` whenever those might have happened, or don't
+/// enable the cache on a database that changes often.
+pub struct OrdinanceDb {
+    conn: Connection,
+    /// Most recently used first, up to `capacity`
+    cache: VecDeque<(CacheKey, Arc<Vec<OrdinanceRecord>>)>,
+    capacity: usize,
+    queries: usize,
+}
+
+impl OrdinanceDb {
+    /// A handle on the database of `conn`, without any cache
+    pub fn new(conn: Connection) -> Self {
+        Self {
+            conn,
+            cache: VecDeque::new(),
+            capacity: 0,
+            queries: 0,
+        }
+    }
+
+    /// Keep the records of the `capacity` most recent queries
+    ///
+    /// The least recently used are evicted first. Zero disables the
+    /// cache.
+    #[must_use]
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self.cache.truncate(capacity);
+        self
+    }
+
+    /// The reVX standard ordinance records of a technology
+    ///
+    /// Only the records matching `filter`, with the quantitative values
+    /// rounded to `precision` decimal places if given, as in the export.
+    /// Served from the cache, if enabled and the same query was recent.
+    pub fn records(
+        &mut self,
+        technology: &str,
+        filter: &OrdinanceFilter,
+        precision: Option<u32>,
+    ) -> Result<Arc<Vec<OrdinanceRecord>>> {
+        let key = (technology.to_string(), filter.clone(), precision);
+        if let Some(i) = self.cache.iter().position(|(k, _)| *k == key) {
+            trace!("Records of {:?} cached", key);
+            let entry = self.cache.remove(i).unwrap();
+            let records = Arc::clone(&entry.1);
+            self.cache.push_front(entry);
+            return Ok(records);
+        }
+
+        let technology = Technology::try_from(technology)?;
+        crate::check_commit(&self.conn, filter)?;
+        self.queries += 1;
+        let records = Arc::new(crate::ordinance_records(
+            &self.conn,
+            &technology,
+            filter,
+            precision,
+        )?);
+        trace!("Queried {} records of {:?}", records.len(), key);
+
+        if self.capacity > 0 {
+            self.cache.truncate(self.capacity - 1);
+            self.cache.push_front((key, Arc::clone(&records)));
+        }
+        Ok(records)
+    }
+
+    /// Number of queries of records run on the database
+    ///
+    /// Those served from the cache are not counted.
+    pub fn queries(&self) -> usize {
+        self.queries
+    }
+
+    /// The connection, to read the database
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// The connection, to write into the database
+    ///
+    /// Invalidates the cache, since any record might change.
+    pub fn connection_mut(&mut self) -> &mut Connection {
+        self.invalidate();
+        &mut self.conn
+    }
+
+    /// Drop all the cached records
+    pub fn invalidate(&mut self) {
+        trace!("Invalidating {} cached queries", self.cache.len());
+        self.cache.clear();
+    }
+}
+
+#[cfg(test)]
+mod test_cache {
+    use super::*;
+    use crate::sample::sample_db;
+
+    fn open(dir: &std::path::Path) -> OrdinanceDb {
+        OrdinanceDb::new(Connection::open(sample_db(dir)).unwrap())
+    }
+
+    #[test]
+    /// Repeating a query hits the cache
+    fn repeated_query() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut db = open(tmp.path()).with_cache(4);
+        let filter = OrdinanceFilter::default();
+
+        let first = db.records("wind", &filter, None).unwrap();
+        let second = db.records("wind", &filter, None).unwrap();
+        assert_eq!(db.queries(), 1);
+        assert_eq!(first.len(), 2);
+        assert_eq!(first, second);
+
+        // Any other query isn't
+        db.records("wind", &filter, Some(1)).unwrap();
+        db.records("wind", &OrdinanceFilter::builder().commit(1).build(), None)
+            .unwrap();
+        assert_eq!(db.queries(), 3);
+    }
+
+    #[test]
+    /// Without the cache, every query runs on the database
+    fn no_cache() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut db = open(tmp.path());
+        let filter = OrdinanceFilter::default();
+
+        db.records("wind", &filter, None).unwrap();
+        db.records("wind", &filter, None).unwrap();
+        assert_eq!(db.queries(), 2);
+    }
+
+    #[test]
+    /// A write invalidates the cache
+    fn invalidated_on_write() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut db = open(tmp.path()).with_cache(4);
+        let filter = OrdinanceFilter::default();
+
+        assert_eq!(db.records("wind", &filter, None).unwrap().len(), 2);
+        db.connection_mut()
+            .execute(
+                "INSERT INTO quantitative (bookkeeper_lnk, county, state, FIPS, feature, value)
+                  VALUES (1, 'county-3', 'state-3', 33333, 'feature-3', 3.5)",
+                [],
+            )
+            .unwrap();
+        assert_eq!(db.records("wind", &filter, None).unwrap().len(), 3);
+        assert_eq!(db.queries(), 2);
+    }
+
+    #[test]
+    /// The least recently used query is evicted first
+    fn evict_least_recent() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut db = open(tmp.path()).with_cache(2);
+        let all = OrdinanceFilter::default();
+        let first = OrdinanceFilter::builder().commit(1).build();
+        let features = OrdinanceFilter::builder().feature("feature-1").build();
+
+        db.records("wind", &all, None).unwrap();
+        db.records("wind", &first, None).unwrap();
+        db.records("wind", &all, None).unwrap();
+        db.records("wind", &features, None).unwrap();
+        assert_eq!(db.queries(), 3);
+
+        // The one evicted
+        db.records("wind", &first, None).unwrap();
+        assert_eq!(db.queries(), 4);
+        db.records("wind", &features, None).unwrap();
+        assert_eq!(db.queries(), 4);
+    }
+
+    #[test]
+    /// An unknown commit fails, and isn't cached
+    fn unknown_commit() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut db = open(tmp.path()).with_cache(2);
+        let filter = OrdinanceFilter::builder().commit(9).build();
+
+        db.records("wind", &filter, None).unwrap_err();
+        db.records("wind", &filter, None).unwrap_err();
+        assert_eq!(db.queries(), 0);
+    }
+}
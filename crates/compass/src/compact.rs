@@ -0,0 +1,126 @@
+//! Compact a database for distribution
+//!
+//! A database that went through many loads, or a removed commit, keeps
+//! free blocks and the layout of its successive writes. Copying all its
+//! content into a fresh file rewrites it densely, with DuckDB's
+//! compression applied to every table at once, resulting in a smaller
+//! file to ship.
+
+use serde::Serialize;
+use tracing::{debug, trace};
+
+use crate::error::Result;
+
+#[derive(Debug, Serialize)]
+/// Outcome of compacting a database
+pub struct Compaction {
+    /// Size, in bytes, of the original database
+    pub before: u64,
+    /// Size, in bytes, of the compacted database
+    pub after: u64,
+}
+
+impl std::fmt::Display for Compaction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} bytes -> {} bytes", self.before, self.after)?;
+        if self.before > 0 {
+            let ratio = self.after as f64 / self.before as f64;
+            write!(f, " ({:.0}%)", ratio * 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Copy a database into a new, compacted, file at `output`
+///
+/// Every table, view, and sequence is copied, thus the compacted
+/// database is used just as the original, which is left untouched. The
+/// `output` must not exist, and is only created if the whole copy
+/// succeeds. The database of `conn` must be a file, not in-memory.
+pub fn compact<P: AsRef<std::path::Path>>(
+    conn: &duckdb::Connection,
+    output: P,
+) -> Result<Compaction> {
+    let output = output.as_ref();
+    let original = crate::database_path(conn)?.ok_or_else(|| {
+        crate::error::Error::Undefined("Can't compact an in-memory database".to_string())
+    })?;
+    trace!("Compacting {:?} into {:?}", original, output);
+
+    // Any pending write-ahead log is merged into the original first,
+    // thus its size is comparable
+    conn.execute_batch("CHECKPOINT;")?;
+    let before = std::fs::metadata(&original)?.len();
+
+    crate::create_atomically(output, false, |conn| {
+        conn.execute_batch(&format!(
+            "ATTACH '{}' AS original (READ_ONLY);",
+            original.to_string_lossy().replace('\'', "''")
+        ))?;
+        let target: String = conn.query_row("SELECT current_database()", [], |row| row.get(0))?;
+        debug!("Copying into {:?}", target);
+        conn.execute_batch(&format!("COPY FROM DATABASE original TO \"{target}\";"))?;
+        conn.execute_batch("DETACH original;")?;
+        Ok(())
+    })?;
+
+    let after = std::fs::metadata(output)?.len();
+    let compaction = Compaction { before, after };
+    trace!("Compacted: {}", compaction);
+    Ok(compaction)
+}
+
+#[cfg(test)]
+mod test_compact {
+    use super::*;
+    use crate::sample::sample_db;
+
+    #[test]
+    fn same_content() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+        let output = tmp.path().join("compact.duckdb");
+
+        let compaction = compact(&crate::open_readonly(&db).unwrap(), &output).unwrap();
+        assert!(compaction.after > 0);
+        assert_eq!(compaction.before, std::fs::metadata(&db).unwrap().len());
+
+        let query = |path: &std::path::Path| -> Vec<(usize, String, Option<f64>)> {
+            let conn = crate::open_readonly(path).unwrap();
+            let mut stmt = conn
+                .prepare("SELECT bookkeeper_lnk, feature, quantitative FROM ordinance ORDER BY ALL")
+                .unwrap();
+            stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .unwrap()
+                .collect::<duckdb::Result<Vec<_>>>()
+                .unwrap()
+        };
+        let original = query(std::path::Path::new(&db));
+        assert_eq!(original.len(), 2);
+        assert_eq!(query(&output), original);
+
+        // Still usable for new loads, without reusing a commit id
+        let conn = duckdb::Connection::open(&output).unwrap();
+        let id: usize = conn
+            .query_row(
+                "INSERT INTO bookkeeper (hash) VALUES ('next') RETURNING id",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(id, 2);
+
+        // Never replaces an existing file
+        assert!(compact(&crate::open_readonly(&db).unwrap(), &output).is_err());
+    }
+
+    #[test]
+    fn in_memory() {
+        let tmp = tempfile::tempdir().unwrap();
+        let output = tmp.path().join("compact.duckdb");
+
+        let conn = duckdb::Connection::open_in_memory().unwrap();
+        assert!(compact(&conn, &output).is_err());
+        assert!(!output.exists());
+    }
+}
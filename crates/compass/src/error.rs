@@ -12,6 +12,88 @@ pub enum Error {
     #[error(transparent)]
     DuckDB(#[from] duckdb::Error),
 
+    #[cfg(feature = "bincode")]
+    #[error(transparent)]
+    BincodeEncode(#[from] bincode::error::EncodeError),
+
+    #[cfg(feature = "bincode")]
+    #[error(transparent)]
+    BincodeDecode(#[from] bincode::error::DecodeError),
+
+    #[error("Unsafe path {0:?} in archive")]
+    /// An archive entry that would be extracted outside the destination
+    UnsafeArchivePath(std::path::PathBuf),
+
+    #[error("Invalid JSON: {0}")]
+    /// A JSON file that doesn't parse as the expected structure
+    InvalidJson(#[from] serde_json::Error),
+
+    #[error("Missing quantitative ordinance file, expected one of: {}", .0.join(", "))]
+    /// None of the candidate quantitative ordinance files exists
+    MissingQuantitative(Vec<String>),
+
+    #[error("Empty CSV {0}, without even a header")]
+    /// An ordinance CSV without any content, while a run that found
+    /// nothing still writes the header
+    EmptyCsv(String),
+
+    #[error("Invalid GeoJSON: {0}")]
+    /// A boundary file that isn't a valid GeoJSON FeatureCollection
+    InvalidGeoJSON(String),
+
+    #[error("Invalid log pattern: {0}")]
+    /// A log pattern that doesn't compile or lacks a required group
+    InvalidLogPattern(String),
+
+    #[error("Timed out after {timeout:?} opening database {path:?}")]
+    /// A database that couldn't be opened in time, such as on a wedged
+    /// network filesystem
+    OpenTimeout {
+        path: std::path::PathBuf,
+        timeout: std::time::Duration,
+    },
+
+    #[error("Load timed out after {0:?}")]
+    /// A load that didn't complete in time, see `LoadOptions::timeout`
+    LoadTimeout(std::time::Duration),
+
+    #[error("Invalid commit hash {0:?}")]
+    /// A commit hash that is empty, or a placeholder
+    InvalidHash(String),
+
+    #[error("Unsupported data model version {0:?}")]
+    /// A stored data model version without a known migration path
+    UnsupportedVersion(String),
+
+    #[error("Unsupported format {0:?}")]
+    /// An export format not supported, or not enabled in this build
+    UnsupportedFormat(String),
+
+    #[error("Unknown commit {0}")]
+    /// A commit (bookkeeper id) that doesn't exist in the database
+    UnknownCommit(usize),
+
+    #[error("Unknown commit hash {0:?}")]
+    /// A commit hash that doesn't exist in the database
+    UnknownHash(String),
+
+    #[error("Unknown jurisdiction {county:?}, {state:?}")]
+    /// A jurisdiction not found in the database
+    UnknownJurisdiction { county: String, state: String },
+
+    #[error("Unknown column {column:?}, valid columns are: {valid}")]
+    /// A column that is not available
+    UnknownColumn { column: String, valid: String },
+
+    #[error("More than {0} jurisdictions in jurisdictions.json")]
+    /// A jurisdictions file beyond what is held in memory, see
+    /// `MAX_JURISDICTIONS`
+    TooManyJurisdictions(usize),
+
+    #[error("Aborted on {} warning(s): {}", .0.len(), .0.join("; "))]
+    /// Warnings raised while loading in strict mode
+    Warnings(Vec<String>),
+
     #[allow(dead_code)]
     #[error("Undefined error")]
     // Used during development while it is not clear a category of error
@@ -0,0 +1,179 @@
+//! Select which ordinances to query
+//!
+//! Filters are translated into a SQL condition on the `ordinance` view,
+//! with placeholders, so that user given values are always passed as
+//! parameters and never interpolated in the query.
+
+use duckdb::ToSql;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// Filter of ordinance records
+///
+/// Each criterion is optional, and all the given ones must match.
+pub struct OrdinanceFilter {
+    /// Only the ordinances of this commit (bookkeeper id)
+    pub commit_id: Option<usize>,
+    /// Only these features, such as "structures" or "property line"
+    ///
+    /// Empty means all features.
+    pub features: Vec<String>,
+    /// Skip the ordinances without any value
+    ///
+    /// Some rows only state that a feature exists, without a value
+    /// extracted, neither a number, a range, nor a summary. Those are
+    /// included by default.
+    pub exclude_empty: bool,
+}
+
+impl OrdinanceFilter {
+    /// Compose a filter one criterion at a time
+    ///
+    /// Any criterion not given keeps its default, thus matching all the
+    /// ordinances.
+    ///
+    /// ```
+    /// use infra_compass_db::OrdinanceFilter;
+    ///
+    /// let filter = OrdinanceFilter::builder()
+    ///     .commit(3)
+    ///     .feature("structures")
+    ///     .feature("roads")
+    ///     .build();
+    /// assert_eq!(
+    ///     filter,
+    ///     OrdinanceFilter {
+    ///         commit_id: Some(3),
+    ///         features: vec!["structures".to_string(), "roads".to_string()],
+    ///         exclude_empty: false,
+    ///     }
+    /// );
+    /// ```
+    pub fn builder() -> OrdinanceFilterBuilder {
+        OrdinanceFilterBuilder::default()
+    }
+
+    /// SQL condition of this filter, and the parameters it requires
+    ///
+    /// The condition refers to the columns of the `ordinance` view, thus
+    /// it can be appended to a `WHERE` clause with `AND`.
+    pub(crate) fn condition(&self) -> (String, Vec<&dyn ToSql>) {
+        let mut condition = "(?::INTEGER IS NULL OR ordinance.bookkeeper_lnk = ?)".to_string();
+        let mut params: Vec<&dyn ToSql> = vec![&self.commit_id, &self.commit_id];
+
+        if !self.features.is_empty() {
+            let placeholders = vec!["?"; self.features.len()].join(", ");
+            condition.push_str(&format!(" AND ordinance.feature IN ({placeholders})"));
+            params.extend(self.features.iter().map(|f| f as &dyn ToSql));
+        }
+
+        if self.exclude_empty {
+            condition.push_str(
+                " AND (ordinance.quantitative IS NOT NULL OR ordinance.value_type IS NOT NULL \
+                 OR NULLIF(trim(ordinance.qualitative), '') IS NOT NULL)",
+            );
+        }
+
+        (condition, params)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+#[must_use]
+/// Builder of an `OrdinanceFilter`, see `OrdinanceFilter::builder()`
+pub struct OrdinanceFilterBuilder(OrdinanceFilter);
+
+impl OrdinanceFilterBuilder {
+    /// Only the ordinances of this commit (bookkeeper id)
+    pub fn commit(mut self, commit_id: usize) -> Self {
+        self.0.commit_id = Some(commit_id);
+        self
+    }
+
+    /// Include this feature, in addition to any previous one
+    pub fn feature<S: Into<String>>(mut self, feature: S) -> Self {
+        self.0.features.push(feature.into());
+        self
+    }
+
+    /// Include these features, in addition to any previous one
+    pub fn features<I, S>(mut self, features: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.0.features.extend(features.into_iter().map(Into::into));
+        self
+    }
+
+    /// Whether to skip the ordinances without any value
+    pub fn exclude_empty(mut self, exclude_empty: bool) -> Self {
+        self.0.exclude_empty = exclude_empty;
+        self
+    }
+
+    /// The filter composed
+    pub fn build(self) -> OrdinanceFilter {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod test_filter {
+    use super::*;
+
+    #[test]
+    fn default_condition() {
+        let filter = OrdinanceFilter::default();
+        let (condition, params) = filter.condition();
+        assert!(!condition.contains("feature"));
+        assert_eq!(params.len(), 2);
+    }
+
+    #[test]
+    fn features_condition() {
+        let filter = OrdinanceFilter {
+            features: vec!["structures".to_string(), "roads".to_string()],
+            ..Default::default()
+        };
+        let (condition, params) = filter.condition();
+        assert!(condition.ends_with(" AND ordinance.feature IN (?, ?)"));
+        assert_eq!(params.len(), 4);
+    }
+
+    #[test]
+    fn exclude_empty_condition() {
+        let filter = OrdinanceFilter {
+            exclude_empty: true,
+            ..Default::default()
+        };
+        let (condition, params) = filter.condition();
+        assert!(condition.contains("ordinance.value_type IS NOT NULL"));
+        assert_eq!(params.len(), 2);
+    }
+
+    #[test]
+    fn builder() {
+        let filter = OrdinanceFilter::builder()
+            .commit(3)
+            .feature("structures")
+            .features(["roads", "railroads"])
+            .exclude_empty(true)
+            .build();
+        assert_eq!(
+            filter,
+            OrdinanceFilter {
+                commit_id: Some(3),
+                features: vec![
+                    "structures".to_string(),
+                    "roads".to_string(),
+                    "railroads".to_string()
+                ],
+                exclude_empty: true,
+            }
+        );
+        assert_eq!(
+            OrdinanceFilter::builder().build(),
+            OrdinanceFilter::default()
+        );
+    }
+}
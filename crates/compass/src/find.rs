@@ -0,0 +1,284 @@
+//! Everything known about a single jurisdiction
+//!
+//! A common interactive need is "show me everything for Jefferson County,
+//! Colorado". This module collects, in one structured report, the
+//! ordinances, the archived documents, and the LLM usage of a
+//! jurisdiction, across all the loads (commits).
+
+use serde::Serialize;
+use tracing::trace;
+
+use crate::error::{Error, Result};
+
+/// SQL expression normalizing a county name for matching
+///
+/// Case insensitive and ignoring a "County" or "Co." suffix, consistent
+/// with `normalize_county()`.
+pub(crate) const NORMALIZED_COUNTY: &str =
+    r"lower(trim(regexp_replace(trim(county), '\s+(county|co\.?)$', '', 'i')))";
+
+#[derive(Debug, Serialize)]
+/// Report of a jurisdiction
+pub struct JurisdictionReport {
+    /// County as stored, such as "Jefferson County"
+    pub county: String,
+    /// State as stored, such as "Colorado"
+    pub state: String,
+    /// Ordinances of the jurisdiction
+    pub ordinances: Vec<OrdinanceEntry>,
+    /// Documents archived for the jurisdiction
+    pub documents: Vec<DocumentEntry>,
+    /// LLM usage to scrape the jurisdiction, by model
+    pub usage: Vec<UsageEntry>,
+}
+
+#[derive(Debug, Serialize)]
+/// A single ordinance of a jurisdiction
+pub struct OrdinanceEntry {
+    /// Commit (bookkeeper id) that loaded it
+    pub commit_id: usize,
+    /// Feature, such as "structures"
+    pub feature: String,
+    /// Quantitative value, if any
+    pub quantitative: Option<f64>,
+    /// Units of the quantitative value, if any
+    pub units: Option<String>,
+    /// Qualitative value, if any
+    pub qualitative: Option<String>,
+    /// Year of the ordinance, if known
+    pub ord_year: Option<u32>,
+    /// Source of the ordinance, such as a URL
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+/// A document archived for a jurisdiction
+pub struct DocumentEntry {
+    /// Source of the document, such as a URL
+    pub source: Option<String>,
+    /// Filename of the document
+    pub filename: Option<String>,
+    /// Year that the ordinance went into effect, if known
+    pub effective_year: Option<u32>,
+    /// Number of pages
+    pub num_pages: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+/// LLM usage of a single model for a jurisdiction
+pub struct UsageEntry {
+    /// Model, such as "gpt-4.1-mini"
+    pub model: String,
+    /// Number of requests
+    pub requests: u64,
+    /// Tokens sent in the prompts
+    pub prompt_tokens: u64,
+    /// Tokens received in the responses
+    pub response_tokens: u64,
+}
+
+impl std::fmt::Display for JurisdictionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "{}, {}", self.county, self.state)?;
+
+        writeln!(f, "\nOrdinances ({}):", self.ordinances.len())?;
+        for o in &self.ordinances {
+            let value = match (&o.quantitative, &o.qualitative) {
+                (Some(v), _) => format!("{v} {}", o.units.as_deref().unwrap_or("")),
+                (None, Some(q)) => q.clone(),
+                (None, None) => String::new(),
+            };
+            writeln!(f, "  {}: {}", o.feature, value.trim_end())?;
+        }
+
+        writeln!(f, "\nDocuments ({}):", self.documents.len())?;
+        for d in &self.documents {
+            writeln!(
+                f,
+                "  {} ({})",
+                d.filename.as_deref().unwrap_or("unknown"),
+                d.source.as_deref().unwrap_or("unknown source")
+            )?;
+        }
+
+        writeln!(f, "\nUsage:")?;
+        for u in &self.usage {
+            writeln!(
+                f,
+                "  {}: {} requests, {} prompt tokens, {} response tokens",
+                u.model, u.requests, u.prompt_tokens, u.response_tokens
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Normalize a county name for matching
+///
+/// Lowercase and without a "County" or "Co." suffix, such that
+/// "Jefferson County", "jefferson co." and "Jefferson" all match.
+pub(crate) fn normalize_county(county: &str) -> String {
+    let county = county.trim().to_lowercase();
+    ["county", "co.", "co"]
+        .iter()
+        .find_map(|suffix| {
+            county
+                .strip_suffix(suffix)
+                .filter(|s| s.ends_with(char::is_whitespace))
+        })
+        .unwrap_or(&county)
+        .trim()
+        .to_string()
+}
+
+/// Find everything about a jurisdiction, given its county and state
+///
+/// The match is case insensitive, and ignores a "County" or "Co." suffix.
+/// Returns an error if the jurisdiction is not in the database.
+pub fn find_jurisdiction(
+    conn: &duckdb::Connection,
+    county: &str,
+    state: &str,
+) -> Result<JurisdictionReport> {
+    trace!("Finding jurisdiction {:?}, {:?}", county, state);
+
+    let (county, state) = (normalize_county(county), state.trim().to_lowercase());
+    let filter = format!("{NORMALIZED_COUNTY} = ? AND lower(trim(state)) = ?");
+
+    let mut stmt = conn.prepare(&format!(
+        r"SELECT bookkeeper_lnk, feature, quantitative, units, qualitative, ord_year, source,
+            county, state
+          FROM ordinance WHERE {filter}
+          ORDER BY bookkeeper_lnk, feature"
+    ))?;
+    let mut names = None;
+    let ordinances = stmt
+        .query_map([&county, &state], |row| {
+            names.get_or_insert((row.get::<_, String>(7)?, row.get::<_, String>(8)?));
+            Ok(OrdinanceEntry {
+                commit_id: row.get(0)?,
+                feature: row.get(1)?,
+                quantitative: row.get(2)?,
+                units: row.get(3)?,
+                qualitative: row.get(4)?,
+                ord_year: row.get(5)?,
+                source: row.get(6)?,
+            })
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+    trace!("Found {} ordinances", ordinances.len());
+
+    let mut stmt = conn.prepare(&format!(
+        r"SELECT DISTINCT a.id, a.source, a.filename, a.effective_year, a.num_pages,
+            s.county, s.state
+          FROM source s
+            JOIN archive a
+              ON list_contains(string_split(s.documents, ','), CAST(a.id AS VARCHAR))
+          WHERE {filter}
+          ORDER BY a.id"
+    ))?;
+    let documents = stmt
+        .query_map([&county, &state], |row| {
+            names.get_or_insert((row.get::<_, String>(5)?, row.get::<_, String>(6)?));
+            Ok(DocumentEntry {
+                source: row.get(1)?,
+                filename: row.get(2)?,
+                effective_year: row.get(3)?,
+                num_pages: row.get(4)?,
+            })
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+    trace!("Found {} documents", documents.len());
+
+    let mut stmt = conn.prepare(&format!(
+        r"SELECT m.model, SUM(m.total_requests), SUM(m.total_prompt_tokens),
+            SUM(m.total_response_tokens)
+          FROM usage_event e JOIN usage_model m ON (e.id = m.usage_lnk)
+          WHERE e.jurisdiction IN (SELECT full_name FROM source WHERE {filter})
+          GROUP BY m.model
+          ORDER BY m.model"
+    ))?;
+    let usage = stmt
+        .query_map([&county, &state], |row| {
+            Ok(UsageEntry {
+                model: row.get(0)?,
+                requests: row.get(1)?,
+                prompt_tokens: row.get(2)?,
+                response_tokens: row.get(3)?,
+            })
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+    trace!("Found usage of {} models", usage.len());
+
+    let Some((county, state)) = names else {
+        return Err(Error::UnknownJurisdiction { county, state });
+    };
+    Ok(JurisdictionReport {
+        county,
+        state,
+        ordinances,
+        documents,
+        usage,
+    })
+}
+
+#[cfg(test)]
+mod test_find {
+    use super::*;
+    use crate::sample::empty_db;
+
+    #[test]
+    fn county_suffix() {
+        assert_eq!(normalize_county("Jefferson County"), "jefferson");
+        assert_eq!(normalize_county(" jefferson co. "), "jefferson");
+        assert_eq!(normalize_county("JEFFERSON CO"), "jefferson");
+        assert_eq!(normalize_county("Jefferson"), "jefferson");
+        assert_eq!(normalize_county("Orco"), "orco");
+    }
+
+    #[test]
+    fn find_sample_jurisdiction() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        let conn = empty_db(&db);
+        conn.execute_batch(
+            r"
+            INSERT INTO bookkeeper (hash, username) VALUES ('sample', 'test');
+            INSERT INTO archive (source, filename, effective_year, num_pages)
+              VALUES ('https://example.com/jefferson.pdf', 'jefferson.pdf', 2023, 10),
+                     ('https://example.com/other.pdf', 'other.pdf', 2022, 5);
+            INSERT INTO source (bookkeeper_lnk, full_name, county, state, fips, documents)
+              VALUES (1, 'Jefferson County, Colorado', 'Jefferson County', 'Colorado', 8059, '1'),
+                     (1, 'Other County, Colorado', 'Other County', 'Colorado', 8001, '2');
+            INSERT INTO quantitative (bookkeeper_lnk, county, state, FIPS, feature, value, units)
+              VALUES (1, 'Jefferson County', 'Colorado', 8059, 'structures', 500, 'feet'),
+                     (1, 'Other County', 'Colorado', 8001, 'structures', 300, 'feet');
+            INSERT INTO qualitative (bookkeeper_lnk, county, state, FIPS, feature, summary)
+              VALUES (1, 'Jefferson County', 'Colorado', 8059, 'color', 'white');
+            INSERT INTO usage_event (bookkeeper_lnk, jurisdiction)
+              VALUES (1, 'Jefferson County, Colorado');
+            INSERT INTO usage_model
+              (usage_lnk, model, total_requests, total_prompt_tokens, total_response_tokens)
+              VALUES (1, 'gpt-4.1-mini', 121, 186099, 6297);
+            ",
+        )
+        .unwrap();
+        let conn = crate::open_readonly(&db).unwrap();
+
+        let report = find_jurisdiction(&conn, "jefferson co.", "COLORADO").unwrap();
+        assert_eq!(report.county, "Jefferson County");
+        assert_eq!(report.state, "Colorado");
+        assert_eq!(report.ordinances.len(), 2);
+        assert_eq!(report.documents.len(), 1);
+        assert_eq!(
+            report.documents[0].filename.as_deref(),
+            Some("jefferson.pdf")
+        );
+        assert_eq!(report.usage.len(), 1);
+        assert_eq!(report.usage[0].prompt_tokens, 186099);
+        assert!(report.to_string().contains("structures: 500 feet"));
+
+        let err = find_jurisdiction(&conn, "Jefferson", "Kansas").unwrap_err();
+        assert!(matches!(err, Error::UnknownJurisdiction { .. }));
+    }
+}
@@ -2,130 +2,491 @@
 
 //! NLR's ordinance database
 
+mod archive;
+mod boundaries;
+mod cache;
+mod compact;
 mod error;
+mod filter;
+mod find;
+mod merge;
+mod migrate;
+mod query;
+mod recheck;
+mod rename;
+mod resolve;
+mod schema;
 mod scraper;
+mod search;
+mod status;
+mod table;
+mod verify;
 
-use duckdb::Connection;
-use serde::Serialize;
+use duckdb::{Connection, OptionalExt};
+use serde::{Deserialize, Serialize};
 use tracing::{self, trace};
 
+pub use boundaries::load_boundaries;
+pub use cache::OrdinanceDb;
+pub use compact::{Compaction, compact};
 use error::Result;
+pub use filter::{OrdinanceFilter, OrdinanceFilterBuilder};
+pub use find::{DocumentEntry, JurisdictionReport, OrdinanceEntry, UsageEntry, find_jurisdiction};
+pub use migrate::migrate;
+pub use query::{
+    ColumnCompleteness, FeatureStats, LoggedError, completeness, counts_by_jurisdiction_type,
+    errors, export_logs, export_provenance, export_usage, feature_stats, resolve_setback,
+};
+pub use recheck::{ChecksumMismatch, recheck};
+pub use rename::rename_feature;
+pub use resolve::{FipsResolution, UnresolvedFips, resolve_fips};
+pub use schema::{Column, Schema, Table, schema, version_warning};
+pub use scraper::{
+    Check, CheckStatus, Diagnosis, Inspection, JsonKind, LLMMetadata, Metadata, UsageTotals,
+    doctor, inspect, validate_json,
+};
+pub use search::{QualitativeRecord, search};
+pub use status::{Status, status};
+pub use verify::{Inconsistency, consistency_check};
+
+/// Version of the ordinance data model
+const ORDINANCEDB_VERSION: &str = "0.0.10";
+
+/// Default size, in bytes, of the buffers to read documents
+const IO_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Default number of documents checksummed at once
+const PARALLEL_HASH: usize = 8;
 
 /// Initialize the database
 ///
 /// Create a new database as a local single file ready to store the ordinance
 /// data.
+///
+/// The database is initialized in a sibling temporary file, which is only
+/// moved to `path` once the whole data model is committed. Therefore, a
+/// failure midway never leaves a partial database at `path`.
+///
+/// It fails if `path` already exists, see `init_db_with()` to replace it.
 pub fn init_db(path: &str) -> Result<()> {
+    init_db_with(path, false)
+}
+
+/// Initialize the database, possibly replacing an existing one
+///
+/// Same as `init_db()`, but with `overwrite` an existing `path` is
+/// replaced, as long as it is an ordinance database or an empty file.
+/// Anything else is refused, so that a mistyped path doesn't destroy an
+/// unrelated file.
+pub fn init_db_with(path: &str, overwrite: bool) -> Result<()> {
     trace!("Creating a new database at {:?}", &path);
 
-    let mut db = Connection::open(path)?;
-    trace!("Database opened: {:?}", &db);
+    create_atomically(std::path::Path::new(path), overwrite, |conn| {
+        conn.execute_batch(&format!(
+            "SET VARIABLE ordinancedb_version = '{ORDINANCEDB_VERSION}';"
+        ))?;
+        trace!(
+            "Defining ordinance data model version as: {}",
+            ORDINANCEDB_VERSION
+        );
 
-    db.execute_batch("SET VARIABLE ordinancedb_version = '0.0.1';")?;
-    trace!("Defining ordinance data model version as: 0.0.1");
+        /*
+         * Change the source structure to have a database of all sources, and
+         * the run links to the source used. Also link that to the jurisdiction
+         * such that it could later request all sources related to a certain
+         * jurisdiction.
+         *
+         * Multiple sources for the same jurisdiction (consider multiple
+         * technologies) should be possible.
+         *
+         * In case of multiple sources for one jurisdiction, we should be able
+         * to support what was the latest document available, or list all of
+         * them.
+         *
+         * Check the new jurisdiction file.
+         *
+         * If user don't have a database. Download it in the right path.
+         *
+         *
+         */
+        init_bookkeeper(conn)?;
+
+        conn.execute_batch(
+            "
+        INSTALL spatial;
+        LOAD spatial;
+
+        CREATE SEQUENCE jurisdiction_sequence START 1;
+        CREATE TYPE jurisdiction_rank AS ENUM ('state', 'county', 'parish', 'city', 'town', 'district', 'other');
+        CREATE TABLE jurisdiction (
+          id INTEGER PRIMARY KEY DEFAULT NEXTVAL('jurisdiction_sequence'),
+          bookkeeper_lnk INTEGER REFERENCES bookkeeper(id) NOT NULL,
+          name TEXT NOT NULL,
+          FIPS UBIGINT NOT NULL,
+          geometry GEOMETRY NOT NULL,
+          rank jurisdiction_rank NOT NULL,
+          parent_id INTEGER REFERENCES jurisdiction(id),
+          created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+          src TEXT,
+          comments TEXT
+          );",
+        )?;
+
+        scraper::ScrapedOrdinance::init_db(conn)?;
+        Ok(())
+    })?;
+
+    trace!("Database initialized");
+    Ok(())
+}
+
+/// Temporary directory in the same directory as `path`
+///
+/// Anything created in there can be moved to `path` by a rename, which is
+/// atomic since it stays in the same filesystem.
+fn tempdir_next_to(path: &std::path::Path, prefix: &str) -> Result<tempfile::TempDir> {
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => std::path::Path::new("."),
+    };
+    Ok(tempfile::Builder::new().prefix(prefix).tempdir_in(parent)?)
+}
+
+/// Create a new database at `path`, only if `init` succeeds
+///
+/// The database is created in a temporary directory next to `path`, so
+/// that the final rename stays in the same filesystem, thus atomic. It
+/// refuses to replace an existing `path`, unless `overwrite` and that is
+/// an ordinance database or an empty file.
+fn create_atomically<F>(path: &std::path::Path, overwrite: bool, init: F) -> Result<()>
+where
+    F: FnOnce(&duckdb::Transaction) -> Result<()>,
+{
+    if path.exists() {
+        if !overwrite {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!(
+                    "Database already exists at {}, use overwrite to replace it",
+                    path.display()
+                ),
+            )
+            .into());
+        }
+        check_replaceable(path)?;
+        trace!("Replacing the existing database at {:?}", path);
+    }
+
+    let tmp = tempdir_next_to(path, ".ordinancedb-init-")?;
+    let tmp_path = tmp.path().join("init.db");
+    trace!("Initializing database at temporary {:?}", &tmp_path);
+
+    {
+        let mut db = Connection::open(&tmp_path)?;
+        trace!("Database opened: {:?}", &db);
+
+        let conn = db.transaction()?;
+        init(&conn)?;
+        conn.commit()?;
+        // Merge the write-ahead log, so that a single file is moved
+        db.execute_batch("CHECKPOINT;")?;
+    }
+
+    std::fs::rename(&tmp_path, path)?;
+    trace!("Database moved to {:?}", path);
+
+    // A write-ahead log left by a replaced database would be replayed
+    // into the new one
+    let wal = path.with_file_name(format!(
+        "{}.wal",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    if overwrite && wal.exists() {
+        std::fs::remove_file(&wal)?;
+        trace!("Removed stale write-ahead log {:?}", wal);
+    }
+    Ok(())
+}
+
+/// Confirm that `path` is an ordinance database or an empty file
+fn check_replaceable(path: &std::path::Path) -> Result<()> {
+    if std::fs::metadata(path)?.len() == 0 {
+        return Ok(());
+    }
+
+    let is_ordinancedb = open_readonly(path)
+        .and_then(|conn| schema::version(&conn))
+        .is_ok_and(|version| version.is_some());
+    if !is_ordinancedb {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "Refusing to overwrite {}, it is not an ordinance database",
+                path.display()
+            ),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Open an existing database in read-only mode
+///
+/// Any attempt to modify the database through this connection fails.
+/// Multiple processes can read the same database concurrently, thus
+/// prefer it whenever only querying.
+pub fn open_readonly<P: AsRef<std::path::Path>>(path: P) -> Result<Connection> {
+    trace!("Opening database read-only: {:?}", path.as_ref());
+    let config = duckdb::Config::default().access_mode(duckdb::AccessMode::ReadOnly)?;
+    let conn = Connection::open_with_flags(path, config)?;
+    Ok(conn)
+}
+
+/// Path of the database file of a connection
+///
+/// For what needs the file itself, such as attaching it to another
+/// database. `None` for an in-memory database.
+pub(crate) fn database_path(conn: &Connection) -> Result<Option<std::path::PathBuf>> {
+    let path: Option<String> = conn.query_row(
+        "SELECT path FROM duckdb_databases() WHERE database_name = current_database()",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(path.map(std::path::PathBuf::from))
+}
+
+/// Open a database, giving up after a timeout
+///
+/// On a slow or wedged networked filesystem, opening a database can hang
+/// indefinitely. The open runs in its own thread, which is abandoned if
+/// it doesn't complete within `timeout`.
+pub fn open_with_timeout<P: AsRef<std::path::Path>>(
+    path: P,
+    timeout: std::time::Duration,
+) -> Result<Connection> {
+    let path = path.as_ref().to_path_buf();
+    trace!(
+        "Opening database {:?} with a timeout of {:?}",
+        path, timeout
+    );
+
+    let target = path.clone();
+    run_with_timeout(timeout, move || Ok(Connection::open(target)?))
+        .unwrap_or(Err(error::Error::OpenTimeout { path, timeout }))
+}
+
+/// Open a database read-only, giving up after a timeout
+///
+/// Same as [`open_readonly`], for the queries, with the timeout of
+/// [`open_with_timeout`].
+pub fn open_readonly_with_timeout<P: AsRef<std::path::Path>>(
+    path: P,
+    timeout: std::time::Duration,
+) -> Result<Connection> {
+    let path = path.as_ref().to_path_buf();
+    trace!(
+        "Opening database {:?} read-only with a timeout of {:?}",
+        path, timeout
+    );
+
+    let target = path.clone();
+    run_with_timeout(timeout, move || open_readonly(target))
+        .unwrap_or(Err(error::Error::OpenTimeout { path, timeout }))
+}
+
+/// Run `f` in its own thread, waiting for its result up to `timeout`
+///
+/// Returns `None` if it timed out, leaving the thread behind.
+fn run_with_timeout<T, F>(timeout: std::time::Duration, f: F) -> Option<Result<T>>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        // The receiver is gone if it already timed out
+        let _ = tx.send(f());
+    });
+    rx.recv_timeout(timeout).ok()
+}
+
+#[doc(hidden)]
+/// Open an in-memory database with the ordinance data model
+///
+/// Intended for the benchmarks, to measure parsing and inserting without
+/// any disk I/O. The jurisdiction table is not created, since it requires
+/// the spatial extension.
+pub fn open_in_memory() -> Result<Connection> {
+    let mut conn = Connection::open_in_memory()?;
+    let tx = conn.transaction()?;
+    init_bookkeeper(&tx)?;
+    scraper::ScrapedOrdinance::init_db(&tx)?;
+    tx.commit()?;
+    Ok(conn)
+}
+
+/// Create the tables that keep track of the database itself
+///
+/// The data model version is stored, as well as the bookkeeper, which
+/// registers every load (commit) into the database.
+fn init_bookkeeper(conn: &duckdb::Transaction) -> Result<()> {
+    trace!("Creating table ordinancedb_version");
+    conn.execute_batch(
+        "CREATE TABLE ordinancedb_version (
+        version TEXT NOT NULL,
+        created_at TIMESTAMP NOT NULL DEFAULT NOW()
+        );",
+    )?;
+    conn.execute(
+        "INSERT INTO ordinancedb_version (version) VALUES (?)",
+        [ORDINANCEDB_VERSION],
+    )?;
 
-    /*
-     * Change the source structure to have a database of all sources, and
-     * the run links to the source used. Also link that to the jurisdiction
-     * such that it could later request all sources related to a certain
-     * jurisdiction.
-     *
-     * Multiple sources for the same jurisdiction (consider multiple
-     * technologies) should be possible.
-     *
-     * In case of multiple sources for one jurisdiction, we should be able
-     * to support what was the latest document available, or list all of
-     * them.
-     *
-     * Check the new jurisdiction file.
-     *
-     * If user don't have a database. Download it in the right path.
-     *
-     *
-     */
     trace!("Creating table bookkeeper");
-    db.execute_batch(
-        "BEGIN;
-    CREATE SEQUENCE bookkeeper_sequence START 1;
+    conn.execute_batch(
+        "CREATE SEQUENCE bookkeeper_sequence START 1;
     CREATE TABLE bookkeeper (
         id INTEGER PRIMARY KEY DEFAULT NEXTVAL('bookkeeper_sequence'),
-        hash TEXT NOT NULL,
+        hash TEXT NOT NULL CHECK (length(trim(hash)) > 0),
         created_at TIMESTAMP NOT NULL DEFAULT NOW(),
         username TEXT,
         comment TEXT,
         model TEXT
-        );
-
-    INSTALL spatial;
-    LOAD spatial;
-
-    CREATE SEQUENCE jurisdiction_sequence START 1;
-    CREATE TYPE jurisdiction_rank AS ENUM ('state', 'county', 'parish', 'city', 'town', 'district', 'other');
-    CREATE TABLE jurisdiction (
-      id INTEGER PRIMARY KEY DEFAULT NEXTVAL('jurisdiction_sequence'),
-      bookkeeper_lnk INTEGER REFERENCES bookkeeper(id) NOT NULL,
-      name TEXT NOT NULL,
-      FIPS UBIGINT NOT NULL,
-      geometry GEOMETRY NOT NULL,
-      rank jurisdiction_rank NOT NULL,
-      parent_id INTEGER REFERENCES jurisdiction(id),
-      created_at TIMESTAMP NOT NULL DEFAULT NOW(),
-      src TEXT,
-      comments TEXT
-      );
-
-    COMMIT;",
+        );",
     )?;
 
-    let conn = db.transaction()?;
-    scraper::ScrapedOrdinance::init_db(&conn)?;
-    conn.commit()?;
+    Ok(())
+}
 
-    println!("{}", db.is_autocommit());
+#[derive(Clone, Debug, Default)]
+/// Options to load a scraper output
+pub struct LoadOptions {
+    /// Check that sources that look like URLs are valid ones
+    ///
+    /// Malformed URLs are recorded as data quality issues and do not
+    /// abort the load.
+    pub validate_sources: bool,
+    /// Load an incomplete output, skipping missing optional files
+    ///
+    /// Only `usage.json` and `meta.json` can be skipped, and those are
+    /// recorded in the bookkeeper comment.
+    pub allow_missing: bool,
+    /// Custom pattern to parse the runtime logs
+    ///
+    /// A regular expression with the named groups `ts`, `level`,
+    /// `subject`, and `message`, for COMPASS versions that format their
+    /// logs differently. Defaults to `[ts] LEVEL - subject: message`.
+    pub log_pattern: Option<String>,
+    /// Abort the load on any warning
+    ///
+    /// Intended for CI pipelines validating the scraper outputs. Skipped
+    /// files and data quality issues, such as a source file not matching
+    /// its checksum or an implausible year, fail the load instead of
+    /// being recorded. Nothing is written in that case.
+    pub strict: bool,
+    /// Size, in bytes, of the buffer to read the source documents
+    ///
+    /// Larger buffers might speed up checksumming large documents on
+    /// high-latency or high-throughput storage. Defaults to 64 KB.
+    pub io_buffer_size: Option<usize>,
+    /// Number of source documents checksummed at once
+    ///
+    /// Checksumming dominates the load of runs with hundreds of
+    /// documents. Defaults to 8.
+    pub parallel_hash: Option<usize>,
+    /// Accept comments and trailing commas in the JSON files
+    ///
+    /// For hand-edited `meta.json` and `usage.json`, as allowed by JSONC,
+    /// which a strict JSON parser rejects.
+    pub tolerant: bool,
+    /// Store the original quantitative ordinance CSV
+    ///
+    /// Kept byte by byte in `raw_inputs`, so that it can be parsed again
+    /// later, such as with an improved parser.
+    pub store_raw: bool,
+    /// Skip consecutive duplicated log records
+    ///
+    /// Records with the same timestamp, level, subject, and message as
+    /// the previous one are not stored. Off by default, keeping the logs
+    /// as they are.
+    pub dedup_logs: bool,
+    /// Load only the first jurisdictions, for debugging
+    ///
+    /// Only the first N jurisdictions of `jurisdictions.json` are kept,
+    /// together with their ordinances, for a quick iteration on a large
+    /// output. The commit is marked as partial in its comment.
+    pub limit_jurisdictions: Option<usize>,
+    /// Name of the quantitative ordinance file in the scraper output
+    ///
+    /// By default, the first of the names used by the scraper versions
+    /// found, such as `quantitative_ordinances.csv` or `ordinances.csv`.
+    pub quantitative_file: Option<String>,
+    /// Give up a load that takes longer than this
+    ///
+    /// Bounds a runaway load, such as of a pathological CSV, in an
+    /// automated pipeline. The load fails with `Error::LoadTimeout` and
+    /// nothing is written. Parsing and writing the ordinance CSV files
+    /// check it on every record, see `Deadline`. No limit by default.
+    pub timeout: Option<std::time::Duration>,
+}
 
-    trace!("Database initialized");
-    Ok(())
+#[derive(Debug)]
+/// Outcome of loading a scraper output
+pub struct LoadSummary {
+    /// Commit id (bookkeeper id) of the new load
+    pub commit_id: usize,
+    /// Number of ordinances loaded, both quantitative and qualitative
+    ///
+    /// Zero is a valid outcome, of a run that found no ordinance.
+    pub ordinances: usize,
+    /// Total LLM usage of the run, if its usage was available
+    pub usage: Option<UsageTotals>,
+    /// Everything worth a warning found in the output
+    ///
+    /// Such as a skipped optional file, a CSV row that couldn't be
+    /// parsed, located by file and line, or a data quality issue. A load
+    /// with `LoadOptions::strict` fails on any of those instead.
+    pub warnings: Vec<String>,
 }
 
 /// Scan and load features from a CSV file
 ///
 /// Proof of concept. Parse a CSV file and load the features into the
 /// database.
+///
+/// The `ordinance_path` can be a scraper output directory, or a gzipped
+/// tarball (`.tar.gz` or `.tgz`) of it, which is extracted into a
+/// temporary directory for the load.
+///
+/// Returns the commit id (bookkeeper id) of the new load, so it can be
+/// referred to later, such as to export only that load, together with
+/// the total LLM usage of that run.
+///
+/// The foreign keys are checked on every insert. DuckDB can't defer
+/// those checks to the commit, so there is no option to load an output
+/// whose intermediate states violate them; the components are written
+/// in an order that satisfies every reference instead.
 pub fn load_ordinance<P: AsRef<std::path::Path> + std::fmt::Debug>(
-    mut database: duckdb::Connection,
-    username: &String,
+    database: duckdb::Connection,
+    username: &str,
     ordinance_path: P,
-) -> Result<()> {
-    // insert into bookkeeper (hash, username) and get the pk to be used in all the following
-    // inserts.
-    trace!("Starting a transaction");
-    let conn = database.transaction().unwrap();
-
-    let commit_id: usize = conn
-        .query_row(
-            "INSERT INTO bookkeeper (hash, username) VALUES (?, ?) RETURNING id",
-            ["dummy hash".to_string(), username.to_string()],
-            |row| row.get(0),
-        )
-        .expect("Failed to insert into bookkeeper");
-
-    tracing::debug!("Commit id: {:?}", commit_id);
-
+    options: &LoadOptions,
+) -> Result<LoadSummary> {
     let runtime = tokio::runtime::Builder::new_current_thread()
         .enable_all()
-        .build()
-        .unwrap();
+        .build()?;
 
-    let ordinance = runtime
-        .block_on(scraper::ScrapedOrdinance::open(ordinance_path))
-        .unwrap();
-    conn.commit().unwrap();
-    tracing::debug!("Transaction committed");
-
-    trace!("Ordinance: {:?}", ordinance);
-    runtime
-        .block_on(ordinance.push(&mut database, commit_id))
-        .unwrap();
+    let deadline = Deadline::after(options.timeout);
+    let summary = runtime.block_on(with_timeout(options.timeout, async {
+        // Keep the extracted archive, if any, until the load is done
+        let (_extracted, ordinance) = open_output(
+            ordinance_path.as_ref().to_path_buf(),
+            options.clone(),
+            deadline,
+        )
+        .await?;
+        record_output(database, username, ordinance, options).await
+    }))?;
 
     /*
     let mut rdr = csv::Reader::from_path(raw_filename).unwrap();
@@ -158,26 +519,454 @@ pub fn load_ordinance<P: AsRef<std::path::Path> + std::fmt::Debug>(
     */
     //let df = polars::io::csv::read::CsvReadOptions::default().with_has_header(true).try_into_reader_with_file_path(Some("sample.csv".into())).unwrap().finish();
 
-    Ok(())
+    Ok(summary)
+}
+
+/// Run a load, failing with `Error::LoadTimeout` if it takes too long
+///
+/// A `future` not completed in time is dropped, thus an open
+/// transaction is rolled back.
+async fn with_timeout<T, F>(timeout: Option<std::time::Duration>, future: F) -> Result<T>
+where
+    F: std::future::Future<Output = Result<T>>,
+{
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future).await.map_err(|_| {
+            tracing::warn!("Load timed out after {:?}", timeout);
+            error::Error::LoadTimeout(timeout)
+        })?,
+        None => future.await,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Point in time by which a load must be done, see `LoadOptions::timeout`
+///
+/// A tokio timeout only fires at an `.await`, while parsing a CSV or
+/// writing its records is blocking. Those run in `spawn_blocking()`,
+/// thus the timeout still fires on time, and check this deadline on
+/// every record to stop the work left behind. Without a timeout, the
+/// default, it never expires.
+pub(crate) struct Deadline(Option<(std::time::Instant, std::time::Duration)>);
+
+impl Deadline {
+    /// A deadline `timeout` from now, if any
+    pub(crate) fn after(timeout: Option<std::time::Duration>) -> Self {
+        Self(timeout.and_then(|timeout| {
+            std::time::Instant::now()
+                .checked_add(timeout)
+                .map(|at| (at, timeout))
+        }))
+    }
+
+    /// Fail with `Error::LoadTimeout` if the deadline has passed
+    pub(crate) fn check(&self) -> Result<()> {
+        match self.0 {
+            Some((at, timeout)) if std::time::Instant::now() >= at => {
+                Err(error::Error::LoadTimeout(timeout))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Placeholder hashes used before the content was hashed
+///
+/// A stream is only hashed once consumed, thus its commit is created as
+/// "pending" and updated with the actual hash afterwards.
+const PLACEHOLDER_HASHES: [&str; 2] = ["dummy hash", "pending"];
+
+/// Ensure a hash is usable to identify the content of a commit
+///
+/// An empty, or known placeholder, hash is an `Error::InvalidHash`.
+fn check_hash(hash: &str) -> Result<&str> {
+    if hash.trim().is_empty() || PLACEHOLDER_HASHES.contains(&hash) {
+        return Err(error::Error::InvalidHash(hash.to_string()));
+    }
+    Ok(hash)
+}
+
+/// A reader that hashes everything read through it
+struct HashingReader<R> {
+    inner: R,
+    hasher: sha2::Sha256,
+}
+
+impl<R: std::io::Read> std::io::Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        use sha2::Digest;
+
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Load a quantitative ordinance CSV from a reader
+///
+/// Intended for pipelines, such as `scraper | compass load -`, where only
+/// the quantitative ordinance is available as a stream, such as the
+/// standard input, instead of a complete scraper output directory. The
+/// records are loaded as a new commit, of the given `technology`, since
+/// there is no `meta.json` to tell it, as needed to export them.
+///
+/// Of the `options`, only `validate_sources`, `strict`, and `timeout`
+/// apply to a stream, with the same meaning as for `load_ordinance()`.
+pub fn load_quantitative_csv<R: std::io::Read>(
+    mut database: duckdb::Connection,
+    username: &str,
+    technology: &str,
+    reader: R,
+    options: &LoadOptions,
+) -> Result<LoadSummary> {
+    use sha2::Digest;
+
+    trace!("Loading a quantitative ordinance CSV stream");
+    let deadline = Deadline::after(options.timeout);
+    let conn = database.transaction()?;
+
+    // The stream is hashed while read, thus the commit is created before
+    // its hash is known
+    let commit_id: usize = conn.query_row(
+        "INSERT INTO bookkeeper (hash, username, comment) VALUES ('pending', ?, ?) RETURNING id",
+        [username, "Quantitative ordinance from a stream"],
+        |row| row.get(0),
+    )?;
+    tracing::debug!("Commit id: {:?}", commit_id);
+    conn.execute(
+        "INSERT INTO scraper_metadata (bookkeeper_lnk, username, technology) VALUES (?, ?, ?)",
+        duckdb::params![commit_id, username, technology],
+    )?;
+
+    let mut reader = HashingReader {
+        inner: reader,
+        hasher: sha2::Sha256::new(),
+    };
+    let (ordinances, warnings) =
+        scraper::write_quantitative_csv(&conn, &mut reader, commit_id, options, deadline)?;
+    // Anything left unparsed is still part of the content
+    std::io::copy(&mut reader, &mut std::io::sink())?;
+    let hash = format!("sha256:{:x}", reader.hasher.finalize());
+    conn.execute(
+        "UPDATE bookkeeper SET hash = ? WHERE id = ?",
+        duckdb::params![check_hash(&hash)?, commit_id],
+    )?;
+    conn.commit()?;
+
+    Ok(LoadSummary {
+        commit_id,
+        ordinances,
+        usage: None,
+        warnings,
+    })
+}
+
+/// Load several scraper outputs concurrently
+///
+/// Each output is loaded, i.e. read, checksummed, validated, and
+/// written, into a temporary database of its own, for up to
+/// `concurrency` outputs at a time on a multi-threaded runtime. Only
+/// merging those into `database` is serialized, one commit per output
+/// as soon as it is ready, so loads never conflict with each other.
+///
+/// If any output fails, the remaining ones are aborted and the error is
+/// returned, while the outputs already merged are kept as their own
+/// commits. The same applies to `LoadOptions::timeout`, which bounds
+/// all the outputs together.
+///
+/// Returns the commit id of each output, in the same order as `paths`.
+pub fn load_parallel<P: AsRef<std::path::Path>>(
+    database: duckdb::Connection,
+    username: &str,
+    paths: &[P],
+    options: &LoadOptions,
+    concurrency: usize,
+) -> Result<Vec<usize>> {
+    let concurrency = concurrency.max(1);
+    trace!("Loading {} outputs, {} at a time", paths.len(), concurrency);
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(concurrency)
+        .enable_all()
+        .build()?;
+
+    let deadline = Deadline::after(options.timeout);
+    runtime.block_on(with_timeout(options.timeout, async {
+        let mut pending = paths.iter().map(|p| p.as_ref().to_path_buf()).enumerate();
+        let mut commits = vec![0; paths.len()];
+        let mut jobs = tokio::task::JoinSet::new();
+        loop {
+            while jobs.len() < concurrency {
+                let Some((i, path)) = pending.next() else {
+                    break;
+                };
+                let username = username.to_string();
+                let options = options.clone();
+                jobs.spawn(
+                    async move { (i, load_isolated(path, &username, options, deadline).await) },
+                );
+            }
+            let Some(job) = jobs.join_next().await else {
+                break;
+            };
+            let (i, loaded) = job.map_err(|e| error::Error::Undefined(e.to_string()))?;
+            let isolated = loaded?;
+
+            // Merging is blocking, thus done on its own connection, in
+            // its own thread, so `LoadOptions::timeout` still fires
+            let mut target = database.try_clone()?;
+            let merged = tokio::task::spawn_blocking(move || {
+                merge::merge_db(&mut target, &isolated.path().join("load.duckdb"))
+            })
+            .await
+            .map_err(|e| error::Error::Undefined(e.to_string()))??;
+            tracing::debug!("Loaded {:?} as commit {:?}", paths[i].as_ref(), merged);
+            commits[i] = merged[0];
+        }
+        Ok(commits)
+    }))
+}
+
+/// Load a scraper output into a new temporary database
+///
+/// Returns the temporary directory with the database, `load.duckdb`,
+/// holding that single load as its only commit.
+async fn load_isolated(
+    path: std::path::PathBuf,
+    username: &str,
+    options: LoadOptions,
+    deadline: Deadline,
+) -> Result<tempfile::TempDir> {
+    let isolated = tempfile::tempdir()?;
+    let mut database = Connection::open(isolated.path().join("load.duckdb"))?;
+    {
+        let tx = database.transaction()?;
+        init_bookkeeper(&tx)?;
+        scraper::ScrapedOrdinance::init_db(&tx)?;
+        tx.commit()?;
+    }
+
+    // Keep the extracted archive, if any, until the load is done
+    let (_extracted, ordinance) = open_output(path, options.clone(), deadline).await?;
+    record_output(database, username, ordinance, &options).await?;
+    Ok(isolated)
+}
+
+/// Open a scraper output, extracting it first if it is an archive
+///
+/// The temporary directory with the extracted archive, if any, must be
+/// kept until the output is recorded.
+async fn open_output(
+    path: std::path::PathBuf,
+    options: LoadOptions,
+    deadline: Deadline,
+) -> Result<(Option<tempfile::TempDir>, scraper::ScrapedOrdinance)> {
+    let (extracted, path) = if archive::is_archive(&path) {
+        let tmp = tempfile::tempdir()?;
+        let root = archive::extract(&path, tmp.path())?;
+        (Some(tmp), root)
+    } else {
+        (None, path)
+    };
+
+    let ordinance = scraper::ScrapedOrdinance::open_until(path, &options, deadline).await?;
+    Ok((extracted, ordinance))
+}
+
+/// Record an opened scraper output as a new commit
+async fn record_output(
+    mut database: duckdb::Connection,
+    username: &str,
+    mut ordinance: scraper::ScrapedOrdinance,
+    options: &LoadOptions,
+) -> Result<LoadSummary> {
+    if options.validate_sources {
+        ordinance.validate_sources().await?;
+    }
+    let warnings = ordinance.warnings();
+    tracing::debug!("Found {} warnings", warnings.len());
+    if options.strict && !warnings.is_empty() {
+        return Err(error::Error::Warnings(warnings));
+    }
+
+    let mut comment = Vec::new();
+    if !ordinance.skipped().is_empty() {
+        comment.push(format!(
+            "Skipped missing: {}",
+            ordinance.skipped().join(", ")
+        ));
+    }
+    if let Some(n) = options.limit_jurisdictions {
+        comment.push(format!("Partial: limited to {n} jurisdiction(s)"));
+    }
+    let comment = (!comment.is_empty()).then(|| comment.join("; "));
+    let hash = ordinance.content_hash().await?;
+
+    // Writing is blocking, thus done in its own thread, so
+    // `LoadOptions::timeout` still fires meanwhile
+    let username = username.to_string();
+    let runtime = tokio::runtime::Handle::current();
+    let (commit_id, usage, ordinances) = tokio::task::spawn_blocking(move || {
+        runtime.block_on(async {
+            // A single transaction for the whole load, so the commit id
+            // is only visible, and used, within it. A failure midway,
+            // including the deadline, leaves neither a bookkeeper entry
+            // nor any partial record behind.
+            trace!("Starting a transaction");
+            let conn = database.transaction()?;
+            let commit_id: usize = conn.query_row(
+                "INSERT INTO bookkeeper (hash, username, comment) VALUES (?, ?, ?) RETURNING id",
+                duckdb::params![check_hash(&hash)?, username, comment],
+                |row| row.get(0),
+            )?;
+            tracing::debug!("Commit id: {:?}", commit_id);
+
+            trace!("Ordinance: {:?}", ordinance);
+            let usage = ordinance.push(&conn, commit_id).await?;
+
+            ordinance.deadline().check()?;
+            conn.commit()?;
+            tracing::debug!("Transaction committed");
+            Ok::<_, error::Error>((commit_id, usage, ordinance.ordinance_count().await?))
+        })
+    })
+    .await
+    .map_err(|e| error::Error::Undefined(e.to_string()))??;
+
+    Ok(LoadSummary {
+        commit_id,
+        ordinances,
+        usage,
+        warnings,
+    })
+}
+
+/// Add the source documents of a scraper output to an existing commit
+///
+/// Additional documents for a jurisdiction might arrive after its run
+/// was loaded. Instead of loading them as a new commit, those are
+/// attached to the existing `commit`, given by its hash or its id
+/// (bookkeeper id). If several commits share the hash, the most recent
+/// one is used. Documents already in the commit, by checksum, are
+/// skipped. Documents without their file in `ordinance_files/`, or with
+/// a different checksum, are also skipped, and recorded as data quality
+/// issues of the commit.
+///
+/// The `path` is a scraper output directory, or a gzipped tarball of
+/// it, of which only `jurisdictions.json` and `ordinance_files/` are
+/// needed. Of the `options`, `io_buffer_size` and `parallel_hash` tune
+/// the checksums, `strict` fails on any document skipped, and `timeout`
+/// bounds the whole operation. Nothing is added on a failure.
+///
+/// # Returns
+///
+/// The number of documents added.
+pub fn add_sources<P: AsRef<std::path::Path>>(
+    database: &mut duckdb::Connection,
+    commit: &str,
+    path: P,
+    options: &LoadOptions,
+) -> Result<usize> {
+    trace!(
+        "Adding sources from {:?} to commit {:?}",
+        path.as_ref(),
+        commit
+    );
+
+    let (_extracted, root) = if archive::is_archive(path.as_ref()) {
+        let tmp = tempfile::tempdir()?;
+        let root = archive::extract(path.as_ref(), tmp.path())?;
+        (Some(tmp), root)
+    } else {
+        (None, path.as_ref().to_path_buf())
+    };
+
+    let conn = database.transaction()?;
+    let commit_id = resolve_commit(&conn, commit)?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let added = runtime.block_on(with_timeout(
+        options.timeout,
+        scraper::append_sources(&conn, root, commit_id, options),
+    ))?;
+    conn.commit()?;
+
+    Ok(added)
+}
+
+/// Find a commit (bookkeeper id) by its hash or its id
+fn resolve_commit(conn: &duckdb::Transaction, commit: &str) -> Result<usize> {
+    let by_hash: Option<usize> = conn
+        .query_row(
+            "SELECT id FROM bookkeeper WHERE hash = ? ORDER BY id DESC LIMIT 1",
+            [commit],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(commit_id) = by_hash {
+        return Ok(commit_id);
+    }
+
+    let Ok(commit_id) = commit.trim().parse::<usize>() else {
+        return Err(error::Error::UnknownHash(commit.to_string()));
+    };
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM bookkeeper WHERE id = ?)",
+        [commit_id],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Err(error::Error::UnknownCommit(commit_id));
+    }
+    Ok(commit_id)
+}
+
+/// Load the new lines of a runtime log that is still growing
+///
+/// Intended to follow a scraper run in progress. Each call ingests only
+/// the log records appended since the previous call for the same commit
+/// and file, which is tracked in the database.
+///
+/// The lines are parsed with `log_pattern`, if given, as
+/// `LoadOptions::log_pattern`, otherwise with the default pattern.
+///
+/// # Returns
+///
+/// The number of log records inserted.
+pub fn load_logs_tail<P: AsRef<std::path::Path>>(
+    database: &mut duckdb::Connection,
+    commit_id: usize,
+    path: P,
+    log_pattern: Option<&str>,
+) -> Result<usize> {
+    trace!("Tailing log {:?} for commit {}", path.as_ref(), commit_id);
+
+    let pattern = log_pattern.map(scraper::compile_pattern).transpose()?;
+    let conn = database.transaction()?;
+    let inserted = scraper::RuntimeLogs::tail(&conn, commit_id, path.as_ref(), pattern.as_ref())?;
+    conn.commit()?;
+
+    Ok(inserted)
 }
 
-#[allow(dead_code, non_snake_case)]
-#[derive(Debug, Serialize)]
+#[allow(non_snake_case)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 /// Ordinance record that combines quantitative and qualitative
 ///
 /// It is currently used for handle reVX ordinance for reVX standard only
 /// but we might expand this in the future to handle more complete outputs.
-struct OrdinanceRecord {
+pub struct OrdinanceRecord {
     /// FIPS code of the jurisdiction
-    FIPS: u64,
+    pub FIPS: u64,
     /// Feature type, e.g., "setback", "height", etc.
-    feature: String,
+    pub feature: String,
     /// Feature subtype, currently not used but required by reVX standard.
-    feature_subtype: Option<String>,
+    pub feature_subtype: Option<String>,
     /// Quantitative feature value, e.g., 3.14
-    quantitative: Option<f64>,
+    pub quantitative: Option<f64>,
     /// Qualitative feature value, e.g., "color of the tips of the blades"
-    qualitative: Option<String>,
+    pub qualitative: Option<String>,
 }
 
 #[derive(Debug)]
@@ -198,6 +987,12 @@ impl std::fmt::Display for Technology {
     }
 }
 
+impl duckdb::ToSql for Technology {
+    fn to_sql(&self) -> duckdb::Result<duckdb::types::ToSqlOutput<'_>> {
+        Ok(duckdb::types::ToSqlOutput::from(self.to_string()))
+    }
+}
+
 impl std::convert::TryFrom<&str> for Technology {
     type Error = error::Error;
 
@@ -210,58 +1005,2256 @@ impl std::convert::TryFrom<&str> for Technology {
     }
 }
 
+#[derive(Debug)]
+/// Options to customize the export
+pub struct ExportOptions {
+    /// Whether to write the header row
+    ///
+    /// Disable it when appending to an existing non-empty output, such
+    /// that the result is still a single valid CSV.
+    pub has_headers: bool,
+    /// Columns to export, in order
+    ///
+    /// Each one must be a column of the ordinance view (see
+    /// `ORDINANCE_COLUMNS`). If not given, exports the reVX standard
+    /// columns.
+    pub columns: Option<Vec<String>>,
+    /// Which ordinances to export
+    pub filter: OrdinanceFilter,
+    /// Decimal places of the quantitative values
+    ///
+    /// Values are stored in single precision, thus exported with noise
+    /// such as `294.69257712364197`. Full precision if not given.
+    pub precision: Option<u32>,
+    /// Export FIPS codes as zero-padded text
+    ///
+    /// GIS tools expect the canonical codes, such as `08059` for a
+    /// county or `08` for a state, instead of `8059` or `8`. Codes below
+    /// 100 are taken as states, thus padded to 2 digits, and all others
+    /// to 5 digits. Not applied to the bincode output.
+    pub fips_padded: bool,
+    /// Aggregate the ordinances by these columns
+    ///
+    /// Instead of each ordinance, export one row per combination of
+    /// values of these columns, with the number of ordinances (`count`)
+    /// and the mean of their quantitative values (`mean`). Each one must
+    /// be a column of the ordinance view. Replaces `columns`, and not
+    /// available for the bincode output.
+    pub group_by: Option<Vec<String>>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            has_headers: true,
+            columns: None,
+            filter: OrdinanceFilter::default(),
+            precision: None,
+            fips_padded: false,
+            group_by: None,
+        }
+    }
+}
+
+/// Columns of the reVX standard, as in `OrdinanceRecord`
+const REVX_COLUMNS: [&str; 5] = [
+    "FIPS",
+    "feature",
+    "feature_subtype",
+    "quantitative",
+    "qualitative",
+];
+
+/// Columns of the ordinance view that can be exported
+pub const ORDINANCE_COLUMNS: [&str; 15] = [
+    "county",
+    "state",
+    "subdivision",
+    "jurisdiction_type",
+    "FIPS",
+    "feature",
+    "feature_subtype",
+    "quantitative",
+    "units",
+    "mult_value",
+    "mult_type",
+    "qualitative",
+    "ord_year",
+    "section",
+    "source",
+];
+
+/// Export formats planned, but without a writer yet
+///
+/// Those are spatial formats, GeoPackage ("gpkg") and GeoParquet
+/// ("gpq"), thus options on the geometries, such as simplifying them, or
+/// on the GeoPackage connection, such as its journal mode, don't apply
+/// either until then. An export in any of those fails with
+/// `Error::UnsupportedFormat`.
+pub const UNIMPLEMENTED_FORMATS: [&str; 2] = ["gpkg", "gpq"];
+
+/// The quantitative value, rounded to `precision` decimal places if given
+fn quantitative_expr(precision: Option<u32>) -> String {
+    match precision {
+        Some(n) => format!("ROUND(ordinance.quantitative::DOUBLE, {n})"),
+        None => "ordinance.quantitative".to_string(),
+    }
+}
+
+/// Column of the ordinance view, as named in the schema
+///
+/// Identifiers can't be given as query parameters, thus any column name
+/// given by the user must match one of `ORDINANCE_COLUMNS`, ignoring the
+/// case, before being used in a query. Anything else is refused.
+fn ordinance_column(column: &str) -> Result<&'static str> {
+    ORDINANCE_COLUMNS
+        .iter()
+        .find(|c| c.eq_ignore_ascii_case(column))
+        .copied()
+        .ok_or_else(|| error::Error::UnknownColumn {
+            column: column.to_string(),
+            valid: ORDINANCE_COLUMNS.join(", "),
+        })
+}
+
+/// The FIPS code as text, zero-padded to 2 (state) or 5 (county) digits
+const PADDED_FIPS: &str = "CASE WHEN ordinance.FIPS < 100 THEN printf('%02d', ordinance.FIPS) ELSE printf('%05d', ordinance.FIPS) END";
+
+/// Build the SELECT list for the requested ordinance columns
+///
+/// Returns the canonical names together with the SELECT list.
+fn select_ordinance_columns(
+    columns: &[String],
+    options: &ExportOptions,
+) -> Result<(Vec<&'static str>, String)> {
+    let canonical = columns
+        .iter()
+        .map(|column| ordinance_column(column))
+        .collect::<Result<Vec<_>>>()?;
+
+    let select = canonical
+        .iter()
+        .map(|&c| match c {
+            "quantitative" => format!("CAST({} AS VARCHAR)", quantitative_expr(options.precision)),
+            "FIPS" if options.fips_padded => PADDED_FIPS.to_string(),
+            _ => format!("CAST(ordinance.\"{c}\" AS VARCHAR)"),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    Ok((canonical, select))
+}
+
+/// Build a query on the ordinances of a technology
+///
+/// All the queries on the ordinances are assembled here. Values, such
+/// as the technology or the filter criteria, are always passed as
+/// parameters, while the `order_by` columns are checked against the
+/// known columns. The `select` list must be built from trusted pieces,
+/// such as by `select_ordinance_columns`.
+fn ordinance_query<'a>(
+    select: &str,
+    technology: &'a Technology,
+    filter: &'a OrdinanceFilter,
+    order_by: &[&str],
+) -> Result<(String, Vec<&'a dyn duckdb::ToSql>)> {
+    let order_by = order_by
+        .iter()
+        .map(|column| ordinance_column(column).map(|c| format!("ordinance.\"{c}\"")))
+        .collect::<Result<Vec<_>>>()?
+        .join(", ");
+
+    let (condition, filter_params) = filter.condition();
+    let mut params: Vec<&dyn duckdb::ToSql> = vec![technology];
+    params.extend(filter_params);
+
+    let mut query = format!(
+        "SELECT {select} FROM ordinance JOIN scraper_metadata ON (ordinance.bookkeeper_lnk=scraper_metadata.bookkeeper_lnk) WHERE scraper_metadata.technology = ? AND {condition}"
+    );
+    if !order_by.is_empty() {
+        query.push_str(&format!(" ORDER BY {order_by}"));
+    }
+    Ok((query, params))
+}
+
 /// Export the database
 ///
 /// Currently, it is a proof of concept. It reads the database and prints
-/// some fields to the standard output in CSV format.
+/// some fields to the standard output in CSV format, or as bincode
+/// (`format` "bincode") if built with the `bincode` feature. The bincode
+/// output always has the reVX standard fields, ignoring the selected
+/// columns. With `format` "table", it renders an aligned table for the
+/// terminal instead, truncating long values.
+///
+/// The writer is flushed before returning, so that any error writing
+/// the output, such as a full disk, is returned instead of lost when
+/// the writer is dropped.
 pub fn export<W: std::io::Write>(
     wtr: &mut W,
-    db_filename: &str,
+    conn: &Connection,
     format: &str,
     technology: &str,
+    options: &ExportOptions,
 ) -> Result<()> {
-    trace!("Exporting database: {:?}", db_filename);
-    // Not used yet, but ready for future use
     trace!("Export format: {:?}", format);
 
+    if UNIMPLEMENTED_FORMATS.contains(&format) {
+        return Err(error::Error::UnsupportedFormat(format!(
+            "{format} (not implemented yet)"
+        )));
+    }
     let technology = Technology::try_from(technology)?;
+    check_commit(conn, &options.filter)?;
 
-    let conn = Connection::open(db_filename)?;
-    trace!("Database opened: {:?}", &conn);
+    if let Some(group_by) = &options.group_by {
+        if format == "bincode" {
+            return Err(error::Error::UnsupportedFormat(
+                "bincode with group by".to_string(),
+            ));
+        }
+        let (columns, rows) = grouped_rows(conn, &technology, group_by, options)?;
+        return write_rows(wtr, format, &columns, &rows, options.has_headers);
+    }
 
-    let mut stmt = conn
-        .prepare( &format!("SELECT FIPS, feature, feature_subtype, quantitative, qualitative FROM ordinance JOIN scraper_metadata ON (ordinance.bookkeeper_lnk=scraper_metadata.bookkeeper_lnk) WHERE scraper_metadata.technology='{technology}' ORDER BY FIPS, feature;")
-            )
-        .expect("Failed to prepare statement");
-    //dbg!("Row count", stmt.row_count());
-    let row_iter = stmt
-        .query_map([], |row| {
-            Ok(OrdinanceRecord {
-                FIPS: row.get(0)?,
-                feature: row.get(1)?,
-                feature_subtype: row.get(2)?,
-                quantitative: row.get(3)?,
-                qualitative: row.get(4)?,
-            })
-        })
-        .expect("Failed to query");
+    if format == "bincode" {
+        let records = ordinance_records(conn, &technology, &options.filter, options.precision)?;
+        return write_bincode(wtr, &records);
+    }
 
-    let mut wtr = csv::Writer::from_writer(wtr);
+    // Padded FIPS codes are text, thus not an `OrdinanceRecord`
+    let columns = match (&options.columns, options.fips_padded || format == "table") {
+        (Some(columns), _) => Some(columns.clone()),
+        (None, true) => Some(REVX_COLUMNS.map(String::from).to_vec()),
+        (None, false) => None,
+    };
+    if let Some(columns) = columns {
+        let (columns, rows) = ordinance_rows(conn, &technology, &columns, options)?;
+        return write_rows(wtr, format, &columns, &rows, options.has_headers);
+    }
 
-    for row in row_iter {
-        wtr.serialize(row?)?;
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(options.has_headers)
+        .from_writer(wtr);
+    let records = ordinance_records(conn, &technology, &options.filter, options.precision)?;
+    for record in records {
+        wtr.serialize(record)?;
     }
     wtr.flush()?;
 
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Export the ordinances into a new SQLite database
+///
+/// For consumers that can't read DuckDB, but can read SQLite. The
+/// ordinances selected by `options`, with its columns or the reVX
+/// standard ones, are written into the table `ordinance` of a new
+/// database at `sqlite_path`, which must not exist yet. The values keep
+/// their types, instead of the text of the CSV. With `jurisdictions`,
+/// the jurisdictions of the commits exported are written into the table
+/// `jurisdiction` as well. Aggregating (`group_by`) is not supported.
+/// The database is only created at `sqlite_path` if the whole export
+/// succeeds.
+///
+/// Requires the `sqlite` extension, installed on the first use.
+///
+/// Returns the number of ordinances exported.
+pub fn export_sqlite<P: AsRef<std::path::Path>>(
+    conn: &Connection,
+    sqlite_path: P,
+    technology: &str,
+    options: &ExportOptions,
+    jurisdictions: bool,
+) -> Result<usize> {
+    let sqlite_path = sqlite_path.as_ref();
+    let original = database_path(conn)?.ok_or_else(|| {
+        error::Error::Undefined("Can't export an in-memory database to SQLite".to_string())
+    })?;
+    trace!("Exporting {:?} to SQLite {:?}", original, sqlite_path);
 
-    #[test]
-    fn it_works() {
-        let _ = init_db("test");
+    let technology = Technology::try_from(technology)?;
+    if options.group_by.is_some() {
+        return Err(error::Error::UnsupportedFormat(
+            "sqlite with group by".to_string(),
+        ));
+    }
+    if sqlite_path.exists() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} already exists", sqlite_path.display()),
+        )
+        .into());
+    }
+
+    // Written next to `sqlite_path` then moved, thus a failure never
+    // leaves a partial database behind
+    let tmp = tempdir_next_to(sqlite_path, ".ordinancedb-export-")?;
+    let tmp_path = tmp.path().join("export.sqlite");
+
+    // Both databases are attached to a transient one, so the data never
+    // goes through this process. Paths can't be given as parameters.
+    let quoted = |path: &str| format!("'{}'", path.replace('\'', "''"));
+    let conn = Connection::open_in_memory()?;
+    conn.execute_batch(&format!(
+        r"
+        INSTALL sqlite;
+        LOAD sqlite;
+        ATTACH {} AS compass (READ_ONLY);
+        USE compass;
+        ATTACH {} AS export (TYPE SQLITE);",
+        quoted(&original.to_string_lossy()),
+        quoted(&tmp_path.to_string_lossy()),
+    ))?;
+    check_commit(&conn, &options.filter)?;
+
+    let columns = options
+        .columns
+        .clone()
+        .unwrap_or_else(|| REVX_COLUMNS.map(String::from).to_vec());
+    let select = columns
+        .iter()
+        .map(|column| {
+            ordinance_column(column).map(|c| match c {
+                "quantitative" => {
+                    format!("{} AS quantitative", quantitative_expr(options.precision))
+                }
+                "FIPS" if options.fips_padded => format!("{PADDED_FIPS} AS FIPS"),
+                // SQLite has no unsigned 64 bits integers
+                "FIPS" => "CAST(ordinance.FIPS AS BIGINT) AS FIPS".to_string(),
+                _ => format!("ordinance.\"{c}\" AS \"{c}\""),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?
+        .join(", ");
+    let (query, params) =
+        ordinance_query(&select, &technology, &options.filter, &["FIPS", "feature"])?;
+    conn.execute(
+        &format!("CREATE TABLE export.ordinance AS {query}"),
+        params.as_slice(),
+    )?;
+
+    if jurisdictions {
+        let (commits, params) = ordinance_query(
+            "ordinance.bookkeeper_lnk",
+            &technology,
+            &options.filter,
+            &[],
+        )?;
+        conn.execute(
+            &format!(
+                r"
+                CREATE TABLE export.jurisdiction AS
+                  SELECT bookkeeper_lnk AS commit_id, full_name, county, state,
+                    subdivision, jurisdiction_type, CAST(fips AS BIGINT) AS FIPS, found
+                  FROM source
+                  WHERE bookkeeper_lnk IN ({commits})
+                  ORDER BY commit_id, FIPS"
+            ),
+            params.as_slice(),
+        )?;
+    }
+
+    let exported: usize = conn.query_row("SELECT COUNT(*) FROM export.ordinance", [], |row| {
+        row.get(0)
+    })?;
+    conn.execute_batch("DETACH export;")?;
+    std::fs::rename(&tmp_path, sqlite_path)?;
+    trace!("Exported {} ordinances", exported);
+    Ok(exported)
+}
+
+/// Ensure the commit filtered on, if any, exists
+fn check_commit(conn: &Connection, filter: &OrdinanceFilter) -> Result<()> {
+    if let Some(commit_id) = filter.commit_id {
+        trace!("Filtering commit: {:?}", commit_id);
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM bookkeeper WHERE id = ?",
+            [commit_id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Err(error::Error::UnknownCommit(commit_id));
+        }
+    }
+    Ok(())
+}
+
+/// Rows of values as text, `None` for NULL
+type TextRows = Vec<Vec<Option<String>>>;
+
+/// Write rows of text as CSV, or as a table (`format` "table")
+fn write_rows<W: std::io::Write>(
+    wtr: &mut W,
+    format: &str,
+    columns: &[&str],
+    rows: &TextRows,
+    has_headers: bool,
+) -> Result<()> {
+    if format == "table" {
+        table::render(wtr, columns, rows)?;
+        wtr.flush()?;
+        return Ok(());
+    }
+
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(has_headers)
+        .from_writer(wtr);
+    if has_headers {
+        wtr.write_record(columns)?;
+    }
+    for record in rows {
+        wtr.write_record(record.iter().map(|v| v.as_deref().unwrap_or("")))?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Aggregate the ordinances of a technology by the given columns
+///
+/// One row per combination of values of the `group_by` columns, which
+/// are followed by the number of ordinances (`count`) and the mean of
+/// their quantitative values (`mean`, NULL without any). Values in
+/// different units, or of a different type, such as a distance and a
+/// multiplier, are not comparable, thus the rows are also grouped by
+/// `units` and `value_type`. Only the records matching the filter of
+/// `options`, with the mean rounded as requested.
+fn grouped_rows(
+    conn: &Connection,
+    technology: &Technology,
+    group_by: &[String],
+    options: &ExportOptions,
+) -> Result<(Vec<&'static str>, TextRows)> {
+    let (mut columns, mut select) = select_ordinance_columns(group_by, options)?;
+    if columns.is_empty() {
+        return Err(error::Error::UnknownColumn {
+            column: String::new(),
+            valid: ORDINANCE_COLUMNS.join(", "),
+        });
+    }
+    for column in ["units", "value_type"] {
+        if !columns.contains(&column) {
+            select.push_str(&format!(", CAST(ordinance.\"{column}\" AS VARCHAR)"));
+            columns.push(column);
+        }
+    }
+    trace!("Grouping by: {:?}", columns);
+
+    let mean = match options.precision {
+        Some(n) => format!("ROUND(AVG(ordinance.quantitative::DOUBLE), {n})"),
+        None => "AVG(ordinance.quantitative::DOUBLE)".to_string(),
+    };
+    let select = format!("{select}, CAST(COUNT(*) AS VARCHAR), CAST({mean} AS VARCHAR)");
+    let (mut query, params) = ordinance_query(&select, technology, &options.filter, &[])?;
+    query.push_str(" GROUP BY ALL ORDER BY ALL");
+    columns.extend(["count", "mean"]);
+
+    let mut stmt = conn.prepare(&query)?;
+    let mut rows = stmt.query(params.as_slice())?;
+    let mut records = Vec::new();
+    while let Some(row) = rows.next()? {
+        let record = (0..columns.len())
+            .map(|i| row.get::<_, Option<String>>(i))
+            .collect::<duckdb::Result<Vec<_>>>()?;
+        records.push(record);
+    }
+    Ok((columns, records))
+}
+
+/// Query the given columns of the ordinances of a technology, as text
+///
+/// Only the records matching the filter of `options`, formatted as
+/// requested, such as the quantitative values rounded. Returns the
+/// canonical column names together with the rows.
+fn ordinance_rows(
+    conn: &Connection,
+    technology: &Technology,
+    columns: &[String],
+    options: &ExportOptions,
+) -> Result<(Vec<&'static str>, TextRows)> {
+    let (columns, select) = select_ordinance_columns(columns, options)?;
+    trace!("Exporting columns: {:?}", columns);
+
+    let (query, params) =
+        ordinance_query(&select, technology, &options.filter, &["FIPS", "feature"])?;
+    let mut stmt = conn.prepare(&query)?;
+
+    let mut rows = stmt.query(params.as_slice())?;
+    let mut records = Vec::new();
+    while let Some(row) = rows.next()? {
+        let record = (0..columns.len())
+            .map(|i| row.get::<_, Option<String>>(i))
+            .collect::<duckdb::Result<Vec<_>>>()?;
+        records.push(record);
+    }
+    Ok((columns, records))
+}
+
+/// Query the reVX standard ordinance records of a technology
+///
+/// Only the records matching `filter`, with the quantitative values
+/// rounded to `precision` decimal places if given.
+fn ordinance_records(
+    conn: &Connection,
+    technology: &Technology,
+    filter: &OrdinanceFilter,
+    precision: Option<u32>,
+) -> Result<Vec<OrdinanceRecord>> {
+    let select = format!(
+        "ordinance.FIPS, ordinance.feature, ordinance.feature_subtype, {}, ordinance.qualitative",
+        quantitative_expr(precision)
+    );
+    let (query, params) = ordinance_query(&select, technology, filter, &["FIPS", "feature"])?;
+    let mut stmt = conn.prepare(&query)?;
+    //dbg!("Row count", stmt.row_count());
+    let records = stmt
+        .query_map(params.as_slice(), |row| {
+            Ok(OrdinanceRecord {
+                FIPS: row.get(0)?,
+                feature: row.get(1)?,
+                feature_subtype: row.get(2)?,
+                quantitative: row.get(3)?,
+                qualitative: row.get(4)?,
+            })
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+
+    Ok(records)
+}
+
+#[cfg(feature = "bincode")]
+/// Write ordinance records as bincode
+///
+/// Much faster to produce and parse than CSV or JSON, but only meant
+/// for Rust-to-Rust pipelines. Read it back with `read_bincode()`.
+fn write_bincode<W: std::io::Write>(wtr: &mut W, records: &[OrdinanceRecord]) -> Result<()> {
+    bincode::serde::encode_into_std_write(records, wtr, bincode::config::standard())?;
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "bincode"))]
+fn write_bincode<W: std::io::Write>(_wtr: &mut W, _records: &[OrdinanceRecord]) -> Result<()> {
+    Err(error::Error::UnsupportedFormat(
+        "bincode (requires the 'bincode' feature)".to_string(),
+    ))
+}
+
+#[cfg(feature = "bincode")]
+/// Read ordinance records exported as bincode
+pub fn read_bincode<R: std::io::Read>(rdr: &mut R) -> Result<Vec<OrdinanceRecord>> {
+    let records = bincode::serde::decode_from_std_read(rdr, bincode::config::standard())?;
+    Ok(records)
+}
+
+#[cfg(test)]
+/// Sample databases to support tests
+pub(crate) mod sample {
+    use duckdb::Connection;
+
+    /// Create an empty database with the ordinance data model
+    ///
+    /// Equivalent to `init_db` but without the jurisdiction table, which
+    /// requires installing the spatial extension (network access).
+    pub(crate) fn empty_db(path: &std::path::Path) -> Connection {
+        let mut conn = Connection::open(path).unwrap();
+        let tx = conn.transaction().unwrap();
+        crate::init_bookkeeper(&tx).unwrap();
+        crate::scraper::ScrapedOrdinance::init_db(&tx).unwrap();
+        tx.commit().unwrap();
+        conn
+    }
+
+    /// Create a database with a couple of wind ordinance records
+    pub(crate) fn sample_db(dir: &std::path::Path) -> String {
+        let db = dir.join("sample.db").to_str().unwrap().to_string();
+        let conn = empty_db(std::path::Path::new(&db));
+        conn.execute_batch(
+            r"
+            INSERT INTO bookkeeper (hash, username) VALUES ('sample', 'test');
+            INSERT INTO scraper_metadata (bookkeeper_lnk, technology) VALUES (1, 'wind');
+            INSERT INTO quantitative (bookkeeper_lnk, county, state, FIPS, feature, value)
+              VALUES (1, 'county-1', 'state-1', 11111, 'feature-1', 1.5),
+                     (1, 'county-2', 'state-2', 22222, 'feature-2', 2.5);
+            ",
+        )
+        .unwrap();
+        db
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sample::sample_db;
+
+    #[test]
+    fn it_works() {
+        let _ = init_db("test");
+    }
+
+    #[test]
+    /// A failure while initializing leaves nothing at the target path
+    fn init_failure_leaves_no_database() {
+        let tmp = tempfile::tempdir().unwrap();
+        let target = tmp.path().join("ordinance.db");
+
+        let err = create_atomically(&target, false, |conn| {
+            init_bookkeeper(conn)?;
+            Err(error::Error::Undefined("Simulated failure".to_string()))
+        });
+        assert!(err.is_err());
+        assert!(!target.exists());
+        assert_eq!(std::fs::read_dir(tmp.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    /// A successful initialization results in a valid database
+    fn init_atomically() {
+        let tmp = tempfile::tempdir().unwrap();
+        let target = tmp.path().join("ordinance.db");
+
+        create_atomically(&target, false, init_bookkeeper).unwrap();
+        let conn = Connection::open(&target).unwrap();
+        assert_eq!(
+            schema::version(&conn).unwrap().as_deref(),
+            Some(ORDINANCEDB_VERSION)
+        );
+
+        // Never overwrite an existing database
+        create_atomically(&target, false, init_bookkeeper).unwrap_err();
+    }
+
+    #[test]
+    /// An existing database is only replaced with overwrite
+    fn init_overwrite() {
+        let tmp = tempfile::tempdir().unwrap();
+        let target = tmp.path().join("ordinance.db");
+        {
+            let conn = sample::empty_db(&target);
+            conn.execute(
+                "INSERT INTO bookkeeper (hash, username) VALUES ('old', 'test')",
+                [],
+            )
+            .unwrap();
+        }
+
+        let err = create_atomically(&target, false, init_bookkeeper).unwrap_err();
+        assert!(err.to_string().contains("already exists"), "{err}");
+
+        create_atomically(&target, true, init_bookkeeper).unwrap();
+        let conn = Connection::open(&target).unwrap();
+        let n: usize = conn
+            .query_row("SELECT COUNT(*) FROM bookkeeper", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(n, 0);
+    }
+
+    #[test]
+    /// Overwrite only replaces an ordinance database or an empty file
+    fn init_overwrite_other_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let target = tmp.path().join("notes.txt");
+        std::fs::write(&target, "Not a database").unwrap();
+
+        let err = create_atomically(&target, true, init_bookkeeper).unwrap_err();
+        assert!(
+            err.to_string().contains("not an ordinance database"),
+            "{err}"
+        );
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "Not a database");
+
+        std::fs::write(&target, "").unwrap();
+        create_atomically(&target, true, init_bookkeeper).unwrap();
+        let conn = Connection::open(&target).unwrap();
+        assert!(schema::version(&conn).unwrap().is_some());
+    }
+
+    #[test]
+    /// Skipped files are recorded in the bookkeeper comment
+    fn load_allow_missing() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample::empty_db(&tmp.path().join("test.db"));
+        let output = tmp.path().join("output");
+        std::fs::create_dir(&output).unwrap();
+        scraper::sample::as_dir(&output).unwrap();
+        std::fs::remove_file(output.join("usage.json")).unwrap();
+
+        let options = LoadOptions {
+            allow_missing: true,
+            ..Default::default()
+        };
+        load_ordinance(db, "test", &output, &options).unwrap();
+
+        let db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let comment: String = db
+            .query_row("SELECT comment FROM bookkeeper", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(comment, "Skipped missing: usage.json");
+    }
+
+    #[test]
+    /// The original quantitative CSV is stored only if requested
+    fn load_store_raw() {
+        let tmp = tempfile::tempdir().unwrap();
+        let output = tmp.path().join("output");
+        std::fs::create_dir(&output).unwrap();
+        scraper::sample::as_dir(&output).unwrap();
+        let original = std::fs::read(output.join("quantitative_ordinances.csv")).unwrap();
+
+        let db = sample::empty_db(&tmp.path().join("test.db"));
+        load_ordinance(db, "test", &output, &LoadOptions::default()).unwrap();
+        let db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let options = LoadOptions {
+            store_raw: true,
+            ..Default::default()
+        };
+        let summary = load_ordinance(db, "test", &output, &options).unwrap();
+
+        let db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let stored: Vec<(usize, Vec<u8>)> = db
+            .prepare("SELECT bookkeeper_lnk, content FROM raw_inputs")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<duckdb::Result<_>>()
+            .unwrap();
+        assert_eq!(stored, [(summary.commit_id, original)]);
+    }
+
+    #[test]
+    /// Duplicated log records are skipped only if requested
+    fn load_dedup_logs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let output = tmp.path().join("output");
+        std::fs::create_dir(&output).unwrap();
+        scraper::sample::as_dir(&output).unwrap();
+
+        let db = sample::empty_db(&tmp.path().join("test.db"));
+        let all = load_ordinance(db, "test", &output, &LoadOptions::default()).unwrap();
+        let db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let options = LoadOptions {
+            dedup_logs: true,
+            ..Default::default()
+        };
+        let dedup = load_ordinance(db, "test", &output, &options).unwrap();
+
+        let db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let running = |commit_id: usize| -> usize {
+            db.query_row(
+                "SELECT COUNT(*) FROM logs WHERE bookkeeper_lnk = ? AND message = 'Running COMPASS'",
+                [commit_id],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(running(all.commit_id), 2);
+        assert_eq!(running(dedup.commit_id), 1);
+    }
+
+    #[test]
+    /// Only the first jurisdiction and its ordinances are loaded
+    fn load_limit_jurisdictions() {
+        let tmp = tempfile::tempdir().unwrap();
+        let output = tmp.path().join("output");
+        std::fs::create_dir(&output).unwrap();
+        scraper::sample::as_dir(&output).unwrap();
+        let path = output.join("jurisdictions.json");
+        let mut source: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let mut second = source["jurisdictions"][0].clone();
+        source["jurisdictions"][0]["FIPS"] = 11111.into();
+        second["FIPS"] = 22222.into();
+        source["jurisdictions"].as_array_mut().unwrap().push(second);
+        std::fs::write(&path, source.to_string()).unwrap();
+
+        let db = sample::empty_db(&tmp.path().join("test.db"));
+        let options = LoadOptions {
+            limit_jurisdictions: Some(1),
+            ..Default::default()
+        };
+        load_ordinance(db, "test", &output, &options).unwrap();
+
+        let db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let count = |query: &str| -> usize { db.query_row(query, [], |row| row.get(0)).unwrap() };
+        assert_eq!(count("SELECT COUNT(*) FROM source"), 1);
+        assert_eq!(count("SELECT COUNT(*) FROM source WHERE fips = 11111"), 1);
+        assert_eq!(
+            count("SELECT COUNT(*) FROM ordinance WHERE FIPS <> 11111"),
+            0
+        );
+        assert!(count("SELECT COUNT(*) FROM ordinance") > 0);
+        let comment: String = db
+            .query_row("SELECT comment FROM bookkeeper", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(comment, "Partial: limited to 1 jurisdiction(s)");
+    }
+
+    #[test]
+    /// Ordinances are linked to the document matching their source
+    fn load_link_archive() {
+        let tmp = tempfile::tempdir().unwrap();
+        let output = tmp.path().join("output");
+        std::fs::create_dir(&output).unwrap();
+        scraper::sample::as_dir(&output).unwrap();
+        for name in ["quantitative_ordinances.csv", "qualitative_ordinances.csv"] {
+            let path = output.join(name);
+            let content = std::fs::read_to_string(&path)
+                .unwrap()
+                .replace("source-1", "https://example.com/sample_ordinance.pdf");
+            std::fs::write(&path, content).unwrap();
+        }
+
+        let db = sample::empty_db(&tmp.path().join("test.db"));
+        load_ordinance(db, "test", &output, &LoadOptions::default()).unwrap();
+
+        let db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let count = |query: &str| -> usize { db.query_row(query, [], |row| row.get(0)).unwrap() };
+        assert_eq!(
+            count(
+                "SELECT COUNT(*) FROM ordinance JOIN archive ON archive_lnk = archive.id
+                  WHERE ordinance.source = archive.source"
+            ),
+            2
+        );
+        assert_eq!(
+            count(
+                "SELECT COUNT(*) FROM ordinance WHERE source = 'source-2' AND archive_lnk IS NULL"
+            ),
+            2
+        );
+        // Once, even if several ordinances share the unmatched source
+        assert_eq!(
+            count(
+                "SELECT COUNT(*) FROM data_quality
+                  WHERE component = 'ordinance' AND field = 'source' AND value = 'source-2'"
+            ),
+            1
+        );
+    }
+
+    #[test]
+    /// Documents arriving later are attached to the existing commit
+    fn add_sources_to_commit() {
+        let tmp = tempfile::tempdir().unwrap();
+        let output = tmp.path().join("output");
+        std::fs::create_dir(&output).unwrap();
+        scraper::sample::as_dir(&output).unwrap();
+        let db = sample::empty_db(&tmp.path().join("test.db"));
+        let summary = load_ordinance(db, "test", &output, &LoadOptions::default()).unwrap();
+
+        // The sample document again, and two new ones
+        let later = tmp.path().join("later");
+        std::fs::create_dir_all(later.join("ordinance_files")).unwrap();
+        let document = |filename: &str, checksum: &str| {
+            format!(
+                r#"{{"source": "https://example.com/{filename}", "effective_month": null,
+                    "effective_year": 2024, "effective_day": null, "ord_filename": "{filename}",
+                    "num_pages": 2, "checksum": "sha256:{checksum}", "is_pdf": true,
+                    "from_ocr": false, "ordinance_text_ngram_score": null,
+                    "permitted_use_text_ngram_score": null}}"#
+            )
+        };
+        use sha2::Digest;
+
+        // Checksum of a new file in `ordinance_files/`
+        let file = |filename: &str| {
+            let content = format!("content of {filename}");
+            std::fs::write(later.join("ordinance_files").join(filename), &content).unwrap();
+            format!("{:x}", sha2::Sha256::digest(content))
+        };
+        let jurisdiction = |fips: u64, documents: &[String]| {
+            format!(
+                r#"{{"full_name": "Jurisdiction {fips}", "county": "County {fips}",
+                    "state": "Sample State", "subdivision": null, "jurisdiction_type": null,
+                    "FIPS": {fips}, "found": true, "total_time": 1.0,
+                    "total_time_string": "0:00:01", "jurisdiction_website": null,
+                    "compass_crawl": false, "documents": [{}]}}"#,
+                documents.join(",")
+            )
+        };
+        std::fs::write(
+            later.join("jurisdictions.json"),
+            format!(
+                r#"{{"jurisdictions": [{}, {}]}}"#,
+                jurisdiction(
+                    12345,
+                    &[
+                        document(
+                            "sample_ordinance.pdf",
+                            "1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef"
+                        ),
+                        document("addendum.pdf", &file("addendum.pdf")),
+                    ]
+                ),
+                jurisdiction(54321, &[document("other.pdf", &file("other.pdf"))]),
+            ),
+        )
+        .unwrap();
+
+        let mut db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let commit = summary.commit_id.to_string();
+        let added = add_sources(&mut db, &commit, &later, &LoadOptions::default()).unwrap();
+        assert_eq!(added, 2);
+
+        let count = |db: &Connection, query: &str| -> usize {
+            db.query_row(query, [summary.commit_id], |row| row.get(0))
+                .unwrap()
+        };
+        let documents = r"
+            SELECT COUNT(*) FROM archive JOIN source ON list_contains(
+              string_split(source.documents, ','), CAST(archive.id AS VARCHAR))
+            WHERE source.bookkeeper_lnk = ?";
+        assert_eq!(count(&db, documents), 3);
+        assert_eq!(
+            count(&db, "SELECT COUNT(*) FROM source WHERE bookkeeper_lnk = ?"),
+            2
+        );
+        assert_eq!(
+            count(&db, "SELECT COUNT(*) FROM bookkeeper WHERE id >= ?"),
+            1
+        );
+
+        // Adding again, by hash, is a no-op
+        let hash: String = db
+            .query_row(
+                "SELECT hash FROM bookkeeper WHERE id = ?",
+                [summary.commit_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let added = add_sources(&mut db, &hash, &later, &LoadOptions::default()).unwrap();
+        assert_eq!(added, 0);
+        assert_eq!(count(&db, documents), 3);
+
+        assert!(matches!(
+            add_sources(&mut db, "unknown", &later, &LoadOptions::default()),
+            Err(error::Error::UnknownHash(_))
+        ));
+        assert!(matches!(
+            add_sources(&mut db, "99", &later, &LoadOptions::default()),
+            Err(error::Error::UnknownCommit(99))
+        ));
+    }
+
+    #[test]
+    /// Documents missing from the files, or with another checksum, aren't added
+    fn add_sources_checks_files() {
+        use sha2::Digest;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let output = tmp.path().join("output");
+        std::fs::create_dir(&output).unwrap();
+        scraper::sample::as_dir(&output).unwrap();
+        let db = sample::empty_db(&tmp.path().join("test.db"));
+        let summary = load_ordinance(db, "test", &output, &LoadOptions::default()).unwrap();
+
+        let later = tmp.path().join("later");
+        std::fs::create_dir_all(later.join("ordinance_files")).unwrap();
+        std::fs::write(later.join("ordinance_files/present.pdf"), "present").unwrap();
+        std::fs::write(later.join("ordinance_files/changed.pdf"), "changed").unwrap();
+        let document = |filename: &str, content: &str| {
+            format!(
+                r#"{{"source": "https://example.com/{filename}", "effective_month": null,
+                    "effective_year": 2024, "effective_day": null, "ord_filename": "{filename}",
+                    "num_pages": 2, "checksum": "sha256:{:x}", "is_pdf": true,
+                    "from_ocr": false, "ordinance_text_ngram_score": null,
+                    "permitted_use_text_ngram_score": null}}"#,
+                sha2::Sha256::digest(content)
+            )
+        };
+        std::fs::write(
+            later.join("jurisdictions.json"),
+            format!(
+                r#"{{"jurisdictions": [{{"full_name": "Jurisdiction 54321", "county": "County 54321",
+                    "state": "Sample State", "subdivision": null, "jurisdiction_type": null,
+                    "FIPS": 54321, "found": true, "total_time": 1.0,
+                    "total_time_string": "0:00:01", "jurisdiction_website": null,
+                    "compass_crawl": false, "documents": [{}, {}, {}]}}]}}"#,
+                document("present.pdf", "present"),
+                document("missing.pdf", "missing"),
+                document("changed.pdf", "original"),
+            ),
+        )
+        .unwrap();
+
+        let mut db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let commit = summary.commit_id.to_string();
+        let strict = LoadOptions {
+            strict: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            add_sources(&mut db, &commit, &later, &strict),
+            Err(error::Error::Warnings(w)) if w.len() == 2
+        ));
+        let added = add_sources(&mut db, &commit, &later, &LoadOptions::default()).unwrap();
+        assert_eq!(added, 1);
+
+        let issues: Vec<(String, String)> = db
+            .prepare(
+                "SELECT field, value FROM data_quality
+                  WHERE bookkeeper_lnk = ? AND field IN ('checksum', 'ord_filename')
+                  ORDER BY field",
+            )
+            .unwrap()
+            .query_map([summary.commit_id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<duckdb::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            issues,
+            [
+                ("checksum".to_string(), "changed.pdf".to_string()),
+                ("ord_filename".to_string(), "missing.pdf".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    /// A run that found no ordinance loads, but an empty file doesn't
+    fn load_no_ordinances() {
+        let tmp = tempfile::tempdir().unwrap();
+        let output = tmp.path().join("output");
+        std::fs::create_dir(&output).unwrap();
+        scraper::sample::as_dir(&output).unwrap();
+        for name in ["quantitative_ordinances.csv", "qualitative_ordinances.csv"] {
+            let path = output.join(name);
+            let content = std::fs::read_to_string(&path).unwrap();
+            let header = content.lines().next().unwrap();
+            std::fs::write(&path, format!("{header}\n")).unwrap();
+        }
+
+        let db = sample::empty_db(&tmp.path().join("test.db"));
+        let summary = load_ordinance(db, "test", &output, &LoadOptions::default()).unwrap();
+        assert_eq!(summary.ordinances, 0);
+
+        let db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let count = |query: &str| -> usize { db.query_row(query, [], |row| row.get(0)).unwrap() };
+        assert_eq!(count("SELECT COUNT(*) FROM ordinance"), 0);
+        assert_eq!(
+            count(
+                "SELECT COUNT(*) FROM data_quality
+                  WHERE component IN ('quantitative', 'qualitative')"
+            ),
+            0
+        );
+        drop(db);
+
+        std::fs::write(output.join("qualitative_ordinances.csv"), "").unwrap();
+        let db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let err = load_ordinance(db, "test", &output, &LoadOptions::default()).unwrap_err();
+        assert!(matches!(err, error::Error::EmptyCsv(_)));
+    }
+
+    #[test]
+    /// Each load gets its own commit id, and only its own records
+    fn sequential_loads_commit_ids() {
+        let tmp = tempfile::tempdir().unwrap();
+        let output = tmp.path().join("output");
+        std::fs::create_dir(&output).unwrap();
+        scraper::sample::as_dir(&output).unwrap();
+
+        let db = sample::empty_db(&tmp.path().join("test.db"));
+        let first = load_ordinance(db, "first", &output, &LoadOptions::default()).unwrap();
+        let db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let second = load_ordinance(db, "second", &output, &LoadOptions::default()).unwrap();
+        assert_ne!(first.commit_id, second.commit_id);
+
+        let db = Connection::open(tmp.path().join("test.db")).unwrap();
+        for (commit_id, username) in [(first.commit_id, "first"), (second.commit_id, "second")] {
+            let stored: String = db
+                .query_row(
+                    "SELECT username FROM bookkeeper WHERE id = ?",
+                    [commit_id],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(stored, username);
+        }
+        for table in [
+            "source",
+            "quantitative",
+            "qualitative",
+            "logs",
+            "scraper_metadata",
+        ] {
+            let (first_rows, second_rows, total): (usize, usize, usize) = db
+                .query_row(
+                    &format!(
+                        "SELECT COUNT(*) FILTER (bookkeeper_lnk = ?),
+                           COUNT(*) FILTER (bookkeeper_lnk = ?), COUNT(*) FROM {table}"
+                    ),
+                    [first.commit_id, second.commit_id],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .unwrap();
+            assert!(first_rows > 0, "No {table} rows in the first commit");
+            assert_eq!(first_rows, second_rows, "{table}");
+            assert_eq!(first_rows + second_rows, total, "{table}");
+        }
+    }
+
+    #[test]
+    /// In strict mode, a source file not matching its checksum fails the load
+    fn load_strict_checksum_mismatch() {
+        let tmp = tempfile::tempdir().unwrap();
+        let output = tmp.path().join("output");
+        std::fs::create_dir(&output).unwrap();
+        scraper::sample::as_dir(&output).unwrap();
+
+        let options = LoadOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let db = sample::empty_db(&tmp.path().join("test.db"));
+        let err = load_ordinance(db, "test", &output, &options).unwrap_err();
+        let error::Error::Warnings(warnings) = &err else {
+            panic!("Expected warnings, got {err:?}");
+        };
+        assert!(
+            warnings
+                .iter()
+                .any(|w| w.starts_with("source.ordinance_files") && w.contains("source.pdf"))
+        );
+
+        // Nothing is recorded
+        let db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let commits: usize = db
+            .query_row("SELECT COUNT(*) FROM bookkeeper", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(commits, 0);
+
+        // While the same output loads without strict mode
+        load_ordinance(db, "test", &output, &LoadOptions::default()).unwrap();
+    }
+
+    #[test]
+    /// A read-only connection can query but not modify
+    fn readonly_connection() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+
+        let conn = open_readonly(&db).unwrap();
+        let total: usize = conn
+            .query_row("SELECT COUNT(*) FROM quantitative", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(total, 2);
+
+        conn.execute("DELETE FROM quantitative", []).unwrap_err();
+    }
+
+    #[test]
+    /// Opening within the timeout gives a usable connection
+    fn open_within_timeout() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+
+        let conn = open_with_timeout(&db, std::time::Duration::from_secs(60)).unwrap();
+        let total: usize = conn
+            .query_row("SELECT COUNT(*) FROM quantitative", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(total, 2);
+    }
+
+    #[test]
+    /// Read-only within the timeout, thus refusing any write
+    fn open_readonly_within_timeout() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+
+        let conn = open_readonly_with_timeout(&db, std::time::Duration::from_secs(60)).unwrap();
+        let total: usize = conn
+            .query_row("SELECT COUNT(*) FROM quantitative", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(total, 2);
+        conn.execute("DELETE FROM quantitative", []).unwrap_err();
+
+        let err = open_readonly_with_timeout(&db, std::time::Duration::ZERO).unwrap_err();
+        assert!(matches!(err, error::Error::OpenTimeout { .. }), "{err}");
+    }
+
+    #[test]
+    /// A slow open is abandoned with a timeout error
+    fn open_timeout() {
+        let timeout = std::time::Duration::from_millis(10);
+        let slow = run_with_timeout(timeout, || {
+            std::thread::sleep(std::time::Duration::from_secs(2));
+            Ok(())
+        });
+        assert!(slow.is_none());
+
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+        let err = open_with_timeout(&db, std::time::Duration::ZERO).unwrap_err();
+        assert!(
+            matches!(err, error::Error::OpenTimeout { ref path, .. } if path.as_path() == std::path::Path::new(&db)),
+            "Unexpected error: {err}"
+        );
+    }
+
+    #[test]
+    /// Load directly from a gzipped tarball
+    fn load_from_archive() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample::empty_db(&tmp.path().join("test.db"));
+        let tarball = tmp.path().join("output.tar.gz");
+        archive::sample::as_file(&tarball).unwrap();
+
+        let summary = load_ordinance(db, "test", &tarball, &LoadOptions::default()).unwrap();
+        assert_eq!(summary.usage.unwrap().prompt_tokens, 186099);
+
+        let db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let (id, username): (usize, String) = db
+            .query_row("SELECT id, username FROM bookkeeper", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(summary.commit_id, id);
+        assert_eq!(username, "test");
+        let total: usize = db
+            .query_row("SELECT COUNT(*) FROM quantitative", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(total, 2);
+    }
+
+    #[test]
+    /// Empty or placeholder hashes are rejected, before and by the schema
+    fn reject_empty_hash() {
+        assert!(matches!(check_hash(""), Err(error::Error::InvalidHash(_))));
+        assert!(matches!(
+            check_hash("  "),
+            Err(error::Error::InvalidHash(_))
+        ));
+        assert!(matches!(
+            check_hash("dummy hash"),
+            Err(error::Error::InvalidHash(_))
+        ));
+        assert_eq!(check_hash("sha256:1234").unwrap(), "sha256:1234");
+
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample::empty_db(&tmp.path().join("test.db"));
+        db.execute("INSERT INTO bookkeeper (hash) VALUES ('')", [])
+            .unwrap_err();
+    }
+
+    #[test]
+    /// Each load is identified by the hash of its content
+    fn load_content_hash() {
+        use sha2::Digest;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let output = tmp.path().join("output");
+        std::fs::create_dir(&output).unwrap();
+        scraper::sample::as_dir(&output).unwrap();
+        let path = tmp.path().join("test.db");
+
+        load_ordinance(
+            sample::empty_db(&path),
+            "test",
+            &output,
+            &Default::default(),
+        )
+        .unwrap();
+        load_ordinance(
+            Connection::open(&path).unwrap(),
+            "test",
+            &output,
+            &Default::default(),
+        )
+        .unwrap();
+        let csv = "county,state,subdivison,jurisdiction_type,FIPS,feature,value,units,offset,min_dist,max_dist,summary,ord_year,section,source
+county-1,state-1,,county,11111,structures,500,feet,,,,,2020,,source-1
+";
+        load_quantitative_csv(
+            Connection::open(&path).unwrap(),
+            "test",
+            "wind",
+            csv.as_bytes(),
+            &Default::default(),
+        )
+        .unwrap();
+
+        let db = Connection::open(&path).unwrap();
+        let hashes: Vec<String> = db
+            .prepare("SELECT hash FROM bookkeeper ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<duckdb::Result<_>>()
+            .unwrap();
+        assert!(hashes.iter().all(|h| h.starts_with("sha256:")));
+        // Same output, same hash
+        assert_eq!(hashes[0], hashes[1]);
+        assert_ne!(hashes[0], hashes[2]);
+        // A stream is hashed as it is read
+        assert_eq!(
+            hashes[2],
+            format!("sha256:{:x}", sha2::Sha256::digest(csv.as_bytes()))
+        );
+    }
+
+    #[test]
+    /// Load a quantitative ordinance CSV as if piped through stdin
+    fn load_from_stream() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample::empty_db(&tmp.path().join("test.db"));
+        let csv = "county,state,subdivison,jurisdiction_type,FIPS,feature,value,units,offset,min_dist,max_dist,summary,ord_year,section,source
+county-1,state-1,,county,11111,structures,500,feet,,,,,2020,,source-1
+county-2,state-2,,county,22222,roads,1.5,tip-height-multiplier,,,,,1066,,source-2
+";
+
+        let summary =
+            load_quantitative_csv(db, "test", "wind", csv.as_bytes(), &LoadOptions::default())
+                .unwrap();
+        assert!(summary.usage.is_none());
+
+        let db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let rows: Vec<(usize, String, f64)> = db
+            .prepare("SELECT bookkeeper_lnk, feature, quantitative FROM ordinance ORDER BY FIPS")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<duckdb::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                (summary.commit_id, "structures".to_string(), 500.0),
+                (summary.commit_id, "roads".to_string(), 1.5),
+            ]
+        );
+        // Validated as a complete output would be
+        let issues: usize = db
+            .query_row("SELECT COUNT(*) FROM data_quality", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(issues, 1);
+    }
+
+    #[test]
+    /// A stream is loaded with the same options as a complete output
+    fn load_from_stream_strict() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("test.db");
+        let csv = "county,state,subdivison,jurisdiction_type,FIPS,feature,value,units,offset,min_dist,max_dist,summary,ord_year,section,source
+county-1,state-1,,county,11111,structures,500,feet,,,,,1066,,source-1
+";
+        let options = LoadOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let result = load_quantitative_csv(
+            sample::empty_db(&path),
+            "test",
+            "wind",
+            csv.as_bytes(),
+            &options,
+        );
+        assert!(
+            matches!(&result, Err(error::Error::Warnings(w)) if w.len() == 1),
+            "{result:?}"
+        );
+
+        let db = Connection::open(&path).unwrap();
+        let commits: usize = db
+            .query_row("SELECT COUNT(*) FROM bookkeeper", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(commits, 0);
+    }
+
+    #[test]
+    /// A stream is exported with its technology, as a complete output
+    fn load_from_stream_then_export() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("test.db");
+        let db = sample::empty_db(&path);
+        let csv = "county,state,subdivison,jurisdiction_type,FIPS,feature,value,units,offset,min_dist,max_dist,summary,ord_year,section,source
+county-1,state-1,,county,11111,structures,500,feet,,,,,2020,,source-1
+";
+        load_quantitative_csv(db, "test", "solar", csv.as_bytes(), &LoadOptions::default())
+            .unwrap();
+
+        let mut output = Vec::new();
+        export(
+            &mut output,
+            &open_readonly(&path).unwrap(),
+            "csv",
+            "solar",
+            &ExportOptions::default(),
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 2, "{output}");
+        assert!(output.contains("11111,structures"), "{output}");
+
+        let mut output = Vec::new();
+        export(
+            &mut output,
+            &open_readonly(&path).unwrap(),
+            "csv",
+            "wind",
+            &ExportOptions::default(),
+        )
+        .unwrap();
+        assert!(!String::from_utf8(output).unwrap().contains("11111"));
+    }
+
+    #[test]
+    /// A "1.1x hub height" setback is stored with its multiplier
+    fn load_setback_multiplier() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample::empty_db(&tmp.path().join("test.db"));
+        let csv = "county,state,subdivison,jurisdiction_type,FIPS,feature,value,units,offset,min_dist,max_dist,mult_value,mult_type,summary,ord_year,section,source
+county-1,state-1,,county,11111,structures,,,,,,1.1,hub-height,,2020,,source-1
+";
+        load_quantitative_csv(db, "test", "wind", csv.as_bytes(), &LoadOptions::default()).unwrap();
+
+        let db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let (mult_value, mult_type): (f32, String) = db
+            .query_row("SELECT mult_value, mult_type FROM ordinance", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((mult_value, mult_type.as_str()), (1.1, "hub-height"));
+        let setback = resolve_setback(mult_value.into(), &mult_type, 90.0);
+        assert!((setback - 99.0).abs() < 1e-4, "{setback}");
+    }
+
+    #[test]
+    /// Load several outputs concurrently, each as its own commit
+    fn load_several_in_parallel() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample::empty_db(&tmp.path().join("test.db"));
+        let outputs: Vec<_> = (0..4)
+            .map(|i| {
+                let path = tmp.path().join(format!("output-{i}"));
+                std::fs::create_dir(&path).unwrap();
+                scraper::sample::as_dir(&path).unwrap();
+                path
+            })
+            .collect();
+
+        let commits = load_parallel(db, "test", &outputs, &LoadOptions::default(), 2).unwrap();
+
+        let mut sorted = commits.clone();
+        sorted.sort();
+        assert_eq!(sorted, vec![1, 2, 3, 4]);
+        let db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let (commits, loaded): (usize, usize) = db
+            .query_row(
+                "SELECT COUNT(DISTINCT bookkeeper_lnk), COUNT(*) FROM quantitative",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(commits, 4);
+        assert_eq!(loaded, 8);
+        // Each commit with its own source and usage
+        let (sources, usage): (usize, usize) = db
+            .query_row(
+                "SELECT (SELECT COUNT(DISTINCT bookkeeper_lnk) FROM source),
+                    (SELECT COUNT(DISTINCT bookkeeper_lnk) FROM usage_event)",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((sources, usage), (4, 4));
+    }
+
+    #[test]
+    /// A failing output aborts the parallel load
+    fn load_parallel_failure() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample::empty_db(&tmp.path().join("test.db"));
+        let outputs = [tmp.path().join("missing")];
+
+        load_parallel(db, "test", &outputs, &LoadOptions::default(), 2).unwrap_err();
+    }
+
+    #[test]
+    /// A huge ordinance CSV is given up as soon as the timeout fires
+    fn load_timeout_huge_csv() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample::empty_db(&tmp.path().join("test.db"));
+        let output = tmp.path().join("output");
+        std::fs::create_dir(&output).unwrap();
+        scraper::sample::as_dir(&output).unwrap();
+        use std::io::Write;
+
+        let mut csv = std::io::BufWriter::new(
+            std::fs::File::create(output.join("quantitative_ordinances.csv")).unwrap(),
+        );
+        writeln!(csv, "county,state,subdivison,jurisdiction_type,FIPS,feature,value,units,offset,min_dist,max_dist,summary,ord_year,section,source").unwrap();
+        for i in 0..500_000 {
+            writeln!(
+                csv,
+                "county-{i},state,,county,{i},feature,1,ft,,,,,2001,,source"
+            )
+            .unwrap();
+        }
+        csv.into_inner().unwrap().sync_all().unwrap();
+
+        let timeout = std::time::Duration::from_millis(100);
+        let options = LoadOptions {
+            timeout: Some(timeout),
+            ..Default::default()
+        };
+        let start = std::time::Instant::now();
+        let err = load_ordinance(db, "test", &output, &options).unwrap_err();
+        let elapsed = start.elapsed();
+        assert!(
+            matches!(err, error::Error::LoadTimeout(t) if t == timeout),
+            "{err}"
+        );
+        // Loading it whole takes minutes
+        assert!(elapsed < std::time::Duration::from_secs(5), "{elapsed:?}");
+
+        let db = Connection::open(tmp.path().join("test.db")).unwrap();
+        let n: usize = db
+            .query_row("SELECT COUNT(*) FROM bookkeeper", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(n, 0);
+    }
+
+    #[test]
+    /// Appending a second batch should not repeat the header
+    fn export_append_without_header() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+
+        let mut output = Vec::new();
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &ExportOptions::default(),
+        )
+        .unwrap();
+        let options = ExportOptions {
+            has_headers: false,
+            ..Default::default()
+        };
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &options,
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines.iter().filter(|l| l.starts_with("FIPS,")).count(), 1);
+        assert!(lines[0].starts_with("FIPS,"));
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode_roundtrip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+
+        let mut output = Vec::new();
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "bincode",
+            "wind",
+            &ExportOptions::default(),
+        )
+        .unwrap();
+        let records = read_bincode(&mut output.as_slice()).unwrap();
+
+        let conn = Connection::open(&db).unwrap();
+        let expected =
+            ordinance_records(&conn, &Technology::Wind, &OrdinanceFilter::default(), None).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records, expected);
+    }
+
+    #[test]
+    /// The same record type parses from CSV and serializes to JSON
+    fn ordinance_record_roundtrip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+
+        let mut output = Vec::new();
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &ExportOptions::default(),
+        )
+        .unwrap();
+        let records = csv::Reader::from_reader(output.as_slice())
+            .deserialize()
+            .collect::<std::result::Result<Vec<OrdinanceRecord>, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+
+        let json = serde_json::to_string(&records).unwrap();
+        let roundtrip: Vec<OrdinanceRecord> = serde_json::from_str(&json).unwrap();
+        assert_eq!(roundtrip, records);
+    }
+
+    #[test]
+    /// Export as a table for the terminal
+    fn export_table() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+
+        let mut output = Vec::new();
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "table",
+            "wind",
+            &ExportOptions::default(),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        // Header, borders above and below it, and one at the end
+        assert_eq!(lines.len(), 2 + 4);
+        assert!(lines[1].starts_with("| FIPS  | feature   | feature_subtype |"));
+        assert!(lines[3].starts_with("| 11111 | feature-1 |"));
+        assert_eq!(lines.iter().filter(|l| l.starts_with('+')).count(), 3);
+    }
+
+    #[test]
+    /// A format without a writer yet fails instead of writing CSV
+    fn export_unimplemented() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+
+        for format in UNIMPLEMENTED_FORMATS {
+            let mut output = Vec::new();
+            let result = export(
+                &mut output,
+                &open_readonly(&db).unwrap(),
+                format,
+                "wind",
+                &ExportOptions::default(),
+            );
+            assert!(
+                matches!(&result, Err(error::Error::UnsupportedFormat(f)) if f.starts_with(format)),
+                "{result:?}"
+            );
+            assert!(output.is_empty());
+        }
+    }
+
+    #[test]
+    /// Value-less ordinances are exported unless excluded
+    fn export_empty_values() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+        let conn = Connection::open(&db).unwrap();
+        conn.execute_batch(
+            r"
+            INSERT INTO quantitative (bookkeeper_lnk, county, state, FIPS, feature, value_type)
+              VALUES (1, 'county-3', 'state-3', 33333, 'lighting', NULL),
+                     (1, 'county-3', 'state-3', 33333, 'noise', 'range');
+            ",
+        )
+        .unwrap();
+        drop(conn);
+
+        let mut output = Vec::new();
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &ExportOptions::default(),
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 5);
+        assert!(output.contains("33333,lighting,"));
+
+        let options = ExportOptions {
+            filter: OrdinanceFilter {
+                exclude_empty: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &options,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 4);
+        assert!(!output.contains("33333,lighting,"));
+        assert!(output.contains("33333,noise,"));
+    }
+
+    #[test]
+    /// Export only some features
+    fn export_selected_features() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+        let conn = Connection::open(&db).unwrap();
+        conn.execute(
+            "INSERT INTO quantitative (bookkeeper_lnk, county, state, FIPS, feature, value)
+              VALUES (1, 'county-3', 'state-3', 33333, 'feature-3', 3.5)",
+            [],
+        )
+        .unwrap();
+        drop(conn);
+
+        let options = ExportOptions {
+            filter: OrdinanceFilter {
+                features: vec!["feature-1".to_string(), "feature-3".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &options,
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("11111,feature-1,"));
+        assert!(lines[2].starts_with("33333,feature-3,"));
+    }
+
+    #[test]
+    /// Quantitative values rounded to the requested decimal places
+    fn export_precision() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+        let conn = Connection::open(&db).unwrap();
+        conn.execute(
+            "UPDATE quantitative SET value = 294.69257 WHERE FIPS = 11111",
+            [],
+        )
+        .unwrap();
+        drop(conn);
+
+        let options = ExportOptions {
+            precision: Some(2),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &options,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[1], "11111,feature-1,,294.69,");
+        assert_eq!(lines[2], "22222,feature-2,,2.5,");
+
+        let options = ExportOptions {
+            columns: Some(vec!["FIPS".to_string(), "quantitative".to_string()]),
+            precision: Some(1),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &options,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, "FIPS,quantitative\n11111,294.7\n22222,2.5\n");
+
+        // Full precision by default
+        let mut output = Vec::new();
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &ExportOptions::default(),
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(!output.contains("294.69,"));
+    }
+
+    #[test]
+    /// Export only the ordinances of a single commit
+    fn export_single_commit() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+        let conn = Connection::open(&db).unwrap();
+        conn.execute_batch(
+            r"
+            INSERT INTO bookkeeper (hash, username) VALUES ('second', 'test');
+            INSERT INTO scraper_metadata (bookkeeper_lnk, technology) VALUES (2, 'wind');
+            INSERT INTO quantitative (bookkeeper_lnk, county, state, FIPS, feature, value)
+              VALUES (2, 'county-3', 'state-3', 33333, 'feature-3', 3.5);
+            ",
+        )
+        .unwrap();
+        drop(conn);
+
+        let mut output = Vec::new();
+        let options = ExportOptions {
+            columns: Some(vec!["FIPS".to_string(), "quantitative".to_string()]),
+            filter: OrdinanceFilter {
+                commit_id: Some(2),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &options,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, "FIPS,quantitative\n33333,3.5\n");
+
+        let mut output = Vec::new();
+        let options = ExportOptions {
+            filter: OrdinanceFilter {
+                commit_id: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &options,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 3);
+        assert!(!output.contains("33333"));
+
+        let options = ExportOptions {
+            filter: OrdinanceFilter {
+                commit_id: Some(42),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let err = export(
+            &mut Vec::new(),
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &options,
+        )
+        .unwrap_err();
+        assert!(matches!(err, error::Error::UnknownCommit(42)));
+    }
+
+    #[test]
+    /// Export only the requested columns, in the requested order
+    fn export_selected_columns() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+
+        let mut output = Vec::new();
+        let options = ExportOptions {
+            columns: Some(vec!["state".to_string(), "quantitative".to_string()]),
+            ..Default::default()
+        };
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &options,
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines, ["state,quantitative", "state-1,1.5", "state-2,2.5"]);
+    }
+
+    #[test]
+    /// An unknown column is refused, listing the valid ones
+    fn export_unknown_column() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+
+        let mut output = Vec::new();
+        let options = ExportOptions {
+            columns: Some(vec!["state".to_string(), "elevation".to_string()]),
+            ..Default::default()
+        };
+        let err = export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &options,
+        )
+        .unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("\"elevation\""));
+        assert!(message.contains("county, state"));
+        assert!(output.is_empty());
+    }
+
+    /// A writer that fails once more than `capacity` bytes are written
+    struct Limited {
+        written: usize,
+        capacity: usize,
+    }
+
+    impl std::io::Write for Limited {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.written + buf.len() > self.capacity {
+                return Err(std::io::Error::other("no space left"));
+            }
+            self.written += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    /// Errors while writing the output are returned, even if buffered
+    fn export_write_error() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+
+        for format in ["csv", "table"] {
+            let mut wtr = std::io::BufWriter::with_capacity(
+                64 * 1024,
+                Limited {
+                    written: 0,
+                    capacity: 16,
+                },
+            );
+            let err = export(
+                &mut wtr,
+                &open_readonly(&db).unwrap(),
+                format,
+                "wind",
+                &ExportOptions::default(),
+            );
+            assert!(
+                matches!(&err, Err(e) if e.to_string().contains("no space left")),
+                "{format}: {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    /// FIPS codes zero-padded, for counties and states
+    fn export_fips_padded() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+        Connection::open(&db)
+            .unwrap()
+            .execute_batch(
+                "INSERT INTO quantitative (bookkeeper_lnk, county, state, FIPS, feature, value)
+                   VALUES (1, 'Jefferson', 'Colorado', 8059, 'feature-3', 3.5),
+                          (1, NULL, 'Colorado', 8, 'feature-4', 4.5);",
+            )
+            .unwrap();
+
+        let mut output = Vec::new();
+        let options = ExportOptions {
+            fips_padded: true,
+            ..Default::default()
+        };
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &options,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[0],
+            "FIPS,feature,feature_subtype,quantitative,qualitative"
+        );
+        assert_eq!(lines[1], "08,feature-4,,4.5,");
+        assert_eq!(lines[2], "08059,feature-3,,3.5,");
+        assert_eq!(lines[3], "11111,feature-1,,1.5,");
+
+        let mut output = Vec::new();
+        let options = ExportOptions {
+            columns: Some(vec!["FIPS".to_string(), "state".to_string()]),
+            fips_padded: true,
+            ..Default::default()
+        };
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &options,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("\n08059,Colorado\n"));
+    }
+
+    #[test]
+    /// One row per state and units, with its number of ordinances and mean value
+    fn export_group_by() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+        Connection::open(&db)
+            .unwrap()
+            .execute_batch(
+                "INSERT INTO quantitative (bookkeeper_lnk, county, state, FIPS, feature, value, units)
+                   VALUES (1, 'county-3', 'state-1', 33333, 'feature-1', 3.5, NULL),
+                          (1, 'county-3', 'state-1', 33333, 'feature-3', NULL, NULL),
+                          (1, 'county-4', 'state-1', 44444, 'feature-1', 500, 'feet');",
+            )
+            .unwrap();
+
+        let mut output = Vec::new();
+        let options = ExportOptions {
+            group_by: Some(vec!["STATE".to_string()]),
+            ..Default::default()
+        };
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &options,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        // Never averaged across units
+        assert_eq!(
+            lines,
+            [
+                "state,units,value_type,count,mean",
+                "state-1,feet,,1,500.0",
+                "state-1,,,3,2.5",
+                "state-2,,,1,2.5"
+            ]
+        );
+
+        // Combined with a filter
+        let mut output = Vec::new();
+        let options = ExportOptions {
+            group_by: Some(vec!["state".to_string(), "feature".to_string()]),
+            filter: OrdinanceFilter {
+                features: vec!["feature-1".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &options,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "state,feature,units,value_type,count,mean\nstate-1,feature-1,feet,,1,500.0\nstate-1,feature-1,,,2,2.5\n"
+        );
+
+        let options = ExportOptions {
+            group_by: Some(vec!["state; DROP TABLE bookkeeper".to_string()]),
+            ..Default::default()
+        };
+        let err = export(
+            &mut Vec::new(),
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &options,
+        )
+        .unwrap_err();
+        assert!(matches!(err, error::Error::UnknownColumn { .. }));
+    }
+
+    #[test]
+    /// A SQLite export is always a new file, and never aggregated
+    fn export_sqlite_refused() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+        let existing = tmp.path().join("existing.sqlite");
+        std::fs::write(&existing, "").unwrap();
+
+        let err = export_sqlite(
+            &open_readonly(&db).unwrap(),
+            &existing,
+            "wind",
+            &ExportOptions::default(),
+            false,
+        );
+        assert!(
+            matches!(err, Err(error::Error::IO(e)) if e.kind() == std::io::ErrorKind::AlreadyExists)
+        );
+
+        let options = ExportOptions {
+            group_by: Some(vec!["state".to_string()]),
+            ..Default::default()
+        };
+        let path = tmp.path().join("new.sqlite");
+        let err = export_sqlite(&open_readonly(&db).unwrap(), &path, "wind", &options, false);
+        assert!(matches!(err, Err(error::Error::UnsupportedFormat(_))));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    /// Rows read back by SQLite, and nothing left by a failed export.
+    /// Without the sqlite extension, such as offline, only the latter.
+    fn export_sqlite_rows() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+        let path = tmp.path().join("export.sqlite");
+        let leftovers = || {
+            std::fs::read_dir(tmp.path())
+                .unwrap()
+                .filter(|e| {
+                    let name = e.as_ref().unwrap().file_name();
+                    name.to_string_lossy().starts_with(".ordinancedb-export-")
+                })
+                .count()
+        };
+
+        let available = Connection::open_in_memory()
+            .unwrap()
+            .execute_batch("INSTALL sqlite; LOAD sqlite;");
+        if let Err(e) = available {
+            eprintln!("Skipping the SQLite export, the sqlite extension is unavailable: {e}");
+            let err = export_sqlite(
+                &open_readonly(&db).unwrap(),
+                &path,
+                "wind",
+                &ExportOptions::default(),
+                true,
+            );
+            assert!(err.is_err());
+            assert!(!path.exists());
+            assert_eq!(leftovers(), 0);
+            return;
+        }
+
+        // Failing once the export started
+        let options = ExportOptions {
+            filter: OrdinanceFilter {
+                commit_id: Some(99),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let err = export_sqlite(&open_readonly(&db).unwrap(), &path, "wind", &options, true);
+        assert!(matches!(err, Err(error::Error::UnknownCommit(99))));
+        assert!(!path.exists());
+        assert_eq!(leftovers(), 0);
+
+        let n = export_sqlite(
+            &open_readonly(&db).unwrap(),
+            &path,
+            "wind",
+            &ExportOptions::default(),
+            true,
+        )
+        .unwrap();
+        assert_eq!(n, 2);
+
+        // Read back as any SQLite consumer would
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(&format!(
+            "LOAD sqlite; ATTACH '{}' AS exported (TYPE SQLITE, READ_ONLY);",
+            path.display()
+        ))
+        .unwrap();
+        let count = |query: &str| -> usize { conn.query_row(query, [], |row| row.get(0)).unwrap() };
+        assert_eq!(count("SELECT COUNT(*) FROM exported.ordinance"), 2);
+        assert_eq!(
+            count("SELECT COUNT(*) FROM exported.ordinance WHERE FIPS = 11111"),
+            1
+        );
+        assert!(count("SELECT COUNT(*) FROM exported.jurisdiction") > 0);
+        assert_eq!(leftovers(), 0);
+    }
+
+    #[test]
+    /// Column names are refused, never interpolated in the query
+    fn export_malicious_column() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+
+        let mut output = Vec::new();
+        let options = ExportOptions {
+            columns: Some(vec!["state; DROP TABLE ordinance".to_string()]),
+            ..Default::default()
+        };
+        let err = export(
+            &mut output,
+            &open_readonly(&db).unwrap(),
+            "csv",
+            "wind",
+            &options,
+        )
+        .unwrap_err();
+        assert!(matches!(err, error::Error::UnknownColumn { .. }));
+
+        let conn = open_readonly(&db).unwrap();
+        let n: usize = conn
+            .query_row("SELECT COUNT(*) FROM ordinance", [], |row| row.get(0))
+            .unwrap();
+        assert!(n > 0);
+    }
+
+    #[test]
+    /// Only known columns can be used to sort
+    fn ordinance_query_order_by() {
+        let technology = Technology::Wind;
+        let filter = OrdinanceFilter::default();
+        let (query, params) =
+            ordinance_query("ordinance.FIPS", &technology, &filter, &["fips"]).unwrap();
+        assert!(query.ends_with(r#" ORDER BY ordinance."FIPS""#));
+        assert!(!query.contains("wind"));
+        assert_eq!(params.len(), 3);
+
+        let result = ordinance_query(
+            "ordinance.FIPS",
+            &technology,
+            &filter,
+            &["FIPS; DROP TABLE ordinance"],
+        );
+        assert!(matches!(result, Err(error::Error::UnknownColumn { .. })));
     }
 }
@@ -0,0 +1,260 @@
+//! Merge a database loaded on its own into another
+//!
+//! A database has a single writer, thus loading several scraper outputs
+//! at once into the same database would only queue them. Instead, each
+//! output can be loaded into a temporary database of its own, which is
+//! then merged into the target. The merge copies every record, with new
+//! ids drawn from the target's sequences, renumbering the links between
+//! records accordingly.
+
+use duckdb::OptionalExt;
+use tracing::{debug, trace};
+
+use crate::error::{Error, Result};
+
+/// Tables written by a load, with their columns linking to another table
+///
+/// Ordered such that every table comes after those it links to, as
+/// required by the foreign keys.
+const TABLES: [(&str, &[(&str, &str)]); 15] = [
+    ("bookkeeper", &[]),
+    ("archive", &[]),
+    ("scraper_metadata", &[("bookkeeper_lnk", "bookkeeper")]),
+    ("llm_config", &[("metadata_lnk", "scraper_metadata")]),
+    (
+        "source",
+        &[("bookkeeper_lnk", "bookkeeper"), ("archive_lnk", "archive")],
+    ),
+    ("usage_event", &[("bookkeeper_lnk", "bookkeeper")]),
+    ("usage_model", &[("usage_lnk", "usage_event")]),
+    ("usage_step", &[("model_lnk", "usage_model")]),
+    (
+        "usage_jurisdiction",
+        &[("usage_lnk", "usage_event"), ("jurisdiction_lnk", "source")],
+    ),
+    (
+        "quantitative",
+        &[("bookkeeper_lnk", "bookkeeper"), ("archive_lnk", "archive")],
+    ),
+    (
+        "qualitative",
+        &[("bookkeeper_lnk", "bookkeeper"), ("archive_lnk", "archive")],
+    ),
+    ("logs", &[("bookkeeper_lnk", "bookkeeper")]),
+    (
+        "log_cursor",
+        &[("bookkeeper_lnk", "bookkeeper"), ("pending_id", "logs")],
+    ),
+    ("data_quality", &[("bookkeeper_lnk", "bookkeeper")]),
+    ("raw_inputs", &[("bookkeeper_lnk", "bookkeeper")]),
+];
+
+/// Merge the database at `path` into the database of `conn`
+///
+/// Every commit of the database at `path` becomes a new commit of the
+/// target, with all its records. The merge is a single transaction, so
+/// a failure leaves the target untouched. The database at `path` is
+/// only read, and must have been initialized as an ordinance database,
+/// without any table unknown to the merge.
+///
+/// Returns the new commit ids (bookkeeper ids), in the order of the
+/// original ones.
+pub(crate) fn merge_db(
+    conn: &mut duckdb::Connection,
+    path: &std::path::Path,
+) -> Result<Vec<usize>> {
+    trace!("Merging {:?}", path);
+    conn.execute_batch(&format!(
+        "ATTACH '{}' AS worker (READ_ONLY);",
+        path.to_string_lossy().replace('\'', "''")
+    ))?;
+    let commits = copy_tables(conn);
+    conn.execute_batch("DETACH worker;")?;
+    commits
+}
+
+/// Copy all the tables of the attached `worker` database
+fn copy_tables(conn: &mut duckdb::Connection) -> Result<Vec<usize>> {
+    let tables = conn
+        .prepare("SELECT table_name FROM duckdb_tables() WHERE database_name = 'worker'")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+    if let Some(unknown) = tables
+        .iter()
+        .find(|t| *t != "ordinancedb_version" && !TABLES.iter().any(|(name, _)| name == t))
+    {
+        return Err(Error::Undefined(format!(
+            "Can't merge unknown table {unknown}"
+        )));
+    }
+
+    let tx = conn.transaction()?;
+    for (table, links) in TABLES {
+        // Ids are renumbered with the same default as a new record,
+        // i.e. the next value of the table's sequence in the target
+        let next_id: Option<String> = tx
+            .query_row(
+                "SELECT column_default FROM duckdb_columns()
+                  WHERE database_name = current_database()
+                    AND table_name = ? AND column_name = 'id'",
+                [table],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+        if let Some(next_id) = &next_id {
+            tx.execute_batch(&format!(
+                "CREATE OR REPLACE TEMP TABLE merge_{table} AS
+                  SELECT id AS old, {next_id} AS new
+                  FROM (SELECT id FROM worker.{table} ORDER BY id);"
+            ))?;
+        }
+
+        let columns = tx
+            .prepare(
+                "SELECT column_name FROM duckdb_columns()
+                  WHERE database_name = 'worker' AND table_name = ?
+                  ORDER BY column_index",
+            )?
+            .query_map([table], |row| row.get::<_, String>(0))?
+            .collect::<duckdb::Result<Vec<_>>>()?;
+        let values = columns
+            .iter()
+            .map(|column| {
+                let linked = if column == "id" && next_id.is_some() {
+                    Some(table)
+                } else {
+                    links
+                        .iter()
+                        .find(|(name, _)| name == column)
+                        .map(|(_, target)| *target)
+                };
+                match linked {
+                    Some(target) => {
+                        format!("(SELECT new FROM merge_{target} WHERE old = t.\"{column}\")")
+                    }
+                    None => format!("t.\"{column}\""),
+                }
+            })
+            .collect::<Vec<_>>();
+        let columns = columns
+            .iter()
+            .map(|column| format!("\"{column}\""))
+            .collect::<Vec<_>>();
+        let merged = tx.execute(
+            &format!(
+                "INSERT INTO {table} ({}) SELECT {} FROM worker.{table} t",
+                columns.join(", "),
+                values.join(", ")
+            ),
+            [],
+        )?;
+        debug!("Merged {} records into {}", merged, table);
+    }
+
+    let commits = tx
+        .prepare("SELECT new FROM merge_bookkeeper ORDER BY old")?
+        .query_map([], |row| row.get(0))?
+        .collect::<duckdb::Result<Vec<usize>>>()?;
+    for (table, _) in TABLES {
+        tx.execute_batch(&format!("DROP TABLE IF EXISTS temp.merge_{table};"))?;
+    }
+    tx.commit()?;
+    trace!("Merged as commits {:?}", commits);
+    Ok(commits)
+}
+
+#[cfg(test)]
+mod test_merge {
+    use super::*;
+    use crate::sample::empty_db;
+
+    #[tokio::test]
+    /// Merging a loaded output renumbers its records after the target's
+    async fn merge_loaded_output() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut target = empty_db(&tmp.path().join("target.db"));
+        target
+            .execute_batch(
+                "INSERT INTO bookkeeper (hash) VALUES ('first');
+                INSERT INTO archive (filename, checksum) VALUES ('first.pdf', 'sha256:first');
+                INSERT INTO source (bookkeeper_lnk, archive_lnk, full_name) VALUES (1, 1, 'First');",
+            )
+            .unwrap();
+
+        let worker = tmp.path().join("worker.db");
+        let mut conn = empty_db(&worker);
+        let output = tmp.path().join("output");
+        std::fs::create_dir(&output).unwrap();
+        crate::scraper::sample::as_dir(&output).unwrap();
+        conn.execute("INSERT INTO bookkeeper (hash) VALUES ('worker')", [])
+            .unwrap();
+        let demo = crate::scraper::ScrapedOrdinance::open(&output)
+            .await
+            .unwrap();
+        let tx = conn.transaction().unwrap();
+        demo.push(&tx, 1).await.unwrap();
+        tx.commit().unwrap();
+        drop(conn);
+
+        let commits = merge_db(&mut target, &worker).unwrap();
+        assert_eq!(commits, vec![2]);
+
+        let reader = target.try_clone().unwrap();
+        let count = |sql: &str| -> usize { reader.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(
+            count("SELECT COUNT(*) FROM quantitative WHERE bookkeeper_lnk = 2"),
+            2
+        );
+        assert_eq!(
+            count("SELECT COUNT(*) FROM source WHERE bookkeeper_lnk = 2"),
+            1
+        );
+        // Renumbered after the records of the target
+        assert_eq!(
+            count(
+                "SELECT COUNT(*) FROM archive WHERE id = 2 AND filename = 'sample_ordinance.pdf'"
+            ),
+            1
+        );
+        assert_eq!(
+            count("SELECT COUNT(*) FROM source WHERE id = 2 AND bookkeeper_lnk = 2"),
+            1
+        );
+        assert_eq!(
+            count(
+                "SELECT COUNT(*) FROM usage_model m JOIN usage_event e ON m.usage_lnk = e.id
+                  WHERE e.bookkeeper_lnk = 2"
+            ),
+            count("SELECT COUNT(*) FROM usage_model")
+        );
+        assert!(count("SELECT COUNT(*) FROM usage_model") > 0);
+
+        // Nothing left behind, and the worker database is still usable
+        assert_eq!(
+            count("SELECT COUNT(*) FROM duckdb_databases() WHERE database_name = 'worker'"),
+            0
+        );
+        merge_db(&mut target, &worker).unwrap();
+        assert_eq!(count("SELECT MAX(id) FROM bookkeeper"), 3);
+    }
+
+    #[test]
+    /// A table the merge doesn't know about is refused
+    fn unknown_table() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut target = empty_db(&tmp.path().join("target.db"));
+        let worker = tmp.path().join("worker.db");
+        let conn = empty_db(&worker);
+        conn.execute_batch("CREATE TABLE surprise (id INTEGER);")
+            .unwrap();
+        drop(conn);
+
+        let err = merge_db(&mut target, &worker).unwrap_err();
+        assert!(matches!(err, Error::Undefined(_)));
+        let commits: usize = target
+            .query_row("SELECT COUNT(*) FROM bookkeeper", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(commits, 0);
+    }
+}
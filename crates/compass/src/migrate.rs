@@ -0,0 +1,220 @@
+//! Upgrade an existing database to the current data model
+//!
+//! Each migration brings a database from one data model version to the
+//! next one, and runs in its own transaction, so a failure leaves the
+//! database at the last successful version. Migrations are applied in
+//! sequence until reaching `ORDINANCEDB_VERSION`.
+
+use duckdb::Connection;
+use tracing::{debug, trace};
+
+use crate::error::{Error, Result};
+
+/// A single step of the data model
+struct Migration {
+    /// Version that this migration upgrades from
+    from: &'static str,
+    /// Version after applying this migration
+    to: &'static str,
+    /// Changes to the data model
+    apply: fn(&duckdb::Transaction) -> Result<()>,
+}
+
+/// All the known migrations, in order
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: "0.0.1",
+        to: "0.0.2",
+        apply: crate::scraper::ScrapedOrdinance::migrate_0_0_2,
+    },
+    Migration {
+        from: "0.0.2",
+        to: "0.0.3",
+        apply: crate::scraper::ScrapedOrdinance::migrate_0_0_3,
+    },
+    Migration {
+        from: "0.0.3",
+        to: "0.0.4",
+        apply: crate::scraper::ScrapedOrdinance::migrate_0_0_4,
+    },
+    Migration {
+        from: "0.0.4",
+        to: "0.0.5",
+        apply: crate::scraper::ScrapedOrdinance::migrate_0_0_5,
+    },
+    Migration {
+        from: "0.0.5",
+        to: "0.0.6",
+        apply: crate::scraper::ScrapedOrdinance::migrate_0_0_6,
+    },
+    Migration {
+        from: "0.0.6",
+        to: "0.0.7",
+        apply: crate::scraper::ScrapedOrdinance::migrate_0_0_7,
+    },
+    Migration {
+        from: "0.0.7",
+        to: "0.0.8",
+        apply: crate::scraper::ScrapedOrdinance::migrate_0_0_8,
+    },
+    Migration {
+        from: "0.0.8",
+        to: "0.0.9",
+        apply: crate::scraper::ScrapedOrdinance::migrate_0_0_9,
+    },
+    Migration {
+        from: "0.0.9",
+        to: "0.0.10",
+        apply: crate::scraper::ScrapedOrdinance::migrate_0_0_10,
+    },
+];
+
+/// Migrate a database to the current data model version
+///
+/// Does nothing if the database is already at the current version. It
+/// fails if the stored version is unknown, such as for a database created
+/// by a newer version of this library.
+pub fn migrate(conn: &mut Connection) -> Result<()> {
+    trace!("Migrating database");
+
+    let mut version = crate::schema::version(conn)?
+        .ok_or_else(|| Error::UnsupportedVersion("unknown".to_string()))?;
+    debug!("Stored data model version: {}", version);
+
+    while version != crate::ORDINANCEDB_VERSION {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|m| m.from == version)
+            .ok_or_else(|| Error::UnsupportedVersion(version.clone()))?;
+        debug!("Migrating from {} to {}", migration.from, migration.to);
+
+        let tx = conn.transaction()?;
+        (migration.apply)(&tx)?;
+        tx.execute(
+            "INSERT INTO ordinancedb_version (version) VALUES (?)",
+            [migration.to],
+        )?;
+        tx.commit()?;
+        version = migration.to.to_string();
+    }
+
+    // Also recovers views of an interrupted migration
+    let tx = conn.transaction()?;
+    crate::scraper::ScrapedOrdinance::init_views(&tx)?;
+    tx.commit()?;
+
+    trace!("Database at data model version {}", version);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_migrate {
+    use super::*;
+    use crate::sample::empty_db;
+
+    /// Create a database with the 0.0.1 data model
+    fn v0_0_1_db(path: &std::path::Path) {
+        let conn = empty_db(path);
+        conn.execute_batch(
+            r"
+            DROP TABLE logs;
+            DROP TABLE log_cursor;
+            DROP TABLE data_quality;
+            DROP TABLE raw_inputs;
+            ALTER TABLE quantitative DROP COLUMN value_type;
+            ALTER TABLE quantitative DROP COLUMN value_meters;
+            ALTER TABLE quantitative DROP COLUMN mult_value;
+            ALTER TABLE quantitative DROP COLUMN mult_type;
+            ALTER TABLE quantitative DROP COLUMN archive_lnk;
+            ALTER TABLE qualitative DROP COLUMN archive_lnk;
+            DROP TABLE usage_jurisdiction;
+            DROP VIEW usage;
+            ALTER TABLE usage_step DROP COLUMN extra;
+            DROP VIEW ordinance;
+            CREATE VIEW ordinance AS
+              SELECT bookkeeper_lnk, FIPS, feature, NULL as feature_subtype,
+                value AS 'quantitative', NULL AS 'qualitative'
+              FROM quantitative
+              UNION
+                SELECT bookkeeper_lnk, FIPS, feature, NULL as feature_subtype,
+                  NULL AS 'quantitative', summary AS 'qualitative'
+                FROM qualitative;
+            UPDATE ordinancedb_version SET version = '0.0.1';
+            ",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn from_0_0_1() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        v0_0_1_db(&db);
+
+        migrate(&mut Connection::open(&db).unwrap()).unwrap();
+
+        let conn = Connection::open(&db).unwrap();
+        assert_eq!(
+            crate::schema::version(&conn).unwrap().as_deref(),
+            Some(crate::ORDINANCEDB_VERSION)
+        );
+        assert_eq!(crate::schema::version_warning(&conn).unwrap(), None);
+        for (table, column) in [
+            ("quantitative", "value_type"),
+            ("quantitative", "value_meters"),
+            ("quantitative", "mult_value"),
+            ("ordinance", "mult_type"),
+            ("usage", "extra"),
+            ("ordinance", "jurisdiction_type"),
+            ("ordinance", "units"),
+            ("ordinance", "value_type"),
+            ("logs", "message"),
+            ("data_quality", "component"),
+            ("raw_inputs", "content"),
+            ("qualitative", "archive_lnk"),
+            ("ordinance", "archive_lnk"),
+            ("usage_jurisdiction", "jurisdiction_lnk"),
+            ("log_cursor", "pending_id"),
+        ] {
+            let exists: bool = conn
+                .query_row(
+                    "SELECT COUNT(*) > 0 FROM information_schema.columns
+                      WHERE table_name = ? AND column_name = ?",
+                    [table, column],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert!(exists, "Missing column {table}.{column}");
+        }
+    }
+
+    #[test]
+    fn current_is_noop() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        empty_db(&db);
+
+        migrate(&mut Connection::open(&db).unwrap()).unwrap();
+
+        let conn = Connection::open(&db).unwrap();
+        let count: usize = conn
+            .query_row("SELECT COUNT(*) FROM ordinancedb_version", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn unknown_version() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        let conn = empty_db(&db);
+        conn.execute("UPDATE ordinancedb_version SET version = '9.9.9'", [])
+            .unwrap();
+        drop(conn);
+
+        let err = migrate(&mut Connection::open(&db).unwrap()).unwrap_err();
+        assert!(matches!(err, Error::UnsupportedVersion(v) if v == "9.9.9"));
+    }
+}
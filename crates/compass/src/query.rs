@@ -0,0 +1,740 @@
+//! Summaries of the ordinances stored in the database
+//!
+//! Quick answers for analysts, such as how many ordinances exist at each
+//! level of government, without writing SQL.
+
+use serde::Serialize;
+use tracing::trace;
+
+use crate::error::{Error, Result};
+
+/// Count ordinances by jurisdiction type, such as county or city
+///
+/// Ordinances without a jurisdiction type are counted as "unknown". The
+/// result is sorted by jurisdiction type.
+pub fn counts_by_jurisdiction_type(conn: &duckdb::Connection) -> Result<Vec<(String, usize)>> {
+    trace!("Counting ordinances by jurisdiction type");
+
+    let mut stmt = conn.prepare(
+        r"
+        SELECT COALESCE(jurisdiction_type, 'unknown') AS kind, COUNT(*)
+        FROM ordinance
+        GROUP BY kind
+        ORDER BY kind",
+    )?;
+    let counts = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+    trace!("Counts by jurisdiction type: {:?}", counts);
+
+    Ok(counts)
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+/// Distribution of the quantitative values of a feature
+pub struct FeatureStats {
+    /// Feature, such as "structures"
+    pub feature: String,
+    /// Units of the values, such as "feet"
+    pub units: Option<String>,
+    /// Type of the values, such as "fixed" or "multiplier"
+    pub value_type: Option<String>,
+    /// Number of ordinances with a value
+    pub count: usize,
+    /// Smallest value
+    pub min: f64,
+    /// Largest value
+    pub max: f64,
+    /// Average value
+    pub mean: f64,
+}
+
+/// Summarize the quantitative values of each feature
+///
+/// Ordinances without a value are ignored, as well as features without
+/// any value. Values in different units, or of a different type, are
+/// not comparable, thus summarized separately, one row for each. The
+/// result is sorted by feature, then units and type.
+pub fn feature_stats(conn: &duckdb::Connection) -> Result<Vec<FeatureStats>> {
+    trace!("Computing feature statistics");
+
+    let mut stmt = conn.prepare(
+        r"
+        SELECT feature, units, value_type, COUNT(value),
+          MIN(value)::DOUBLE, MAX(value)::DOUBLE, AVG(value::DOUBLE)
+        FROM quantitative
+        WHERE value IS NOT NULL
+        GROUP BY feature, units, value_type
+        ORDER BY feature, units, value_type",
+    )?;
+    let stats = stmt
+        .query_map([], |row| {
+            Ok(FeatureStats {
+                feature: row.get(0)?,
+                units: row.get(1)?,
+                value_type: row.get(2)?,
+                count: row.get(3)?,
+                min: row.get(4)?,
+                max: row.get(5)?,
+                mean: row.get(6)?,
+            })
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+    trace!("Statistics of {} features", stats.len());
+
+    Ok(stats)
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+/// How often a column of the ordinances is missing
+pub struct ColumnCompleteness {
+    /// Column of the ordinances, as in `ORDINANCE_COLUMNS`
+    pub column: &'static str,
+    /// Number of rows without a value
+    pub nulls: usize,
+    /// Percentage of the rows without a value, 0 without any rows
+    pub percent: f64,
+}
+
+/// Count the missing values of each column of the ordinances
+///
+/// Reports every column in `ORDINANCE_COLUMNS`, in that order. Some are
+/// expected to be partially missing, such as `quantitative` in the
+/// qualitative ordinances.
+pub fn completeness(conn: &duckdb::Connection) -> Result<Vec<ColumnCompleteness>> {
+    trace!("Counting missing values");
+
+    let nulls = crate::ORDINANCE_COLUMNS
+        .iter()
+        .map(|c| format!(r#"COUNT(*) - COUNT("{c}")"#))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let (total, nulls): (usize, Vec<usize>) = conn.query_row(
+        &format!("SELECT COUNT(*), {nulls} FROM ordinance"),
+        [],
+        |row| {
+            let nulls = (1..=crate::ORDINANCE_COLUMNS.len())
+                .map(|i| row.get(i))
+                .collect::<duckdb::Result<_>>()?;
+            Ok((row.get(0)?, nulls))
+        },
+    )?;
+    trace!("Missing values in {} rows: {:?}", total, nulls);
+
+    Ok(crate::ORDINANCE_COLUMNS
+        .iter()
+        .zip(nulls)
+        .map(|(&column, nulls)| ColumnCompleteness {
+            column,
+            nulls,
+            percent: match total {
+                0 => 0.0,
+                _ => 100.0 * nulls as f64 / total as f64,
+            },
+        })
+        .collect())
+}
+
+#[derive(Debug, Serialize)]
+/// An ERROR level record from the scraper runtime logs
+pub struct LoggedError {
+    /// Commit (bookkeeper id) of the load that included this record
+    pub commit_id: usize,
+    /// When it was logged, such as "2025-03-04 05:12:30,801"
+    pub timestamp: Option<String>,
+    /// Who logged it, typically the async task, such as "Task-3"
+    pub subject: Option<String>,
+    /// The message, possibly with multiple lines, such as a traceback
+    pub message: Option<String>,
+}
+
+/// Errors logged by the scraper, optionally of a single commit
+///
+/// This is the fast path to triage a failed scraping run. The result is
+/// in the order the records were loaded.
+pub fn errors(conn: &duckdb::Connection, commit_id: Option<usize>) -> Result<Vec<LoggedError>> {
+    trace!("Querying logged errors of commit {:?}", commit_id);
+
+    let mut stmt = conn.prepare(
+        r"
+        SELECT bookkeeper_lnk, timestamp, subject, message
+        FROM logs
+        WHERE level = 'ERROR' AND (?::INTEGER IS NULL OR bookkeeper_lnk = ?)
+        ORDER BY id",
+    )?;
+    let errors = stmt
+        .query_map(duckdb::params![commit_id, commit_id], |row| {
+            Ok(LoggedError {
+                commit_id: row.get(0)?,
+                timestamp: row.get(1)?,
+                subject: row.get(2)?,
+                message: row.get(3)?,
+            })
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+    trace!("Found {} logged errors", errors.len());
+
+    Ok(errors)
+}
+
+#[derive(Debug, Serialize)]
+/// A record of the scraper runtime logs, of any level
+struct LogRecord {
+    /// Commit (bookkeeper id) of the load that included this record
+    commit_id: usize,
+    /// When it was logged, such as "2025-03-04 05:12:30,801"
+    timestamp: Option<String>,
+    /// Level of the record, such as "INFO" or "ERROR"
+    level: Option<String>,
+    /// Who logged it, typically the async task, such as "Task-3"
+    subject: Option<String>,
+    /// The message, possibly with multiple lines
+    message: Option<String>,
+}
+
+/// Export the runtime logs, optionally of a single commit
+///
+/// Write all the log records, in the order they were loaded, as CSV with
+/// a header (`format` "csv") or as a JSON array of records (`format`
+/// "json").
+pub fn export_logs<W: std::io::Write>(
+    conn: &duckdb::Connection,
+    wtr: &mut W,
+    format: &str,
+    commit_id: Option<usize>,
+) -> Result<()> {
+    trace!("Exporting logs of commit {:?} as {:?}", commit_id, format);
+
+    if !matches!(format, "csv" | "json") {
+        return Err(Error::UnsupportedFormat(format.to_string()));
+    }
+
+    let mut stmt = conn.prepare(
+        r"
+        SELECT bookkeeper_lnk, timestamp, level, subject, message
+        FROM logs
+        WHERE ?::INTEGER IS NULL OR bookkeeper_lnk = ?
+        ORDER BY id",
+    )?;
+    let records = stmt
+        .query_map(duckdb::params![commit_id, commit_id], |row| {
+            Ok(LogRecord {
+                commit_id: row.get(0)?,
+                timestamp: row.get(1)?,
+                level: row.get(2)?,
+                subject: row.get(3)?,
+                message: row.get(4)?,
+            })
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+    trace!("Exporting {} log records", records.len());
+
+    write_records(wtr, format, &records)
+}
+
+/// Write records as CSV with a header (`format` "csv") or as a JSON
+/// array (`format` "json")
+fn write_records<W: std::io::Write, T: Serialize>(
+    wtr: &mut W,
+    format: &str,
+    records: &[T],
+) -> Result<()> {
+    match format {
+        "json" => {
+            serde_json::to_writer(&mut *wtr, records)?;
+            writeln!(wtr)?;
+        }
+        "csv" => {
+            let mut wtr = csv::Writer::from_writer(wtr);
+            for record in records {
+                wtr.serialize(record)?;
+            }
+            wtr.flush()?;
+        }
+        _ => return Err(Error::UnsupportedFormat(format.to_string())),
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+/// Usage of one step of the scraper, for one jurisdiction and model
+struct UsageRecord {
+    /// Commit (bookkeeper id) of the load that included this usage
+    commit_id: usize,
+    /// Jurisdiction, such as "Decatur County, Indiana"
+    jurisdiction: String,
+    /// Model used, such as "gpt-4.1-mini"
+    model: String,
+    /// Step of the scraper, such as "document_location_validation"
+    step: String,
+    requests: u64,
+    prompt_tokens: u64,
+    response_tokens: u64,
+}
+
+/// Export the usage, flattened as one record per step
+///
+/// Write the usage of each step, for each jurisdiction and model, in
+/// the order they were loaded, as CSV with a header (`format` "csv") or
+/// as a JSON array of records (`format` "json"). The totals per model
+/// are not included, since those are tracked independently of the
+/// steps.
+pub fn export_usage<W: std::io::Write>(
+    conn: &duckdb::Connection,
+    wtr: &mut W,
+    format: &str,
+) -> Result<()> {
+    trace!("Exporting usage as {:?}", format);
+
+    if !matches!(format, "csv" | "json") {
+        return Err(Error::UnsupportedFormat(format.to_string()));
+    }
+
+    let mut stmt = conn.prepare(
+        r"
+        SELECT bookkeeper_lnk, jurisdiction, model, step,
+          requests, prompt_tokens, response_tokens
+        FROM usage
+        ORDER BY usage_event_id, usage_model_id, usage_step_id",
+    )?;
+    let records = stmt
+        .query_map([], |row| {
+            Ok(UsageRecord {
+                commit_id: row.get(0)?,
+                jurisdiction: row.get(1)?,
+                model: row.get(2)?,
+                step: row.get(3)?,
+                requests: row.get(4)?,
+                prompt_tokens: row.get(5)?,
+                response_tokens: row.get(6)?,
+            })
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+    trace!("Exporting {} usage records", records.len());
+
+    write_records(wtr, format, &records)
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+/// Provenance of a single ordinance, from its commit to its document
+struct ProvenanceRecord {
+    /// Commit (bookkeeper id) of the load that included this ordinance
+    commit_id: usize,
+    /// Hash of the content of that load
+    hash: String,
+    /// When the load was done
+    created_at: String,
+    /// LLM model(s) of the run, such as "gpt-4.1-mini"
+    model: Option<String>,
+    /// Version of the scraper that produced the output
+    compass_version: Option<String>,
+    FIPS: Option<u64>,
+    county: Option<String>,
+    state: Option<String>,
+    feature: Option<String>,
+    quantitative: Option<f64>,
+    units: Option<String>,
+    qualitative: Option<String>,
+    ord_year: Option<u32>,
+    /// Filename of the document backing the ordinance, if linked
+    filename: Option<String>,
+    /// Where that document was retrieved from, such as a URL
+    document_source: Option<String>,
+    /// Checksum of that document, such as "sha256:..."
+    checksum: Option<String>,
+}
+
+/// Export the provenance of the ordinances
+///
+/// Each ordinance is tied to the commit that loaded it, with the hash,
+/// time, LLM model, and scraper version of that run, and to the document
+/// it was extracted from, with its filename, source, and checksum. The
+/// document is only known for the ordinances linked to one when loaded.
+/// Optionally, only the ordinances of a jurisdiction (`fips`).
+///
+/// Written as CSV with a header (`format` "csv") or as a JSON array of
+/// records (`format` "json").
+pub fn export_provenance<W: std::io::Write>(
+    conn: &duckdb::Connection,
+    wtr: &mut W,
+    format: &str,
+    fips: Option<u64>,
+) -> Result<()> {
+    trace!("Exporting provenance as {:?}, FIPS: {:?}", format, fips);
+
+    if !matches!(format, "csv" | "json") {
+        return Err(Error::UnsupportedFormat(format.to_string()));
+    }
+
+    let mut stmt = conn.prepare(
+        r"
+        SELECT o.bookkeeper_lnk, b.hash, CAST(b.created_at AS VARCHAR),
+          COALESCE(b.model, (
+            SELECT string_agg(DISTINCT l.name, ', ' ORDER BY l.name)
+            FROM scraper_metadata m JOIN llm_config l ON l.metadata_lnk = m.id
+            WHERE m.bookkeeper_lnk = b.id)),
+          (SELECT any_value(m.versions) FROM scraper_metadata m
+            WHERE m.bookkeeper_lnk = b.id),
+          o.FIPS, o.county, o.state, o.feature, o.quantitative, o.units,
+          o.qualitative, o.ord_year, a.filename, a.source, a.checksum
+        FROM ordinance o
+          JOIN bookkeeper b ON b.id = o.bookkeeper_lnk
+          LEFT JOIN archive a ON a.id = o.archive_lnk
+        WHERE (?::UBIGINT IS NULL OR o.FIPS = ?)
+        ORDER BY o.bookkeeper_lnk, o.FIPS, o.feature",
+    )?;
+    let records = stmt
+        .query_map(duckdb::params![fips, fips], |row| {
+            // The versions of every component of the run, as JSON
+            let versions: Option<String> = row.get(4)?;
+            let compass_version = versions
+                .and_then(|v| serde_json::from_str::<serde_json::Value>(&v).ok())
+                .and_then(|v| v.get("compass")?.as_str().map(String::from));
+            Ok(ProvenanceRecord {
+                commit_id: row.get(0)?,
+                hash: row.get(1)?,
+                created_at: row.get(2)?,
+                model: row.get(3)?,
+                compass_version,
+                FIPS: row.get(5)?,
+                county: row.get(6)?,
+                state: row.get(7)?,
+                feature: row.get(8)?,
+                quantitative: row.get(9)?,
+                units: row.get(10)?,
+                qualitative: row.get(11)?,
+                ord_year: row.get(12)?,
+                filename: row.get(13)?,
+                document_source: row.get(14)?,
+                checksum: row.get(15)?,
+            })
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+    trace!("Exporting {} provenance records", records.len());
+
+    write_records(wtr, format, &records)
+}
+
+/// Effective distance of a setback given as a multiplier
+///
+/// A `mult_type` such as "hub-height" is a multiple of the turbine
+/// height, thus 1.1 is 110% of it. A percentage type, such as
+/// "hub-height-percent" or "% tip-height", takes `mult_value` as a
+/// percentage instead, thus 110 is also 110% of it. The `turbine_height`
+/// must be the height the multiplier refers to, such as the hub height,
+/// and the result is in the same units.
+pub fn resolve_setback(mult_value: f64, mult_type: &str, turbine_height: f64) -> f64 {
+    let mult_type = mult_type.to_lowercase();
+    if mult_type.contains("percent") || mult_type.contains('%') {
+        mult_value / 100.0 * turbine_height
+    } else {
+        mult_value * turbine_height
+    }
+}
+
+#[cfg(test)]
+mod test_query {
+    use super::*;
+    use crate::sample::empty_db;
+
+    #[test]
+    fn counts_by_type() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        let conn = empty_db(&db);
+        conn.execute_batch(
+            r"
+            INSERT INTO bookkeeper (hash, username) VALUES ('sample', 'test');
+            INSERT INTO quantitative (bookkeeper_lnk, jurisdiction_type, FIPS, feature, value)
+              VALUES (1, 'county', 11111, 'structures', 500),
+                     (1, 'county', 11111, 'roads', 1.1),
+                     (1, 'city', 22222, 'structures', 300),
+                     (1, NULL, 33333, 'structures', 200);
+            INSERT INTO qualitative (bookkeeper_lnk, jurisdiction_type, FIPS, feature, summary)
+              VALUES (1, 'county', 11111, 'color', 'white');
+            ",
+        )
+        .unwrap();
+
+        let counts = counts_by_jurisdiction_type(&conn).unwrap();
+        assert_eq!(
+            counts,
+            [
+                ("city".to_string(), 1),
+                ("county".to_string(), 3),
+                ("unknown".to_string(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn logged_errors() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        let conn = empty_db(&db);
+        conn.execute_batch(
+            r"
+            INSERT INTO bookkeeper (hash, username) VALUES ('first', 'test'), ('second', 'test');
+            INSERT INTO logs (bookkeeper_lnk, timestamp, level, subject, message)
+              VALUES (1, '2025-03-04 05:10:52,266', 'INFO', 'Task-1', 'Running COMPASS'),
+                     (1, '2025-03-04 05:12:30,801', 'ERROR', 'Task-3', 'Failed to process'),
+                     (2, '2025-03-05 05:12:30,801', 'ERROR', 'Task-2', 'Failed again');
+            ",
+        )
+        .unwrap();
+        let conn = crate::open_readonly(&db).unwrap();
+
+        let all = errors(&conn, None).unwrap();
+        assert_eq!(all.len(), 2);
+
+        let first = errors(&conn, Some(1)).unwrap();
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].commit_id, 1);
+        assert_eq!(first[0].subject.as_deref(), Some("Task-3"));
+        assert_eq!(first[0].message.as_deref(), Some("Failed to process"));
+    }
+
+    #[test]
+    /// Every ingested log record is exported
+    fn export_logs_rows() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        let output = tmp.path().join("output");
+        std::fs::create_dir(&output).unwrap();
+        crate::scraper::sample::as_dir(&output).unwrap();
+        crate::load_ordinance(empty_db(&db), "test", &output, &Default::default()).unwrap();
+
+        let ingested: usize = duckdb::Connection::open(&db)
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM logs", [], |row| row.get(0))
+            .unwrap();
+        assert!(ingested > 0);
+        let conn = crate::open_readonly(&db).unwrap();
+
+        let mut output = Vec::new();
+        export_logs(&conn, &mut output, "csv", None).unwrap();
+        let mut rdr = csv::Reader::from_reader(output.as_slice());
+        assert_eq!(
+            rdr.headers().unwrap(),
+            vec!["commit_id", "timestamp", "level", "subject", "message"]
+        );
+        assert_eq!(rdr.records().count(), ingested);
+
+        let mut output = Vec::new();
+        export_logs(&conn, &mut output, "json", Some(1)).unwrap();
+        let records: Vec<serde_json::Value> = serde_json::from_slice(&output).unwrap();
+        assert_eq!(records.len(), ingested);
+
+        let mut output = Vec::new();
+        export_logs(&conn, &mut output, "csv", Some(2)).unwrap();
+        assert_eq!(
+            csv::Reader::from_reader(output.as_slice())
+                .records()
+                .count(),
+            0
+        );
+
+        let err = export_logs(&conn, &mut Vec::new(), "xml", None).unwrap_err();
+        assert!(matches!(err, Error::UnsupportedFormat(_)));
+    }
+
+    #[test]
+    /// One usage record per step, for each jurisdiction and model
+    fn export_usage_rows() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        let output = tmp.path().join("output");
+        std::fs::create_dir(&output).unwrap();
+        crate::scraper::sample::as_dir(&output).unwrap();
+        crate::load_ordinance(empty_db(&db), "test", &output, &Default::default()).unwrap();
+        let conn = crate::open_readonly(&db).unwrap();
+
+        let mut output = Vec::new();
+        export_usage(&conn, &mut output, "csv").unwrap();
+        let mut rdr = csv::Reader::from_reader(output.as_slice());
+        assert_eq!(
+            rdr.headers().unwrap(),
+            vec![
+                "commit_id",
+                "jurisdiction",
+                "model",
+                "step",
+                "requests",
+                "prompt_tokens",
+                "response_tokens"
+            ]
+        );
+        let records: Vec<csv::StringRecord> = rdr.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        let location = records
+            .iter()
+            .find(|r| &r[3] == "document_location_validation")
+            .unwrap();
+        assert_eq!(&location[1], "Decatur County, Indiana");
+        assert_eq!(&location[2], "gpt-4.1-mini");
+        assert_eq!(&location[4], "55");
+        assert_eq!(&location[5], "114614");
+
+        let mut output = Vec::new();
+        export_usage(&conn, &mut output, "json").unwrap();
+        let records: Vec<serde_json::Value> = serde_json::from_slice(&output).unwrap();
+        assert_eq!(records.len(), 2);
+
+        let err = export_usage(&conn, &mut Vec::new(), "xml").unwrap_err();
+        assert!(matches!(err, Error::UnsupportedFormat(_)));
+    }
+
+    #[test]
+    /// An ordinance value is tied to the checksum of its document
+    fn export_provenance_rows() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        let output = tmp.path().join("output");
+        std::fs::create_dir(&output).unwrap();
+        crate::scraper::sample::as_dir(&output).unwrap();
+        let path = output.join("quantitative_ordinances.csv");
+        let csv = std::fs::read_to_string(&path).unwrap()
+            + "county-3,state-3,,county,33333,feature-3,150,feet,,,,,2003,,sample_ordinance.pdf\n";
+        std::fs::write(&path, csv).unwrap();
+        crate::load_ordinance(empty_db(&db), "test", &output, &Default::default()).unwrap();
+        let checksum: String = duckdb::Connection::open(&db)
+            .unwrap()
+            .query_row("SELECT checksum FROM archive", [], |row| row.get(0))
+            .unwrap();
+        let conn = crate::open_readonly(&db).unwrap();
+
+        let mut output = Vec::new();
+        export_provenance(&conn, &mut output, "json", Some(33333)).unwrap();
+        let records: Vec<serde_json::Value> = serde_json::from_slice(&output).unwrap();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record["commit_id"], 1);
+        assert_eq!(record["feature"], "feature-3");
+        assert_eq!(record["quantitative"], 150.0);
+        assert_eq!(record["filename"], "sample_ordinance.pdf");
+        assert_eq!(record["checksum"], checksum.as_str());
+        assert_eq!(record["compass_version"], "0.1.1.dev17+gb569353.d20250304");
+        assert!(record["model"].as_str().is_some());
+
+        // Every ordinance, with or without a document
+        let mut output = Vec::new();
+        export_provenance(&conn, &mut output, "csv", None).unwrap();
+        let mut rdr = csv::Reader::from_reader(output.as_slice());
+        assert_eq!(&rdr.headers().unwrap()[15], "checksum");
+        let records: Vec<csv::StringRecord> = rdr.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 3 + 2);
+        assert!(records.iter().any(|r| r[15].is_empty()));
+
+        let err = export_provenance(&conn, &mut Vec::new(), "xml", None).unwrap_err();
+        assert!(matches!(err, Error::UnsupportedFormat(_)));
+    }
+
+    #[test]
+    /// A "1.1x hub height" rule, as a multiple and as a percentage
+    fn setback_multiplier() {
+        assert!((resolve_setback(1.1, "hub-height", 100.0) - 110.0).abs() < 1e-9);
+        assert!((resolve_setback(110.0, "hub-height-percent", 100.0) - 110.0).abs() < 1e-9);
+        assert!((resolve_setback(110.0, "% hub height", 100.0) - 110.0).abs() < 1e-9);
+        assert_eq!(resolve_setback(3.0, "tip-height", 150.0), 450.0);
+    }
+
+    #[test]
+    fn stats_by_feature() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        let conn = empty_db(&db);
+        conn.execute_batch(
+            r"
+            INSERT INTO bookkeeper (hash, username) VALUES ('sample', 'test');
+            INSERT INTO quantitative (bookkeeper_lnk, feature, value, units)
+              VALUES (1, 'structures', 500, 'feet'), (1, 'structures', 1000, 'feet'),
+                     (1, 'structures', 1500, 'feet'), (1, 'structures', NULL, 'feet'),
+                     (1, 'structures', 150, 'meters'),
+                     (1, 'roads', 1.5, NULL), (1, 'lighting', NULL, NULL);
+            ",
+        )
+        .unwrap();
+
+        let stats = feature_stats(&conn).unwrap();
+        assert_eq!(
+            stats,
+            [
+                FeatureStats {
+                    feature: "roads".to_string(),
+                    units: None,
+                    value_type: None,
+                    count: 1,
+                    min: 1.5,
+                    max: 1.5,
+                    mean: 1.5,
+                },
+                FeatureStats {
+                    feature: "structures".to_string(),
+                    units: Some("feet".to_string()),
+                    value_type: None,
+                    count: 3,
+                    min: 500.0,
+                    max: 1500.0,
+                    mean: 1000.0,
+                },
+                FeatureStats {
+                    feature: "structures".to_string(),
+                    units: Some("meters".to_string()),
+                    value_type: None,
+                    count: 1,
+                    min: 150.0,
+                    max: 150.0,
+                    mean: 150.0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_values() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        let conn = empty_db(&db);
+        conn.execute_batch(
+            r"
+            INSERT INTO bookkeeper (hash, username) VALUES ('sample', 'test');
+            INSERT INTO quantitative (bookkeeper_lnk, county, feature, value, units, source)
+              VALUES (1, 'county-1', 'structures', 500, 'feet', 'source-1'),
+                     (1, 'county-2', 'roads', 1.5, NULL, 'source-2'),
+                     (1, 'county-3', 'lighting', NULL, NULL, NULL);
+            INSERT INTO qualitative (bookkeeper_lnk, county, feature, summary, source)
+              VALUES (1, NULL, 'color', 'white', 'source-3');
+            ",
+        )
+        .unwrap();
+
+        let completeness = completeness(&conn).unwrap();
+        assert_eq!(completeness.len(), crate::ORDINANCE_COLUMNS.len());
+        let missing = |column| {
+            let c = completeness.iter().find(|c| c.column == column).unwrap();
+            (c.nulls, c.percent)
+        };
+        assert_eq!(missing("county"), (1, 25.0));
+        assert_eq!(missing("feature"), (0, 0.0));
+        assert_eq!(missing("quantitative"), (2, 50.0));
+        assert_eq!(missing("units"), (3, 75.0));
+        assert_eq!(missing("source"), (1, 25.0));
+        assert_eq!(missing("qualitative"), (3, 75.0));
+        assert_eq!(missing("FIPS"), (4, 100.0));
+    }
+
+    #[test]
+    fn completeness_empty() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        let conn = empty_db(&db);
+
+        let completeness = completeness(&conn).unwrap();
+        assert!(
+            completeness
+                .iter()
+                .all(|c| c.nulls == 0 && c.percent == 0.0)
+        );
+    }
+}
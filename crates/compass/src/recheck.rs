@@ -0,0 +1,180 @@
+//! Verify the archived documents against their stored checksums
+//!
+//! Archived documents might be re-fetched over time, so their content
+//! could drift from the checksum recorded in the `archive` table when
+//! they were loaded. This module recomputes the checksums of a directory
+//! of documents and compares them with the stored ones.
+
+use duckdb::Connection;
+use serde::Serialize;
+use tracing::{debug, trace};
+
+use crate::error::Result;
+
+#[derive(Debug, PartialEq, Serialize)]
+/// A document whose content doesn't match its stored checksum
+pub struct ChecksumMismatch {
+    /// Filename of the document, as in the `archive` table
+    pub filename: String,
+    /// Checksum stored in the database
+    pub stored: String,
+    /// Checksum of the current file
+    pub actual: String,
+}
+
+impl std::fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: stored {}, actual {}",
+            self.filename, self.stored, self.actual
+        )
+    }
+}
+
+/// Recompute the checksum of each document in `dir` and compare
+///
+/// Files are matched to the `archive` by filename, and files unknown to
+/// the database are ignored. With `fix`, the stored checksums of the
+/// mismatched documents are updated to the actual ones.
+///
+/// # Returns
+///
+/// The mismatches found, sorted by filename.
+pub fn recheck<P: AsRef<std::path::Path>>(
+    conn: &mut Connection,
+    dir: P,
+    fix: bool,
+) -> Result<Vec<ChecksumMismatch>> {
+    trace!("Rechecking documents in {:?}", dir.as_ref());
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    let mut mismatches = Vec::new();
+    {
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT checksum FROM archive WHERE filename = ? AND checksum IS NOT NULL",
+        )?;
+        for entry in std::fs::read_dir(dir.as_ref())? {
+            let path = entry?.path();
+            if !path.is_file() {
+                continue;
+            }
+            let Some(filename) = path.file_name().and_then(|f| f.to_str()) else {
+                continue;
+            };
+
+            let stored = stmt
+                .query_map([filename], |row| row.get::<_, String>(0))?
+                .collect::<duckdb::Result<Vec<_>>>()?;
+            if stored.is_empty() {
+                trace!("Ignoring file unknown to the archive: {:?}", path);
+                continue;
+            }
+
+            let actual =
+                runtime.block_on(crate::scraper::checksum_file(&path, crate::IO_BUFFER_SIZE))?;
+            for stored in stored.into_iter().filter(|s| s != &actual) {
+                debug!("Checksum mismatch for {:?}", path);
+                mismatches.push(ChecksumMismatch {
+                    filename: filename.to_string(),
+                    stored,
+                    actual: actual.clone(),
+                });
+            }
+        }
+    }
+    mismatches.sort_by(|a, b| a.filename.cmp(&b.filename));
+
+    if fix && !mismatches.is_empty() {
+        let tx = conn.transaction()?;
+        for m in &mismatches {
+            tx.execute(
+                "UPDATE archive SET checksum = ? WHERE filename = ? AND checksum = ?",
+                [&m.actual, &m.filename, &m.stored],
+            )?;
+        }
+        tx.commit()?;
+        debug!("Fixed {} checksums", mismatches.len());
+    }
+
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod test_recheck {
+    use super::*;
+    use crate::sample::empty_db;
+
+    /// Database with two archived documents and their files
+    fn sample(dir: &std::path::Path) -> String {
+        let files = dir.join("ordinance_files");
+        std::fs::create_dir(&files).unwrap();
+        std::fs::write(files.join("a.pdf"), "original a").unwrap();
+        std::fs::write(files.join("b.pdf"), "original b").unwrap();
+        std::fs::write(files.join("unknown.pdf"), "not archived").unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let db = dir.join("test.db");
+        let conn = empty_db(&db);
+        for name in ["a.pdf", "b.pdf"] {
+            let checksum = runtime
+                .block_on(crate::scraper::checksum_file(
+                    files.join(name),
+                    crate::IO_BUFFER_SIZE,
+                ))
+                .unwrap();
+            conn.execute(
+                "INSERT INTO archive (filename, checksum) VALUES (?, ?)",
+                [name, &checksum],
+            )
+            .unwrap();
+        }
+        db.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn untouched() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample(tmp.path());
+
+        let mismatches = recheck(
+            &mut Connection::open(&db).unwrap(),
+            tmp.path().join("ordinance_files"),
+            false,
+        )
+        .unwrap();
+        assert!(mismatches.is_empty());
+    }
+
+    #[test]
+    fn tampered() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample(tmp.path());
+        let files = tmp.path().join("ordinance_files");
+        std::fs::write(files.join("b.pdf"), "tampered b").unwrap();
+
+        let mismatches = recheck(&mut Connection::open(&db).unwrap(), &files, false).unwrap();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].filename, "b.pdf");
+        assert_ne!(mismatches[0].stored, mismatches[0].actual);
+        // Not fixed, so it is reported again
+        assert_eq!(
+            recheck(&mut Connection::open(&db).unwrap(), &files, false).unwrap(),
+            mismatches
+        );
+
+        let fixed = recheck(&mut Connection::open(&db).unwrap(), &files, true).unwrap();
+        assert_eq!(fixed, mismatches);
+        assert!(
+            recheck(&mut Connection::open(&db).unwrap(), &files, false)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,92 @@
+//! Rename a feature of the ordinances
+//!
+//! The feature names used by the scraper drift over time, such as
+//! "structure setback" versus "setback from structures". Renaming one
+//! after the fact normalizes the vocabulary across all the commits, thus
+//! a query by feature finds them all under the same name.
+
+use duckdb::Connection;
+use tracing::{debug, trace};
+
+use crate::error::Result;
+
+/// Rename the feature `old` to `new` in all the ordinances
+///
+/// Both the quantitative and the qualitative ordinances are updated in a
+/// single transaction. Renaming into a feature that already exists merges
+/// both. Returns the number of rows changed.
+pub fn rename_feature(conn: &mut Connection, old: &str, new: &str) -> Result<usize> {
+    trace!("Renaming feature {:?} to {:?}", old, new);
+
+    let tx = conn.transaction()?;
+    let mut renamed = 0;
+    for table in ["quantitative", "qualitative"] {
+        let n = tx.execute(
+            &format!("UPDATE {table} SET feature = ? WHERE feature = ?"),
+            [new, old],
+        )?;
+        debug!("Renamed {} rows in {}", n, table);
+        renamed += n;
+    }
+    tx.commit()?;
+
+    trace!("Renamed feature {:?} in {} rows", old, renamed);
+    Ok(renamed)
+}
+
+#[cfg(test)]
+mod test_rename {
+    use super::*;
+    use crate::sample::empty_db;
+
+    #[test]
+    fn rename() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        let conn = empty_db(&db);
+        conn.execute_batch(
+            r"
+            INSERT INTO bookkeeper (hash, username) VALUES ('sample', 'test');
+            INSERT INTO quantitative (bookkeeper_lnk, county, state, FIPS, feature, value)
+              VALUES (1, 'Jefferson County', 'Colorado', 8059, 'structure setback', 500),
+                     (1, 'Decatur County', 'Indiana', 18031, 'structure setback', 300),
+                     (1, 'Boulder County', 'Colorado', 8013, 'roads', 200);
+            INSERT INTO qualitative (bookkeeper_lnk, county, state, FIPS, feature, summary)
+              VALUES (1, 'Jefferson County', 'Colorado', 8059, 'structure setback', 'white');
+            ",
+        )
+        .unwrap();
+        drop(conn);
+
+        let renamed = rename_feature(
+            &mut Connection::open(&db).unwrap(),
+            "structure setback",
+            "setback from structures",
+        )
+        .unwrap();
+        assert_eq!(renamed, 3);
+
+        let conn = Connection::open(&db).unwrap();
+        let count = |feature: &str| -> usize {
+            conn.query_row(
+                "SELECT COUNT(*) FROM ordinance WHERE feature = ?",
+                [feature],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(count("setback from structures"), 3);
+        assert_eq!(count("structure setback"), 0);
+        assert_eq!(count("roads"), 1);
+        drop(conn);
+
+        // Nothing left to rename
+        let renamed = rename_feature(
+            &mut Connection::open(&db).unwrap(),
+            "structure setback",
+            "anything",
+        )
+        .unwrap();
+        assert_eq!(renamed, 0);
+    }
+}
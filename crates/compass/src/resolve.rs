@@ -0,0 +1,229 @@
+//! Resolve the missing FIPS codes of the ordinances
+//!
+//! Some ordinance rows arrive with the county and state names, but
+//! without a FIPS code, or with a FIPS of 0. Those are filled in by
+//! matching the county and state against a lookup of known jurisdictions,
+//! which is built from the sources already in the database, optionally
+//! extended with a county lookup file.
+
+use duckdb::Connection;
+use serde::Serialize;
+use tracing::{debug, trace};
+
+use crate::error::Result;
+use crate::find::{NORMALIZED_COUNTY, normalize_county};
+
+#[allow(non_snake_case)]
+#[derive(Debug, serde::Deserialize)]
+/// A row of a county lookup file
+struct LookupRecord {
+    county: String,
+    state: String,
+    #[serde(alias = "fips")]
+    FIPS: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+/// A jurisdiction whose FIPS code could not be resolved
+pub struct UnresolvedFips {
+    /// County as stored, such as "Jefferson County"
+    pub county: Option<String>,
+    /// State as stored, such as "Colorado"
+    pub state: Option<String>,
+    /// Number of ordinance rows of this jurisdiction
+    pub rows: usize,
+}
+
+impl std::fmt::Display for UnresolvedFips {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}, {}: {} rows",
+            self.county.as_deref().unwrap_or("?"),
+            self.state.as_deref().unwrap_or("?"),
+            self.rows
+        )
+    }
+}
+
+#[derive(Debug, Serialize)]
+/// Outcome of resolving the missing FIPS codes
+pub struct FipsResolution {
+    /// Number of ordinance rows that got a FIPS code
+    pub resolved: usize,
+    /// Jurisdictions still without a FIPS code
+    pub unresolved: Vec<UnresolvedFips>,
+}
+
+/// Fill in the missing FIPS codes of the ordinances
+///
+/// Ordinance rows without a FIPS code, or with a FIPS of 0, are matched
+/// by county and state, case insensitive and ignoring a "County" or "Co."
+/// suffix, against the jurisdictions in the sources. A `lookup` CSV file,
+/// with the columns `county`, `state`, and `FIPS`, such as the Census
+/// county list, extends those. A name matching more than one FIPS code is
+/// ambiguous, thus left unresolved.
+pub fn resolve_fips(
+    conn: &mut Connection,
+    lookup: Option<&std::path::Path>,
+) -> Result<FipsResolution> {
+    trace!("Resolving missing FIPS codes");
+
+    let tx = conn.transaction()?;
+    tx.execute_batch(&format!(
+        r"
+        CREATE TEMP TABLE fips_lookup AS
+          SELECT DISTINCT {NORMALIZED_COUNTY} AS norm_county,
+            lower(trim(state)) AS norm_state, fips
+          FROM source
+          WHERE county IS NOT NULL AND state IS NOT NULL AND fips > 0;"
+    ))?;
+
+    if let Some(path) = lookup {
+        debug!("Using county lookup {:?}", path);
+        let mut stmt = tx.prepare("INSERT INTO fips_lookup VALUES (?, ?, ?)")?;
+        for record in csv::Reader::from_path(path)?.deserialize() {
+            let record: LookupRecord = record?;
+            stmt.execute(duckdb::params![
+                normalize_county(&record.county),
+                record.state.trim().to_lowercase(),
+                record.FIPS
+            ])?;
+        }
+    }
+
+    let mut resolved = 0;
+    for table in ["quantitative", "qualitative"] {
+        let n = tx.execute(
+            &format!(
+                r"
+                UPDATE {table} SET FIPS = l.fips
+                FROM (
+                  SELECT norm_county, norm_state, any_value(fips) AS fips
+                  FROM fips_lookup
+                  GROUP BY norm_county, norm_state
+                  HAVING COUNT(DISTINCT fips) = 1
+                ) l
+                WHERE ({table}.FIPS IS NULL OR {table}.FIPS = 0)
+                  AND {NORMALIZED_COUNTY} = l.norm_county
+                  AND lower(trim(state)) = l.norm_state"
+            ),
+            [],
+        )?;
+        debug!("Resolved {} FIPS codes in {}", n, table);
+        resolved += n;
+    }
+
+    let mut stmt = tx.prepare(
+        r"
+        SELECT county, state, COUNT(*)
+        FROM (
+          SELECT county, state, FIPS FROM quantitative
+          UNION ALL
+          SELECT county, state, FIPS FROM qualitative
+        )
+        WHERE FIPS IS NULL OR FIPS = 0
+        GROUP BY county, state
+        ORDER BY state, county",
+    )?;
+    let unresolved = stmt
+        .query_map([], |row| {
+            Ok(UnresolvedFips {
+                county: row.get(0)?,
+                state: row.get(1)?,
+                rows: row.get(2)?,
+            })
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+    drop(stmt);
+
+    tx.execute_batch("DROP TABLE fips_lookup;")?;
+    tx.commit()?;
+    trace!(
+        "Resolved {} FIPS codes, {} jurisdictions unresolved",
+        resolved,
+        unresolved.len()
+    );
+
+    Ok(FipsResolution {
+        resolved,
+        unresolved,
+    })
+}
+
+#[cfg(test)]
+mod test_resolve {
+    use super::*;
+    use crate::sample::empty_db;
+
+    /// Database with ordinances missing their FIPS codes
+    fn sample(path: &std::path::Path) {
+        let conn = empty_db(path);
+        conn.execute_batch(
+            r"
+            INSERT INTO bookkeeper (hash, username) VALUES ('sample', 'test');
+            INSERT INTO source (bookkeeper_lnk, county, state, fips, found)
+              VALUES (1, 'Jefferson County', 'Colorado', 8059, true);
+            INSERT INTO quantitative (bookkeeper_lnk, county, state, FIPS, feature, value)
+              VALUES (1, 'Jefferson', 'colorado', NULL, 'structures', 500),
+                     (1, 'Jefferson County', 'Colorado', 0, 'roads', 1.5),
+                     (1, 'Decatur County', 'Indiana', NULL, 'structures', 300),
+                     (1, 'Boulder County', 'Colorado', 8013, 'structures', 200);
+            INSERT INTO qualitative (bookkeeper_lnk, county, state, FIPS, feature, summary)
+              VALUES (1, 'Jefferson County', 'Colorado', NULL, 'color', 'white');
+            ",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn from_sources() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        sample(&db);
+
+        let resolution = resolve_fips(&mut Connection::open(&db).unwrap(), None).unwrap();
+        assert_eq!(resolution.resolved, 3);
+        assert_eq!(
+            resolution.unresolved,
+            [UnresolvedFips {
+                county: Some("Decatur County".to_string()),
+                state: Some("Indiana".to_string()),
+                rows: 1,
+            }]
+        );
+
+        let conn = Connection::open(&db).unwrap();
+        let jefferson: usize = conn
+            .query_row(
+                "SELECT COUNT(*) FROM ordinance WHERE FIPS = 8059",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(jefferson, 3);
+    }
+
+    #[test]
+    fn from_lookup_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        sample(&db);
+        let lookup = tmp.path().join("counties.csv");
+        std::fs::write(&lookup, "county,state,FIPS\nDecatur,Indiana,18031\n").unwrap();
+
+        let resolution = resolve_fips(&mut Connection::open(&db).unwrap(), Some(&lookup)).unwrap();
+        assert_eq!(resolution.resolved, 4);
+        assert!(resolution.unresolved.is_empty());
+
+        let conn = Connection::open(&db).unwrap();
+        let fips: u64 = conn
+            .query_row(
+                "SELECT FIPS FROM quantitative WHERE county = 'Decatur County'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(fips, 18031);
+    }
+}
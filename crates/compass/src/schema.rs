@@ -0,0 +1,221 @@
+//! Describe the schema of an existing database
+//!
+//! The data model is still evolving, so a given database file might not
+//! match what the current version of this library would create. This
+//! module inspects what is actually stored in a database.
+
+use duckdb::Connection;
+use serde::Serialize;
+use tracing::trace;
+
+use crate::error::Result;
+
+#[derive(Debug, Serialize)]
+/// Schema of an ordinance database
+pub struct Schema {
+    /// Stored version of the ordinance data model, if any
+    pub version: Option<String>,
+    /// Tables and views available
+    pub tables: Vec<Table>,
+}
+
+#[derive(Debug, Serialize)]
+/// A table, or view, of the database
+pub struct Table {
+    /// Name of the table
+    pub name: String,
+    /// Type of the table, such as "BASE TABLE" or "VIEW"
+    pub kind: String,
+    /// Columns of the table, in order
+    pub columns: Vec<Column>,
+}
+
+#[derive(Debug, Serialize)]
+/// A column of a table
+pub struct Column {
+    /// Name of the column
+    pub name: String,
+    /// Data type, such as "INTEGER" or "VARCHAR"
+    pub data_type: String,
+    /// Whether the column accepts NULL values
+    pub nullable: bool,
+}
+
+impl std::fmt::Display for Schema {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(
+            f,
+            "ordinancedb_version: {}",
+            self.version.as_deref().unwrap_or("unknown")
+        )?;
+        for table in &self.tables {
+            writeln!(f)?;
+            writeln!(f, "{} ({})", table.name, table.kind)?;
+            for column in &table.columns {
+                let null = if column.nullable { "" } else { " NOT NULL" };
+                writeln!(f, "  {} {}{}", column.name, column.data_type, null)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Inspect the schema of a database
+pub fn schema(conn: &Connection) -> Result<Schema> {
+    trace!("Inspecting schema of database");
+
+    let version = version(conn)?;
+    trace!("Stored data model version: {:?}", version);
+
+    let mut stmt = conn.prepare(
+        r"
+        SELECT t.table_name, t.table_type, c.column_name, c.data_type, c.is_nullable
+        FROM information_schema.tables t
+          JOIN information_schema.columns c
+            ON (t.table_schema = c.table_schema AND t.table_name = c.table_name)
+        WHERE t.table_schema = 'main'
+        ORDER BY t.table_name, c.ordinal_position",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut tables: Vec<Table> = Vec::new();
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(0)?;
+        let column = Column {
+            name: row.get(2)?,
+            data_type: row.get(3)?,
+            nullable: row.get::<_, String>(4)? == "YES",
+        };
+        match tables.last_mut() {
+            Some(table) if table.name == name => table.columns.push(column),
+            _ => tables.push(Table {
+                name,
+                kind: row.get(1)?,
+                columns: vec![column],
+            }),
+        }
+    }
+    trace!("Found {} tables", tables.len());
+
+    Ok(Schema { version, tables })
+}
+
+/// Stored version of the ordinance data model
+///
+/// Databases created before the version was stored return `None`.
+pub(crate) fn version(conn: &Connection) -> Result<Option<String>> {
+    let has_version: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM information_schema.tables
+          WHERE table_name = 'ordinancedb_version'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_version {
+        return Ok(None);
+    }
+
+    let version = conn.query_row(
+        "SELECT version FROM ordinancedb_version ORDER BY created_at DESC LIMIT 1",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(Some(version))
+}
+
+/// Warning if the stored data model is older than this library's
+///
+/// The data model still changes between releases, and an older database
+/// might lack columns that this library relies on. Rather than failing,
+/// return a message suggesting a migration, so the caller can decide how
+/// to surface it. Databases without a stored version are considered older.
+pub fn version_warning(conn: &Connection) -> Result<Option<String>> {
+    let stored = version(conn)?;
+    trace!("Checking stored data model version: {:?}", stored);
+
+    let expected = parse_version(crate::ORDINANCEDB_VERSION);
+    let outdated = match &stored {
+        Some(v) => parse_version(v) < expected,
+        None => true,
+    };
+    if !outdated {
+        return Ok(None);
+    }
+
+    Ok(Some(format!(
+        "database data model version {} is older than the expected {}; consider migrating it first",
+        stored.as_deref().unwrap_or("unknown"),
+        crate::ORDINANCEDB_VERSION
+    )))
+}
+
+/// Numeric components of a dotted version, such as "0.0.1"
+fn parse_version(version: &str) -> Vec<u64> {
+    version
+        .trim()
+        .split('.')
+        .map(|part| part.parse().unwrap_or(0))
+        .collect()
+}
+
+#[cfg(test)]
+mod test_schema {
+    use super::*;
+    use crate::sample::sample_db;
+
+    #[test]
+    fn expected_tables() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+
+        let schema = schema(&crate::open_readonly(&db).unwrap()).unwrap();
+        assert_eq!(schema.version.as_deref(), Some(crate::ORDINANCEDB_VERSION));
+
+        let names: Vec<&str> = schema.tables.iter().map(|t| t.name.as_str()).collect();
+        for expected in ["bookkeeper", "source", "quantitative", "ordinance", "usage"] {
+            assert!(names.contains(&expected), "Missing table {expected}");
+        }
+
+        let bookkeeper = schema
+            .tables
+            .iter()
+            .find(|t| t.name == "bookkeeper")
+            .unwrap();
+        assert_eq!(bookkeeper.columns[0].name, "id");
+        assert!(!bookkeeper.columns[1].nullable);
+
+        let text = schema.to_string();
+        assert!(text.starts_with("ordinancedb_version: 0.0.10"));
+        assert!(text.contains("\nbookkeeper (BASE TABLE)\n"));
+    }
+
+    #[test]
+    fn current_version() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+
+        let conn = Connection::open(&db).unwrap();
+        assert_eq!(version_warning(&conn).unwrap(), None);
+    }
+
+    #[test]
+    fn older_version() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = sample_db(tmp.path());
+
+        let conn = Connection::open(&db).unwrap();
+        conn.execute("UPDATE ordinancedb_version SET version = '0.0.0'", [])
+            .unwrap();
+        let warning = version_warning(&conn).unwrap().unwrap();
+        assert!(warning.contains("0.0.0 is older than the expected 0.0.10"));
+
+        conn.execute("DROP TABLE ordinancedb_version", []).unwrap();
+        assert!(version_warning(&conn).unwrap().is_some());
+    }
+
+    #[test]
+    fn version_ordering() {
+        assert!(parse_version("0.0.1") < parse_version("0.0.10"));
+        assert!(parse_version("0.1") < parse_version("0.1.0"));
+        assert!(parse_version("1.0.0") > parse_version("0.9.9"));
+    }
+}
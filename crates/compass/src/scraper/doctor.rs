@@ -0,0 +1,424 @@
+//! Diagnosis of a scraper output, before loading it
+//!
+//! A load stops at the first problem found, which is frustrating on a
+//! large output with several problems. Here every part of the output is
+//! checked on its own, such as the layout, each JSON file, the ordinance
+//! CSVs, the checksums of the documents, and the runtime logs, reporting
+//! all the problems at once with a hint on how to fix each one. As for
+//! `inspect()`, no database is created or touched.
+
+use std::path::Path;
+
+use serde::Serialize;
+use tracing::{debug, trace};
+
+use super::log::RuntimeLogs;
+use super::metadata::{METADATA_FILES, Metadata};
+use super::ordinance::{Ordinance, quantitative_path};
+use super::source::Source;
+use super::usage::Usage;
+use crate::error::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+/// Outcome of a single check
+pub enum CheckStatus {
+    /// Nothing to fix
+    Pass,
+    /// The output still loads, but something might be off
+    Warn,
+    /// The output can't be loaded as it is
+    Fail,
+}
+
+#[derive(Debug, Serialize)]
+/// A single check of a scraper output
+pub struct Check {
+    /// What was checked, such as "jurisdictions.json"
+    pub name: String,
+    /// Outcome of the check
+    pub status: CheckStatus,
+    /// What was found, such as the number of records or the error
+    pub detail: String,
+    /// How to fix it, unless passed
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass<D: Into<String>>(name: &str, detail: D) -> Self {
+        Self {
+            name: name.to_string(),
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warn<D: Into<String>>(name: &str, detail: D, hint: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            status: CheckStatus::Warn,
+            detail: detail.into(),
+            hint: Some(hint.to_string()),
+        }
+    }
+
+    fn fail<D: Into<String>>(name: &str, detail: D, hint: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            status: CheckStatus::Fail,
+            detail: detail.into(),
+            hint: Some(hint.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let status = match self.status {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+        };
+        write!(f, "[{status}] {}: {}", self.name, self.detail)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n       hint: {hint}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+/// All the checks of a scraper output
+pub struct Diagnosis {
+    /// Each check, in the order performed
+    pub checks: Vec<Check>,
+}
+
+impl Diagnosis {
+    /// Checks that failed, thus would prevent a load
+    pub fn failures(&self) -> impl Iterator<Item = &Check> {
+        self.checks.iter().filter(|c| c.status == CheckStatus::Fail)
+    }
+
+    /// Whether no check failed, although some might have warned
+    pub fn is_healthy(&self) -> bool {
+        self.failures().next().is_none()
+    }
+}
+
+/// Diagnose a scraper output directory
+///
+/// Every check is performed, even after a failure, thus all the
+/// problems are found at once. Returns an error only if the directory
+/// itself can't be read, otherwise see `Diagnosis::is_healthy()`.
+pub fn doctor<P: AsRef<Path>>(dir: P) -> Result<Diagnosis> {
+    let root = dir.as_ref();
+    trace!("Diagnosing scraper output {:?}", root);
+
+    if !root.is_dir() {
+        return Err(crate::error::Error::Undefined(format!(
+            "Not a directory: {}",
+            root.display()
+        )));
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let mut checks = layout(root);
+    checks.extend(runtime.block_on(components(root)));
+    debug!(
+        "Diagnosis of {:?}: {} checks, {} failed",
+        root,
+        checks.len(),
+        checks
+            .iter()
+            .filter(|c| c.status == CheckStatus::Fail)
+            .count()
+    );
+
+    Ok(Diagnosis { checks })
+}
+
+/// Check that the expected files are in place
+fn layout(root: &Path) -> Vec<Check> {
+    let mut missing = Vec::new();
+    for required in [
+        "jurisdictions.json",
+        "ordinance_files",
+        "qualitative_ordinances.csv",
+    ] {
+        if !root.join(required).exists() {
+            missing.push(required.to_string());
+        }
+    }
+    if let Err(candidates) = quantitative_path(root, None) {
+        missing.push(candidates.join(" or "));
+    }
+
+    let mut optional = Vec::new();
+    if !METADATA_FILES.iter().any(|f| root.join(f).exists()) {
+        optional.push("meta.json");
+    }
+    if !root.join("usage.json").exists() {
+        optional.push("usage.json");
+    }
+
+    let mut checks = Vec::new();
+    if missing.is_empty() {
+        checks.push(Check::pass("layout", "all the required files are present"));
+    } else {
+        checks.push(Check::fail(
+            "layout",
+            format!("missing {}", missing.join(", ")),
+            "Copy the complete scraper output, or run the scraper again",
+        ));
+    }
+    if !optional.is_empty() {
+        checks.push(Check::warn(
+            "optional files",
+            format!("missing {}", optional.join(", ")),
+            "Load with `infra-compass-cli load --allow-missing` to skip them",
+        ));
+    }
+    checks
+}
+
+/// Check each component that is present, parsing it as in a load
+async fn components(root: &Path) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let jurisdictions_hint = &format!(
+        "Run `infra-compass-cli validate-json --kind jurisdictions {}` to locate the problem",
+        root.join("jurisdictions.json").display()
+    );
+    if !root.join("jurisdictions.json").exists() {
+        // Already reported by the layout
+    } else if !root.join("ordinance_files").exists() {
+        // Without the documents, only the JSON itself can be checked
+        match super::validate_json(
+            super::JsonKind::Jurisdictions,
+            root.join("jurisdictions.json"),
+            false,
+        ) {
+            Ok(()) => checks.push(Check::pass("jurisdictions.json", "valid")),
+            Err(e) => checks.push(Check::fail(
+                "jurisdictions.json",
+                e.to_string(),
+                jurisdictions_hint,
+            )),
+        }
+    } else {
+        match Source::open(root, crate::IO_BUFFER_SIZE, crate::PARALLEL_HASH).await {
+            Ok(source) => {
+                checks.push(Check::pass(
+                    "jurisdictions.json",
+                    format!("{} jurisdictions", source.jurisdictions.len()),
+                ));
+                let unmatched = source.unmatched();
+                if unmatched.is_empty() {
+                    checks.push(Check::pass(
+                        "checksums",
+                        format!("{} documents", source.document_count()),
+                    ));
+                } else {
+                    checks.push(Check::warn(
+                        "checksums",
+                        format!(
+                            "{} files don't match any known document, such as {}",
+                            unmatched.len(),
+                            unmatched[0].display()
+                        ),
+                        "Replace the files, or fix their checksum in jurisdictions.json",
+                    ));
+                }
+            }
+            Err(e) => checks.push(Check::fail(
+                "jurisdictions.json",
+                e.to_string(),
+                jurisdictions_hint,
+            )),
+        }
+    }
+
+    if METADATA_FILES.iter().any(|f| root.join(f).exists()) {
+        match Metadata::open(root, false).await {
+            Ok(metadata) if metadata.report().is_empty() => {
+                checks.push(Check::pass("meta.json", "valid"));
+            }
+            Ok(metadata) => checks.push(Check::warn(
+                "meta.json",
+                format!(
+                    "{} warnings, such as {}",
+                    metadata.report().len(),
+                    metadata.report().iter().next().unwrap()
+                ),
+                "Check the fields of meta.json and config.json",
+            )),
+            Err(e) => checks.push(Check::fail(
+                "meta.json",
+                e.to_string(),
+                "Fix the JSON, or load with `infra-compass-cli load --tolerant` if edited by hand",
+            )),
+        }
+    }
+
+    if root.join("usage.json").exists() {
+        match Usage::open(root, false).await {
+            Ok(usage) => checks.push(Check::pass(
+                "usage.json",
+                format!("{} jurisdictions", usage.jurisdiction.len()),
+            )),
+            Err(e) => checks.push(Check::fail(
+                "usage.json",
+                e.to_string(),
+                "Fix the JSON, or load with `infra-compass-cli load --tolerant` if edited by hand",
+            )),
+        }
+    }
+
+    if quantitative_path(root, None).is_ok() && root.join("qualitative_ordinances.csv").exists() {
+        match Ordinance::open(root, None, crate::Deadline::default()).await {
+            Ok(ordinance) if ordinance.report().is_empty() => {
+                checks.push(Check::pass(
+                    "ordinances",
+                    format!("{} ordinances", ordinance.len()),
+                ));
+            }
+            Ok(ordinance) => checks.push(Check::warn(
+                "ordinances",
+                format!(
+                    "{} ordinances, {} rows merged or skipped, such as {}",
+                    ordinance.len(),
+                    ordinance.report().len(),
+                    ordinance.report().iter().next().unwrap()
+                ),
+                "Check the columns and quoting of the ordinance CSV files",
+            )),
+            Err(e) => checks.push(Check::fail(
+                "ordinances",
+                e.to_string(),
+                "Check the header of the ordinance CSV files",
+            )),
+        }
+    }
+
+    match RuntimeLogs::open(root, None).await {
+        Ok(logs) if logs.is_empty() => {}
+        Ok(logs) if logs.report().is_empty() => {
+            checks.push(Check::pass("logs", format!("{} records", logs.len())));
+        }
+        Ok(logs) => checks.push(Check::warn(
+            "logs",
+            format!(
+                "{} records, {} warnings, such as {}",
+                logs.len(),
+                logs.report().len(),
+                logs.report().iter().next().unwrap()
+            ),
+            "Check the encoding of the log files",
+        )),
+        Err(e) => checks.push(Check::fail(
+            "logs",
+            e.to_string(),
+            "Replace or remove the corrupted log files",
+        )),
+    }
+
+    checks
+}
+
+#[cfg(test)]
+mod test_doctor {
+    use super::*;
+
+    /// A complete scraper output, with every document known
+    fn good_output(root: &Path) {
+        super::super::sample::as_dir(root).unwrap();
+        std::fs::remove_file(root.join("ordinance_files").join("source.pdf")).unwrap();
+    }
+
+    #[test]
+    fn all_pass() {
+        let tmp = tempfile::tempdir().unwrap();
+        good_output(tmp.path());
+
+        let diagnosis = doctor(tmp.path()).unwrap();
+        assert!(diagnosis.is_healthy());
+        for check in &diagnosis.checks {
+            assert_eq!(check.status, CheckStatus::Pass, "{check}");
+        }
+        let names = diagnosis
+            .checks
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "layout",
+                "jurisdictions.json",
+                "checksums",
+                "meta.json",
+                "usage.json",
+                "ordinances",
+                "logs"
+            ]
+        );
+    }
+
+    #[test]
+    /// The missing file is named, and the other checks still performed
+    fn missing_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        good_output(tmp.path());
+        std::fs::remove_file(tmp.path().join("qualitative_ordinances.csv")).unwrap();
+
+        let diagnosis = doctor(tmp.path()).unwrap();
+        assert!(!diagnosis.is_healthy());
+        let failures = diagnosis.failures().collect::<Vec<_>>();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].name, "layout");
+        assert_eq!(failures[0].detail, "missing qualitative_ordinances.csv");
+        assert!(failures[0].hint.is_some());
+        assert!(diagnosis.checks.iter().any(|c| c.name == "usage.json"));
+    }
+
+    #[test]
+    /// The hint of a broken file is a command of the CLI
+    fn broken_jurisdictions_hint() {
+        let tmp = tempfile::tempdir().unwrap();
+        good_output(tmp.path());
+        std::fs::write(tmp.path().join("jurisdictions.json"), "{").unwrap();
+
+        let diagnosis = doctor(tmp.path()).unwrap();
+        let failures = diagnosis.failures().collect::<Vec<_>>();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].name, "jurisdictions.json");
+        assert!(
+            failures[0]
+                .hint
+                .as_ref()
+                .unwrap()
+                .starts_with("Run `infra-compass-cli validate-json --kind jurisdictions ")
+        );
+    }
+
+    #[test]
+    /// Unknown documents are only a warning
+    fn unmatched_checksum() {
+        let tmp = tempfile::tempdir().unwrap();
+        super::super::sample::as_dir(tmp.path()).unwrap();
+
+        let diagnosis = doctor(tmp.path()).unwrap();
+        assert!(diagnosis.is_healthy());
+        let checksums = diagnosis
+            .checks
+            .iter()
+            .find(|c| c.name == "checksums")
+            .unwrap();
+        assert_eq!(checksums.status, CheckStatus::Warn);
+        assert!(checksums.detail.contains("source.pdf"), "{checksums}");
+    }
+}
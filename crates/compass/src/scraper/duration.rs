@@ -0,0 +1,160 @@
+//! Durations as reported by the scraper
+//!
+//! The scraper reports elapsed times both in seconds and as a human
+//! readable string, such as "0:04:54.692577", which is Python's
+//! `str(datetime.timedelta)`. Long national runs exceed a day, which
+//! Python formats as "1 day, 4:32:55", while other tools just let the
+//! hours overflow, as in "28:32:55".
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, serde::Deserialize)]
+#[serde(from = "f64")]
+/// Elapsed time of a run, such as the whole scraper or a single jurisdiction
+///
+/// Stored as seconds, which is also how it is saved in the database,
+/// while it can be created from either representation given by the
+/// scraper. It is displayed as `H:MM:SS.ffffff`, hours overflowing
+/// past a day.
+pub(crate) struct RunDuration(f64);
+
+impl RunDuration {
+    /// Duration of the given number of seconds
+    pub(crate) fn from_seconds(seconds: f64) -> Self {
+        Self(seconds)
+    }
+
+    /// Parse a duration formatted as `H:MM:SS.ffffff`
+    ///
+    /// See `parse_hms` for the accepted formats. Returns `None` if the
+    /// string doesn't follow any of them.
+    pub(crate) fn from_hms_string(value: &str) -> Option<Self> {
+        parse_hms(value).map(Self)
+    }
+
+    /// Number of seconds
+    pub(crate) fn as_secs_f64(&self) -> f64 {
+        self.0
+    }
+}
+
+impl From<f64> for RunDuration {
+    fn from(seconds: f64) -> Self {
+        Self::from_seconds(seconds)
+    }
+}
+
+impl std::fmt::Display for RunDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let micros = (self.0.max(0.0) * 1e6).round() as u64;
+        let (seconds, micros) = (micros / 1_000_000, micros % 1_000_000);
+        let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+        write!(f, "{hours}:{minutes:02}:{seconds:02}")?;
+        if micros > 0 {
+            write!(f, ".{micros:06}")?;
+        }
+        Ok(())
+    }
+}
+
+impl duckdb::ToSql for RunDuration {
+    fn to_sql(&self) -> duckdb::Result<duckdb::types::ToSqlOutput<'_>> {
+        self.0.to_sql()
+    }
+}
+
+/// Parse a duration formatted as `H:MM:SS.ffffff` into seconds
+///
+/// The fractional part of the seconds is optional, and the hours may
+/// exceed 24. It might be prefixed by a number of days, as in
+/// `N day(s), H:MM:SS`. Returns `None` if the string doesn't follow that
+/// format.
+fn parse_hms(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let (days, value) = match value.split_once(',') {
+        Some((days, hms)) => {
+            let days = days
+                .strip_suffix("days")
+                .or_else(|| days.strip_suffix("day"))?;
+            (days.trim().parse::<u64>().ok()?, hms)
+        }
+        None => (0, value),
+    };
+
+    let mut parts = value.trim().split(':');
+    let (hours, minutes, seconds) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let hours: u64 = hours.parse().ok()?;
+    let minutes: u64 = minutes.parse().ok()?;
+    let seconds: f64 = seconds.parse().ok()?;
+    if minutes >= 60 || !(0.0..60.0).contains(&seconds) {
+        return None;
+    }
+
+    Some((days * 86400 + hours * 3600 + minutes * 60) as f64 + seconds)
+}
+
+#[cfg(test)]
+mod test_duration {
+    use super::*;
+
+    #[test]
+    fn parse_valid() {
+        assert_eq!(parse_hms("0:00:00"), Some(0.0));
+        assert_eq!(parse_hms("1:02:03"), Some(3723.0));
+        let seconds = parse_hms("0:04:54.692577").unwrap();
+        assert!((seconds - 294.692577).abs() < 1e-9);
+    }
+
+    #[test]
+    /// Durations longer than a day
+    fn parse_days() {
+        assert_eq!(parse_hms("28:32:55"), Some(102775.0));
+        assert_eq!(parse_hms("1 day, 4:32:55"), Some(102775.0));
+        assert_eq!(parse_hms("2 days, 0:00:01.5"), Some(172801.5));
+        assert_eq!(parse_hms("0 days, 0:00:00"), Some(0.0));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(parse_hms(""), None);
+        assert_eq!(parse_hms("04:54"), None);
+        assert_eq!(parse_hms("0::0::03.14"), None);
+        assert_eq!(parse_hms("0:61:00"), None);
+        assert_eq!(parse_hms("0:00:75"), None);
+        assert_eq!(parse_hms("1 week, 0:00:00"), None);
+        assert_eq!(parse_hms("-1 day, 23:59:59"), None);
+        assert_eq!(parse_hms("day, 0:00:00"), None);
+    }
+
+    #[test]
+    fn run_duration_from_seconds() {
+        let duration = RunDuration::from_seconds(294.692577);
+        assert_eq!(duration.as_secs_f64(), 294.692577);
+        assert_eq!(duration.to_string(), "0:04:54.692577");
+        assert_eq!(RunDuration::from_seconds(3723.0).to_string(), "1:02:03");
+        assert_eq!(RunDuration::from_seconds(102775.0).to_string(), "28:32:55");
+    }
+
+    #[test]
+    fn run_duration_from_hms_string() {
+        let duration = RunDuration::from_hms_string("0:08:57.500929").unwrap();
+        assert!((duration.as_secs_f64() - 537.500929).abs() < 1e-9);
+        assert_eq!(duration.to_string(), "0:08:57.500929");
+        assert_eq!(
+            RunDuration::from_hms_string("1 day, 4:32:55"),
+            Some(RunDuration::from_seconds(102775.0))
+        );
+        assert_eq!(RunDuration::from_hms_string("04:54"), None);
+    }
+
+    #[test]
+    /// Deserialized from the seconds, as given by the scraper
+    fn run_duration_deserialize() {
+        let duration: RunDuration = serde_json::from_str("537.5").unwrap();
+        assert_eq!(duration, RunDuration::from_seconds(537.5));
+        let duration: RunDuration = serde_json::from_str("3").unwrap();
+        assert_eq!(duration.as_secs_f64(), 3.0);
+    }
+}
@@ -0,0 +1,113 @@
+//! Summary of a scraper output, without a database
+//!
+//! A quick look at what a scraper output contains, such as before
+//! deciding to load it. Only the output directory is read, no database
+//! is created or touched.
+
+use std::path::Path;
+
+use serde::Serialize;
+use tracing::trace;
+
+use super::ScrapedOrdinance;
+use crate::error::Result;
+
+#[derive(Debug, Serialize)]
+/// Summary of a scraper output
+pub struct Inspection {
+    /// Number of target jurisdictions
+    pub jurisdictions: usize,
+    /// Number of documents found for those jurisdictions
+    pub documents: usize,
+    /// Number of ordinance rows, both quantitative and qualitative
+    pub ordinances: usize,
+    /// Number of runtime log records
+    pub log_records: usize,
+    /// Name of the LLM models configured for the run
+    pub models: Vec<String>,
+    /// Duration of the whole run, in seconds, if the metadata is available
+    pub total_time: Option<f64>,
+    /// Share of the searched jurisdictions found, as announced by the
+    /// metadata, if available
+    pub found_rate: Option<f64>,
+    /// Optional files missing from the output, such as "usage.json"
+    pub skipped: Vec<String>,
+}
+
+/// Summarize a scraper output directory
+///
+/// Parses the output as if it was loaded, but without any database, and
+/// counts what it contains. Missing optional files, such as `meta.json`,
+/// are tolerated, and listed in the summary.
+pub fn inspect<P: AsRef<Path>>(dir: P) -> Result<Inspection> {
+    trace!("Inspecting scraper output {:?}", dir.as_ref());
+
+    let options = crate::LoadOptions {
+        allow_missing: true,
+        ..Default::default()
+    };
+    let ordinance = ScrapedOrdinance::open_lazy(dir, &options)?;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(ordinance.inspection())
+}
+
+impl ScrapedOrdinance {
+    /// Summary of this output, see `inspect()`
+    async fn inspection(&self) -> Result<Inspection> {
+        let source = self.source().await?;
+        let metadata = self.metadata().await?;
+
+        Ok(Inspection {
+            jurisdictions: source.jurisdictions.len(),
+            documents: source.document_count(),
+            ordinances: self.ordinance().await?.len(),
+            log_records: self.logs().await?.len(),
+            models: metadata.map(|m| m.model_names()).unwrap_or_default(),
+            total_time: metadata
+                .and_then(|m| m.total_time())
+                .map(|t| t.as_secs_f64()),
+            found_rate: metadata.and_then(|m| m.found_rate()),
+            skipped: self.skipped().to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_inspect {
+    use super::*;
+
+    #[test]
+    fn sample_output() {
+        let tmp = tempfile::tempdir().unwrap();
+        super::super::sample::as_dir(tmp.path()).unwrap();
+
+        let inspection = inspect(tmp.path()).unwrap();
+        assert_eq!(inspection.jurisdictions, 1);
+        assert_eq!(inspection.documents, 1);
+        assert_eq!(inspection.ordinances, 4);
+        assert_eq!(inspection.log_records, 8);
+        assert_eq!(inspection.models, ["gpt-4.1-mini"]);
+        assert_eq!(inspection.total_time, Some(537.5009291959941));
+        assert_eq!(inspection.found_rate, Some(0.7));
+        assert!(inspection.skipped.is_empty());
+    }
+
+    #[test]
+    /// Nothing else is created in the output directory
+    fn read_only() {
+        let tmp = tempfile::tempdir().unwrap();
+        super::super::sample::as_dir(tmp.path()).unwrap();
+        std::fs::remove_file(tmp.path().join("meta.json")).unwrap();
+        let before = std::fs::read_dir(tmp.path()).unwrap().count();
+
+        let inspection = inspect(tmp.path()).unwrap();
+        assert!(inspection.models.is_empty());
+        assert_eq!(inspection.total_time, None);
+        assert_eq!(inspection.found_rate, None);
+        assert_eq!(inspection.skipped, ["meta.json"]);
+        assert_eq!(std::fs::read_dir(tmp.path()).unwrap().count(), before);
+    }
+}
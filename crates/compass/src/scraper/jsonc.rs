@@ -0,0 +1,109 @@
+//! Tolerant parsing of hand-edited JSON files
+//!
+//! The scraper writes strict JSON, but files edited by hand, such as a
+//! tweaked `meta.json`, often end up with comments or trailing commas,
+//! as allowed by JSONC. Those are removed here, so that the result can
+//! be parsed as usual.
+
+/// Remove the comments and trailing commas of a JSON text
+///
+/// Both line (`// ...`) and block (`/* ... */`) comments are removed,
+/// keeping their line breaks, so that the line and column of any error
+/// in the result still match the original text. Content within strings
+/// is never changed.
+pub(super) fn normalize(json: &str) -> String {
+    let mut output = String::with_capacity(json.len());
+    let mut chars = json.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            output.push(c);
+            match c {
+                '\\' => {
+                    if let Some(escaped) = chars.next() {
+                        output.push(escaped);
+                    }
+                }
+                '"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                output.push(c);
+            }
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        output.push(c);
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = None;
+                for c in chars.by_ref() {
+                    if previous == Some('*') && c == '/' {
+                        break;
+                    }
+                    if c == '\n' {
+                        output.push(c);
+                    }
+                    previous = Some(c);
+                }
+            }
+            ('}' | ']', _) => {
+                if let Some(i) = output.rfind(|c: char| !c.is_whitespace()) {
+                    if output[i..].starts_with(',') {
+                        output.remove(i);
+                    }
+                }
+                output.push(c);
+            }
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod test_jsonc {
+    use super::*;
+
+    #[test]
+    fn trailing_commas() {
+        let json = normalize(r#"{"a": [1, 2,], "b": {"c": 3,},}"#);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value, serde_json::json!({"a": [1, 2], "b": {"c": 3}}));
+    }
+
+    #[test]
+    fn comments() {
+        let json = normalize(
+            "{\n  // The user\n  \"username\": \"test\", /* who ran it */\n  /* multi\n  line */\n  \"cost\": 1.5\n}",
+        );
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value, serde_json::json!({"username": "test", "cost": 1.5}));
+        assert_eq!(json.lines().count(), 7);
+    }
+
+    #[test]
+    /// Strings are never changed, even if they look like comments
+    fn strings() {
+        let json = r#"{"url": "https://example.com/a,]", "note": "/* \"kept\", */"}"#;
+        assert_eq!(normalize(json), json);
+    }
+
+    #[test]
+    /// Errors in the result are still located in the original text
+    fn error_location() {
+        let json = normalize("{\n  // comment\n  \"a\": 1,\n  \"b\": ?\n}");
+        let err = serde_json::from_str::<serde_json::Value>(&json).unwrap_err();
+        assert_eq!(err.line(), 4);
+    }
+}
@@ -0,0 +1,876 @@
+//! Runtime logs of the scraper
+//!
+//! When running with `keep_async_logs`, the scraper saves every log
+//! record of the run in `logs/all.log`, one record per line, such as:
+//!
+//! ```text
+//! [2025-03-04 05:10:52,266] INFO - Task-1: Processing 250 jurisdiction(s)
+//! ```
+//!
+//! Messages can span multiple lines, such as tracebacks, in which case
+//! the following lines are continuations of the previous record. This
+//! module parses those logs and records them in the database, either all
+//! at once, or incrementally while the scraper is still running.
+//!
+//! Logs of long runs might be rotated, as `all.log.1`, `all.log.2`, and
+//! so on, the higher the older, and possibly gzip compressed, such as
+//! `all.log.gz` or `all.log.2.gz`.
+
+use std::io::{Read, Seek};
+use std::sync::LazyLock;
+
+use regex::Regex;
+use tracing::{debug, trace, warn};
+
+use super::quality::{DataQuality, Issue};
+use super::report::{ParseReport, line_of};
+use crate::error::{Error, Result};
+
+/// Pattern of a log line: `[timestamp] LEVEL - subject: message`
+static LOG_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\[(?P<ts>[^\]]+)\] (?P<level>[A-Z]+) - (?P<subject>[^:]+): (?P<message>.*)$")
+        .unwrap()
+});
+
+/// Name of the runtime logs file, before any rotation or compression
+const LOG_FILENAME: &str = "all.log";
+
+/// Named groups required in a log pattern
+const PATTERN_GROUPS: [&str; 4] = ["ts", "level", "subject", "message"];
+
+/// Compile a custom pattern of a log line
+///
+/// The pattern must have the named groups `ts`, `level`, `subject`, and
+/// `message`, such as `^(?P<level>[A-Z]+) \[(?P<ts>[^\]]+)\] ...` for a
+/// `LEVEL [timestamp]` format.
+pub(crate) fn compile_pattern(pattern: &str) -> Result<Regex> {
+    let regex = Regex::new(pattern).map_err(|e| Error::InvalidLogPattern(e.to_string()))?;
+    for group in PATTERN_GROUPS {
+        if !regex.capture_names().any(|name| name == Some(group)) {
+            return Err(Error::InvalidLogPattern(format!(
+                "missing named group {group:?} in {pattern:?}"
+            )));
+        }
+    }
+    Ok(regex)
+}
+
+/// Pattern of the message announcing how many jurisdictions will be processed
+static PROCESSING_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^Processing (?P<count>\d+) jurisdiction\(s\)$").unwrap());
+
+#[derive(Debug, Default)]
+/// All the log records of a scraper run, and the warnings parsing them
+pub(crate) struct RuntimeLogs(Vec<LogRecord>, ParseReport);
+
+#[derive(Debug, Clone, PartialEq)]
+/// A single log record
+pub(crate) struct LogRecord {
+    /// When it was logged, such as "2025-03-04 05:10:52,266"
+    timestamp: String,
+    /// Severity, such as "INFO" or "ERROR"
+    level: String,
+    /// Who logged it, typically the async task, such as "Task-1"
+    subject: String,
+    /// The message itself, possibly with multiple lines
+    message: String,
+}
+
+impl LogRecord {
+    /// Parse a single log line
+    ///
+    /// Returns `None` if the line doesn't start a new record, such as the
+    /// continuation of a multi-line message.
+    pub(crate) fn parse(line: &str, pattern: &Regex) -> Option<Self> {
+        let captures = pattern.captures(line)?;
+        Some(Self {
+            timestamp: captures["ts"].to_string(),
+            level: captures["level"].to_string(),
+            subject: captures["subject"].to_string(),
+            message: captures["message"].to_string(),
+        })
+    }
+}
+
+impl RuntimeLogs {
+    /// Initialize the database for the RuntimeLogs context
+    pub(super) fn init_db(conn: &duckdb::Transaction) -> Result<()> {
+        trace!("Initializing database for RuntimeLogs");
+        conn.execute_batch(
+            r"
+            CREATE SEQUENCE IF NOT EXISTS logs_sequence START 1;
+            CREATE TABLE IF NOT EXISTS logs (
+              id INTEGER PRIMARY KEY DEFAULT NEXTVAL('logs_sequence'),
+              bookkeeper_lnk INTEGER REFERENCES bookkeeper(id) NOT NULL,
+              timestamp TEXT,
+              level TEXT,
+              subject TEXT,
+              message TEXT,
+              );
+
+            CREATE TABLE IF NOT EXISTS log_cursor (
+              bookkeeper_lnk INTEGER REFERENCES bookkeeper(id) NOT NULL,
+              path TEXT NOT NULL,
+              byte_offset UBIGINT NOT NULL,
+              pending_id INTEGER,
+              updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+              PRIMARY KEY (bookkeeper_lnk, path),
+              );",
+        )?;
+
+        trace!("Database ready for RuntimeLogs");
+        Ok(())
+    }
+
+    /// Files of the runtime logs in a scraper output, oldest first
+    ///
+    /// These are `logs/all.log` and its rotations, such as `all.log.2`
+    /// then `all.log.1` then `all.log`, each possibly gzip compressed. If
+    /// both a plain and a compressed version of the same file exist, only
+    /// the plain one is used. Empty if there are no logs.
+    pub(super) fn files<P: AsRef<std::path::Path>>(root: P) -> Result<Vec<std::path::PathBuf>> {
+        let dir = root.as_ref().join("logs");
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        // By rotation, 0 for the current file, then if compressed
+        let mut files = std::collections::BTreeMap::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            let Some(rest) = name.strip_prefix(LOG_FILENAME) else {
+                continue;
+            };
+            let (rest, compressed) = match rest.strip_suffix(".gz") {
+                Some(rest) => (rest, true),
+                None => (rest, false),
+            };
+            let rotation = match rest {
+                "" => 0,
+                _ => match rest.strip_prefix('.').and_then(|n| n.parse::<u32>().ok()) {
+                    Some(n) => n,
+                    None => {
+                        trace!("Ignoring {:?}, not a runtime logs file", path);
+                        continue;
+                    }
+                },
+            };
+            files.entry((rotation, compressed)).or_insert(path);
+        }
+
+        let mut paths = Vec::new();
+        for (&(rotation, compressed), path) in files.iter().rev() {
+            if compressed && files.contains_key(&(rotation, false)) {
+                debug!("Ignoring {:?}, using its uncompressed version", path);
+                continue;
+            }
+            paths.push(path.clone());
+        }
+        Ok(paths)
+    }
+
+    /// Open the runtime logs of the scraper output
+    ///
+    /// The logs are only available if the scraper was run keeping the
+    /// async logs, thus missing logs result in empty logs. Rotated and
+    /// compressed files are concatenated in order, see `files()`.
+    /// The lines are parsed with `pattern`, if given, otherwise with the
+    /// default pattern.
+    pub(super) async fn open<P: AsRef<std::path::Path>>(
+        root: P,
+        pattern: Option<&Regex>,
+    ) -> Result<Self> {
+        debug!("Opening RuntimeLogs from {:?}", root.as_ref());
+
+        let paths = Self::files(&root)?;
+        if paths.is_empty() {
+            debug!("Missing runtime logs in {:?}", root.as_ref());
+            return Ok(Self::default());
+        }
+
+        let mut content = String::new();
+        let mut report = ParseReport::default();
+        for path in paths {
+            trace!("Identified RuntimeLogs at {:?}", path);
+            let mut bytes = tokio::fs::read(&path).await?;
+            if path.extension().is_some_and(|e| e == "gz") {
+                let mut decompressed = Vec::new();
+                flate2::read::GzDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed)?;
+                bytes = decompressed;
+            }
+            let (decoded, invalid) = decode_lossy(&bytes);
+            if let Some(line) = invalid {
+                let name = path.strip_prefix(root.as_ref()).unwrap_or(&path);
+                report.push(
+                    name.display(),
+                    Some(line),
+                    "Invalid UTF-8, replaced from this line on",
+                );
+            }
+            content.push_str(&decoded);
+            if !content.is_empty() && !content.ends_with('\n') {
+                content.push('\n');
+            }
+        }
+        let mut logs = Self::parse_with(&content, pattern.unwrap_or(&LOG_PATTERN));
+        logs.1 = report;
+        debug!(
+            "Parsed {} log records, {} errors",
+            logs.len(),
+            logs.errors().len()
+        );
+
+        Ok(logs)
+    }
+
+    /// Parse the content of a log file with `pattern`
+    ///
+    /// Lines that don't start a new record are appended to the message
+    /// of the previous one. Any lines before the first record are
+    /// ignored.
+    fn parse_with(content: &str, pattern: &Regex) -> Self {
+        let mut records: Vec<LogRecord> = Vec::new();
+        for line in content.lines() {
+            match (LogRecord::parse(line, pattern), records.last_mut()) {
+                (Some(record), _) => records.push(record),
+                (None, Some(last)) => {
+                    last.message.push('\n');
+                    last.message.push_str(line);
+                }
+                (None, None) => trace!("Ignoring orphan log line: {:?}", line),
+            }
+        }
+        Self(records, ParseReport::default())
+    }
+
+    /// Warnings found while parsing
+    pub(super) fn report(&self) -> &ParseReport {
+        &self.1
+    }
+
+    /// Iterate over the log records, in the order they were logged
+    pub(crate) fn iter(&self) -> std::slice::Iter<'_, LogRecord> {
+        self.0.iter()
+    }
+
+    /// Number of log records
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// True if there are no log records
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Drop consecutive duplicated records
+    ///
+    /// The scraper sometimes logs the very same record twice in a row,
+    /// with the same timestamp, level, subject, and message. Only the
+    /// first one is kept. Returns how many records were dropped.
+    pub(super) fn dedup(&mut self) -> usize {
+        let before = self.0.len();
+        self.0.dedup();
+        let dropped = before - self.0.len();
+        debug!("Dropped {} duplicated log records", dropped);
+        dropped
+    }
+
+    /// Log records at the ERROR level
+    pub(crate) fn errors(&self) -> Vec<&LogRecord> {
+        self.iter().filter(|r| r.level == "ERROR").collect()
+    }
+
+    /// Number of jurisdictions the scraper announced it would process
+    ///
+    /// The scraper logs "Processing N jurisdiction(s)" at the start of a
+    /// run. Returns `None` if that record is not in the logs.
+    pub(super) fn expected_jurisdiction_count(&self) -> Option<u32> {
+        self.iter().find_map(|record| {
+            PROCESSING_PATTERN
+                .captures(&record.message)
+                .and_then(|c| c["count"].parse().ok())
+        })
+    }
+
+    /// Cross-check the logs against the jurisdictions found
+    ///
+    /// Fewer jurisdictions than announced at the start of the run
+    /// suggests an incomplete run.
+    pub(super) fn validate(&self, quality: &mut DataQuality, n_jurisdictions: usize) {
+        if let Some(expected) = self.expected_jurisdiction_count() {
+            if expected as usize != n_jurisdictions {
+                quality.push(Issue::new(
+                    "logs",
+                    "jurisdictions",
+                    expected,
+                    format!(
+                        "Run announced {expected} jurisdictions but {n_jurisdictions} were found, possibly an incomplete run"
+                    ),
+                ));
+            }
+        }
+    }
+
+    /// Write the log records to the database
+    pub(super) fn write(&self, conn: &duckdb::Transaction, commit_id: usize) -> Result<()> {
+        trace!("Writing {} log records to the database", self.len());
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let mut stmt = conn.prepare(
+            r"INSERT INTO logs (bookkeeper_lnk, timestamp, level, subject, message)
+              VALUES (?, ?, ?, ?, ?)",
+        )?;
+        for record in self {
+            stmt.execute(duckdb::params![
+                commit_id,
+                record.timestamp,
+                record.level,
+                record.subject,
+                record.message,
+            ])?;
+        }
+
+        Ok(())
+    }
+
+    /// Ingest only the new content of a log file that is still growing
+    ///
+    /// Resumes from the byte offset stored in `log_cursor` for this
+    /// commit and file, parses only the complete lines appended since
+    /// then, and stores the new offset. A partial last line is left for
+    /// the next call. The last record inserted is kept pending, thus
+    /// continuation lines appended later, such as the rest of a
+    /// traceback, extend its message. If the file shrank, such as by a
+    /// rotation, it is read again from the start. As in `open()`, the
+    /// lines are parsed with `pattern`, if given, otherwise with the
+    /// default pattern.
+    ///
+    /// Returns the number of log records inserted.
+    pub(crate) fn tail(
+        conn: &duckdb::Transaction,
+        commit_id: usize,
+        path: &std::path::Path,
+        pattern: Option<&Regex>,
+    ) -> Result<usize> {
+        let pattern = pattern.unwrap_or(&LOG_PATTERN);
+        let cursor_path = path.to_string_lossy().to_string();
+        let (offset, pending): (u64, Option<usize>) = conn
+            .query_row(
+                "SELECT byte_offset, pending_id FROM log_cursor
+                  WHERE bookkeeper_lnk = ? AND path = ?",
+                duckdb::params![commit_id, cursor_path],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .or_else(|e| match e {
+                duckdb::Error::QueryReturnedNoRows => Ok((0, None)),
+                e => Err(e),
+            })?;
+        trace!(
+            "Resuming log {:?} from byte {}, pending record: {:?}",
+            path, offset, pending
+        );
+
+        let mut file = std::fs::File::open(path)?;
+        let (offset, pending) = if file.metadata()?.len() < offset {
+            warn!("Log file {:?} shrank, reading it from the start", path);
+            (0, None)
+        } else {
+            (offset, pending)
+        };
+        file.seek(std::io::SeekFrom::Start(offset))?;
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+
+        let Some(end) = buffer.iter().rposition(|b| *b == b'\n') else {
+            trace!("No complete new lines in {:?}", path);
+            return Ok(0);
+        };
+        let (content, invalid) = decode_lossy(&buffer[..=end]);
+        if let Some(line) = invalid {
+            warn!(
+                "Invalid UTF-8 in {:?} at line {} of the new content, replacing invalid bytes",
+                path, line
+            );
+        }
+
+        // Lines before the first new record continue the pending one
+        let continued: usize = content
+            .split_inclusive('\n')
+            .take_while(|line| {
+                LogRecord::parse(line.trim_end_matches(['\r', '\n']), pattern).is_none()
+            })
+            .map(str::len)
+            .sum();
+        let (continuation, content) = content.split_at(continued);
+        match pending {
+            Some(id) if !continuation.is_empty() => {
+                let mut message = String::new();
+                for line in continuation.lines() {
+                    message.push('\n');
+                    message.push_str(line);
+                }
+                trace!("Extending pending log record {} with {:?}", id, message);
+                conn.execute(
+                    "UPDATE logs SET message = message || ? WHERE id = ?",
+                    duckdb::params![message, id],
+                )?;
+            }
+            _ => {
+                for line in continuation.lines() {
+                    trace!("Ignoring orphan log line: {:?}", line);
+                }
+            }
+        }
+
+        let logs = Self::parse_with(content, pattern);
+        logs.write(conn, commit_id)?;
+        let pending = if logs.is_empty() {
+            pending
+        } else {
+            conn.query_row(
+                "SELECT MAX(id) FROM logs WHERE bookkeeper_lnk = ?",
+                [commit_id],
+                |row| row.get(0),
+            )?
+        };
+
+        let new_offset = offset + end as u64 + 1;
+        conn.execute(
+            "INSERT OR REPLACE INTO log_cursor (bookkeeper_lnk, path, byte_offset, pending_id)
+              VALUES (?, ?, ?, ?)",
+            duckdb::params![commit_id, cursor_path, new_offset, pending],
+        )?;
+        debug!(
+            "Inserted {} log records from {:?}, now at byte {}",
+            logs.len(),
+            path,
+            new_offset
+        );
+
+        Ok(logs.len())
+    }
+}
+
+impl IntoIterator for RuntimeLogs {
+    type Item = LogRecord;
+    type IntoIter = std::vec::IntoIter<LogRecord>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a RuntimeLogs {
+    type Item = &'a LogRecord;
+    type IntoIter = std::slice::Iter<'a, LogRecord>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Decode the content of a log file, replacing invalid UTF-8
+///
+/// Logs of long runs might have a few invalid bytes, such as text
+/// extracted from a document with the wrong encoding. Those are replaced
+/// instead of losing the whole log. Also returns the line of the first
+/// invalid byte, if any, for the caller to warn.
+fn decode_lossy(content: &[u8]) -> (std::borrow::Cow<'_, str>, Option<u64>) {
+    let invalid = std::str::from_utf8(content)
+        .err()
+        .map(|e| line_of(content, e.valid_up_to()));
+    (String::from_utf8_lossy(content), invalid)
+}
+
+#[cfg(test)]
+/// Samples of runtime logs to support tests
+pub(crate) mod sample {
+    use crate::error::Result;
+    use std::io::Write;
+
+    pub(crate) fn as_text_v1() -> String {
+        r#"[2025-03-04 05:10:52,266] INFO - Task-1: Running COMPASS version 0.1.1.dev17+gb569353.d20250304
+[2025-03-04 05:10:52,267] INFO - Task-1: Processing 250 jurisdiction(s)
+[2025-03-04 05:10:52,268] INFO - Task-1: Running COMPASS
+[2025-03-04 05:10:52,268] INFO - Task-1: Running COMPASS
+[2025-03-04 05:10:53,012] DEBUG - Task-2: Searching for ordinance documents of Decatur County, Indiana
+[2025-03-04 05:11:02,415] WARNING - Task-2: Could not download https://example.com/missing.pdf
+[2025-03-04 05:12:30,801] ERROR - Task-3: Failed to process Sample County, Sample State
+Traceback (most recent call last):
+  File "compass/scripts/process.py", line 726, in process_jurisdictions
+ValueError: Unable to parse document
+[2025-03-04 05:19:49,767] INFO - Task-1: Total runtime: 0:08:57.500929
+"#
+        .to_string()
+    }
+
+    pub(crate) fn as_file<P: AsRef<std::path::Path>>(path: P) -> Result<std::fs::File> {
+        let mut f = std::fs::File::create(path)?;
+        write!(f, "{}", as_text_v1())?;
+        Ok(f)
+    }
+}
+
+#[cfg(test)]
+mod test_scraper_log {
+    use super::*;
+    use std::io::Write;
+
+    impl RuntimeLogs {
+        /// Parse the content of a log file with the default pattern
+        fn parse(content: &str) -> Self {
+            Self::parse_with(content, &LOG_PATTERN)
+        }
+    }
+
+    #[test]
+    fn parse_line() {
+        let record = LogRecord::parse(
+            "[2025-03-04 05:10:52,267] INFO - Task-1: Processing 250 jurisdiction(s)",
+            &LOG_PATTERN,
+        )
+        .unwrap();
+        assert_eq!(record.timestamp, "2025-03-04 05:10:52,267");
+        assert_eq!(record.level, "INFO");
+        assert_eq!(record.subject, "Task-1");
+        assert_eq!(record.message, "Processing 250 jurisdiction(s)");
+
+        assert!(LogRecord::parse("Traceback (most recent call last):", &LOG_PATTERN).is_none());
+    }
+
+    #[test]
+    fn parse_sample() {
+        let logs = RuntimeLogs::parse(&sample::as_text_v1());
+        assert_eq!(logs.0.len(), 8);
+
+        let error = &logs.0[6];
+        assert_eq!(error.level, "ERROR");
+        assert!(
+            error
+                .message
+                .ends_with("ValueError: Unable to parse document")
+        );
+        assert_eq!(error.message.lines().count(), 4);
+    }
+
+    #[tokio::test]
+    /// An invalid byte doesn't lose the rest of the log
+    async fn open_invalid_utf8() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tmp.path().join("logs")).unwrap();
+        let mut content = sample::as_text_v1().into_bytes();
+        // Corrupt the DEBUG record, the 5th line
+        let position = content.windows(5).position(|w| w == b"DEBUG").unwrap();
+        content.insert(position + 20, 0xff);
+        std::fs::write(tmp.path().join("logs").join("all.log"), content).unwrap();
+
+        let logs = RuntimeLogs::open(tmp.path(), None).await.unwrap();
+        assert_eq!(logs.0.len(), 8);
+        assert!(logs.0[4].message.contains('\u{FFFD}'));
+        assert_eq!(logs.0[5].level, "WARNING");
+    }
+
+    #[tokio::test]
+    /// A compressed log, when there is no plain one
+    async fn open_gzip() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tmp.path().join("logs")).unwrap();
+        let file = std::fs::File::create(tmp.path().join("logs").join("all.log.gz")).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        encoder.write_all(sample::as_text_v1().as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        let logs = RuntimeLogs::open(tmp.path(), None).await.unwrap();
+        assert_eq!(logs.0.len(), 8);
+        assert_eq!(logs.errors().len(), 1);
+    }
+
+    #[tokio::test]
+    /// Rotated logs are concatenated from the oldest
+    async fn open_rotated() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("logs");
+        std::fs::create_dir(&dir).unwrap();
+        let line = |n: u32| format!("[2025-03-04 05:10:5{n},266] INFO - Task-1: Record {n}\n");
+
+        let file = std::fs::File::create(dir.join("all.log.2.gz")).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        encoder.write_all(line(1).as_bytes()).unwrap();
+        encoder.finish().unwrap();
+        // Without a trailing newline
+        std::fs::write(dir.join("all.log.1"), line(2).trim_end()).unwrap();
+        std::fs::write(dir.join("all.log"), line(3)).unwrap();
+        // Superseded by its uncompressed version
+        std::fs::write(dir.join("all.log.gz"), b"not really compressed").unwrap();
+        std::fs::write(dir.join("all.log.bak"), line(9)).unwrap();
+
+        let files: Vec<_> = RuntimeLogs::files(tmp.path())
+            .unwrap()
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(files, ["all.log.2.gz", "all.log.1", "all.log"]);
+
+        let logs = RuntimeLogs::open(tmp.path(), None).await.unwrap();
+        let messages: Vec<_> = logs.0.iter().map(|r| r.message.as_str()).collect();
+        assert_eq!(messages, ["Record 1", "Record 2", "Record 3"]);
+    }
+
+    #[test]
+    /// Logs in a `LEVEL [timestamp]` format, as other COMPASS versions
+    fn parse_alternate_pattern() {
+        let pattern = compile_pattern(
+            r"^(?P<level>[A-Z]+) \[(?P<ts>[^\]]+)\] (?P<subject>[^:]+): (?P<message>.*)$",
+        )
+        .unwrap();
+        let content = "INFO [2025-03-04 05:10:52,266] Task-1: Running COMPASS\n\
+                       ERROR [2025-03-04 05:12:30,801] Task-3: Failed to process\n\
+                       Traceback (most recent call last):\n";
+
+        let logs = RuntimeLogs::parse_with(content, &pattern);
+        assert_eq!(logs.0.len(), 2);
+        assert_eq!(logs.0[1].level, "ERROR");
+        assert_eq!(logs.0[1].timestamp, "2025-03-04 05:12:30,801");
+        assert_eq!(logs.0[1].message.lines().count(), 2);
+
+        // The default pattern doesn't recognize it
+        assert!(RuntimeLogs::parse(content).0.is_empty());
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        assert!(compile_pattern(r"^(?P<ts>\S+) (?P<message>.*)$").is_err());
+        assert!(compile_pattern(r"^(?P<ts>[").is_err());
+    }
+
+    #[test]
+    fn iterate_records() {
+        let logs = RuntimeLogs::parse(&sample::as_text_v1());
+        assert_eq!(logs.len(), 8);
+        assert!(!logs.is_empty());
+
+        let info = logs.iter().filter(|r| r.level == "INFO").count();
+        assert_eq!(info, 5);
+
+        let mut borrowed = 0;
+        for record in &logs {
+            borrowed += usize::from(record.level == "INFO");
+        }
+        assert_eq!(borrowed, info);
+
+        let levels: Vec<String> = logs.into_iter().map(|r| r.level).collect();
+        assert_eq!(levels.iter().filter(|l| *l == "INFO").count(), info);
+
+        assert!(RuntimeLogs::default().is_empty());
+    }
+
+    #[test]
+    fn dedup_records() {
+        let mut logs = RuntimeLogs::parse(&sample::as_text_v1());
+        assert_eq!(logs.dedup(), 1);
+        assert_eq!(logs.len(), 7);
+        let running = logs
+            .iter()
+            .filter(|r| r.message == "Running COMPASS")
+            .count();
+        assert_eq!(running, 1);
+
+        // Only consecutive ones, the same message later on is kept
+        let content = "[2025-03-04 05:10:52,268] INFO - Task-1: Running COMPASS\n\
+                       [2025-03-04 05:10:52,268] INFO - Task-2: Running COMPASS\n\
+                       [2025-03-04 05:10:52,268] INFO - Task-1: Running COMPASS\n";
+        let mut logs = RuntimeLogs::parse(content);
+        assert_eq!(logs.dedup(), 0);
+        assert_eq!(logs.len(), 3);
+    }
+
+    #[test]
+    fn errors() {
+        let logs = RuntimeLogs::parse(&sample::as_text_v1());
+        let errors = logs.errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].subject, "Task-3");
+        assert!(
+            errors[0]
+                .message
+                .starts_with("Failed to process Sample County")
+        );
+    }
+
+    #[test]
+    fn expected_jurisdiction_count() {
+        let logs = RuntimeLogs::parse(&sample::as_text_v1());
+        assert_eq!(logs.expected_jurisdiction_count(), Some(250));
+
+        assert_eq!(RuntimeLogs::default().expected_jurisdiction_count(), None);
+    }
+
+    #[test]
+    fn validate_jurisdiction_count() {
+        let logs = RuntimeLogs::parse(&sample::as_text_v1());
+
+        let mut quality = DataQuality::default();
+        logs.validate(&mut quality, 250);
+        assert!(quality.is_empty());
+
+        logs.validate(&mut quality, 1);
+        assert_eq!(quality.len(), 1);
+        assert_eq!(quality.iter().next().unwrap().value, "250");
+    }
+
+    #[test]
+    /// Only the lines appended since the previous call are inserted
+    fn tail_growing_log() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut db = crate::sample::empty_db(&tmp.path().join("test.db"));
+        db.execute("INSERT INTO bookkeeper (hash) VALUES ('tail')", [])
+            .unwrap();
+
+        let path = tmp.path().join("all.log");
+        let mut file = std::fs::File::create(&path).unwrap();
+        let content = sample::as_text_v1();
+        let lines: Vec<&str> = content.lines().collect();
+        for line in &lines[..2] {
+            writeln!(file, "{line}").unwrap();
+        }
+        // Incomplete line, still being written
+        write!(file, "{}", &lines[2][..10]).unwrap();
+
+        let conn = db.transaction().unwrap();
+        assert_eq!(RuntimeLogs::tail(&conn, 1, &path, None).unwrap(), 2);
+        conn.commit().unwrap();
+
+        writeln!(file, "{}", &lines[2][10..]).unwrap();
+        writeln!(file, "{}", lines[3]).unwrap();
+        writeln!(file, "{}", lines[4]).unwrap();
+
+        let conn = db.transaction().unwrap();
+        assert_eq!(RuntimeLogs::tail(&conn, 1, &path, None).unwrap(), 3);
+        conn.commit().unwrap();
+
+        let conn = db.transaction().unwrap();
+        assert_eq!(RuntimeLogs::tail(&conn, 1, &path, None).unwrap(), 0);
+        conn.commit().unwrap();
+
+        let total: usize = db
+            .query_row("SELECT COUNT(*) FROM logs", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(total, 5);
+        let message: String = db
+            .query_row("SELECT message FROM logs WHERE id = 3", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(message, "Running COMPASS");
+    }
+
+    #[test]
+    /// Continuation lines of a later tail extend the previous record
+    fn tail_split_record() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut db = crate::sample::empty_db(&tmp.path().join("test.db"));
+        db.execute("INSERT INTO bookkeeper (hash) VALUES ('tail')", [])
+            .unwrap();
+
+        let path = tmp.path().join("all.log");
+        let mut file = std::fs::File::create(&path).unwrap();
+        writeln!(
+            file,
+            "[2025-03-04 05:10:52,266] INFO - Task-1: Running COMPASS"
+        )
+        .unwrap();
+        writeln!(file, "[2025-03-04 05:12:30,801] ERROR - Task-3: Traceback:").unwrap();
+        writeln!(file, "  File \"compass.py\", line 1").unwrap();
+
+        let conn = db.transaction().unwrap();
+        assert_eq!(RuntimeLogs::tail(&conn, 1, &path, None).unwrap(), 2);
+        conn.commit().unwrap();
+
+        writeln!(file, "ValueError: invalid").unwrap();
+        writeln!(file, "[2025-03-04 05:12:31,000] INFO - Task-1: Done").unwrap();
+
+        let conn = db.transaction().unwrap();
+        assert_eq!(RuntimeLogs::tail(&conn, 1, &path, None).unwrap(), 1);
+        conn.commit().unwrap();
+
+        let messages: Vec<String> = db
+            .prepare("SELECT message FROM logs ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<duckdb::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            messages,
+            [
+                "Running COMPASS",
+                "Traceback:\n  File \"compass.py\", line 1\nValueError: invalid",
+                "Done"
+            ]
+        );
+    }
+
+    #[test]
+    /// A log in a custom format is tailed with its pattern
+    fn tail_alternate_pattern() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut db = crate::sample::empty_db(&tmp.path().join("test.db"));
+        db.execute("INSERT INTO bookkeeper (hash) VALUES ('tail')", [])
+            .unwrap();
+        let pattern = compile_pattern(
+            r"^(?P<level>[A-Z]+) \[(?P<ts>[^\]]+)\] (?P<subject>[^:]+): (?P<message>.*)$",
+        )
+        .unwrap();
+
+        let path = tmp.path().join("all.log");
+        let mut file = std::fs::File::create(&path).unwrap();
+        writeln!(
+            file,
+            "INFO [2025-03-04 05:10:52,266] Task-1: Running COMPASS"
+        )
+        .unwrap();
+        writeln!(file, "ERROR [2025-03-04 05:12:30,801] Task-3: Traceback:").unwrap();
+
+        let conn = db.transaction().unwrap();
+        assert_eq!(
+            RuntimeLogs::tail(&conn, 1, &path, Some(&pattern)).unwrap(),
+            2
+        );
+        conn.commit().unwrap();
+
+        writeln!(file, "ValueError: invalid").unwrap();
+        writeln!(file, "INFO [2025-03-04 05:12:31,000] Task-1: Done").unwrap();
+
+        let conn = db.transaction().unwrap();
+        assert_eq!(
+            RuntimeLogs::tail(&conn, 1, &path, Some(&pattern)).unwrap(),
+            1
+        );
+        conn.commit().unwrap();
+
+        let records: Vec<(String, String)> = db
+            .prepare("SELECT level, message FROM logs ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<duckdb::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            records,
+            [
+                ("INFO".to_string(), "Running COMPASS".to_string()),
+                (
+                    "ERROR".to_string(),
+                    "Traceback:\nValueError: invalid".to_string()
+                ),
+                ("INFO".to_string(), "Done".to_string())
+            ]
+        );
+    }
+}
@@ -8,27 +8,40 @@ use std::collections::HashMap;
 
 use tracing::debug;
 
+use super::duration::RunDuration;
+use super::quality::{self, DataQuality, Issue};
+use super::report::ParseReport;
 use crate::error::Result;
 
 // An arbitrary limit to protect against maliciously large JSON files
 const MAX_JSON_FILE_SIZE: u64 = 5_000_000;
 
+/// Files with the metadata of a run, in order of precedence
+///
+/// Some outputs have the configuration of the run in `config.json`,
+/// overlapping with `meta.json`, and some have both.
+pub(super) const METADATA_FILES: [&str; 2] = ["meta.json", "config.json"];
+
 #[allow(dead_code)]
 #[derive(Debug, serde::Deserialize)]
-/// Configuration used to run the scraper
-pub(super) struct Metadata {
+/// Configuration used to run the scraper, as in `meta.json`
+pub struct Metadata {
     username: String,
     versions: HashMap<String, String>,
     technology: String,
     models: Vec<LLMMetadata>,
     time_start_utc: String,
     time_end_utc: String,
-    total_time: f64,
+    /// Missing if not a finite number, see `nonfinite`
+    total_time: Option<RunDuration>,
     total_time_string: String,
     num_jurisdictions_searched: u16,
     num_jurisdictions_found: u16,
     cost: Option<f64>,
     manifest: HashMap<String, String>,
+    #[serde(skip)]
+    /// Warnings found while parsing
+    report: ParseReport,
 
     #[serde(flatten)]
     pub(crate) extra: HashMap<String, serde_json::Value>,
@@ -36,8 +49,8 @@ pub(super) struct Metadata {
 
 #[allow(dead_code)]
 #[derive(Debug, serde::Deserialize)]
-/// Configuration used to run the scraper
-pub(super) struct LLMMetadata {
+/// Configuration of one of the LLM models of a run
+pub struct LLMMetadata {
     name: String,
     llm_call_kwargs: Option<HashMap<String, serde_json::Value>>,
     llm_service_rate_limit: u64,
@@ -50,7 +63,23 @@ pub(super) struct LLMMetadata {
     pub(crate) extra: HashMap<String, serde_json::Value>,
 }
 
+impl LLMMetadata {
+    /// Get a value not in the known configuration, see `Metadata::extra_get()`
+    pub fn extra_get(&self, key: &str) -> Option<&serde_json::Value> {
+        super::extra_get(&self.extra, key)
+    }
+}
+
 impl Metadata {
+    /// Get a value not in the known configuration
+    ///
+    /// Fields unknown to this library are kept as JSON in `extra`. The
+    /// `key` is a dotted path to reach nested values, such as
+    /// "config.llm_call_kwargs.seed".
+    pub fn extra_get(&self, key: &str) -> Option<&serde_json::Value> {
+        super::extra_get(&self.extra, key)
+    }
+
     /// Initialize the database to support Metadata
     pub(super) fn init_db(conn: &duckdb::Transaction) -> Result<()> {
         tracing::trace!("Initializing database for Metadata");
@@ -94,44 +123,158 @@ impl Metadata {
         Ok(())
     }
 
-    pub(super) async fn open<P: AsRef<std::path::Path>>(root: P) -> Result<Self> {
+    /// Open the metadata of a scraper output
+    ///
+    /// Read from `meta.json` and `config.json`, whichever exist, see
+    /// `METADATA_FILES`. With both, the fields are merged, nested objects
+    /// included, with `meta.json` taking precedence on a conflicting
+    /// value, which is registered in the report.
+    ///
+    /// With `tolerant`, comments and trailing commas, as in a hand-edited
+    /// file, are accepted.
+    pub(super) async fn open<P: AsRef<std::path::Path>>(root: P, tolerant: bool) -> Result<Self> {
         debug!("Opening Metadata from {:?}", root.as_ref());
 
-        let path = root.as_ref().join("meta.json");
-        if !path.exists() {
-            tracing::error!("Missing metadata file: {:?}", path);
-            return Err(crate::error::Error::Undefined(
-                "Missing metadata file".to_string(),
-            ));
-        }
+        let mut merged: Option<serde_json::Value> = None;
+        let mut report = ParseReport::default();
+        for filename in METADATA_FILES {
+            let path = root.as_ref().join(filename);
+            if !path.exists() {
+                tracing::trace!("No metadata file {:?}", path);
+                continue;
+            }
+            tracing::trace!("Identified Metadata at {:?}", path);
 
-        tracing::trace!("Identified Metadata at {:?}", path);
+            // These JSON files are expected to be tiny, so protect against
+            // huge files that probably means some mistake.
+            let filesize = tokio::fs::metadata(&path).await?.len();
+            if filesize > MAX_JSON_FILE_SIZE {
+                tracing::error!("Metadata file too large: {:?}", filesize);
+                return Err(crate::error::Error::Undefined(
+                    "Metadata file too large".to_string(),
+                ));
+            }
 
-        // These JSON files are expected to be tiny, so protect against
-        // huge files that probably means some mistake.
-        let filesize = tokio::fs::metadata(&path).await?.len();
-        if filesize > MAX_JSON_FILE_SIZE {
-            tracing::error!("Metadata file too large: {:?}", filesize);
-            return Err(crate::error::Error::Undefined(
-                "Metadata file too large".to_string(),
-            ));
+            let mut content = tokio::fs::read_to_string(path).await?;
+            if tolerant {
+                content = super::jsonc::normalize(&content);
+            }
+            let (json, replaced) = super::nonfinite::to_null(&content);
+            for line in replaced {
+                report.push(filename, Some(line), "Non-finite number taken as missing");
+            }
+            let value: serde_json::Value = serde_json::from_str(&json)?;
+            merged = Some(match merged {
+                None => value,
+                Some(mut merged) => {
+                    merge(&mut merged, value, "", filename, &mut report);
+                    merged
+                }
+            });
         }
 
-        let content = tokio::fs::read_to_string(path).await?;
-        let metadata = Self::from_json(&content)?;
+        let Some(merged) = merged else {
+            tracing::error!("Missing metadata file in: {:?}", root.as_ref());
+            return Err(crate::error::Error::Undefined(
+                "Missing metadata file".to_string(),
+            ));
+        };
+        let mut metadata: Metadata = serde_json::from_value(merged)?;
+        metadata.report = report;
         tracing::trace!("Metadata loaded: {:?}", metadata);
 
         Ok(metadata)
     }
 
-    #[allow(dead_code)]
     /// Extract the configuration from a JSON string
-    pub(super) fn from_json(json: &str) -> Result<Self> {
+    pub fn from_json(json: &str) -> Result<Self> {
         tracing::trace!("Parsing Metadata from JSON: {:?}", json);
-        let metadata: Metadata = serde_json::from_str(json).unwrap();
+        let (json, replaced) = super::nonfinite::to_null(json);
+        let mut metadata: Metadata = serde_json::from_str(&json)?;
+        for line in replaced {
+            metadata.report.push(
+                "meta.json",
+                Some(line),
+                "Non-finite number taken as missing",
+            );
+        }
         Ok(metadata)
     }
 
+    /// Warnings found while parsing
+    pub(super) fn report(&self) -> &ParseReport {
+        &self.report
+    }
+
+    /// Configuration of each LLM model of the run
+    pub fn models(&self) -> &[LLMMetadata] {
+        &self.models
+    }
+
+    /// Name of the LLM models configured for the run
+    pub(super) fn model_names(&self) -> Vec<String> {
+        self.models.iter().map(|m| m.name.clone()).collect()
+    }
+
+    /// Duration of the whole run, if known
+    pub(super) fn total_time(&self) -> Option<RunDuration> {
+        self.total_time
+    }
+
+    /// Share of the searched jurisdictions with a document found
+    ///
+    /// Key completeness metric of a run, from 0 to 1. Returns `None` if
+    /// no jurisdiction was searched.
+    pub(super) fn found_rate(&self) -> Option<f64> {
+        (self.num_jurisdictions_searched > 0).then(|| {
+            f64::from(self.num_jurisdictions_found) / f64::from(self.num_jurisdictions_searched)
+        })
+    }
+
+    /// Validate the Metadata, registering any issue found
+    ///
+    /// With the `counts` of jurisdictions searched and found in
+    /// `jurisdictions.json`, those announced in the metadata are
+    /// cross-checked.
+    pub(super) fn validate(&self, quality: &mut DataQuality, counts: Option<(usize, usize)>) {
+        tracing::trace!("Validating Metadata");
+
+        if let Some(total_time) = self.total_time {
+            quality::check_duration(
+                quality,
+                "metadata",
+                "total_time_string",
+                total_time,
+                &self.total_time_string,
+            );
+        }
+
+        let Some((searched, found)) = counts else {
+            return;
+        };
+        for (field, announced, actual) in [
+            (
+                "num_jurisdictions_searched",
+                self.num_jurisdictions_searched,
+                searched,
+            ),
+            (
+                "num_jurisdictions_found",
+                self.num_jurisdictions_found,
+                found,
+            ),
+        ] {
+            if usize::from(announced) != actual {
+                quality.push(Issue::new(
+                    "metadata",
+                    field,
+                    announced,
+                    format!("Metadata announced {announced} but jurisdictions.json has {actual}"),
+                ));
+            }
+        }
+    }
+
     pub(super) fn write(&self, conn: &duckdb::Transaction, commit_id: usize) -> Result<()> {
         tracing::trace!("Writing Metadata to the database {:?}", self);
         let metadata_id: u32 = conn
@@ -189,6 +332,43 @@ impl Metadata {
     }
 }
 
+/// Merge the fields of `other` into `target`, recursively
+///
+/// Fields missing in `target` are taken from `other`, while on a
+/// conflict, `target` is kept and the conflict is registered in the
+/// report, as found in `filename`. The `path` is the dotted path of
+/// `target`, such as "versions", empty at the top level.
+fn merge(
+    target: &mut serde_json::Value,
+    other: serde_json::Value,
+    path: &str,
+    filename: &str,
+    report: &mut ParseReport,
+) {
+    match (target, other) {
+        (serde_json::Value::Object(target), serde_json::Value::Object(other)) => {
+            for (key, value) in other {
+                let key_path = match path {
+                    "" => key.clone(),
+                    _ => format!("{path}.{key}"),
+                };
+                match target.get_mut(&key) {
+                    Some(existing) => merge(existing, value, &key_path, filename, report),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (target, other) if *target != other => report.push(
+            filename,
+            None,
+            format!("Conflicting {path}: {other}, keeping {target} of meta.json"),
+        ),
+        _ => (),
+    }
+}
+
 #[cfg(test)]
 /// Samples of scraper configuration to support tests
 ///
@@ -248,6 +428,7 @@ pub(crate) mod sample {
 mod test_scraper_metadata {
     use super::Metadata;
     use super::sample::as_text_v1;
+    use crate::scraper::quality::DataQuality;
 
     #[test]
     /// Load a Metadata from a JSON string
@@ -257,4 +438,160 @@ mod test_scraper_metadata {
         assert_eq!(metadata.username, "ppinchuk");
         assert_eq!(metadata.num_jurisdictions_searched, 10);
     }
+
+    #[test]
+    /// A non-finite duration, as written by Python, is taken as missing
+    fn nan_total_time() {
+        let json =
+            as_text_v1().replace(r#""total_time": 537.5009291959941"#, r#""total_time": NaN"#);
+        assert!(json.contains("NaN"));
+        let metadata = Metadata::from_json(&json).unwrap();
+        assert_eq!(metadata.total_time(), None);
+
+        let mut quality = DataQuality::default();
+        metadata.validate(&mut quality, None);
+        assert!(quality.is_empty());
+    }
+
+    #[test]
+    /// Nested values of the unknown fields
+    fn extra_values() {
+        let json = as_text_v1().trim_end().trim_end_matches('}').to_string()
+            + r#",
+            "config": {"llm_call_kwargs": {"seed": 42, "stop": ["END", "STOP"]}},
+            "mode": "batch"
+            }"#;
+        let metadata = Metadata::from_json(&json).unwrap();
+
+        assert_eq!(
+            metadata.extra_get("mode"),
+            Some(&serde_json::json!("batch"))
+        );
+        assert_eq!(
+            metadata.extra_get("config.llm_call_kwargs.seed"),
+            Some(&serde_json::json!(42))
+        );
+        assert_eq!(
+            metadata.extra_get("config.llm_call_kwargs.stop.1"),
+            Some(&serde_json::json!("STOP"))
+        );
+        assert_eq!(
+            metadata.extra_get("config.llm_call_kwargs.temperature"),
+            None
+        );
+        assert_eq!(metadata.extra_get("username"), None);
+        assert_eq!(metadata.models()[0].extra_get("seed"), None);
+    }
+
+    #[tokio::test]
+    /// A hand-edited file, with a trailing comma, only in tolerant mode
+    async fn open_tolerant() {
+        let tmp = tempfile::tempdir().unwrap();
+        let json = as_text_v1().trim_end().trim_end_matches('}').to_string()
+            + ",\n    // Edited by hand\n    }";
+        std::fs::write(tmp.path().join("meta.json"), json).unwrap();
+
+        assert!(Metadata::open(tmp.path(), false).await.is_err());
+        let metadata = Metadata::open(tmp.path(), true).await.unwrap();
+        assert_eq!(metadata.username, "ppinchuk");
+    }
+
+    #[tokio::test]
+    /// Both meta.json and config.json, merged, with meta.json first
+    async fn open_merged() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut meta: serde_json::Value = serde_json::from_str(&as_text_v1()).unwrap();
+        meta.as_object_mut().unwrap().remove("username");
+        std::fs::write(tmp.path().join("meta.json"), meta.to_string()).unwrap();
+        let config = serde_json::json!({
+            "username": "config-user",
+            "technology": "solar",
+            "versions": {"compass": "0.1.1.dev17+gb569353.d20250304", "ords": "0.2.0"},
+            "config": {"out_dir": "outputs"},
+        });
+        std::fs::write(tmp.path().join("config.json"), config.to_string()).unwrap();
+
+        let metadata = Metadata::open(tmp.path(), false).await.unwrap();
+        // Only in config.json
+        assert_eq!(metadata.username, "config-user");
+        assert_eq!(metadata.versions["ords"], "0.2.0");
+        assert_eq!(
+            metadata.extra_get("config.out_dir"),
+            Some(&serde_json::json!("outputs"))
+        );
+        // In both, meta.json first
+        assert_eq!(metadata.technology, "wind");
+        assert_eq!(metadata.versions["elm"], "0.0.11");
+
+        let conflicts: Vec<String> = metadata.report().iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            conflicts,
+            [r#"config.json: Conflicting technology: "solar", keeping "wind" of meta.json"#]
+        );
+    }
+
+    #[tokio::test]
+    /// Only config.json is enough
+    async fn open_config_only() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(tmp.path().join("config.json"), as_text_v1()).unwrap();
+
+        let metadata = Metadata::open(tmp.path(), false).await.unwrap();
+        assert_eq!(metadata.username, "ppinchuk");
+        assert!(metadata.report().is_empty());
+
+        std::fs::remove_file(tmp.path().join("config.json")).unwrap();
+        assert!(Metadata::open(tmp.path(), false).await.is_err());
+    }
+
+    #[test]
+    /// Jurisdictions searched and found, cross-checked with the source
+    fn jurisdiction_counts() {
+        let metadata = Metadata::from_json(&as_text_v1()).unwrap();
+        assert_eq!(metadata.num_jurisdictions_searched, 10);
+        assert_eq!(metadata.num_jurisdictions_found, 7);
+        assert_eq!(metadata.found_rate(), Some(0.7));
+
+        let mut quality = DataQuality::default();
+        metadata.validate(&mut quality, Some((10, 7)));
+        assert!(quality.is_empty());
+        metadata.validate(&mut quality, None);
+        assert!(quality.is_empty());
+
+        metadata.validate(&mut quality, Some((10, 6)));
+        assert_eq!(quality.len(), 1);
+        let issue = quality.iter().next().unwrap();
+        assert_eq!(issue.field, "num_jurisdictions_found");
+        assert_eq!(issue.value, "7");
+
+        let none_searched = as_text_v1().replace(
+            r#""num_jurisdictions_searched": 10"#,
+            r#""num_jurisdictions_searched": 0"#,
+        );
+        let metadata = Metadata::from_json(&none_searched).unwrap();
+        assert_eq!(metadata.found_rate(), None);
+    }
+
+    #[test]
+    /// Both counts are stored in the database
+    fn write_jurisdiction_counts() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut db = crate::sample::empty_db(&tmp.path().join("test.db"));
+        let conn = db.transaction().unwrap();
+        conn.execute("INSERT INTO bookkeeper (hash) VALUES ('meta')", [])
+            .unwrap();
+        Metadata::from_json(&as_text_v1())
+            .unwrap()
+            .write(&conn, 1)
+            .unwrap();
+
+        let counts: (u16, u16) = conn
+            .query_row(
+                "SELECT num_jurisdictions_searched, num_jurisdictions_found FROM scraper_metadata",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(counts, (10, 7));
+    }
 }
@@ -1,26 +1,71 @@
 //! Support for the ordinance scraper output
 
+mod doctor;
+mod duration;
+mod inspect;
+mod jsonc;
+mod log;
 mod metadata;
+mod nonfinite;
 mod ordinance;
+mod quality;
+mod raw;
+mod report;
+mod sink;
 mod source;
 mod usage;
 
 use std::path::{Path, PathBuf};
 
-use tracing::{self, debug, trace};
+use tokio::sync::OnceCell;
+use tracing::{self, debug, trace, warn};
 
 use crate::error;
 use crate::error::Result;
-use metadata::Metadata;
+pub use doctor::{Check, CheckStatus, Diagnosis, doctor};
+pub use inspect::{Inspection, inspect};
+pub(crate) use log::{RuntimeLogs, compile_pattern};
+pub use metadata::{LLMMetadata, Metadata};
 use ordinance::Ordinance;
-#[allow(unused_imports)]
+use quality::DataQuality;
+use report::ParseReport;
+use sink::{DuckDBSink, RecordSink};
 use source::Source;
+pub(crate) use source::checksum_file;
 use usage::Usage;
+pub use usage::UsageTotals;
 
 pub(crate) const SCRAPED_ORDINANCE_VERSION: &str = "0.0.1";
 
-// An arbitrary limit (5MB) to protect against maliciously large JSON files
-const MAX_JSON_FILE_SIZE: u64 = 5 * 1024 * 1024;
+/// Files of the scraper output that might be missing
+const OPTIONAL_FILES: [&str; 3] = ["meta.json", "usage.json", "logs/all.log"];
+
+/// Get a value from the unknown fields collapsed into an `extra` map
+///
+/// The `key` is a dotted path, such as "llm_call_kwargs.seed", to reach
+/// into nested objects. An array element is reached by its index, such
+/// as "tasks.0".
+fn extra_get<'a>(
+    extra: &'a std::collections::HashMap<String, serde_json::Value>,
+    key: &str,
+) -> Option<&'a serde_json::Value> {
+    let (first, rest) = match key.split_once('.') {
+        Some((first, rest)) => (first, Some(rest)),
+        None => (key, None),
+    };
+    let value = extra.get(first)?;
+    match rest {
+        None => Some(value),
+        Some(rest) => {
+            // As a JSON pointer, escaping what is special there
+            let pointer = rest
+                .split('.')
+                .map(|s| format!("/{}", s.replace('~', "~0").replace('/', "~1")))
+                .collect::<String>();
+            value.pointer(&pointer)
+        }
+    }
+}
 
 // Concepts
 // - Lazy loading a scraper output
@@ -60,19 +105,36 @@ const MAX_JSON_FILE_SIZE: u64 = 5 * 1024 * 1024;
 /// The ordinance scraper outputs a directory with a standard structure,
 /// including multiple files and sub-directories. The `ScrapedOrdinance`
 /// compose all that information.
+///
+/// Each component is parsed on its first access, and kept from then on,
+/// thus reading only the usage never parses the ordinance CSV files.
 pub(crate) struct ScrapedOrdinance {
     /// The data model version
     format_version: String,
     /// The root path of the scraped ordinance output
     root: PathBuf,
-    /// The metadata section
-    metadata: Metadata,
+    /// Options to parse the components
+    options: crate::LoadOptions,
+    /// Compiled `options.log_pattern`, if any
+    log_pattern: Option<regex::Regex>,
+    /// The metadata section, if available
+    metadata: OnceCell<Option<Metadata>>,
     /// The source section
-    source: Source,
-    /// The usage section
-    usage: Usage,
+    source: OnceCell<Source>,
+    /// The usage section, if available
+    usage: OnceCell<Option<Usage>>,
     /// The ordinance section
-    ordinance: Ordinance,
+    ordinance: OnceCell<Ordinance>,
+    /// The runtime logs section
+    logs: OnceCell<RuntimeLogs>,
+    /// Data quality issues found while parsing, see `load()`
+    quality: DataQuality,
+    /// Warnings of all the components found while parsing, see `load()`
+    report: ParseReport,
+    /// Optional files missing from the scraper output
+    skipped: Vec<String>,
+    /// When to give up parsing or writing, see `LoadOptions::timeout`
+    deadline: crate::Deadline,
 }
 
 impl ScrapedOrdinance {
@@ -92,15 +154,215 @@ impl ScrapedOrdinance {
         metadata::Metadata::init_db(conn)?;
         usage::Usage::init_db(conn)?;
         ordinance::Ordinance::init_db(conn)?;
+        log::RuntimeLogs::init_db(conn)?;
+        quality::DataQuality::init_db(conn)?;
+        raw::RawInputs::init_db(conn)?;
+
+        Ok(())
+    }
+
+    /// Create the views of the scraper tables that are missing
+    ///
+    /// Migrations drop the views depending on the tables they change,
+    /// and these are only recreated once all the migrations are applied,
+    /// since the views follow the current data model.
+    pub(crate) fn init_views(conn: &duckdb::Transaction) -> Result<()> {
+        debug!("Initializing ScrapedOrdinance views");
+
+        ordinance::Ordinance::init_db(conn)?;
+        usage::Usage::init_view(conn)?;
+
+        Ok(())
+    }
+
+    /// Upgrade the scraper tables from the 0.0.9 data model
+    ///
+    /// Adds the pending record of the log cursors, so a record continued
+    /// in a later tail is extended. Existing cursors have none pending.
+    pub(crate) fn migrate_0_0_10(conn: &duckdb::Transaction) -> Result<()> {
+        debug!("Migrating ScrapedOrdinance database to 0.0.10");
+
+        log::RuntimeLogs::init_db(conn)?;
+        conn.execute_batch("ALTER TABLE log_cursor ADD COLUMN IF NOT EXISTS pending_id INTEGER;")?;
+
+        Ok(())
+    }
+
+    /// Upgrade the scraper tables from the 0.0.8 data model
+    ///
+    /// Adds the link from the usage to the jurisdiction of its source.
+    /// Existing usage is left unlinked.
+    pub(crate) fn migrate_0_0_9(conn: &duckdb::Transaction) -> Result<()> {
+        debug!("Migrating ScrapedOrdinance database to 0.0.9");
+
+        usage::Usage::init_jurisdiction(conn)?;
+
+        Ok(())
+    }
+
+    /// Upgrade the scraper tables from the 0.0.7 data model
+    ///
+    /// Adds the link from the ordinances to their source document, also
+    /// to the `ordinance` view. Existing ordinances are left unlinked.
+    pub(crate) fn migrate_0_0_8(conn: &duckdb::Transaction) -> Result<()> {
+        debug!("Migrating ScrapedOrdinance database to 0.0.8");
+
+        conn.execute_batch(
+            r"
+            DROP VIEW IF EXISTS ordinance;
+            ALTER TABLE quantitative ADD COLUMN IF NOT EXISTS archive_lnk INTEGER;
+            ALTER TABLE qualitative ADD COLUMN IF NOT EXISTS archive_lnk INTEGER;",
+        )?;
+
+        Ok(())
+    }
+
+    /// Upgrade the scraper tables from the 0.0.6 data model
+    ///
+    /// Adds the raw inputs, to store the original ordinance CSV.
+    pub(crate) fn migrate_0_0_7(conn: &duckdb::Transaction) -> Result<()> {
+        debug!("Migrating ScrapedOrdinance database to 0.0.7");
+
+        raw::RawInputs::init_db(conn)?;
+
+        Ok(())
+    }
+
+    /// Upgrade the scraper tables from the 0.0.5 data model
+    ///
+    /// Adds the multipliers of the quantitative ordinances, also to the
+    /// `ordinance` view. Existing multipliers are left only in their
+    /// value and units.
+    pub(crate) fn migrate_0_0_6(conn: &duckdb::Transaction) -> Result<()> {
+        debug!("Migrating ScrapedOrdinance database to 0.0.6");
+
+        conn.execute_batch(
+            r"
+            DROP VIEW IF EXISTS ordinance;
+            ALTER TABLE quantitative ADD COLUMN IF NOT EXISTS mult_value REAL;
+            ALTER TABLE quantitative ADD COLUMN IF NOT EXISTS mult_type TEXT;",
+        )?;
+
+        Ok(())
+    }
+
+    /// Upgrade the scraper tables from the 0.0.4 data model
+    ///
+    /// Adds the `value_type` of the quantitative ordinances to the
+    /// `ordinance` view.
+    pub(crate) fn migrate_0_0_5(conn: &duckdb::Transaction) -> Result<()> {
+        debug!("Migrating ScrapedOrdinance database to 0.0.5");
+
+        conn.execute_batch("DROP VIEW IF EXISTS ordinance;")?;
+
+        Ok(())
+    }
+
+    /// Upgrade the scraper tables from the 0.0.3 data model
+    ///
+    /// Adds the extra values of each usage step, such as cache tokens, as
+    /// JSON.
+    pub(crate) fn migrate_0_0_4(conn: &duckdb::Transaction) -> Result<()> {
+        debug!("Migrating ScrapedOrdinance database to 0.0.4");
+
+        conn.execute_batch(
+            r"
+            DROP VIEW IF EXISTS usage;
+            ALTER TABLE usage_step ADD COLUMN IF NOT EXISTS extra TEXT;",
+        )?;
+
+        Ok(())
+    }
+
+    /// Upgrade the scraper tables from the 0.0.2 data model
+    ///
+    /// Adds the quantitative values normalized to meters. Existing
+    /// records are left without it, since their units were never parsed.
+    pub(crate) fn migrate_0_0_3(conn: &duckdb::Transaction) -> Result<()> {
+        debug!("Migrating ScrapedOrdinance database to 0.0.3");
+
+        conn.execute_batch(
+            r"
+            DROP VIEW IF EXISTS ordinance;
+            ALTER TABLE quantitative ADD COLUMN IF NOT EXISTS value_meters REAL;",
+        )?;
+
+        Ok(())
+    }
+
+    /// Upgrade the scraper tables from the 0.0.1 data model
+    ///
+    /// Adds the `value_type` of quantitative ordinances, the jurisdiction
+    /// details of the `ordinance` view, and the runtime logs and data
+    /// quality tables.
+    pub(crate) fn migrate_0_0_2(conn: &duckdb::Transaction) -> Result<()> {
+        debug!("Migrating ScrapedOrdinance database to 0.0.2");
+
+        conn.execute_batch(
+            r"
+            ALTER TABLE quantitative ADD COLUMN IF NOT EXISTS value_type TEXT;
+            DROP VIEW IF EXISTS ordinance;",
+        )?;
+        log::RuntimeLogs::init_db(conn)?;
+        quality::DataQuality::init_db(conn)?;
 
         Ok(())
     }
 
-    // Keep in mind a lazy state.
     #[allow(dead_code)]
     /// Open an existing scraped ordinance folder
     pub(crate) async fn open<P: AsRef<Path>>(root: P) -> Result<Self> {
+        Self::open_with(root, &crate::LoadOptions::default()).await
+    }
+
+    /// Open an existing scraped ordinance folder, possibly incomplete
+    ///
+    /// Some archived runs legitimately lack some files. With
+    /// `allow_missing`, a missing `usage.json` or `meta.json` is only
+    /// warned, and that component is skipped. The `jurisdictions.json`
+    /// is always required. The runtime logs are always optional. Check
+    /// `skipped()` for what was missing.
+    ///
+    /// The runtime logs are parsed with `log_pattern`, if given.
+    ///
+    /// All the components are parsed and validated right away, see
+    /// `open_lazy()` to parse them only as needed.
+    pub(crate) async fn open_with<P: AsRef<Path>>(
+        root: P,
+        options: &crate::LoadOptions,
+    ) -> Result<Self> {
+        let mut scraped = Self::open_lazy(root.as_ref(), options)?;
+        scraped.load().await?;
+        Ok(scraped)
+    }
+
+    /// Same as `open_with()`, bounded by a `deadline` already running
+    ///
+    /// Such as when several outputs share the same `LoadOptions::timeout`.
+    pub(crate) async fn open_until<P: AsRef<Path>>(
+        root: P,
+        options: &crate::LoadOptions,
+        deadline: crate::Deadline,
+    ) -> Result<Self> {
+        let mut scraped = Self::open_lazy(root.as_ref(), options)?;
+        scraped.deadline = deadline;
+        scraped.load().await?;
+        Ok(scraped)
+    }
+
+    /// Open an existing scraped ordinance folder, without parsing it
+    ///
+    /// Same as `open_with()`, but each component is only parsed on its
+    /// first access, such as `usage()`. Nothing is validated until
+    /// `load()`.
+    pub(crate) fn open_lazy<P: AsRef<Path>>(root: P, options: &crate::LoadOptions) -> Result<Self> {
         trace!("Opening scraped ordinance");
+        let allow_missing = options.allow_missing;
+        let log_pattern = options
+            .log_pattern
+            .as_deref()
+            .map(log::compile_pattern)
+            .transpose()?;
 
         let root = root.as_ref().to_path_buf();
         trace!("Scraper output located at: {:?}", root);
@@ -112,69 +374,501 @@ impl ScrapedOrdinance {
             return Err(error::Error::Undefined("Path does not exist".to_string()));
         }
 
-        let (source, metadata, usage, ordinance) = tokio::try_join!(
-            source::Source::open(&root),
-            metadata::Metadata::open(&root),
-            usage::Usage::open(&root),
-            ordinance::Ordinance::open(&root)
-        )?;
-        trace!("Scraped ordinance opened successfully");
+        let mut skipped = Vec::new();
+        let has_logs = !log::RuntimeLogs::files(&root)?.is_empty();
+        // Without `allow_missing`, only the runtime logs are optional,
+        // and missing them is no reason for a warning
+        for filename in OPTIONAL_FILES {
+            let exists = match filename {
+                "logs/all.log" => has_logs,
+                "meta.json" => metadata::METADATA_FILES
+                    .iter()
+                    .any(|f| root.join(f).exists()),
+                _ => root.join(filename).exists(),
+            };
+            if allow_missing && !exists {
+                warn!("Missing optional file {:?}, skipping it", filename);
+                skipped.push(filename.to_string());
+            }
+        }
 
         Ok(Self {
             root,
             format_version: SCRAPED_ORDINANCE_VERSION.to_string(),
-            metadata,
-            source,
-            usage,
-            ordinance,
+            options: options.clone(),
+            log_pattern,
+            metadata: OnceCell::new(),
+            source: OnceCell::new(),
+            usage: OnceCell::new(),
+            ordinance: OnceCell::new(),
+            logs: OnceCell::new(),
+            quality: DataQuality::default(),
+            report: ParseReport::default(),
+            skipped,
+            deadline: crate::Deadline::after(options.timeout),
         })
     }
 
+    /// Parse all the components, and validate them
+    ///
+    /// The components are parsed concurrently, except those already
+    /// accessed. The issues found replace any previous ones, and so does
+    /// the report of the parse warnings of every component.
+    pub(crate) async fn load(&mut self) -> Result<()> {
+        tokio::try_join!(
+            self.source(),
+            self.metadata(),
+            self.usage(),
+            self.ordinance(),
+            self.logs()
+        )?;
+        trace!("Scraped ordinance opened successfully");
+
+        let source = self.source().await?;
+        let mut report = source.report().clone();
+        if let Some(metadata) = self.metadata().await? {
+            report.extend(metadata.report());
+        }
+        if let Some(usage) = self.usage().await? {
+            report.extend(usage.report());
+        }
+        report.extend(self.ordinance().await?.report());
+        report.extend(self.logs().await?.report());
+        debug!("Found {} parse warnings", report.len());
+
+        let mut quality = DataQuality::default();
+        // A limited load doesn't have all the jurisdictions to compare
+        let counts = self
+            .options
+            .limit_jurisdictions
+            .is_none()
+            .then(|| (source.jurisdictions.len(), source.found_count()));
+        if let Some(metadata) = self.metadata().await? {
+            metadata.validate(&mut quality, counts);
+        }
+        source.validate(&mut quality);
+        self.ordinance().await?.validate(&mut quality);
+        self.logs()
+            .await?
+            .validate(&mut quality, source.jurisdictions.len());
+        // Neither can the usage of the jurisdictions left out be matched
+        if let Some(usage) = self.usage().await?
+            && self.options.limit_jurisdictions.is_none()
+        {
+            usage.validate(&mut quality, &source.names());
+        }
+        debug!("Found {} data quality issues", quality.len());
+
+        self.quality = quality;
+        self.report = report;
+        Ok(())
+    }
+
+    /// Whether an optional file was missing, thus skipped
+    fn is_skipped(&self, filename: &str) -> bool {
+        self.skipped.iter().any(|s| s == filename)
+    }
+
+    /// The source documents, parsed on first access
+    async fn source(&self) -> Result<&Source> {
+        self.source
+            .get_or_try_init(|| async {
+                let mut source = source::Source::open(
+                    &self.root,
+                    self.options.io_buffer_size.unwrap_or(crate::IO_BUFFER_SIZE),
+                    self.options.parallel_hash.unwrap_or(crate::PARALLEL_HASH),
+                )
+                .await?;
+                if let Some(n) = self.options.limit_jurisdictions {
+                    source.truncate(n);
+                }
+                Ok(source)
+            })
+            .await
+    }
+
+    /// The metadata, if not skipped, parsed on first access
+    async fn metadata(&self) -> Result<Option<&Metadata>> {
+        let metadata = self
+            .metadata
+            .get_or_try_init(|| async {
+                match self.is_skipped("meta.json") {
+                    true => Ok(None),
+                    false => Metadata::open(&self.root, self.options.tolerant)
+                        .await
+                        .map(Some),
+                }
+            })
+            .await?;
+        Ok(metadata.as_ref())
+    }
+
+    /// The LLM usage, if not skipped, parsed on first access
+    async fn usage(&self) -> Result<Option<&Usage>> {
+        let usage = self
+            .usage
+            .get_or_try_init(|| async {
+                match self.is_skipped("usage.json") {
+                    true => Ok(None),
+                    false => Usage::open(&self.root, self.options.tolerant)
+                        .await
+                        .map(Some),
+                }
+            })
+            .await?;
+        Ok(usage.as_ref())
+    }
+
+    /// The ordinances, parsed on first access
+    async fn ordinance(&self) -> Result<&Ordinance> {
+        self.ordinance
+            .get_or_try_init(|| async {
+                let mut ordinance = Ordinance::open(
+                    &self.root,
+                    self.options.quantitative_file.as_deref(),
+                    self.deadline,
+                )
+                .await?;
+                if self.options.limit_jurisdictions.is_some() {
+                    ordinance.retain_fips(&self.source().await?.fips());
+                }
+                Ok(ordinance)
+            })
+            .await
+    }
+
+    /// Number of ordinances, both quantitative and qualitative
+    pub(crate) async fn ordinance_count(&self) -> Result<usize> {
+        Ok(self.ordinance().await?.len())
+    }
+
+    /// The runtime logs, parsed on first access
+    async fn logs(&self) -> Result<&RuntimeLogs> {
+        self.logs
+            .get_or_try_init(|| async {
+                let mut logs = RuntimeLogs::open(&self.root, self.log_pattern.as_ref()).await?;
+                if self.options.dedup_logs {
+                    logs.dedup();
+                }
+                Ok(logs)
+            })
+            .await
+    }
+
+    /// Optional files that were missing, thus skipped
+    pub(crate) fn skipped(&self) -> &[String] {
+        &self.skipped
+    }
+
+    /// When to give up parsing or writing this output
+    pub(crate) fn deadline(&self) -> crate::Deadline {
+        self.deadline
+    }
+
+    /// Hash of the content of this output, such as "sha256:..."
+    ///
+    /// Covers the files at the root of the output, such as the JSON and
+    /// the ordinance CSV files, in the order of their names. The
+    /// documents themselves are not read again, since their checksums
+    /// are already part of `jurisdictions.json`.
+    pub(crate) async fn content_hash(&self) -> Result<String> {
+        use sha2::Digest;
+        use tokio::io::AsyncReadExt;
+
+        let mut files = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.root).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_file() {
+                files.push(entry.path());
+            }
+        }
+        files.sort();
+
+        let mut hasher = sha2::Sha256::new();
+        let mut buffer = vec![
+            0;
+            self.options
+                .io_buffer_size
+                .unwrap_or(crate::IO_BUFFER_SIZE)
+                .max(1)
+        ];
+        for path in files {
+            trace!("Hashing {:?}", path);
+            hasher.update(path.file_name().unwrap_or_default().as_encoded_bytes());
+            let mut file = tokio::fs::File::open(&path).await?;
+            loop {
+                let n = file.read(&mut buffer).await?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..n]);
+            }
+        }
+        Ok(format!("sha256:{:x}", hasher.finalize()))
+    }
+
+    /// Everything worth a warning found so far in this output
+    ///
+    /// Includes the skipped optional files, the parse warnings, such as
+    /// a skipped CSV row, and the data quality issues, such as source
+    /// files not matching their checksum.
+    pub(crate) fn warnings(&self) -> Vec<String> {
+        self.skipped
+            .iter()
+            .map(|f| format!("missing {f}"))
+            .chain(self.report.iter().map(|w| w.to_string()))
+            .chain(self.quality.iter().map(|i| i.to_string()))
+            .collect()
+    }
+
+    /// Validate the sources of the documents and ordinances
+    ///
+    /// Optional, since the sources are free text that might legitimately
+    /// be a citation instead of a URL. Only the syntax is checked, without
+    /// any network request.
+    pub(crate) async fn validate_sources(&mut self) -> Result<()> {
+        trace!("Validating sources");
+
+        let mut quality = std::mem::take(&mut self.quality);
+        let before = quality.len();
+        self.source().await?.validate_sources(&mut quality);
+        self.ordinance().await?.validate_sources(&mut quality);
+        debug!("Found {} malformed sources", quality.len() - before);
+
+        self.quality = quality;
+        Ok(())
+    }
+
     #[allow(dead_code)]
-    pub(crate) async fn push(&self, conn: &mut duckdb::Connection, commit_id: usize) -> Result<()> {
+    /// Push the scraped ordinance into the database
+    ///
+    /// Returns the LLM usage totals of this run, if its usage was
+    /// available. With `store_raw`, the original ordinance CSV is also
+    /// stored.
+    pub(crate) async fn push(
+        &self,
+        conn: &duckdb::Transaction<'_>,
+        commit_id: usize,
+    ) -> Result<Option<UsageTotals>> {
         // Load the ordinance into the database
         tracing::trace!("Pushing scraped ordinance into the database");
-        let conn = conn.transaction().unwrap();
-        tracing::trace!("Transaction started");
 
+        let mut sink = DuckDBSink::new(conn, self.deadline);
+        self.write_to(&mut sink, commit_id).await?;
+        let mut quality = DataQuality::default();
+        for source in ordinance::Ordinance::link_archive(conn, commit_id)? {
+            quality.push(quality::Issue::new(
+                "ordinance",
+                "source",
+                source,
+                "Doesn't match the source or filename of any document",
+            ));
+        }
+        quality.write(conn, commit_id)?;
+        if self.usage().await?.is_some() {
+            usage::Usage::link_source(conn, commit_id)?;
+        }
+        if self.options.store_raw {
+            raw::RawInputs::open(&self.root, self.options.quantitative_file.as_deref())
+                .await?
+                .write(conn, commit_id)?;
+        }
+
+        Ok(sink.usage)
+    }
+
+    /// Write all the components into a sink
+    ///
+    /// The components are written in an order that satisfies the
+    /// references between them in the database, since DuckDB checks
+    /// foreign keys on every insert and can't defer them to the commit:
+    /// the bookkeeper must exist before anything else, source documents
+    /// (`archive`) before the `source` that refers to them, the
+    /// `scraper_metadata` before its details, and each `usage_event`
+    /// before its models and steps.
+    async fn write_to(&self, sink: &mut dyn RecordSink, commit_id: usize) -> Result<()> {
         // Do I need to extract the hash here from the full ScrapedOutput?
         // What about username?
-        self.source.record(&conn, commit_id).unwrap();
-        self.metadata.write(&conn, commit_id).unwrap();
-        self.usage().await.unwrap().write(&conn, commit_id).unwrap();
-        self.ordinance.write(&conn, commit_id).unwrap();
-
-        tracing::trace!("Committing transaction");
-        conn.commit()?;
+        // The ordinances check the deadline on every record, while the
+        // other components are checked in between
+        sink.write_source(self.source().await?, commit_id)?;
+        self.deadline.check()?;
+        if let Some(metadata) = self.metadata().await? {
+            sink.write_metadata(metadata, commit_id)?;
+            self.deadline.check()?;
+        }
+        if let Some(usage) = self.usage().await? {
+            sink.write_usage(usage, commit_id)?;
+            self.deadline.check()?;
+        }
+        sink.write_ordinance(self.ordinance().await?, commit_id)?;
+        sink.write_logs(self.logs().await?, commit_id)?;
+        self.deadline.check()?;
+        sink.write_quality(&self.quality, commit_id)?;
 
         Ok(())
     }
+}
 
-    #[allow(dead_code)]
-    async fn usage(&self) -> Result<Usage> {
-        let usage_file = &self.root.join("usage.json");
-        if !usage_file.exists() {
-            trace!("Missing usage file: {:?}", usage_file);
-            return Err(error::Error::Undefined(
-                "Features file does not exist".to_string(),
-            ));
+/// Record a standalone quantitative ordinance CSV
+///
+/// For pipelines that only produce the quantitative ordinance, without
+/// the rest of a scraper output, such as when streamed from the standard
+/// input. The records are validated as in a complete output, and any
+/// issue is recorded together. Of the `options`, only those that apply
+/// to the quantitative ordinance are used: `validate_sources`, `strict`,
+/// and `timeout`, through `deadline`.
+///
+/// Returns the number of records, and the warnings, such as rows skipped
+/// or data quality issues.
+pub(crate) fn write_quantitative_csv<R: std::io::Read>(
+    conn: &duckdb::Transaction,
+    reader: R,
+    commit_id: usize,
+    options: &crate::LoadOptions,
+    deadline: crate::Deadline,
+) -> Result<(usize, Vec<String>)> {
+    let ordinance = Ordinance::from_quantitative_csv(reader)?;
+    deadline.check()?;
+    let mut quality = DataQuality::default();
+    ordinance.validate(&mut quality);
+    if options.validate_sources {
+        ordinance.validate_sources(&mut quality);
+    }
+
+    let warnings: Vec<String> = ordinance
+        .report()
+        .iter()
+        .map(|w| w.to_string())
+        .chain(quality.iter().map(|i| i.to_string()))
+        .collect();
+    if options.strict && !warnings.is_empty() {
+        return Err(crate::error::Error::Warnings(warnings));
+    }
+
+    ordinance.write(conn, commit_id, deadline)?;
+    quality.write(conn, commit_id)?;
+    Ok((ordinance.len(), warnings))
+}
+
+/// Add the source documents of a scraper output to an existing commit
+///
+/// Only `jurisdictions.json` and `ordinance_files/` are used, see
+/// `Source::append()`. The ordinances of the commit are linked again to
+/// the archive, since some might refer to the new documents.
+///
+/// With `LoadOptions::strict`, any document skipped fails instead.
+///
+/// Returns the number of documents added.
+pub(crate) async fn append_sources<P: AsRef<Path>>(
+    conn: &duckdb::Transaction<'_>,
+    root: P,
+    commit_id: usize,
+    options: &crate::LoadOptions,
+) -> Result<usize> {
+    let source = Source::open(
+        root,
+        options.io_buffer_size.unwrap_or(crate::IO_BUFFER_SIZE),
+        options.parallel_hash.unwrap_or(crate::PARALLEL_HASH),
+    )
+    .await?;
+    let mut quality = DataQuality::default();
+    let added = source.append(conn, commit_id, &mut quality)?;
+    if options.strict && !quality.is_empty() {
+        return Err(crate::error::Error::Warnings(
+            quality.iter().map(|i| i.to_string()).collect(),
+        ));
+    }
+    quality.write(conn, commit_id)?;
+    // The sources still unmatched were recorded when the commit was loaded
+    Ordinance::link_archive(conn, commit_id)?;
+    Ok(added)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The JSON files of a scraper output
+pub enum JsonKind {
+    /// Run metadata, `meta.json`
+    Meta,
+    /// LLM usage, `usage.json`
+    Usage,
+    /// Jurisdictions and their documents, `jurisdictions.json`
+    Jurisdictions,
+}
+
+/// Validate a single JSON file of a scraper output
+///
+/// The file is parsed exactly as when loading the complete output, so
+/// this is a quick check before a load. A file that doesn't parse gives
+/// an [`error::Error::InvalidJson`], which locates the problem by line
+/// and column. With `tolerant`, comments and trailing commas are
+/// accepted in the metadata and usage, as when loading with
+/// `LoadOptions::tolerant`.
+pub fn validate_json<P: AsRef<Path>>(kind: JsonKind, path: P, tolerant: bool) -> Result<()> {
+    trace!("Validating {:?} as {:?}", path.as_ref(), kind);
+
+    let read = |path: &Path| -> Result<String> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(match tolerant {
+            true => jsonc::normalize(&content),
+            false => content,
+        })
+    };
+    match kind {
+        JsonKind::Meta => {
+            Metadata::from_json(&read(path.as_ref())?)?;
+        }
+        JsonKind::Usage => {
+            Usage::from_json(&read(path.as_ref())?)?;
         }
+        JsonKind::Jurisdictions => {
+            let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+            Source::stream(reader, &mut report::ParseReport::default(), |_| Ok(()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+/// Samples of a complete scraper output to support tests
+pub(crate) mod sample {
+    use super::{log, metadata, ordinance, source, usage};
+    use crate::error::Result;
+    use std::io::Write;
 
-        let usage = Usage::from_json(&std::fs::read_to_string(usage_file)?)
-            .expect("Failed to parse usage file");
+    /// Populate a directory as a complete scraper output
+    pub(crate) fn as_dir<P: AsRef<std::path::Path>>(path: P) -> Result<()> {
+        let root = path.as_ref();
 
-        Ok(usage)
+        let _source_file = source::sample::as_file(root.join("jurisdictions.json"))?;
+        let ordinance_files_path = root.join("ordinance_files");
+        std::fs::create_dir(&ordinance_files_path)?;
+        let source_filename = ordinance_files_path.join("source.pdf");
+        let mut source_file = std::fs::File::create(source_filename)?;
+        writeln!(source_file, "This is a sample ordinance file")?;
+
+        let _metadata_file = metadata::sample::as_file(root.join("meta.json"))?;
+        let _usage_file = usage::sample::as_file(root.join("usage.json"))?;
+        ordinance::sample::as_file(root)?;
+
+        std::fs::create_dir(root.join("logs"))?;
+        let _log_file = log::sample::as_file(root.join("logs").join("all.log"))?;
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::ScrapedOrdinance;
-    use super::metadata;
-    use super::ordinance;
-    use super::source;
-    use super::usage;
-    use std::io::Write;
+    use super::log::RuntimeLogs;
+    use super::metadata::Metadata;
+    use super::ordinance::Ordinance;
+    use super::quality::DataQuality;
+    use super::sample;
+    use super::sink::RecordSink;
+    use super::source::Source;
+    use super::usage::Usage;
+    use crate::error::Result;
 
     #[tokio::test]
     /// Opening an inexistent path should give an error
@@ -187,23 +881,85 @@ mod tests {
         ScrapedOrdinance::open(target).await.unwrap_err();
     }
 
+    #[tokio::test]
+    /// Everything tolerated while parsing is reported, by file and line
+    async fn parse_report() {
+        use std::io::Write;
+
+        let tmp = tempfile::tempdir().unwrap();
+        sample::as_dir(tmp.path()).unwrap();
+
+        let csv = super::ordinance::sample::quantitative_basic()
+            + "county-3,state-3,,county,not-a-fips,feature-3,,,,,,,2003,,source-3\n"
+            + "county-4,state-4,,county,44444,feature-4,,,,,,,2004,,source,4\n";
+        std::fs::write(tmp.path().join("quantitative_ordinances.csv"), csv).unwrap();
+
+        let meta = std::fs::read_to_string(tmp.path().join("meta.json")).unwrap();
+        let nan_line = meta
+            .lines()
+            .position(|l| l.contains("\"total_time\""))
+            .unwrap()
+            + 1;
+        let meta = meta.replace(r#""total_time": 537.5009291959941"#, r#""total_time": NaN"#);
+        std::fs::write(tmp.path().join("meta.json"), meta).unwrap();
+
+        let log_path = tmp.path().join("logs").join("all.log");
+        let log_lines = std::fs::read_to_string(&log_path).unwrap().lines().count();
+        let mut log = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&log_path)
+            .unwrap();
+        log.write_all(b"\xff\n").unwrap();
+
+        let ordinance = ScrapedOrdinance::open(tmp.path()).await.unwrap();
+        let report: Vec<(&str, Option<u64>)> = ordinance
+            .report
+            .iter()
+            .map(|w| (w.file.as_str(), w.line))
+            .collect();
+        assert_eq!(
+            report,
+            [
+                ("meta.json", Some(nan_line as u64)),
+                ("quantitative_ordinances.csv", Some(4)),
+                ("quantitative_ordinances.csv", Some(5)),
+                ("logs/all.log", Some(log_lines as u64 + 1)),
+            ]
+        );
+        assert!(
+            ordinance
+                .report
+                .iter()
+                .nth(1)
+                .unwrap()
+                .message
+                .starts_with("Skipped")
+        );
+        assert!(
+            ordinance
+                .report
+                .iter()
+                .nth(2)
+                .unwrap()
+                .message
+                .starts_with("Merged")
+        );
+        // Loaded nonetheless, with the two valid rows and the merged one
+        assert_eq!(ordinance.ordinance_count().await.unwrap(), 3 + 2);
+        assert!(
+            ordinance
+                .warnings()
+                .iter()
+                .any(|w| w.starts_with("quantitative_ordinances.csv:4: Skipped unparsable row"))
+        );
+    }
+
     #[tokio::test]
     /// Open a Scraped Ordinance raw output
     async fn open_scraped_ordinance() {
         // A sample ordinance file for now.
         let target = tempfile::tempdir().unwrap();
-
-        let _source_file =
-            source::sample::as_file(target.path().join("jurisdictions.json")).unwrap();
-        let ordinance_files_path = target.path().join("ordinance_files");
-        std::fs::create_dir(&ordinance_files_path).unwrap();
-        let source_filename = ordinance_files_path.join("source.pdf");
-        let mut source_file = std::fs::File::create(source_filename).unwrap();
-        writeln!(source_file, "This is a sample ordinance file").unwrap();
-
-        let _metadata_file = metadata::sample::as_file(target.path().join("meta.json")).unwrap();
-        let _usage_file = usage::sample::as_file(target.path().join("usage.json")).unwrap();
-        ordinance::sample::as_file(target.path()).unwrap();
+        sample::as_dir(target.path()).unwrap();
 
         let demo = ScrapedOrdinance::open(target).await.unwrap();
         dbg!(&demo);
@@ -228,4 +984,310 @@ mod tests {
         demo.push(&mut db, commit_id).await.unwrap();
         */
     }
+
+    #[derive(Default)]
+    /// A sink that only counts what it receives
+    struct CountingSink {
+        calls: usize,
+        jurisdictions: usize,
+    }
+
+    impl RecordSink for CountingSink {
+        fn write_source(&mut self, source: &Source, _commit_id: usize) -> Result<()> {
+            self.calls += 1;
+            self.jurisdictions += source.jurisdictions.len();
+            Ok(())
+        }
+
+        fn write_metadata(&mut self, _metadata: &Metadata, _commit_id: usize) -> Result<()> {
+            self.calls += 1;
+            Ok(())
+        }
+
+        fn write_usage(&mut self, _usage: &Usage, _commit_id: usize) -> Result<()> {
+            self.calls += 1;
+            Ok(())
+        }
+
+        fn write_ordinance(&mut self, _ordinance: &Ordinance, _commit_id: usize) -> Result<()> {
+            self.calls += 1;
+            Ok(())
+        }
+
+        fn write_logs(&mut self, _logs: &RuntimeLogs, _commit_id: usize) -> Result<()> {
+            self.calls += 1;
+            Ok(())
+        }
+
+        fn write_quality(&mut self, _quality: &DataQuality, _commit_id: usize) -> Result<()> {
+            self.calls += 1;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    /// Write a Scraped Ordinance into an alternative sink
+    async fn write_to_counting_sink() {
+        let target = tempfile::tempdir().unwrap();
+        sample::as_dir(target.path()).unwrap();
+        let demo = ScrapedOrdinance::open(target.path()).await.unwrap();
+
+        let mut sink = CountingSink::default();
+        demo.write_to(&mut sink, 1).await.unwrap();
+        assert_eq!(sink.calls, 6);
+        assert_eq!(sink.jurisdictions, 1);
+    }
+
+    /// A DuckDB sink taking a while on every component
+    struct SlowSink<'a>(super::DuckDBSink<'a>);
+
+    impl SlowSink<'_> {
+        fn wait() {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+    }
+
+    impl RecordSink for SlowSink<'_> {
+        fn write_source(&mut self, source: &Source, commit_id: usize) -> Result<()> {
+            Self::wait();
+            self.0.write_source(source, commit_id)
+        }
+
+        fn write_metadata(&mut self, metadata: &Metadata, commit_id: usize) -> Result<()> {
+            Self::wait();
+            self.0.write_metadata(metadata, commit_id)
+        }
+
+        fn write_usage(&mut self, usage: &Usage, commit_id: usize) -> Result<()> {
+            Self::wait();
+            self.0.write_usage(usage, commit_id)
+        }
+
+        fn write_ordinance(&mut self, ordinance: &Ordinance, commit_id: usize) -> Result<()> {
+            Self::wait();
+            self.0.write_ordinance(ordinance, commit_id)
+        }
+
+        fn write_logs(&mut self, logs: &RuntimeLogs, commit_id: usize) -> Result<()> {
+            Self::wait();
+            self.0.write_logs(logs, commit_id)
+        }
+
+        fn write_quality(&mut self, quality: &DataQuality, commit_id: usize) -> Result<()> {
+            Self::wait();
+            self.0.write_quality(quality, commit_id)
+        }
+    }
+
+    #[tokio::test]
+    /// A load exceeding its timeout is interrupted and rolled back
+    async fn timeout_rolls_back() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut db = crate::sample::empty_db(&tmp.path().join("test.db"));
+
+        let target = tempfile::tempdir().unwrap();
+        sample::as_dir(target.path()).unwrap();
+        let mut demo = ScrapedOrdinance::open(target.path()).await.unwrap();
+
+        let timeout = std::time::Duration::from_millis(30);
+        demo.deadline = crate::Deadline::after(Some(timeout));
+        let tx = db.transaction().unwrap();
+        let result = async {
+            tx.execute("INSERT INTO bookkeeper (hash) VALUES ('slow')", [])?;
+            let mut sink = SlowSink(super::DuckDBSink::new(&tx, demo.deadline()));
+            demo.write_to(&mut sink, 1).await?;
+            tx.commit()?;
+            Ok::<_, crate::error::Error>(())
+        }
+        .await;
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::LoadTimeout(t)) if t == timeout
+        ));
+
+        for table in ["bookkeeper", "source", "archive", "quantitative"] {
+            let n: usize = db
+                .query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+                    row.get(0)
+                })
+                .unwrap();
+            assert_eq!(n, 0, "{table} was not rolled back");
+        }
+    }
+
+    #[tokio::test]
+    /// Pushing a complete output satisfies every foreign key
+    async fn push_satisfies_foreign_keys() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut db = crate::sample::empty_db(&tmp.path().join("test.db"));
+        db.execute("INSERT INTO bookkeeper (hash) VALUES ('fk')", [])
+            .unwrap();
+
+        let target = tempfile::tempdir().unwrap();
+        sample::as_dir(target.path()).unwrap();
+        let demo = ScrapedOrdinance::open(target.path()).await.unwrap();
+        let tx = db.transaction().unwrap();
+        demo.push(&tx, 1).await.unwrap();
+        tx.commit().unwrap();
+
+        let mut stmt = db
+            .prepare(
+                r"SELECT table_name, constraint_column_names[1],
+                    referenced_table, referenced_column_names[1]
+                  FROM duckdb_constraints()
+                  WHERE constraint_type = 'FOREIGN KEY'",
+            )
+            .unwrap();
+        let foreign_keys = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })
+            .unwrap()
+            .collect::<duckdb::Result<Vec<_>>>()
+            .unwrap();
+        assert!(foreign_keys.len() > 5);
+
+        for (table, column, referenced, referenced_column) in foreign_keys {
+            let orphans: usize = db
+                .query_row(
+                    &format!(
+                        r#"SELECT COUNT(*) FROM "{table}" t
+                          WHERE t."{column}" IS NOT NULL AND NOT EXISTS
+                            (SELECT 1 FROM "{referenced}" r
+                              WHERE r."{referenced_column}" = t."{column}")"#
+                    ),
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(orphans, 0, "{table}.{column} -> {referenced}");
+        }
+
+        let sources: usize = db
+            .query_row("SELECT COUNT(*) FROM source", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(sources, 1);
+    }
+
+    #[tokio::test]
+    /// Reading only the usage never parses the ordinances
+    async fn open_lazy_usage_only() {
+        let target = tempfile::tempdir().unwrap();
+        sample::as_dir(target.path()).unwrap();
+        // Not valid UTF-8, thus fails if parsed
+        std::fs::write(
+            target.path().join("quantitative_ordinances.csv"),
+            b"county,\xff\xfe\n",
+        )
+        .unwrap();
+
+        let lazy =
+            ScrapedOrdinance::open_lazy(target.path(), &crate::LoadOptions::default()).unwrap();
+        let usage = lazy.usage().await.unwrap().unwrap();
+        assert!(!usage.jurisdiction.is_empty());
+        assert!(lazy.ordinance.get().is_none());
+
+        // The malformed CSV, if it were parsed
+        assert!(lazy.ordinance().await.is_err());
+        ScrapedOrdinance::open(target.path()).await.unwrap_err();
+    }
+
+    #[test]
+    /// DuckDB checks foreign keys on each insert, even in a transaction
+    ///
+    /// Hence the order of `write_to()`, and no way to load transiently
+    /// inconsistent data with deferred checks.
+    fn foreign_keys_not_deferred() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = crate::sample::empty_db(&tmp.path().join("test.db"));
+        let result = db.execute_batch(
+            "BEGIN;
+            INSERT INTO source (bookkeeper_lnk, full_name) VALUES (1, 'early');
+            INSERT INTO bookkeeper (hash) VALUES ('late');
+            COMMIT;",
+        );
+        assert!(result.is_err());
+        db.execute_batch("ROLLBACK").unwrap();
+
+        let sources: usize = db
+            .query_row("SELECT COUNT(*) FROM source", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(sources, 0);
+    }
+
+    #[tokio::test]
+    /// A missing usage.json fails unless explicitly allowed
+    async fn open_without_usage() {
+        let target = tempfile::tempdir().unwrap();
+        sample::as_dir(target.path()).unwrap();
+        std::fs::remove_file(target.path().join("usage.json")).unwrap();
+
+        ScrapedOrdinance::open(target.path()).await.unwrap_err();
+
+        let options = crate::LoadOptions {
+            allow_missing: true,
+            ..Default::default()
+        };
+        let demo = ScrapedOrdinance::open_with(target.path(), &options)
+            .await
+            .unwrap();
+        assert_eq!(demo.skipped(), ["usage.json"]);
+
+        let mut sink = CountingSink::default();
+        demo.write_to(&mut sink, 1).await.unwrap();
+        assert_eq!(sink.calls, 5);
+    }
+
+    #[tokio::test]
+    /// Missing runtime logs are neither skipped nor warned, unless allowed
+    async fn open_without_logs() {
+        let target = tempfile::tempdir().unwrap();
+        sample::as_dir(target.path()).unwrap();
+        std::fs::remove_dir_all(target.path().join("logs")).unwrap();
+
+        let demo = ScrapedOrdinance::open(target.path()).await.unwrap();
+        assert!(demo.skipped().is_empty());
+        assert!(!demo.warnings().iter().any(|w| w.contains("all.log")));
+
+        let options = crate::LoadOptions {
+            allow_missing: true,
+            ..Default::default()
+        };
+        let demo = ScrapedOrdinance::open_with(target.path(), &options)
+            .await
+            .unwrap();
+        assert_eq!(demo.skipped(), ["logs/all.log"]);
+    }
+
+    #[test]
+    /// Each kind of JSON file, valid and malformed
+    fn validate_json() {
+        use super::{JsonKind, validate_json};
+
+        let tmp = tempfile::tempdir().unwrap();
+        sample::as_dir(tmp.path()).unwrap();
+        for (kind, filename) in [
+            (JsonKind::Meta, "meta.json"),
+            (JsonKind::Usage, "usage.json"),
+            (JsonKind::Jurisdictions, "jurisdictions.json"),
+        ] {
+            let path = tmp.path().join(filename);
+            validate_json(kind, &path, false).unwrap();
+
+            // Truncated in the middle of the content
+            let content = std::fs::read_to_string(&path).unwrap();
+            std::fs::write(&path, &content[..content.len() / 2]).unwrap();
+            let err = validate_json(kind, &path, false).unwrap_err();
+            assert!(
+                matches!(err, crate::error::Error::InvalidJson(_)),
+                "{filename}: {err:?}"
+            );
+            assert!(err.to_string().contains(" line "), "{filename}: {err}");
+        }
+    }
 }
@@ -0,0 +1,201 @@
+//! Non-finite numbers in the scraper output
+//!
+//! A scraper bug can produce `NaN` or `Infinity` in a numeric field,
+//! such as a value or a duration. Python writes those as the bare
+//! tokens `NaN`, `Infinity`, and `-Infinity`, which are not valid JSON,
+//! while in a CSV those parse as floats that can't be stored
+//! meaningfully. Instead of losing the whole file for one bad cell, such
+//! values are taken as missing, with a warning.
+
+use std::io::{BufRead, Read};
+
+use tracing::warn;
+
+/// Deserialize an optional float, taking a non-finite one as missing
+///
+/// Intended for `#[serde(deserialize_with = "...")]` on `Option<f64>`
+/// fields, such as the values of a CSV.
+pub(super) fn finite<'de, D>(deserializer: D) -> std::result::Result<Option<f64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value: Option<f64> = serde::Deserialize::deserialize(deserializer)?;
+    match value {
+        Some(v) if !v.is_finite() => {
+            warn!("Ignoring non-finite value {}", v);
+            Ok(None)
+        }
+        v => Ok(v),
+    }
+}
+
+/// Replace the non-finite numbers of a JSON text with `null`
+///
+/// See `NullNonFinite`. Also returns the lines where those were replaced.
+pub(super) fn to_null(json: &str) -> (String, Vec<u64>) {
+    let mut output = String::with_capacity(json.len());
+    let mut reader = NullNonFinite::new(json.as_bytes());
+    reader
+        .read_to_string(&mut output)
+        // Only ASCII is inserted, and reading from a slice never fails
+        .expect("Replacing tokens of valid UTF-8 is valid UTF-8");
+    (output, reader.replaced)
+}
+
+/// Reader replacing the non-finite numbers of a JSON text with `null`
+///
+/// The bare tokens `NaN`, `Infinity`, and `-Infinity` become `null`,
+/// thus a field that accepts a missing value can be parsed. Content
+/// within strings is never changed. Streams, thus never holding the
+/// whole text in memory. The lines where tokens were replaced are kept,
+/// see `replaced()`, for the caller to report.
+pub(super) struct NullNonFinite<R> {
+    inner: std::io::BufReader<R>,
+    /// Bytes ready to be read, such as the replacement of a token
+    pending: std::collections::VecDeque<u8>,
+    in_string: bool,
+    escaped: bool,
+    /// Current line, starting from 1
+    line: u64,
+    /// Line of each token replaced so far
+    replaced: Vec<u64>,
+}
+
+impl<R: Read> NullNonFinite<R> {
+    pub(super) fn new(inner: R) -> Self {
+        Self {
+            inner: std::io::BufReader::new(inner),
+            pending: std::collections::VecDeque::new(),
+            in_string: false,
+            escaped: false,
+            line: 1,
+            replaced: Vec::new(),
+        }
+    }
+
+    /// Line of each token replaced so far
+    pub(super) fn replaced(&self) -> &[u64] {
+        &self.replaced
+    }
+
+    /// Next byte of the inner reader, if any
+    fn next_byte(&mut self) -> std::io::Result<Option<u8>> {
+        let byte = self.inner.fill_buf()?.first().copied();
+        if byte.is_some() {
+            self.inner.consume(1);
+        }
+        Ok(byte)
+    }
+
+    /// Skip the rest of a token, such as the "aN" of "NaN"
+    fn skip_token(&mut self) -> std::io::Result<()> {
+        while let Some(&b) = self.inner.fill_buf()?.first() {
+            if !b.is_ascii_alphabetic() {
+                break;
+            }
+            self.inner.consume(1);
+        }
+        Ok(())
+    }
+
+    /// Process the next byte of the inner reader into `pending`
+    ///
+    /// Returns false at the end of the inner reader.
+    fn advance(&mut self) -> std::io::Result<bool> {
+        let Some(b) = self.next_byte()? else {
+            return Ok(false);
+        };
+        if b == b'\n' {
+            self.line += 1;
+        }
+
+        if self.in_string {
+            match (self.escaped, b) {
+                (true, _) => self.escaped = false,
+                (false, b'\\') => self.escaped = true,
+                (false, b'"') => self.in_string = false,
+                _ => (),
+            }
+            self.pending.push_back(b);
+            return Ok(true);
+        }
+
+        let negative_infinity = b == b'-' && self.inner.fill_buf()?.first().copied() == Some(b'I');
+        // Outside strings, JSON has no other token starting in uppercase
+        if b == b'N' || b == b'I' || negative_infinity {
+            self.skip_token()?;
+            self.replaced.push(self.line);
+            self.pending.extend(b"null");
+        } else {
+            self.in_string = b == b'"';
+            self.pending.push_back(b);
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for NullNonFinite<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pending.len() < buf.len() && self.advance()? {}
+
+        let n = self.pending.len().min(buf.len());
+        for (dst, src) in buf.iter_mut().zip(self.pending.drain(..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test_nonfinite {
+    use super::*;
+
+    #[test]
+    fn json_tokens() {
+        let (json, replaced) = to_null("{\"a\": NaN,\n\"b\": [Infinity, -Infinity, -1.5]}");
+        assert_eq!(json, "{\"a\": null,\n\"b\": [null, null, -1.5]}");
+        assert_eq!(replaced, [1, 2, 2]);
+    }
+
+    #[test]
+    /// Strings are never changed, even with escaped quotes
+    fn json_strings() {
+        let json = r#"{"NaN": "Infinity \" NaN", "b": NaN}"#;
+        assert_eq!(
+            to_null(json),
+            (
+                r#"{"NaN": "Infinity \" NaN", "b": null}"#.to_string(),
+                vec![1]
+            )
+        );
+    }
+
+    #[test]
+    /// Read in tiny chunks, as a streaming parser could
+    fn small_reads() {
+        let mut reader = NullNonFinite::new(&b"[NaN,1,-Infinity]"[..]);
+        let mut output = Vec::new();
+        let mut buf = [0; 1];
+        while reader.read(&mut buf).unwrap() > 0 {
+            output.push(buf[0]);
+        }
+        assert_eq!(output, b"[null,1,null]");
+        assert_eq!(reader.replaced(), [1, 1]);
+    }
+
+    #[test]
+    fn csv_values() {
+        #[derive(Debug, serde::Deserialize)]
+        struct Row {
+            #[serde(deserialize_with = "finite")]
+            value: Option<f64>,
+        }
+
+        let csv = "value\nNaN\ninf\n-Infinity\n1.5\n\"\"\n";
+        let values: Vec<Option<f64>> = csv::Reader::from_reader(csv.as_bytes())
+            .deserialize()
+            .map(|r: csv::Result<Row>| r.unwrap().value)
+            .collect();
+        assert_eq!(values, [None, None, None, Some(1.5), None]);
+    }
+}
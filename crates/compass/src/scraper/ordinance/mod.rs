@@ -2,15 +2,138 @@
 
 mod qualitative;
 mod quantitative;
+mod units;
 
-use tracing::{debug, trace};
+use tracing::{debug, trace, warn};
 
 use crate::error::Result;
+use crate::scraper::quality::DataQuality;
+use crate::scraper::report::ParseReport;
+
+/// Known names of the quantitative ordinance file, in order of preference
+///
+/// The scraper named it differently over its versions.
+const QUANTITATIVE_FILENAMES: [&str; 3] = [
+    "quantitative_ordinances.csv",
+    "ordinances.csv",
+    "quantitative.csv",
+];
+
+/// Locate the quantitative ordinance file of a scraper output
+///
+/// Tries `filename`, if given, otherwise each one of the known names, in
+/// order. Returns the first existing path, or the candidates tried if
+/// none exists.
+pub(super) fn quantitative_path(
+    root: &std::path::Path,
+    filename: Option<&str>,
+) -> std::result::Result<std::path::PathBuf, Vec<String>> {
+    let candidates: Vec<&str> = match filename {
+        Some(filename) => vec![filename],
+        None => QUANTITATIVE_FILENAMES.to_vec(),
+    };
+    candidates
+        .iter()
+        .map(|filename| root.join(filename))
+        .find(|path| path.exists())
+        .ok_or_else(|| candidates.iter().map(|c| c.to_string()).collect())
+}
+
+/// Read all the records of an ordinance CSV file
+///
+/// The last column is free text, and the scraper doesn't always quote it,
+/// thus a comma there results in more fields than columns. Instead of
+/// losing that row, the extra trailing fields are merged back into the
+/// last column. Rows that still can't be parsed are skipped, and
+/// both are registered in the returned report.
+///
+/// Blocking, thus parsed in `spawn_blocking()`, giving up once past the
+/// `deadline`.
+async fn read_records<T>(
+    path: std::path::PathBuf,
+    deadline: crate::Deadline,
+) -> Result<(Vec<T>, ParseReport)>
+where
+    T: serde::de::DeserializeOwned + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        parse_records(std::fs::File::open(&path)?, &name, deadline)
+    })
+    .await
+    .map_err(|e| crate::error::Error::Undefined(e.to_string()))?
+}
+
+/// Parse all the records of an ordinance CSV from a reader
+///
+/// Same as `read_records`, for content that is not in a file, such as
+/// the standard input. `name` identifies the content in the warnings.
+///
+/// Only a header, without any row, is a valid output of a run that found
+/// no ordinance, resulting in no records. Without even a header, it is
+/// an `Error::EmptyCsv`. Past the `deadline`, it is an
+/// `Error::LoadTimeout`.
+fn parse_records<T, R>(
+    reader: R,
+    name: &str,
+    deadline: crate::Deadline,
+) -> Result<(Vec<T>, ParseReport)>
+where
+    T: serde::de::DeserializeOwned,
+    R: std::io::Read,
+{
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .delimiter(b',')
+        .from_reader(reader);
+    let headers = rdr.headers()?.clone();
+    if headers.iter().all(|h| h.trim().is_empty()) {
+        return Err(crate::error::Error::EmptyCsv(format!("{name:?}")));
+    }
+    let n_columns = headers.len();
+
+    let mut output = Vec::new();
+    let mut report = ParseReport::default();
+    for result in rdr.records() {
+        deadline.check()?;
+        let mut record = result?;
+        let line = record.position().map(|p| p.line());
+        if record.len() > n_columns {
+            trace!("Merging extra fields of {:?}", record);
+            report.push(
+                name,
+                line,
+                format!(
+                    "Merged {} extra field(s) into the last column",
+                    record.len() - n_columns
+                ),
+            );
+            let last = record
+                .iter()
+                .skip(n_columns - 1)
+                .collect::<Vec<_>>()
+                .join(",");
+            let mut merged: csv::StringRecord = record.iter().take(n_columns - 1).collect();
+            merged.push_field(&last);
+            merged.set_position(record.position().cloned());
+            record = merged;
+        }
+        match record.deserialize(Some(&headers)) {
+            Ok(r) => output.push(r),
+            Err(e) => report.push(name, line, format!("Skipped unparsable row: {e}")),
+        }
+    }
+
+    Ok((output, report))
+}
 
 #[derive(Debug)]
 pub(super) struct Ordinance {
     quantitative: quantitative::Quantitative,
     qualitative: qualitative::Qualitative,
+    /// Warnings found while parsing both files
+    report: ParseReport,
 }
 
 impl Ordinance {
@@ -26,12 +149,17 @@ impl Ordinance {
         conn.execute_batch(
             r"
             CREATE VIEW IF NOT EXISTS ordinance AS
-              SELECT bookkeeper_lnk, FIPS, feature, NULL as feature_subtype,
-                value AS 'quantitative', NULL AS 'qualitative'
+              SELECT bookkeeper_lnk, county, state, subdivison AS subdivision,
+                jurisdiction_type, FIPS, feature, NULL as feature_subtype,
+                value AS 'quantitative', value_type, units, mult_value, mult_type,
+                NULL AS 'qualitative', ord_year, section, source, archive_lnk
               FROM quantitative
               UNION
-                SELECT bookkeeper_lnk, FIPS, feature, NULL as feature_subtype,
-                  NULL AS 'quantitative', summary AS 'qualitative'
+                SELECT bookkeeper_lnk, county, state, subdivison AS subdivision,
+                  jurisdiction_type, FIPS, feature, NULL as feature_subtype,
+                  NULL AS 'quantitative', NULL AS value_type, NULL AS units,
+                  NULL AS mult_value, NULL AS mult_type, summary AS 'qualitative',
+                  ord_year, section, source, archive_lnk
                 FROM qualitative;",
         )?;
 
@@ -40,16 +168,25 @@ impl Ordinance {
     }
 
     /// Open the quantitative ordinance from scrapped output
-    pub(super) async fn open<P: AsRef<std::path::Path>>(root: P) -> Result<Ordinance> {
+    ///
+    /// The quantitative ordinances are read from `quantitative_file`, if
+    /// given, otherwise from the first of the known filenames found.
+    pub(super) async fn open<P: AsRef<std::path::Path>>(
+        root: P,
+        quantitative_file: Option<&str>,
+        deadline: crate::Deadline,
+    ) -> Result<Ordinance> {
         debug!("Opening ordinance from {:?}", root.as_ref());
 
-        let (quantitative, qualitative) = tokio::try_join!(
-            quantitative::Quantitative::open(root.as_ref()),
-            qualitative::Qualitative::open(root.as_ref())
+        let ((quantitative, mut report), (qualitative, qualitative_report)) = tokio::try_join!(
+            quantitative::Quantitative::open(root.as_ref(), quantitative_file, deadline),
+            qualitative::Qualitative::open(root.as_ref(), deadline)
         )?;
+        report.extend(&qualitative_report);
         let ordinance = Ordinance {
             quantitative,
             qualitative,
+            report,
         };
 
         trace!("Opened ordinance: {:?}", ordinance);
@@ -57,11 +194,119 @@ impl Ordinance {
         Ok(ordinance)
     }
 
-    pub(super) fn write(&self, conn: &duckdb::Transaction, commit_id: usize) -> Result<()> {
+    /// Parse a quantitative ordinance CSV, without qualitative ordinances
+    pub(super) fn from_quantitative_csv<R: std::io::Read>(reader: R) -> Result<Ordinance> {
+        debug!("Parsing quantitative ordinance from a reader");
+
+        let (quantitative, report) = quantitative::Quantitative::from_reader(reader)?;
+        let ordinance = Ordinance {
+            quantitative,
+            qualitative: qualitative::Qualitative::default(),
+            report,
+        };
+
+        trace!("Parsed ordinance: {:?}", ordinance);
+        Ok(ordinance)
+    }
+
+    /// Link the ordinances of a commit to their source documents
+    ///
+    /// The source of an ordinance is free text, usually the URL or the
+    /// filename of the document it was extracted from. An ordinance with
+    /// a source matching either one of a document of the same commit is
+    /// linked to it with `archive_lnk`. Returns the sources without a match.
+    ///
+    /// The link is only set here, to ids taken from the archive, and isn't
+    /// a foreign key: DuckDB can't add a column with a constraint, as
+    /// the migration to 0.0.8 does, nor drop or change any column after
+    /// a foreign key in a later migration.
+    pub(super) fn link_archive(
+        conn: &duckdb::Transaction,
+        commit_id: usize,
+    ) -> Result<Vec<String>> {
+        debug!("Linking ordinances of commit {} to the archive", commit_id);
+
+        for table in ["quantitative", "qualitative"] {
+            let linked = conn.execute(
+                &format!(
+                    r"
+                    UPDATE {table} SET archive_lnk = documents.id
+                    FROM (
+                      SELECT archive.id, archive.source, archive.filename
+                      FROM archive JOIN source ON list_contains(
+                        string_split(source.documents, ','), CAST(archive.id AS VARCHAR))
+                      WHERE source.bookkeeper_lnk = ?
+                    ) AS documents
+                    WHERE {table}.bookkeeper_lnk = ?
+                      AND trim({table}.source) IN (documents.source, documents.filename)"
+                ),
+                [commit_id, commit_id],
+            )?;
+            trace!("Linked {} {} ordinances", linked, table);
+        }
+
+        let unmatched = conn
+            .prepare(
+                r"
+                SELECT DISTINCT source FROM ordinance
+                WHERE bookkeeper_lnk = ? AND archive_lnk IS NULL
+                  AND NULLIF(trim(source), '') IS NOT NULL
+                ORDER BY source",
+            )?
+            .query_map([commit_id], |row| row.get(0))?
+            .collect::<duckdb::Result<Vec<String>>>()?;
+        for source in &unmatched {
+            warn!("Ordinance source {:?} doesn't match any document", source);
+        }
+
+        Ok(unmatched)
+    }
+
+    /// Warnings found while parsing
+    pub(super) fn report(&self) -> &ParseReport {
+        &self.report
+    }
+
+    /// Keep only the ordinances of the given jurisdictions (FIPS)
+    pub(super) fn retain_fips(&mut self, fips: &std::collections::HashSet<u64>) {
+        self.quantitative.retain_fips(fips);
+        self.qualitative.retain_fips(fips);
+    }
+
+    /// Number of ordinance rows, both quantitative and qualitative
+    pub(super) fn len(&self) -> usize {
+        self.quantitative.len() + self.qualitative.len()
+    }
+
+    /// Validate the ordinance, registering any issue found
+    pub(super) fn validate(&self, quality: &mut DataQuality) {
+        trace!("Validating ordinance");
+
+        self.quantitative.validate(quality);
+        self.qualitative.validate(quality);
+    }
+
+    /// Validate the source of each ordinance, registering malformed URLs
+    pub(super) fn validate_sources(&self, quality: &mut DataQuality) {
+        trace!("Validating ordinance sources");
+
+        self.quantitative.validate_sources(quality);
+        self.qualitative.validate_sources(quality);
+    }
+
+    /// Write both the quantitative and qualitative ordinances
+    ///
+    /// Gives up once past the `deadline`, checked on every record.
+    pub(super) fn write(
+        &self,
+        conn: &duckdb::Transaction,
+        commit_id: usize,
+        deadline: crate::Deadline,
+    ) -> Result<()> {
         trace!("Writing ordinance to database");
 
-        self.quantitative.write(conn, commit_id)?;
-        self.qualitative.write(conn, commit_id)?;
+        self.quantitative.write(conn, commit_id, deadline)?;
+        self.qualitative.write(conn, commit_id, deadline)?;
 
         trace!("Ordinance written to database");
         Ok(())
@@ -73,6 +318,11 @@ impl Ordinance {
 pub(crate) mod sample {
     use super::*;
 
+    /// Content of a basic quantitative ordinance CSV
+    pub(crate) fn quantitative_basic() -> String {
+        quantitative::sample::basic()
+    }
+
     pub(crate) fn as_file<P: AsRef<std::path::Path>>(path: P) -> Result<()> {
         let _quantitative =
             quantitative::sample::as_file(path.as_ref().join("quantitative_ordinances.csv"))
@@ -91,6 +341,101 @@ mod test {
     async fn dev() {
         let tmp = tempfile::tempdir().unwrap();
         sample::as_file(tmp.path()).unwrap();
-        let _ordinance = Ordinance::open(&tmp).await.unwrap();
+        let _ordinance = Ordinance::open(&tmp, None, crate::Deadline::default())
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    /// Parsing stops on the first record past the deadline
+    fn parse_past_deadline() {
+        let deadline = crate::Deadline::after(Some(std::time::Duration::ZERO));
+        let err = parse_records::<quantitative::QuantitativeRecord, _>(
+            sample::quantitative_basic().as_bytes(),
+            "quantitative",
+            deadline,
+        )
+        .unwrap_err();
+        assert!(matches!(err, crate::error::Error::LoadTimeout(_)));
+    }
+
+    #[tokio::test]
+    /// The quantitative ordinances under an alternative name
+    async fn alternative_quantitative_filename() {
+        let tmp = tempfile::tempdir().unwrap();
+        sample::as_file(tmp.path()).unwrap();
+        std::fs::rename(
+            tmp.path().join("quantitative_ordinances.csv"),
+            tmp.path().join("ordinances.csv"),
+        )
+        .unwrap();
+
+        let ordinance = Ordinance::open(tmp.path(), None, crate::Deadline::default())
+            .await
+            .unwrap();
+        assert_eq!(ordinance.quantitative.len(), 2);
+        assert_eq!(
+            quantitative_path(tmp.path(), None).unwrap(),
+            tmp.path().join("ordinances.csv")
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_quantitative_filename() {
+        let tmp = tempfile::tempdir().unwrap();
+        sample::as_file(tmp.path()).unwrap();
+        std::fs::rename(
+            tmp.path().join("quantitative_ordinances.csv"),
+            tmp.path().join("values.csv"),
+        )
+        .unwrap();
+
+        let err = Ordinance::open(tmp.path(), None, crate::Deadline::default())
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("quantitative_ordinances.csv, ordinances.csv")
+        );
+
+        let ordinance = Ordinance::open(tmp.path(), Some("values.csv"), crate::Deadline::default())
+            .await
+            .unwrap();
+        assert_eq!(ordinance.quantitative.len(), 2);
+    }
+
+    #[tokio::test]
+    /// Only the header is a run that found nothing, not an error
+    async fn header_only() {
+        let tmp = tempfile::tempdir().unwrap();
+        sample::as_file(tmp.path()).unwrap();
+        for name in ["quantitative_ordinances.csv", "qualitative_ordinances.csv"] {
+            let path = tmp.path().join(name);
+            let content = std::fs::read_to_string(&path).unwrap();
+            let header = content.lines().next().unwrap();
+            std::fs::write(&path, format!("{header}\n")).unwrap();
+        }
+
+        let ordinance = Ordinance::open(tmp.path(), None, crate::Deadline::default())
+            .await
+            .unwrap();
+        assert_eq!(ordinance.len(), 0);
+        let mut quality = DataQuality::default();
+        ordinance.validate(&mut quality);
+        assert!(quality.is_empty());
+    }
+
+    #[tokio::test]
+    /// Without even a header, the file is broken
+    async fn completely_empty() {
+        let tmp = tempfile::tempdir().unwrap();
+        sample::as_file(tmp.path()).unwrap();
+        std::fs::write(tmp.path().join("quantitative_ordinances.csv"), "").unwrap();
+
+        let err = Ordinance::open(tmp.path(), None, crate::Deadline::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::error::Error::EmptyCsv(_)));
+        assert!(err.to_string().contains("quantitative_ordinances.csv"));
     }
 }
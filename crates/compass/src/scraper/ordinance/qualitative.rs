@@ -3,8 +3,10 @@
 use tracing::trace;
 
 use crate::error::Result;
+use crate::scraper::quality::{self, DataQuality};
+use crate::scraper::report::ParseReport;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub(super) struct Qualitative(Vec<QualitativeRecord>);
 
 #[allow(dead_code, non_snake_case)]
@@ -14,7 +16,8 @@ pub(super) struct QualitativeRecord {
     state: String,
     subdivison: Option<String>,
     jurisdiction_type: Option<String>,
-    FIPS: u64,
+    /// Missing in some rows, see `resolve_fips()`
+    FIPS: Option<u64>,
     feature: String,
     summary: String,
     ord_year: Option<u32>,
@@ -42,7 +45,8 @@ impl Qualitative {
               summary TEXT,
               ord_year INTEGER,
               section TEXT,
-              source TEXT
+              source TEXT,
+              archive_lnk INTEGER
             );",
         )?;
 
@@ -51,7 +55,12 @@ impl Qualitative {
     }
 
     /// Open the qualitative ordinance from scrapped output
-    pub(super) async fn open<P: AsRef<std::path::Path>>(root: P) -> Result<Qualitative> {
+    ///
+    /// Returns, together, the warnings found while parsing it.
+    pub(super) async fn open<P: AsRef<std::path::Path>>(
+        root: P,
+        deadline: crate::Deadline,
+    ) -> Result<(Qualitative, ParseReport)> {
         trace!("Opening qualitative ordinance of {:?}", root.as_ref());
 
         let path = root.as_ref().join("qualitative_ordinances.csv");
@@ -72,31 +81,48 @@ impl Qualitative {
             .unwrap();
         */
 
-        let mut rdr = csv::ReaderBuilder::new()
-            .has_headers(true)
-            .delimiter(b',')
-            .from_path(&path)
-            .unwrap();
+        let (output, report): (Vec<QualitativeRecord>, _) =
+            super::read_records(path, deadline).await?;
+        trace!("Qualitative ordinance records {:?}", output);
+
+        Ok((Qualitative(output), report))
+    }
+
+    /// Keep only the records of the given jurisdictions (FIPS)
+    ///
+    /// Records without a FIPS code are dropped.
+    pub(super) fn retain_fips(&mut self, fips: &std::collections::HashSet<u64>) {
+        self.0.retain(|r| r.FIPS.is_some_and(|f| fips.contains(&f)));
+    }
+
+    /// Number of records
+    pub(super) fn len(&self) -> usize {
+        self.0.len()
+    }
 
-        trace!("Qualitative reader {:?}", rdr);
-
-        let mut output = Vec::new();
-        for result in rdr.deserialize() {
-            let record: QualitativeRecord = match result {
-                Ok(record) => record,
-                Err(_) => {
-                    trace!("Error {:?}", result);
-                    continue;
-                }
-            };
-            output.push(record);
+    /// Validate the qualitative ordinance records
+    ///
+    /// Problems found are registered in `quality` instead of failing,
+    /// thus the records are still loaded.
+    pub(super) fn validate(&self, quality: &mut DataQuality) {
+        for record in &self.0 {
+            quality::check_year(quality, "qualitative", "ord_year", record.ord_year);
         }
-        trace!("Qualitative ordinance records {:?}", output);
+    }
 
-        Ok(Qualitative(output))
+    /// Validate the source of each record, registering malformed URLs
+    pub(super) fn validate_sources(&self, quality: &mut DataQuality) {
+        for source in self.0.iter().filter_map(|r| r.source.as_deref()) {
+            quality::check_source(quality, "qualitative", "source", source);
+        }
     }
 
-    pub(super) fn write(&self, conn: &duckdb::Transaction, commit_id: usize) -> Result<()> {
+    pub(super) fn write(
+        &self,
+        conn: &duckdb::Transaction,
+        commit_id: usize,
+        deadline: crate::Deadline,
+    ) -> Result<()> {
         trace!("Writing qualitative ordinance to database");
 
         let mut stmt = conn
@@ -111,6 +137,7 @@ impl Qualitative {
             .expect("Failed to prepare qualitative ordinance statement");
 
         for record in &self.0 {
+            deadline.check()?;
             trace!("Writing qualitative ordinance record {:?}", &record);
             stmt.execute(duckdb::params![
                 commit_id,
@@ -167,9 +194,34 @@ mod test {
         let tmp = tempfile::tempdir().unwrap();
         let _file = sample::as_file(tmp.path().join("qualitative_ordinances.csv")).unwrap();
 
-        let ord = Qualitative::open(&tmp).await.unwrap();
+        let (ord, _) = Qualitative::open(&tmp, crate::Deadline::default())
+            .await
+            .unwrap();
         dbg!(&ord);
         //assert_eq!(&ord.0[0].county, "county-1");
         //assert_eq!(&ord.0[0].feature, "feature-1");
     }
+
+    #[tokio::test]
+    /// An unquoted comma in the last column is kept instead of dropping the row
+    async fn extra_trailing_fields() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            tmp.path().join("qualitative_ordinances.csv"),
+            "county,state,subdivison,jurisdiction_type,FIPS,feature,summary,ord_year,section,source\n\
+             county-1,state-1,,county,11111,color,white,2001,section-1,Sample County Code, Sec. 12\n\
+             county-2,state-2,,county,22222,color,gray,2002,section-2,source-2\n",
+        )
+        .unwrap();
+
+        let (ord, _) = Qualitative::open(&tmp, crate::Deadline::default())
+            .await
+            .unwrap();
+        assert_eq!(ord.0.len(), 2);
+        assert_eq!(
+            ord.0[0].source.as_deref(),
+            Some("Sample County Code, Sec. 12")
+        );
+        assert_eq!(ord.0[1].source.as_deref(), Some("source-2"));
+    }
 }
@@ -2,7 +2,10 @@
 
 use tracing::trace;
 
+use super::units::Units;
 use crate::error::Result;
+use crate::scraper::quality::{self, DataQuality};
+use crate::scraper::report::ParseReport;
 
 #[derive(Debug)]
 pub(super) struct Quantitative(Vec<QuantitativeRecord>);
@@ -14,19 +17,126 @@ pub(super) struct QuantitativeRecord {
     state: String,
     subdivison: Option<String>,
     jurisdiction_type: Option<String>,
-    FIPS: u64,
+    /// Missing in some rows, see `resolve_fips()`
+    FIPS: Option<u64>,
     feature: String,
-    value: f64,
+    #[serde(default, deserialize_with = "crate::scraper::nonfinite::finite")]
+    value: Option<f64>,
     units: Option<String>,
-    offset: Option<f64>,
+    /// Fixed distance added on top of a multiplier
+    ///
+    /// The scraper output names it `offset`, while `adder` is the term
+    /// used in the database and across this crate, thus both are
+    /// accepted.
+    #[serde(
+        alias = "offset",
+        default,
+        deserialize_with = "crate::scraper::nonfinite::finite"
+    )]
+    adder: Option<f64>,
+    #[serde(default, deserialize_with = "crate::scraper::nonfinite::finite")]
     min_dist: Option<f64>,
+    #[serde(default, deserialize_with = "crate::scraper::nonfinite::finite")]
     max_dist: Option<f64>,
+    /// Multiple of a reference, such as 1.1, or a percentage of it
+    #[serde(default, deserialize_with = "crate::scraper::nonfinite::finite")]
+    mult_value: Option<f64>,
+    /// Reference of the multiplier, such as "hub-height"
+    mult_type: Option<String>,
     summary: Option<String>,
     ord_year: Option<u32>,
     section: Option<String>,
     source: Option<String>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+/// Value of a quantitative ordinance
+///
+/// The scraper output flattens the value into a few columns, and which
+/// of those are populated defines the meaning of the value.
+pub(super) enum OrdinanceValue {
+    /// A fixed value, such as a setback of 500 ft
+    Fixed(f64),
+    /// A multiple of a reference, such as 1.1 times the tip height,
+    /// possibly with a fixed adder on top of it
+    Multiplier {
+        factor: f64,
+        of: String,
+        adder: Option<f64>,
+    },
+    /// A range, such as at least 500 ft and at most 1000 ft
+    Range { min: Option<f64>, max: Option<f64> },
+}
+
+impl OrdinanceValue {
+    /// Suffix used in the units of a multiplier, such as "tip-height-multiplier"
+    const MULTIPLIER_SUFFIX: &str = "-multiplier";
+
+    /// Tag identifying the kind of value, as stored in the database
+    pub(super) fn kind(&self) -> &'static str {
+        match self {
+            OrdinanceValue::Fixed(_) => "fixed",
+            OrdinanceValue::Multiplier { .. } => "multiplier",
+            OrdinanceValue::Range { .. } => "range",
+        }
+    }
+}
+
+impl QuantitativeRecord {
+    /// Identify the value from the populated columns
+    ///
+    /// A value with units such as "tip-height-multiplier" is a
+    /// multiplier, any other value is fixed, and without a value but with
+    /// a minimum or maximum distance it is a range. Returns `None` if
+    /// none of those columns are populated.
+    pub(super) fn ordinance_value(&self) -> Option<OrdinanceValue> {
+        let multiplier_of = self
+            .units
+            .as_deref()
+            .and_then(|u| u.strip_suffix(OrdinanceValue::MULTIPLIER_SUFFIX));
+
+        match (self.value, multiplier_of) {
+            (Some(factor), Some(of)) => Some(OrdinanceValue::Multiplier {
+                factor,
+                of: of.to_string(),
+                adder: self.adder,
+            }),
+            (Some(value), None) => Some(OrdinanceValue::Fixed(value)),
+            (None, _) if self.min_dist.is_some() || self.max_dist.is_some() => {
+                Some(OrdinanceValue::Range {
+                    min: self.min_dist,
+                    max: self.max_dist,
+                })
+            }
+            (None, _) => None,
+        }
+    }
+
+    /// The multiplier, as its value and type
+    ///
+    /// Given by the `mult_value` and `mult_type` columns, if present,
+    /// otherwise from a multiplier value, such as 1.1 with the units
+    /// "tip-height-multiplier".
+    pub(super) fn multiplier(&self) -> (Option<f64>, Option<String>) {
+        if self.mult_value.is_some() || self.mult_type.is_some() {
+            return (self.mult_value, self.mult_type.clone());
+        }
+        match self.ordinance_value() {
+            Some(OrdinanceValue::Multiplier { factor, of, .. }) => (Some(factor), Some(of)),
+            _ => (None, None),
+        }
+    }
+
+    /// The value converted to meters
+    ///
+    /// Returns `None` without a value, or if the units are not a known
+    /// distance, such as a multiplier or a noise level.
+    pub(super) fn value_meters(&self) -> Option<f64> {
+        let units: Units = self.units.as_deref()?.parse().ok()?;
+        self.value.map(|v| units.to_meters(v))
+    }
+}
+
 impl Quantitative {
     pub(super) fn init_db(conn: &duckdb::Transaction) -> Result<()> {
         trace!("Initializing database for Quantitative");
@@ -45,14 +155,19 @@ impl Quantitative {
               FIPS UBIGINT,
               feature TEXT,
               value REAL,
+              value_type TEXT,
+              value_meters REAL,
               units TEXT,
               adder REAL,
               min_dist REAL,
               max_dist REAL,
+              mult_value REAL,
+              mult_type TEXT,
               summary TEXT,
               ord_year INTEGER,
               section TEXT,
-              source TEXT
+              source TEXT,
+              archive_lnk INTEGER
             );",
         )?;
 
@@ -61,16 +176,23 @@ impl Quantitative {
     }
 
     /// Open the quantitative ordinance from scrapped output
-    pub(super) async fn open<P: AsRef<std::path::Path>>(root: P) -> Result<Quantitative> {
+    ///
+    /// See `quantitative_path()` for the file used. Returns, together,
+    /// the warnings found while parsing it.
+    pub(super) async fn open<P: AsRef<std::path::Path>>(
+        root: P,
+        filename: Option<&str>,
+        deadline: crate::Deadline,
+    ) -> Result<(Quantitative, ParseReport)> {
         trace!("Opening quantitative ordinance of {:?}", root.as_ref());
 
-        let path = root.as_ref().join("quantitative_ordinances.csv");
-        if !path.exists() {
-            trace!("Missing quantitative ordinance file: {:?}", path);
-            return Err(crate::error::Error::Undefined(
-                "Missing quantitative ordinance file".to_string(),
-            ));
-        }
+        let path = super::quantitative_path(root.as_ref(), filename).map_err(|candidates| {
+            trace!(
+                "Missing quantitative ordinance file, tried: {:?}",
+                candidates
+            );
+            crate::error::Error::MissingQuantitative(candidates)
+        })?;
 
         trace!("Identified quantitative ordinance at {:?}", path);
 
@@ -82,46 +204,75 @@ impl Quantitative {
             .unwrap();
         */
 
-        let mut rdr = csv::ReaderBuilder::new()
-            .has_headers(true)
-            .delimiter(b',')
-            .from_path(&path)
-            .unwrap();
+        let (output, report): (Vec<QuantitativeRecord>, _) =
+            super::read_records(path, deadline).await?;
+        trace!("Quantitative ordinance records {:?}", output);
 
-        trace!("Quantitative reader {:?}", rdr);
-
-        let mut output = Vec::new();
-        for result in rdr.deserialize() {
-            let record: QuantitativeRecord = match result {
-                Ok(record) => record,
-                Err(_) => {
-                    trace!("Error {:?}", result);
-                    continue;
-                }
-            };
-            output.push(record);
-        }
+        Ok((Quantitative(output), report))
+    }
+
+    /// Parse the quantitative ordinance from a CSV reader
+    pub(super) fn from_reader<R: std::io::Read>(reader: R) -> Result<(Quantitative, ParseReport)> {
+        let (output, report): (Vec<QuantitativeRecord>, _) =
+            super::parse_records(reader, "quantitative", crate::Deadline::default())?;
         trace!("Quantitative ordinance records {:?}", output);
 
-        Ok(Quantitative(output))
+        Ok((Quantitative(output), report))
+    }
+
+    /// Keep only the records of the given jurisdictions (FIPS)
+    ///
+    /// Records without a FIPS code are dropped.
+    pub(super) fn retain_fips(&mut self, fips: &std::collections::HashSet<u64>) {
+        self.0.retain(|r| r.FIPS.is_some_and(|f| fips.contains(&f)));
+    }
+
+    /// Number of records
+    pub(super) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Validate the quantitative ordinance records
+    ///
+    /// Problems found are registered in `quality` instead of failing,
+    /// thus the records are still loaded.
+    pub(super) fn validate(&self, quality: &mut DataQuality) {
+        for record in &self.0 {
+            quality::check_year(quality, "quantitative", "ord_year", record.ord_year);
+        }
+    }
+
+    /// Validate the source of each record, registering malformed URLs
+    pub(super) fn validate_sources(&self, quality: &mut DataQuality) {
+        for source in self.0.iter().filter_map(|r| r.source.as_deref()) {
+            quality::check_source(quality, "quantitative", "source", source);
+        }
     }
 
-    pub(super) fn write(&self, conn: &duckdb::Transaction, commit_id: usize) -> Result<()> {
+    pub(super) fn write(
+        &self,
+        conn: &duckdb::Transaction,
+        commit_id: usize,
+        deadline: crate::Deadline,
+    ) -> Result<()> {
         trace!("Writing ordinance to database");
 
         let mut stmt = conn
             .prepare(
                 r"INSERT INTO quantitative
             (bookkeeper_lnk, county, state, subdivison,
-            jurisdiction_type, FIPS, feature, value, units, adder,
-            min_dist, max_dist, summary, ord_year, section, source)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            jurisdiction_type, FIPS, feature, value, value_type, value_meters,
+            units, adder, min_dist, max_dist, mult_value, mult_type, summary,
+            ord_year, section, source)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             ",
             )
             .expect("Failed to prepare ordinance statement");
 
         for record in &self.0 {
+            deadline.check()?;
             trace!("Writing ordinance record {:?}", &record);
+            let (mult_value, mult_type) = record.multiplier();
             stmt.execute(duckdb::params![
                 commit_id,
                 record.county,
@@ -131,10 +282,14 @@ impl Quantitative {
                 record.FIPS,
                 record.feature,
                 record.value,
+                record.ordinance_value().map(|v| v.kind()),
+                record.value_meters(),
                 record.units,
-                record.offset,
+                record.adder,
                 record.min_dist,
                 record.max_dist,
+                mult_value,
+                mult_type,
                 record.summary,
                 record.ord_year,
                 record.section,
@@ -182,9 +337,155 @@ mod test {
         let tmp = tempfile::tempdir().unwrap();
         let _file = sample::as_file(tmp.path().join("quantitative_ordinances.csv")).unwrap();
 
-        let ord = Quantitative::open(&tmp).await.unwrap();
+        let (ord, _) = Quantitative::open(&tmp, None, crate::Deadline::default())
+            .await
+            .unwrap();
         dbg!(&ord);
         //assert_eq!(&ord.0[0].county, "county-1");
         //assert_eq!(&ord.0[0].feature, "feature-1");
     }
+
+    #[tokio::test]
+    /// An implausible year is flagged, but the record is still loaded
+    async fn out_of_range_year() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            tmp.path().join("quantitative_ordinances.csv"),
+            "county,state,subdivison,jurisdiction_type,FIPS,feature,value,units,offset,min_dist,max_dist,summary,ord_year,section,source\n\
+             county-1,state-1,,county,11111,feature-1,1.1,ft,,,,,20222,,source-1\n",
+        )
+        .unwrap();
+
+        let (ord, _) = Quantitative::open(&tmp, None, crate::Deadline::default())
+            .await
+            .unwrap();
+        assert_eq!(ord.0.len(), 1);
+
+        let mut quality = DataQuality::default();
+        ord.validate(&mut quality);
+        assert_eq!(quality.len(), 1);
+        let issue = quality.iter().next().unwrap();
+        assert_eq!(issue.field, "ord_year");
+        assert_eq!(issue.value, "20222");
+    }
+
+    #[tokio::test]
+    /// The kind of value is identified by the populated columns
+    async fn ordinance_value_variants() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            tmp.path().join("quantitative_ordinances.csv"),
+            "county,state,subdivison,jurisdiction_type,FIPS,feature,value,units,offset,min_dist,max_dist,summary,ord_year,section,source\n\
+             county-1,state-1,,county,11111,structures,500,feet,,,,,2001,,source-1\n\
+             county-1,state-1,,county,11111,roads,1.1,tip-height-multiplier,50,,,,2001,,source-1\n\
+             county-1,state-1,,county,11111,noise,,,,300,1000,,2001,,source-1\n\
+             county-1,state-1,,county,11111,lighting,,,,,,,2001,,source-1\n",
+        )
+        .unwrap();
+
+        let (ord, _) = Quantitative::open(&tmp, None, crate::Deadline::default())
+            .await
+            .unwrap();
+        assert_eq!(ord.0.len(), 4);
+
+        assert_eq!(
+            ord.0[0].ordinance_value(),
+            Some(OrdinanceValue::Fixed(500.0))
+        );
+        assert_eq!(
+            ord.0[1].ordinance_value(),
+            Some(OrdinanceValue::Multiplier {
+                factor: 1.1,
+                of: "tip-height".to_string(),
+                adder: Some(50.0),
+            })
+        );
+        assert_eq!(
+            ord.0[2].ordinance_value(),
+            Some(OrdinanceValue::Range {
+                min: Some(300.0),
+                max: Some(1000.0),
+            })
+        );
+        assert_eq!(ord.0[3].ordinance_value(), None);
+
+        assert_eq!(ord.0[0].value_meters(), Some(152.4));
+        assert_eq!(ord.0[1].value_meters(), None);
+        assert_eq!(ord.0[2].value_meters(), None);
+
+        let kinds: Vec<_> = ord
+            .0
+            .iter()
+            .map(|r| r.ordinance_value().map(|v| v.kind()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [Some("fixed"), Some("multiplier"), Some("range"), None]
+        );
+    }
+
+    #[test]
+    /// Multipliers from their own columns, or from the units
+    fn multiplier_columns() {
+        let (ord, _) = Quantitative::from_reader(
+            "county,state,subdivison,jurisdiction_type,FIPS,feature,value,units,offset,min_dist,max_dist,mult_value,mult_type,summary,ord_year,section,source\n\
+             county-1,state-1,,county,11111,structures,,,,,,1.1,hub-height,,2001,,source-1\n\
+             county-1,state-1,,county,11111,roads,1.5,tip-height-multiplier,,,,,,,2001,,source-1\n\
+             county-1,state-1,,county,11111,railroads,500,feet,,,,,,,2001,,source-1\n"
+                .as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            ord.0[0].multiplier(),
+            (Some(1.1), Some("hub-height".to_string()))
+        );
+        assert_eq!(
+            ord.0[1].multiplier(),
+            (Some(1.5), Some("tip-height".to_string()))
+        );
+        assert_eq!(ord.0[2].multiplier(), (None, None));
+    }
+
+    #[test]
+    /// The adder is parsed from either an `offset` or an `adder` column
+    fn adder_alias() {
+        for term in ["offset", "adder"] {
+            let (ord, _) = Quantitative::from_reader(
+                format!(
+                    "county,state,subdivison,jurisdiction_type,FIPS,feature,value,units,{term},min_dist,max_dist,summary,ord_year,section,source\n\
+                     county-1,state-1,,county,11111,roads,1.1,tip-height-multiplier,50,,,,2001,,source-1\n"
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+
+            assert_eq!(ord.0[0].adder, Some(50.0), "Parsing {term}");
+            assert_eq!(
+                ord.0[0].ordinance_value(),
+                Some(OrdinanceValue::Multiplier {
+                    factor: 1.1,
+                    of: "tip-height".to_string(),
+                    adder: Some(50.0),
+                })
+            );
+        }
+    }
+
+    #[test]
+    /// Non-finite values are taken as missing
+    fn non_finite_values() {
+        let (ord, _) = Quantitative::from_reader(
+            "county,state,subdivison,jurisdiction_type,FIPS,feature,value,units,adder,min_dist,max_dist,summary,ord_year,section,source\n\
+             county-1,state-1,,county,11111,roads,NaN,tip-height-multiplier,inf,-Infinity,1.5,,2001,,source-1\n"
+                .as_bytes(),
+        )
+        .unwrap();
+
+        let record = &ord.0[0];
+        assert_eq!(record.value, None);
+        assert_eq!(record.adder, None);
+        assert_eq!(record.min_dist, None);
+        assert_eq!(record.max_dist, Some(1.5));
+    }
 }
@@ -0,0 +1,84 @@
+//! Units of the quantitative ordinances
+//!
+//! The scraper reports units as free text, thus the same unit shows up
+//! under different spellings, such as "ft" and "feet". Distances are
+//! normalized here so that values can be compared across jurisdictions.
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Units of distance
+pub(super) enum Units {
+    /// International foot
+    Feet,
+    /// Meter, the base unit
+    Meters,
+    /// International mile
+    Miles,
+    /// Kilometer
+    Kilometers,
+}
+
+impl Units {
+    /// Length of one unit, in meters
+    fn meters(&self) -> f64 {
+        match self {
+            Units::Feet => 0.3048,
+            Units::Meters => 1.0,
+            Units::Miles => 1609.344,
+            Units::Kilometers => 1000.0,
+        }
+    }
+
+    /// Convert a value in these units to meters
+    pub(super) fn to_meters(self, value: f64) -> f64 {
+        value * self.meters()
+    }
+}
+
+impl std::str::FromStr for Units {
+    type Err = Error;
+
+    /// Parse the units, accepting the usual synonyms and abbreviations
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().trim_end_matches('.').to_lowercase().as_str() {
+            "ft" | "foot" | "feet" => Ok(Units::Feet),
+            "m" | "meter" | "meters" | "metre" | "metres" => Ok(Units::Meters),
+            "mi" | "mile" | "miles" => Ok(Units::Miles),
+            "km" | "kilometer" | "kilometers" | "kilometre" | "kilometres" => Ok(Units::Kilometers),
+            _ => Err(Error::Undefined(format!("Unknown units: {s}"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_units {
+    use super::*;
+
+    #[test]
+    fn synonyms() {
+        for s in ["ft", "feet", "Feet", "foot", " ft. "] {
+            assert_eq!(s.parse::<Units>().unwrap(), Units::Feet, "{s:?}");
+        }
+        for s in ["m", "meters", "Metres"] {
+            assert_eq!(s.parse::<Units>().unwrap(), Units::Meters, "{s:?}");
+        }
+        assert_eq!("mi".parse::<Units>().unwrap(), Units::Miles);
+        assert_eq!("km".parse::<Units>().unwrap(), Units::Kilometers);
+    }
+
+    #[test]
+    fn unknown() {
+        assert!("tip-height-multiplier".parse::<Units>().is_err());
+        assert!("dBA".parse::<Units>().is_err());
+        assert!("".parse::<Units>().is_err());
+    }
+
+    #[test]
+    fn conversion() {
+        assert_eq!(Units::Feet.to_meters(1000.0), 304.8);
+        assert_eq!(Units::Meters.to_meters(150.0), 150.0);
+        assert_eq!(Units::Miles.to_meters(1.0), 1609.344);
+        assert_eq!(Units::Kilometers.to_meters(1.5), 1500.0);
+    }
+}
@@ -0,0 +1,337 @@
+//! Data quality of the scraper output
+//!
+//! Some problems in the scraper output are not severe enough to abort a
+//! load, but should not go unnoticed either, such as an implausible
+//! ordinance year caused by an OCR or extraction error. Those are
+//! collected while parsing and recorded in the database together with
+//! the data, so they can be reviewed later.
+
+use tracing::{trace, warn};
+
+use super::duration::RunDuration;
+use crate::error::Result;
+
+/// Earliest year considered plausible for an ordinance
+const MIN_PLAUSIBLE_YEAR: u32 = 1800;
+
+/// Tolerance, in seconds, between the two representations of a duration
+///
+/// The string form might be rounded, so allow up to a second of
+/// difference.
+const DURATION_TOLERANCE: f64 = 1.0;
+
+#[derive(Debug, Default)]
+/// Collection of data quality issues found in a scraper output
+pub(super) struct DataQuality(Vec<Issue>);
+
+#[derive(Debug, Clone, PartialEq)]
+/// A single data quality issue
+pub(super) struct Issue {
+    /// Component where the issue was found, such as "quantitative"
+    pub(super) component: String,
+    /// Field with the issue, such as "ord_year"
+    pub(super) field: String,
+    /// The offending value, as found in the scraper output
+    pub(super) value: String,
+    /// Human readable description of the issue
+    pub(super) message: String,
+}
+
+impl Issue {
+    pub(super) fn new<V: ToString, M: Into<String>>(
+        component: &str,
+        field: &str,
+        value: V,
+        message: M,
+    ) -> Self {
+        Self {
+            component: component.to_string(),
+            field: field.to_string(),
+            value: value.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}.{} {:?}: {}",
+            self.component, self.field, self.value, self.message
+        )
+    }
+}
+
+impl DataQuality {
+    /// Initialize the database to support DataQuality
+    pub(super) fn init_db(conn: &duckdb::Transaction) -> Result<()> {
+        trace!("Initializing database for DataQuality");
+        conn.execute_batch(
+            r"
+            CREATE SEQUENCE IF NOT EXISTS data_quality_sequence START 1;
+            CREATE TABLE IF NOT EXISTS data_quality (
+              id INTEGER PRIMARY KEY DEFAULT
+                NEXTVAL('data_quality_sequence'),
+              bookkeeper_lnk INTEGER REFERENCES bookkeeper(id) NOT NULL,
+              component TEXT NOT NULL,
+              field TEXT NOT NULL,
+              value TEXT,
+              message TEXT NOT NULL,
+              created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+            );",
+        )?;
+
+        trace!("Database ready for DataQuality");
+        Ok(())
+    }
+
+    /// Register a new issue
+    pub(super) fn push(&mut self, issue: Issue) {
+        warn!(
+            "Data quality issue on {}.{}: {}",
+            issue.component, issue.field, issue.message
+        );
+        self.0.push(issue);
+    }
+
+    pub(super) fn iter(&self) -> std::slice::Iter<'_, Issue> {
+        self.0.iter()
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Record all the issues in the database
+    pub(super) fn write(&self, conn: &duckdb::Transaction, commit_id: usize) -> Result<()> {
+        trace!("Writing {} data quality issues to the database", self.len());
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let mut stmt = conn.prepare(
+            r"INSERT INTO data_quality
+              (bookkeeper_lnk, component, field, value, message)
+              VALUES (?, ?, ?, ?, ?)",
+        )?;
+        for issue in self.iter() {
+            stmt.execute(duckdb::params![
+                commit_id,
+                issue.component,
+                issue.field,
+                issue.value,
+                issue.message,
+            ])?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Current calendar year (UTC)
+///
+/// Approximated from the average length of a Gregorian year, which is
+/// accurate enough for a plausibility check.
+fn current_year() -> u32 {
+    let seconds = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    1970 + (seconds / 31_556_952) as u32
+}
+
+/// Check if a year is plausible for an ordinance
+///
+/// Accepts from `MIN_PLAUSIBLE_YEAR` up to next year, since an ordinance
+/// might be adopted with a future effective date. Anything outside that
+/// range, such as `20222` or `199`, is registered as an issue.
+pub(super) fn check_year(
+    quality: &mut DataQuality,
+    component: &str,
+    field: &str,
+    year: Option<u32>,
+) {
+    let Some(year) = year else {
+        return;
+    };
+
+    let max_year = current_year() + 1;
+    if !(MIN_PLAUSIBLE_YEAR..=max_year).contains(&year) {
+        quality.push(Issue::new(
+            component,
+            field,
+            year,
+            format!("Year {year} outside plausible range {MIN_PLAUSIBLE_YEAR}-{max_year}"),
+        ));
+    }
+}
+
+/// Check if a duration string agrees with the duration in seconds
+///
+/// The scraper reports the total time both in seconds and as a string
+/// (`H:MM:SS.ffffff`), and those might disagree due to a bug in the
+/// scraper. A string that can't be parsed is also registered as an issue.
+pub(super) fn check_duration(
+    quality: &mut DataQuality,
+    component: &str,
+    field: &str,
+    total_time: RunDuration,
+    value: &str,
+) {
+    match RunDuration::from_hms_string(value) {
+        Some(parsed)
+            if (parsed.as_secs_f64() - total_time.as_secs_f64()).abs() <= DURATION_TOLERANCE => {}
+        Some(parsed) => quality.push(Issue::new(
+            component,
+            field,
+            value,
+            format!("Duration of {parsed} disagrees with total_time of {total_time}"),
+        )),
+        None => quality.push(Issue::new(
+            component,
+            field,
+            value,
+            "Duration is not in the format H:MM:SS.ffffff",
+        )),
+    }
+}
+
+/// Check if a source string that looks like a URL is a valid one
+///
+/// Sources might be a URL or a bare citation, such as "Sample County
+/// Code, Sec. 12". Only strings with a scheme (`://`) or starting with
+/// `www.` are expected to be URLs, and those are parsed, without any
+/// network request. A malformed URL is registered as an issue.
+pub(super) fn check_source(quality: &mut DataQuality, component: &str, field: &str, value: &str) {
+    let value = value.trim();
+    let url = if value.contains("://") {
+        value.to_string()
+    } else if value.starts_with("www.") {
+        format!("http://{value}")
+    } else {
+        return;
+    };
+
+    if let Err(e) = url::Url::parse(&url) {
+        quality.push(Issue::new(
+            component,
+            field,
+            value,
+            format!("Malformed URL: {e}"),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test_quality {
+    use super::*;
+
+    #[test]
+    fn plausible_years() {
+        let mut quality = DataQuality::default();
+        check_year(&mut quality, "test", "year", None);
+        check_year(&mut quality, "test", "year", Some(1800));
+        check_year(&mut quality, "test", "year", Some(2023));
+        check_year(&mut quality, "test", "year", Some(current_year() + 1));
+        assert!(quality.is_empty());
+    }
+
+    #[test]
+    fn implausible_years() {
+        let mut quality = DataQuality::default();
+        check_year(&mut quality, "test", "year", Some(199));
+        check_year(&mut quality, "test", "year", Some(20222));
+        assert_eq!(quality.len(), 2);
+        assert_eq!(quality.iter().next().unwrap().value, "199");
+    }
+
+    #[test]
+    fn matching_duration() {
+        let mut quality = DataQuality::default();
+        check_duration(
+            &mut quality,
+            "test",
+            "time",
+            RunDuration::from_seconds(294.692577),
+            "0:04:54.692577",
+        );
+        check_duration(
+            &mut quality,
+            "test",
+            "time",
+            RunDuration::from_seconds(537.5009291959941),
+            "0:08:57.500929",
+        );
+        assert!(quality.is_empty());
+    }
+
+    #[test]
+    fn mismatching_duration() {
+        let mut quality = DataQuality::default();
+        check_duration(
+            &mut quality,
+            "test",
+            "time",
+            RunDuration::from_seconds(294.692577),
+            "0:14:54.692577",
+        );
+        check_duration(
+            &mut quality,
+            "test",
+            "time",
+            RunDuration::from_seconds(3.5),
+            "0::0::03.5",
+        );
+        assert_eq!(quality.len(), 2);
+    }
+
+    #[test]
+    fn valid_source_url() {
+        let mut quality = DataQuality::default();
+        check_source(
+            &mut quality,
+            "test",
+            "source",
+            "https://example.com/ord.pdf",
+        );
+        check_source(&mut quality, "test", "source", "www.example.com/ord.pdf");
+        assert!(quality.is_empty());
+    }
+
+    #[test]
+    fn citation_source() {
+        let mut quality = DataQuality::default();
+        check_source(
+            &mut quality,
+            "test",
+            "source",
+            "Sample County Code, Sec. 12",
+        );
+        assert!(quality.is_empty());
+    }
+
+    #[test]
+    fn malformed_source_url() {
+        let mut quality = DataQuality::default();
+        check_source(
+            &mut quality,
+            "test",
+            "source",
+            "https://exa mple.com/ord.pdf",
+        );
+        check_source(&mut quality, "test", "source", "http://");
+        assert_eq!(quality.len(), 2);
+        assert!(
+            quality
+                .iter()
+                .next()
+                .unwrap()
+                .message
+                .starts_with("Malformed URL")
+        );
+    }
+}
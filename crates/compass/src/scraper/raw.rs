@@ -0,0 +1,125 @@
+//! Raw inputs of the scraper output
+//!
+//! The parsing of the ordinances improves over time, and a previous load
+//! can only benefit from it if the original content is still available.
+//! Optionally, the ordinance CSV is stored as is, byte by byte, together
+//! with the parsed records, trading storage for reproducibility.
+
+use tracing::{debug, trace};
+
+use crate::error::Result;
+
+#[derive(Debug, Default)]
+/// The original content of some files of a scraper output
+pub(super) struct RawInputs(Vec<RawInput>);
+
+#[derive(Debug)]
+/// A single file, as found in the scraper output
+struct RawInput {
+    /// Path relative to the scraper output, such as "quantitative_ordinances.csv"
+    filename: String,
+    /// The content, as is
+    content: Vec<u8>,
+}
+
+impl RawInputs {
+    /// Initialize the database to support RawInputs
+    pub(super) fn init_db(conn: &duckdb::Transaction) -> Result<()> {
+        trace!("Initializing database for RawInputs");
+        conn.execute_batch(
+            r"
+            CREATE SEQUENCE IF NOT EXISTS raw_inputs_sequence START 1;
+            CREATE TABLE IF NOT EXISTS raw_inputs (
+              id INTEGER PRIMARY KEY DEFAULT
+                NEXTVAL('raw_inputs_sequence'),
+              bookkeeper_lnk INTEGER REFERENCES bookkeeper(id) NOT NULL,
+              filename TEXT NOT NULL,
+              content BLOB NOT NULL,
+              created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+            );",
+        )?;
+
+        trace!("Database ready for RawInputs");
+        Ok(())
+    }
+
+    /// Read the raw inputs of a scraper output
+    ///
+    /// That is the quantitative ordinance file, `quantitative_file` if
+    /// given, otherwise the first of the known names found. A missing
+    /// file is ignored, since that is reported while parsing.
+    pub(super) async fn open<P: AsRef<std::path::Path>>(
+        root: P,
+        quantitative_file: Option<&str>,
+    ) -> Result<Self> {
+        debug!("Opening RawInputs from {:?}", root.as_ref());
+
+        let mut inputs = Vec::new();
+        match super::ordinance::quantitative_path(root.as_ref(), quantitative_file) {
+            Ok(path) => inputs.push(RawInput {
+                filename: path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                content: tokio::fs::read(&path).await?,
+            }),
+            Err(candidates) => trace!("Missing raw input, tried: {:?}", candidates),
+        }
+
+        Ok(Self(inputs))
+    }
+
+    /// Write the raw inputs to the database
+    pub(super) fn write(&self, conn: &duckdb::Transaction, commit_id: usize) -> Result<()> {
+        trace!("Writing {} raw inputs to the database", self.0.len());
+
+        let mut stmt = conn.prepare(
+            "INSERT INTO raw_inputs (bookkeeper_lnk, filename, content) VALUES (?, ?, ?)",
+        )?;
+        for input in &self.0 {
+            stmt.execute(duckdb::params![commit_id, input.filename, input.content])?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_raw {
+    use super::*;
+
+    #[tokio::test]
+    /// The content is stored as is, even if not valid UTF-8
+    async fn roundtrip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let content = b"county,state\r\ncounty-1,\xff state\r\n\r\n".to_vec();
+        std::fs::write(tmp.path().join("quantitative_ordinances.csv"), &content).unwrap();
+
+        let mut db = crate::sample::empty_db(&tmp.path().join("test.db"));
+        let conn = db.transaction().unwrap();
+        conn.execute("INSERT INTO bookkeeper (hash) VALUES ('raw')", [])
+            .unwrap();
+        RawInputs::open(tmp.path(), None)
+            .await
+            .unwrap()
+            .write(&conn, 1)
+            .unwrap();
+        conn.commit().unwrap();
+
+        let (filename, stored): (String, Vec<u8>) = db
+            .query_row("SELECT filename, content FROM raw_inputs", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(filename, "quantitative_ordinances.csv");
+        assert_eq!(stored, content);
+    }
+
+    #[tokio::test]
+    async fn missing_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let raw = RawInputs::open(tmp.path(), None).await.unwrap();
+        assert!(raw.0.is_empty());
+    }
+}
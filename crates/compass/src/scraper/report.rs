@@ -0,0 +1,101 @@
+//! Warnings found while parsing the scraper output
+//!
+//! Parsing is forgiving, such as skipping a CSV row that can't be parsed
+//! instead of failing the whole load. Each of those decisions is
+//! registered in a `ParseReport`, where possible with the line of the
+//! file, so it can be reviewed together at the end of a load, or fail it
+//! with `LoadOptions::strict`.
+//!
+//! Differently from the data quality issues, which are about the content,
+//! such as an implausible year, these are about the files themselves.
+
+use tracing::warn;
+
+#[derive(Debug, Default, Clone)]
+/// Warnings found while parsing one or more files
+pub(super) struct ParseReport(Vec<ParseWarning>);
+
+#[derive(Debug, Clone, PartialEq)]
+/// A single warning found while parsing
+pub(super) struct ParseWarning {
+    /// File where it was found, such as "quantitative_ordinances.csv"
+    pub(super) file: String,
+    /// Line of the file, starting from 1, if known
+    pub(super) line: Option<u64>,
+    /// Human readable description of the warning
+    pub(super) message: String,
+}
+
+impl std::fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file, line, self.message),
+            None => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+impl ParseReport {
+    /// Register a new warning
+    pub(super) fn push<F: ToString, M: Into<String>>(
+        &mut self,
+        file: F,
+        line: Option<u64>,
+        message: M,
+    ) {
+        let warning = ParseWarning {
+            file: file.to_string(),
+            line,
+            message: message.into(),
+        };
+        warn!("{}", warning);
+        self.0.push(warning);
+    }
+
+    /// Append all the warnings of another report
+    pub(super) fn extend(&mut self, other: &ParseReport) {
+        self.0.extend(other.0.iter().cloned());
+    }
+
+    pub(super) fn iter(&self) -> std::slice::Iter<'_, ParseWarning> {
+        self.0.iter()
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Line, starting from 1, of a byte offset in some content
+pub(super) fn line_of(content: &[u8], offset: usize) -> u64 {
+    content[..offset.min(content.len())]
+        .iter()
+        .filter(|&&b| b == b'\n')
+        .count() as u64
+        + 1
+}
+
+#[cfg(test)]
+mod test_report {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let mut report = ParseReport::default();
+        report.push("a.csv", Some(3), "Skipped row");
+        report.push("meta.json", None, "Replaced NaN");
+        let lines: Vec<String> = report.iter().map(|w| w.to_string()).collect();
+        assert_eq!(lines, ["a.csv:3: Skipped row", "meta.json: Replaced NaN"]);
+    }
+
+    #[test]
+    fn lines() {
+        assert_eq!(line_of(b"a\nb\nc", 0), 1);
+        assert_eq!(line_of(b"a\nb\nc", 2), 2);
+        assert_eq!(line_of(b"a\nb\nc", 99), 3);
+    }
+}
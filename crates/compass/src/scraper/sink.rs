@@ -0,0 +1,88 @@
+//! Destinations for the scraper output
+//!
+//! Loading a scraper output is split in two steps: parsing it into a
+//! `ScrapedOrdinance`, and writing each of its components into a
+//! `RecordSink`. The DuckDB database is the default sink, but other
+//! implementations can receive the same records, such as a dry-run that
+//! only counts what would be written, without duplicating the parsing.
+
+use tracing::trace;
+
+use super::log::RuntimeLogs;
+use super::metadata::Metadata;
+use super::ordinance::Ordinance;
+use super::quality::DataQuality;
+use super::source::Source;
+use super::usage::{Usage, UsageTotals};
+use crate::error::Result;
+
+/// Destination for the components of a scraped ordinance
+///
+/// Each method receives one component of the scraper output and the
+/// commit (bookkeeper) id that it belongs to.
+pub(super) trait RecordSink {
+    /// Write the source documents and jurisdictions
+    fn write_source(&mut self, source: &Source, commit_id: usize) -> Result<()>;
+    /// Write the configuration used to run the scraper
+    fn write_metadata(&mut self, metadata: &Metadata, commit_id: usize) -> Result<()>;
+    /// Write the LLM usage
+    fn write_usage(&mut self, usage: &Usage, commit_id: usize) -> Result<()>;
+    /// Write the quantitative and qualitative ordinances
+    fn write_ordinance(&mut self, ordinance: &Ordinance, commit_id: usize) -> Result<()>;
+    /// Write the runtime logs
+    fn write_logs(&mut self, logs: &RuntimeLogs, commit_id: usize) -> Result<()>;
+    /// Write the data quality issues found while parsing
+    fn write_quality(&mut self, quality: &DataQuality, commit_id: usize) -> Result<()>;
+}
+
+/// The default sink, writing into a DuckDB transaction
+pub(super) struct DuckDBSink<'a> {
+    conn: &'a duckdb::Transaction<'a>,
+    /// When to give up writing the ordinances, see `crate::Deadline`
+    deadline: crate::Deadline,
+    /// Totals of the usage written, if any
+    pub(super) usage: Option<UsageTotals>,
+}
+
+impl<'a> DuckDBSink<'a> {
+    pub(super) fn new(conn: &'a duckdb::Transaction<'a>, deadline: crate::Deadline) -> Self {
+        Self {
+            conn,
+            deadline,
+            usage: None,
+        }
+    }
+}
+
+impl RecordSink for DuckDBSink<'_> {
+    fn write_source(&mut self, source: &Source, commit_id: usize) -> Result<()> {
+        trace!("Writing source into DuckDB");
+        source.record(self.conn, commit_id)
+    }
+
+    fn write_metadata(&mut self, metadata: &Metadata, commit_id: usize) -> Result<()> {
+        trace!("Writing metadata into DuckDB");
+        metadata.write(self.conn, commit_id)
+    }
+
+    fn write_usage(&mut self, usage: &Usage, commit_id: usize) -> Result<()> {
+        trace!("Writing usage into DuckDB");
+        self.usage = Some(usage.write(self.conn, commit_id)?);
+        Ok(())
+    }
+
+    fn write_ordinance(&mut self, ordinance: &Ordinance, commit_id: usize) -> Result<()> {
+        trace!("Writing ordinance into DuckDB");
+        ordinance.write(self.conn, commit_id, self.deadline)
+    }
+
+    fn write_logs(&mut self, logs: &RuntimeLogs, commit_id: usize) -> Result<()> {
+        trace!("Writing runtime logs into DuckDB");
+        logs.write(self.conn, commit_id)
+    }
+
+    fn write_quality(&mut self, quality: &DataQuality, commit_id: usize) -> Result<()> {
+        trace!("Writing data quality into DuckDB");
+        quality.write(self.conn, commit_id)
+    }
+}
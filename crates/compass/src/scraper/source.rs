@@ -15,13 +15,31 @@ use sha2::Digest;
 use tokio::io::AsyncReadExt;
 use tracing::{debug, error, trace, warn};
 
-use super::MAX_JSON_FILE_SIZE;
+use super::duration::RunDuration;
+use super::quality::{self, DataQuality};
+use super::report::ParseReport;
 use crate::error::Result;
 
+/// Maximum number of jurisdictions parsed from `jurisdictions.json`
+///
+/// The file is streamed, but the jurisdictions parsed are kept to be
+/// validated before being recorded, thus this bounds the memory used. It
+/// is well above all the counties, cities, and townships of the US.
+const MAX_JURISDICTIONS: usize = 100_000;
+
 #[derive(Debug, Deserialize)]
 /// A collection of target jurisdictions and related information
 pub(super) struct Source {
     pub(super) jurisdictions: Vec<Jurisdiction>,
+    #[serde(skip)]
+    /// Files in `ordinance_files/` not matching any known document
+    unmatched: Vec<std::path::PathBuf>,
+    #[serde(skip)]
+    /// Checksum of each file in `ordinance_files/`, by its filename
+    files: std::collections::HashMap<String, String>,
+    #[serde(skip)]
+    /// Warnings found while parsing
+    report: ParseReport,
 }
 
 #[derive(Debug, Deserialize)]
@@ -42,8 +60,9 @@ pub(super) struct Jurisdiction {
     fips: u64,
     /// Whether the jurisdiction was found during the scraping
     found: bool,
-    /// Total time spent scraping the jurisdiction, in seconds
-    total_time: f64,
+    /// Total time spent scraping the jurisdiction, in seconds, if a
+    /// finite number
+    total_time: Option<RunDuration>,
     /// Total time spent scraping the jurisdiction, as a string
     total_time_string: String,
     /// Main jurisdiction website used for web crawling, if any, as a string
@@ -150,19 +169,67 @@ impl Source {
         Ok(())
     }
 
-    fn from_json(content: &str) -> Result<Self> {
-        trace!("Parsing sources' jurisdictions from json: {:?}", content);
-
-        let source = match serde_json::from_str(content) {
-            Ok(source) => source,
+    /// Parse the jurisdictions one at a time from a JSON reader
+    ///
+    /// National runs produce large `jurisdictions.json` files, thus
+    /// instead of reading the whole content at once, each element of the
+    /// `jurisdictions` array is handed to `f` as soon as it is parsed, and
+    /// only one element is held in memory at a time. Other top level
+    /// fields are ignored.
+    ///
+    /// Some versions of the scraper write a bare array of jurisdictions
+    /// instead, without the wrapping object, which is accepted as well.
+    ///
+    /// Non-finite numbers are taken as missing, registered in `report`.
+    ///
+    /// # Returns
+    ///
+    /// The number of jurisdictions parsed.
+    pub(super) fn stream<R, F>(reader: R, report: &mut ParseReport, f: F) -> Result<usize>
+    where
+        R: std::io::Read,
+        F: FnMut(Jurisdiction) -> Result<()>,
+    {
+        let mut reader = super::nonfinite::NullNonFinite::new(reader);
+        let mut de = serde_json::Deserializer::from_reader(&mut reader);
+        let n = match serde::Deserializer::deserialize_any(&mut de, JurisdictionsVisitor(f)) {
+            Ok(n) => n,
             Err(e) => {
                 error!("Error parsing sources' jurisdictions from json: {:?}", e);
-                return Err(crate::error::Error::Undefined(
-                    "Failed to parse sources' jurisdiction as a json".to_string(),
-                ));
+                return Err(e.into());
             }
         };
-        Ok(source)
+        de.end()?;
+        for &line in reader.replaced() {
+            report.push(
+                "jurisdictions.json",
+                Some(line),
+                "Non-finite number taken as missing",
+            );
+        }
+
+        trace!("Parsed {} jurisdictions", n);
+        Ok(n)
+    }
+
+    /// Read up to `max` jurisdictions from a `jurisdictions.json` file
+    fn read(path: &std::path::Path, max: usize) -> Result<Self> {
+        let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+        let mut jurisdictions = Vec::new();
+        let mut report = ParseReport::default();
+        Self::stream(reader, &mut report, |j| {
+            if jurisdictions.len() == max {
+                return Err(crate::error::Error::TooManyJurisdictions(max));
+            }
+            jurisdictions.push(j);
+            Ok(())
+        })?;
+        Ok(Source {
+            jurisdictions,
+            unmatched: Vec::new(),
+            files: std::collections::HashMap::new(),
+            report,
+        })
     }
 
     /// Open a Source collection from a scrapped output directory
@@ -178,7 +245,14 @@ impl Source {
     /// # Arguments
     ///
     /// * `root` - The root directory where the scrapped output is located.
-    pub(super) async fn open<P: AsRef<std::path::Path>>(root: P) -> Result<Self> {
+    /// * `buffer_size` - Size, in bytes, of the buffer to checksum the
+    ///   documents.
+    /// * `parallel` - Maximum number of documents checksummed at once.
+    pub(super) async fn open<P: AsRef<std::path::Path>>(
+        root: P,
+        buffer_size: usize,
+        parallel: usize,
+    ) -> Result<Self> {
         debug!("Opening source documents from {:?}", root.as_ref());
 
         trace!("Opening jurisdictions collection");
@@ -193,16 +267,14 @@ impl Source {
 
         trace!("Identified jurisdictions.json file");
 
-        let file_size = tokio::fs::metadata(&path).await?.len();
-        if file_size > MAX_JSON_FILE_SIZE {
-            error!("Jurisdictions file too large: {:?}", file_size);
-            return Err(crate::error::Error::Undefined(
-                "jurisdictions.json file is too large".to_string(),
-            ));
-        }
-
-        let content = tokio::fs::read_to_string(&path).await?;
-        let jurisdictions = match Self::from_json(&content) {
+        // Parsing is blocking, but streamed, so the raw content is never
+        // held in memory, and the jurisdictions parsed are bounded.
+        let parsing = path.clone();
+        let jurisdictions =
+            tokio::task::spawn_blocking(move || Self::read(&parsing, MAX_JURISDICTIONS))
+                .await
+                .map_err(|e| crate::error::Error::Undefined(e.to_string()))?;
+        let mut jurisdictions = match jurisdictions {
             Ok(jurisdictions) => jurisdictions,
             Err(e) => {
                 error!("Failed parsing file: {:?}", &path);
@@ -218,7 +290,7 @@ impl Source {
             .iter()
             .filter_map(|j| j.documents.as_ref())
             .flatten()
-            .map(|d| (d.ord_filename.clone(), d.checksum.clone()))
+            .map(|d| (relative_filename(&d.ord_filename), d.checksum.clone()))
             .collect::<Vec<_>>();
         trace!("Known sources: {:?}", known_sources);
 
@@ -230,16 +302,7 @@ impl Source {
             ));
         }
 
-        trace!("Scanning source directory: {:?}", path);
-
-        let mut walker = tokio::fs::read_dir(&path).await?;
-        let mut jobs = tokio::task::JoinSet::new();
-        while let Some(entry) = walker.next_entry().await? {
-            trace!("Spawning job for entry: {:?}", entry.path());
-            jobs.spawn(async move { File::new(entry.path()).await });
-        }
-        trace!("Waiting for all jobs to complete");
-        let inventory = jobs.join_all().await;
+        let inventory = inventory(&path, buffer_size, parallel).await?;
         trace!("Inventory of files: {:?}", inventory);
         debug!("Finished reading {} source documents", inventory.len());
 
@@ -247,10 +310,14 @@ impl Source {
             match file {
                 Ok(file) => {
                     let (file_name, checksum) = (file.filename, file.checksum);
+                    jurisdictions
+                        .files
+                        .insert(file_name.clone(), checksum.clone());
                     if known_sources.contains(&(file_name, checksum)) {
                         trace!("File {:?} matches known jurisdiction source", file.path);
                     } else {
                         warn!("File {:?} doesn't match known sources", file.path);
+                        jurisdictions.unmatched.push(file.path);
                     }
                 }
                 Err(e) => {
@@ -262,6 +329,128 @@ impl Source {
         Ok(jurisdictions)
     }
 
+    /// Warnings found while parsing
+    pub(super) fn report(&self) -> &ParseReport {
+        &self.report
+    }
+
+    /// Keep only the first `n` jurisdictions
+    pub(super) fn truncate(&mut self, n: usize) {
+        debug!(
+            "Keeping {} of {} jurisdictions",
+            n.min(self.jurisdictions.len()),
+            self.jurisdictions.len()
+        );
+        self.jurisdictions.truncate(n);
+    }
+
+    /// FIPS codes of the jurisdictions
+    pub(super) fn fips(&self) -> std::collections::HashSet<u64> {
+        self.jurisdictions.iter().map(|j| j.fips).collect()
+    }
+
+    /// Names that identify each jurisdiction
+    ///
+    /// Besides the full name, a jurisdiction without subdivision is also
+    /// named by its county and state, such as "Decatur County, Indiana".
+    pub(super) fn names(&self) -> std::collections::HashSet<String> {
+        let mut names = std::collections::HashSet::new();
+        for jurisdiction in &self.jurisdictions {
+            names.insert(jurisdiction.full_name.clone());
+            if jurisdiction.subdivision.is_none()
+                && let Some(county) = &jurisdiction.county
+            {
+                names.insert(format!("{}, {}", county, jurisdiction.state));
+            }
+        }
+        names
+    }
+
+    /// Files in `ordinance_files` that don't match any known document
+    pub(super) fn unmatched(&self) -> &[std::path::PathBuf] {
+        &self.unmatched
+    }
+
+    /// Number of jurisdictions with a document found
+    pub(super) fn found_count(&self) -> usize {
+        self.jurisdictions.iter().filter(|j| j.found).count()
+    }
+
+    /// Number of documents found, over all the jurisdictions
+    pub(super) fn document_count(&self) -> usize {
+        self.jurisdictions
+            .iter()
+            .filter_map(|j| j.documents.as_ref())
+            .map(Vec::len)
+            .sum()
+    }
+
+    /// Validate the Source collection, registering any issue found
+    pub(super) fn validate(&self, quality: &mut DataQuality) {
+        trace!("Validating jurisdictions");
+
+        for jurisdiction in &self.jurisdictions {
+            if let Some(total_time) = jurisdiction.total_time {
+                quality::check_duration(
+                    quality,
+                    "source",
+                    "total_time_string",
+                    total_time,
+                    &jurisdiction.total_time_string,
+                );
+            }
+        }
+
+        let documents = self
+            .jurisdictions
+            .iter()
+            .filter_map(|j| j.documents.as_ref())
+            .flatten();
+        for document in documents {
+            quality::check_year(
+                quality,
+                "source",
+                "effective_year",
+                document.effective_year.map(u32::from),
+            );
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        for jurisdiction in &self.jurisdictions {
+            if !seen.insert(jurisdiction.fips) {
+                quality.push(quality::Issue::new(
+                    "source",
+                    "FIPS",
+                    jurisdiction.fips,
+                    "Jurisdiction listed more than once, its entries are merged",
+                ));
+            }
+        }
+
+        for path in &self.unmatched {
+            quality.push(quality::Issue::new(
+                "source",
+                "ordinance_files",
+                path.display(),
+                "File doesn't match the filename and checksum of any known document",
+            ));
+        }
+    }
+
+    /// Validate the source of each document, registering malformed URLs
+    pub(super) fn validate_sources(&self, quality: &mut DataQuality) {
+        trace!("Validating document sources");
+
+        let documents = self
+            .jurisdictions
+            .iter()
+            .filter_map(|j| j.documents.as_ref())
+            .flatten();
+        for document in documents {
+            quality::check_source(quality, "source", "source", &document.source);
+        }
+    }
+
     /// Record the Source collection in the database
     ///
     /// While the information (metadata) of the source documents are
@@ -273,80 +462,296 @@ impl Source {
     pub(super) fn record(&self, conn: &duckdb::Transaction, commit_id: usize) -> Result<()> {
         debug!("Recording jurisdictions on database");
 
+        // A jurisdiction listed more than once is recorded only once per
+        // commit, with the documents of all its entries.
+        let mut rows: Vec<(&Jurisdiction, Vec<i64>)> = Vec::new();
+        let mut by_fips: std::collections::HashMap<u64, usize> = std::collections::HashMap::new();
         for jurisdiction in &self.jurisdictions {
-            trace!("Inserting jurisdiction: {:?}", jurisdiction);
-
-            let mut dids = Vec::new();
-            if let Some(documents) = &jurisdiction.documents {
-                // Replace this by a query, if not found already in the database, insert and return
-                // the id.
-                let mut stmt_archive = conn.prepare(
-                    r"
-                    INSERT INTO archive
-                    (source, effective_day, effective_month, effective_year, filename, num_pages,
-                      checksum, is_pdf, from_ocr, ordinance_text_ngram_score, permitted_use_text_ngram_score)
-                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-                    RETURNING id",
-                )?;
-
-                for document in documents {
-                    trace!("Inserting document: {:?}", document);
-                    let did = stmt_archive
-                        .query(duckdb::params![
-                            document.source,
-                            document.effective_day,
-                            document.effective_month,
-                            document.effective_year,
-                            document.ord_filename,
-                            document.num_pages,
-                            document.checksum,
-                            document.is_pdf,
-                            document.from_ocr,
-                            document.ordinance_text_ngram_score,
-                            document.permitted_use_text_ngram_score,
-                        ])?
-                        .next()
-                        .unwrap()
-                        .unwrap()
-                        .get::<_, i64>(0)
-                        .unwrap();
-                    dids.push(did);
+            trace!("Inserting documents of jurisdiction: {:?}", jurisdiction);
+
+            let dids = match &jurisdiction.documents {
+                Some(documents) => archive_documents(conn, documents)?,
+                None => {
+                    trace!("No documents found for jurisdiction: {:?}", jurisdiction);
+                    Vec::new()
                 }
-                trace!("Inserted documents' ids: {:?}", dids);
-            } else {
-                trace!("No documents found for jurisdiction: {:?}", jurisdiction);
+            };
+
+            match by_fips.get(&jurisdiction.fips) {
+                Some(&i) => {
+                    warn!("Merging repeated jurisdiction FIPS {}", jurisdiction.fips);
+                    rows[i].1.extend(dids);
+                }
+                None => {
+                    by_fips.insert(jurisdiction.fips, rows.len());
+                    rows.push((jurisdiction, dids));
+                }
+            }
+        }
+
+        for (jurisdiction, dids) in rows {
+            insert_jurisdiction(conn, commit_id, jurisdiction, &dids)?;
+        }
+        Ok(())
+    }
+
+    /// Add the documents to an existing commit
+    ///
+    /// For documents that arrive after the commit was loaded. Documents
+    /// with the checksum of one already in the commit are skipped, so
+    /// the same output can be added again safely. The new documents are
+    /// appended to the jurisdiction of the commit with the same FIPS, if
+    /// any, otherwise the jurisdiction is recorded as in `record()`.
+    /// Jurisdictions without any new document are left untouched.
+    /// Documents missing from `ordinance_files/`, or with a different
+    /// checksum there, are not added, and registered in `quality`.
+    ///
+    /// # Returns
+    ///
+    /// The number of documents added.
+    pub(super) fn append(
+        &self,
+        conn: &duckdb::Transaction,
+        commit_id: usize,
+        quality: &mut DataQuality,
+    ) -> Result<usize> {
+        debug!("Appending documents to commit {}", commit_id);
+
+        let mut known: std::collections::HashSet<String> = conn
+            .prepare(
+                r"
+                SELECT DISTINCT archive.checksum
+                FROM archive JOIN source ON list_contains(
+                  string_split(source.documents, ','), CAST(archive.id AS VARCHAR))
+                WHERE source.bookkeeper_lnk = ? AND archive.checksum IS NOT NULL",
+            )?
+            .query_map([commit_id], |row| row.get(0))?
+            .collect::<duckdb::Result<_>>()?;
+        trace!("Checksums already in the commit: {:?}", known);
+
+        let mut added = 0;
+        for jurisdiction in &self.jurisdictions {
+            let documents = jurisdiction
+                .documents
+                .iter()
+                .flatten()
+                .filter(|d| {
+                    if known.contains(&d.checksum) {
+                        warn!(
+                            "Skipping {:?}, already in commit {}",
+                            d.ord_filename, commit_id
+                        );
+                        return false;
+                    }
+                    match self.files.get(&relative_filename(&d.ord_filename)) {
+                        None => {
+                            warn!("Skipping {:?}, missing file", d.ord_filename);
+                            quality.push(quality::Issue::new(
+                                "source",
+                                "ord_filename",
+                                &d.ord_filename,
+                                "Document missing from ordinance_files, not added",
+                            ));
+                            false
+                        }
+                        Some(checksum) if *checksum != d.checksum => {
+                            warn!("Skipping {:?}, mismatched checksum", d.ord_filename);
+                            quality.push(quality::Issue::new(
+                                "source",
+                                "checksum",
+                                &d.ord_filename,
+                                format!("File has checksum {checksum} instead, not added"),
+                            ));
+                            false
+                        }
+                        Some(_) => known.insert(d.checksum.clone()),
+                    }
+                })
+                .collect::<Vec<_>>();
+            if documents.is_empty() {
+                trace!("No new documents for jurisdiction: {:?}", jurisdiction);
+                continue;
             }
 
-            let mut stmt_source = conn.prepare(
+            let dids = archive_documents(conn, documents)?;
+            let updated = conn.execute(
                 r"
-                INSERT INTO source
-                (bookkeeper_lnk, full_name, county, state,
-                  subdivision, jurisdiction_type, fips,
-                  found, total_time, total_time_string,
-                  jurisdiction_website, compass_crawl, cost, documents)
-                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                UPDATE source
+                SET documents = concat_ws(',', NULLIF(documents, ''), ?), found = true
+                WHERE bookkeeper_lnk = ? AND fips = ?",
+                duckdb::params![join_ids(&dids), commit_id, jurisdiction.fips],
             )?;
-            stmt_source.execute(duckdb::params![
-                commit_id,
-                jurisdiction.full_name,
-                jurisdiction.county,
-                jurisdiction.state,
-                jurisdiction.subdivision,
-                jurisdiction.jurisdiction_type,
-                jurisdiction.fips,
-                jurisdiction.found,
-                jurisdiction.total_time,
-                jurisdiction.total_time_string,
-                jurisdiction.jurisdiction_website,
-                jurisdiction.compass_crawl,
-                jurisdiction.cost,
-                dids.iter()
-                    .map(|did| did.to_string())
-                    .collect::<Vec<String>>()
-                    .join(","),
-            ])?;
+            if updated == 0 {
+                insert_jurisdiction(conn, commit_id, jurisdiction, &dids)?;
+            }
+            added += dids.len();
         }
-        Ok(())
+
+        debug!("Appended {} documents to commit {}", added, commit_id);
+        Ok(added)
+    }
+}
+
+/// Insert documents into the archive
+///
+/// Returns the ids of the new archive rows, in the same order.
+fn archive_documents<'a>(
+    conn: &duckdb::Transaction,
+    documents: impl IntoIterator<Item = &'a Document>,
+) -> Result<Vec<i64>> {
+    let mut stmt_archive = conn.prepare(
+        r"
+        INSERT INTO archive
+        (source, effective_day, effective_month, effective_year, filename, num_pages,
+          checksum, is_pdf, from_ocr, ordinance_text_ngram_score, permitted_use_text_ngram_score)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        RETURNING id",
+    )?;
+
+    let mut dids = Vec::new();
+    for document in documents {
+        trace!("Inserting document: {:?}", document);
+        let did = stmt_archive.query_row(
+            duckdb::params![
+                document.source,
+                document.effective_day,
+                document.effective_month,
+                document.effective_year,
+                document.ord_filename,
+                document.num_pages,
+                document.checksum,
+                document.is_pdf,
+                document.from_ocr,
+                document.ordinance_text_ngram_score,
+                document.permitted_use_text_ngram_score,
+            ],
+            |row| row.get::<_, i64>(0),
+        )?;
+        dids.push(did);
+    }
+    trace!("Inserted documents' ids: {:?}", dids);
+    Ok(dids)
+}
+
+/// Insert a jurisdiction, referring to its documents by archive id
+fn insert_jurisdiction(
+    conn: &duckdb::Transaction,
+    commit_id: usize,
+    jurisdiction: &Jurisdiction,
+    dids: &[i64],
+) -> Result<()> {
+    trace!("Inserting jurisdiction: {:?}", jurisdiction);
+    conn.execute(
+        r"
+        INSERT INTO source
+        (bookkeeper_lnk, full_name, county, state,
+          subdivision, jurisdiction_type, fips,
+          found, total_time, total_time_string,
+          jurisdiction_website, compass_crawl, cost, documents)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        duckdb::params![
+            commit_id,
+            jurisdiction.full_name,
+            jurisdiction.county,
+            jurisdiction.state,
+            jurisdiction.subdivision,
+            jurisdiction.jurisdiction_type,
+            jurisdiction.fips,
+            jurisdiction.found,
+            jurisdiction.total_time,
+            jurisdiction.total_time_string,
+            jurisdiction.jurisdiction_website,
+            jurisdiction.compass_crawl,
+            jurisdiction.cost,
+            join_ids(dids),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Archive ids as stored in `source.documents`, such as "1,2"
+fn join_ids(dids: &[i64]) -> String {
+    dids.iter()
+        .map(|did| did.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// Visit the top level of `jurisdictions.json`
+///
+/// Either an object with a `jurisdictions` array, or that array alone.
+struct JurisdictionsVisitor<F>(F);
+
+impl<'de, F> serde::de::Visitor<'de> for JurisdictionsVisitor<F>
+where
+    F: FnMut(Jurisdiction) -> Result<()>,
+{
+    type Value = usize;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("an object with a jurisdictions array, or that array")
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(
+        mut self,
+        seq: A,
+    ) -> std::result::Result<usize, A::Error> {
+        JurisdictionsSeed(&mut self.0).visit_seq(seq)
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(
+        mut self,
+        mut map: A,
+    ) -> std::result::Result<usize, A::Error> {
+        let mut n = None;
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "jurisdictions" {
+                n = Some(map.next_value_seed(JurisdictionsSeed(&mut self.0))?);
+            } else {
+                map.next_value::<serde::de::IgnoredAny>()?;
+            }
+        }
+        n.ok_or_else(|| serde::de::Error::missing_field("jurisdictions"))
+    }
+}
+
+/// Visit the `jurisdictions` array, one element at a time
+struct JurisdictionsSeed<'a, F>(&'a mut F);
+
+impl<'de, F> serde::de::DeserializeSeed<'de> for JurisdictionsSeed<'_, F>
+where
+    F: FnMut(Jurisdiction) -> Result<()>,
+{
+    type Value = usize;
+
+    fn deserialize<D: serde::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<usize, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F> serde::de::Visitor<'de> for JurisdictionsSeed<'_, F>
+where
+    F: FnMut(Jurisdiction) -> Result<()>,
+{
+    type Value = usize;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("an array of jurisdictions")
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(
+        self,
+        mut seq: A,
+    ) -> std::result::Result<usize, A::Error> {
+        let mut n = 0;
+        while let Some(jurisdiction) = seq.next_element::<Jurisdiction>()? {
+            (self.0)(jurisdiction).map_err(serde::de::Error::custom)?;
+            n += 1;
+        }
+        Ok(n)
     }
 }
 
@@ -358,7 +763,15 @@ struct File {
 }
 
 impl File {
-    async fn new<P: AsRef<std::path::Path> + std::fmt::Debug>(path: P) -> Result<Self> {
+    /// Process a file found under the `base` directory
+    ///
+    /// The filename is the path relative to `base`, thus nested files
+    /// can be matched with the documents in `jurisdictions.json`.
+    async fn new<P: AsRef<std::path::Path> + std::fmt::Debug>(
+        path: P,
+        base: std::path::PathBuf,
+        buffer_size: usize,
+    ) -> Result<Self> {
         debug!("Processing ordinance file: {:?}", path.as_ref());
 
         let metadata = tokio::fs::metadata(&path).await?;
@@ -369,8 +782,14 @@ impl File {
             ));
         }
         let path = path.as_ref().to_path_buf();
-        let checksum = checksum_file(&path).await?;
-        let filename = path.file_name().unwrap().to_str().unwrap().to_string();
+        let checksum = checksum_file(&path, buffer_size).await?;
+        let filename = path
+            .strip_prefix(&base)
+            .unwrap_or(&path)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
         Ok(Self {
             path,
             filename,
@@ -378,20 +797,100 @@ impl File {
         })
     }
 }
+
+/// Checksum all the files under `base`, recursively
+///
+/// Up to `parallel` files are checksummed at once. The results are in
+/// the order of the paths, no matter which one completed first, thus any
+/// error is reported in the same order on every run.
+async fn inventory(
+    base: &std::path::Path,
+    buffer_size: usize,
+    parallel: usize,
+) -> Result<Vec<Result<File>>> {
+    trace!("Scanning source directory: {:?}", base);
+
+    let mut paths = Vec::new();
+    let mut pending = vec![base.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut walker = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = walker.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                trace!("Scanning subdirectory: {:?}", entry.path());
+                pending.push(entry.path());
+            } else {
+                paths.push(entry.path());
+            }
+        }
+    }
+    paths.sort();
+
+    trace!(
+        "Checksumming {} files, up to {} at once",
+        paths.len(),
+        parallel
+    );
+    let permits = std::sync::Arc::new(tokio::sync::Semaphore::new(parallel.max(1)));
+    let mut jobs = tokio::task::JoinSet::new();
+    for (i, path) in paths.into_iter().enumerate() {
+        trace!("Spawning job for entry: {:?}", path);
+        let base = base.to_path_buf();
+        let permits = permits.clone();
+        jobs.spawn(async move {
+            // Held until the file is done, the semaphore is never closed
+            let _permit = permits.acquire_owned().await.ok();
+            (i, File::new(path, base, buffer_size).await)
+        });
+    }
+    trace!("Waiting for all jobs to complete");
+    let mut files = jobs.join_all().await;
+    files.sort_by_key(|(i, _)| *i);
+
+    Ok(files.into_iter().map(|(_, file)| file).collect())
+}
+
+/// Normalize a document filename given in `jurisdictions.json`
+///
+/// The filename could be a bare name or a path relative to
+/// `ordinance_files/`, possibly including the directory itself, such
+/// as `ordinance_files/subdir/doc.pdf`. The result is always relative
+/// to `ordinance_files/` with `/` as separator, i.e. `subdir/doc.pdf`.
+fn relative_filename(filename: &str) -> String {
+    let mut filename = filename.replace('\\', "/");
+    while let Some(stripped) = filename.strip_prefix("./") {
+        filename = stripped.to_string();
+    }
+    match filename.strip_prefix("ordinance_files/") {
+        Some(stripped) => stripped.to_string(),
+        None => filename,
+    }
+}
+
 /// Calculate the checksum of a local file
 ///
+/// The file is read in chunks of `buffer_size` bytes, which can be tuned
+/// for the storage, such as larger chunks for high-latency network
+/// filesystems.
+///
 /// # Returns
 ///
 /// * The checksum of the file with a tag indicating the algorithm used
 ///   (e.g. `sha256:...`)
-async fn checksum_file<P: AsRef<std::path::Path>>(path: P) -> Result<String> {
-    trace!("Calculating checksum for {:?}", path.as_ref());
+pub(crate) async fn checksum_file<P: AsRef<std::path::Path>>(
+    path: P,
+    buffer_size: usize,
+) -> Result<String> {
+    trace!(
+        "Calculating checksum for {:?} with a buffer of {} bytes",
+        path.as_ref(),
+        buffer_size
+    );
     let mut hasher = sha2::Sha256::new();
 
-    let f = tokio::fs::File::open(&path).await?;
-    let mut reader = tokio::io::BufReader::new(f);
-    let mut buffer: [u8; 1024] = [0; 1024];
-    while let Ok(n) = reader.read(&mut buffer).await {
+    let mut reader = tokio::fs::File::open(&path).await?;
+    let mut buffer = vec![0; buffer_size.max(1)];
+    loop {
+        let n = reader.read(&mut buffer).await?;
         if n == 0 {
             break;
         }
@@ -427,7 +926,7 @@ pub(crate) mod sample {
                     "FIPS": 12345,
                     "found": true,
                     "total_time": 3.14,
-                    "total_time_string": "0::0::03.14",
+                    "total_time_string": "0:00:03.14",
                     "jurisdiction_website": null,
                     "compass_crawl": false,
                     "documents": [
@@ -457,3 +956,268 @@ pub(crate) mod sample {
         Ok(file)
     }
 }
+
+#[cfg(test)]
+mod test_source {
+    use super::*;
+
+    /// A jurisdictions.json content with `n` copies of the sample jurisdiction
+    fn repeated(n: usize) -> String {
+        let sample: serde_json::Value = serde_json::from_str(&sample::as_text()).unwrap();
+        let jurisdiction = sample["jurisdictions"][0].to_string();
+        format!(
+            r#"{{"jurisdictions": [{}], "extra": {{"ignored": true}}}}"#,
+            vec![jurisdiction; n].join(",")
+        )
+    }
+
+    /// A reader that counts how many bytes were consumed so far
+    struct Counting<'a> {
+        inner: &'a [u8],
+        consumed: std::rc::Rc<std::cell::Cell<usize>>,
+    }
+
+    impl std::io::Read for Counting<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = std::io::Read::read(&mut self.inner, buf)?;
+            self.consumed.set(self.consumed.get() + n);
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn stream_one_at_a_time() {
+        let content = repeated(1000);
+        let consumed = std::rc::Rc::new(std::cell::Cell::new(0));
+        let reader = std::io::BufReader::new(Counting {
+            inner: content.as_bytes(),
+            consumed: consumed.clone(),
+        });
+
+        let mut first = None;
+        let n = Source::stream(reader, &mut ParseReport::default(), |j| {
+            assert_eq!(j.full_name, "Sample Jurisdiction");
+            first.get_or_insert(consumed.get());
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(n, 1000);
+        // The first jurisdiction is handed over before reading the rest
+        assert!(first.unwrap() < content.len() / 10);
+    }
+
+    #[test]
+    /// Non-finite numbers, as written by Python, are taken as missing
+    fn stream_non_finite() {
+        let content = sample::as_text()
+            .replace(r#""total_time": 3.14"#, r#""total_time": Infinity"#)
+            .replace(
+                r#""ordinance_text_ngram_score": 0.95"#,
+                r#""ordinance_text_ngram_score": NaN"#,
+            );
+        let mut jurisdictions = Vec::new();
+        let mut report = ParseReport::default();
+        Source::stream(content.as_bytes(), &mut report, |j| {
+            jurisdictions.push(j);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(report.len(), 2);
+
+        assert_eq!(jurisdictions[0].total_time, None);
+        let documents = jurisdictions[0].documents.as_ref().unwrap();
+        assert_eq!(documents[0].ordinance_text_ngram_score, None);
+    }
+
+    #[test]
+    /// A bare array gives the same as the array wrapped in an object
+    fn stream_bare_array() {
+        let sample: serde_json::Value = serde_json::from_str(&sample::as_text()).unwrap();
+        let bare = sample["jurisdictions"].to_string();
+
+        let mut names = Vec::new();
+        for content in [sample::as_text(), bare] {
+            let mut jurisdictions = Vec::new();
+            let n = Source::stream(content.as_bytes(), &mut ParseReport::default(), |j| {
+                jurisdictions.push(j.full_name);
+                Ok(())
+            })
+            .unwrap();
+            assert_eq!(n, jurisdictions.len());
+            names.push(jurisdictions);
+        }
+        assert_eq!(names[0], ["Sample Jurisdiction"]);
+        assert_eq!(names[0], names[1]);
+
+        let result = Source::stream(&b"42"[..], &mut ParseReport::default(), |_| Ok(()));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn stream_callback_error() {
+        let content = repeated(3);
+        let mut seen = 0;
+        let result = Source::stream(content.as_bytes(), &mut ParseReport::default(), |_| {
+            seen += 1;
+            Err(crate::error::Error::Undefined("stop".to_string()))
+        });
+        assert!(result.is_err());
+        assert_eq!(seen, 1);
+    }
+
+    #[tokio::test]
+    /// Files larger than what used to be the 5MB limit
+    async fn open_large_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tmp.path().join("ordinance_files")).unwrap();
+        let content = repeated(10_000);
+        assert!(content.len() > 5 * 1024 * 1024);
+        std::fs::write(tmp.path().join("jurisdictions.json"), content).unwrap();
+
+        let source = Source::open(tmp.path(), crate::IO_BUFFER_SIZE, crate::PARALLEL_HASH)
+            .await
+            .unwrap();
+        assert_eq!(source.jurisdictions.len(), 10_000);
+    }
+
+    #[test]
+    /// Beyond the maximum, the jurisdictions are not all kept in memory
+    fn too_many_jurisdictions() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("jurisdictions.json");
+        std::fs::write(&path, repeated(3)).unwrap();
+
+        assert_eq!(Source::read(&path, 3).unwrap().jurisdictions.len(), 3);
+        let err = Source::read(&path, 2).unwrap_err();
+        assert!(
+            err.to_string().contains("More than 2 jurisdictions"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn relative_filenames() {
+        assert_eq!(relative_filename("doc.pdf"), "doc.pdf");
+        assert_eq!(relative_filename("subdir/doc.pdf"), "subdir/doc.pdf");
+        assert_eq!(relative_filename("./subdir/doc.pdf"), "subdir/doc.pdf");
+        assert_eq!(
+            relative_filename("ordinance_files/subdir/doc.pdf"),
+            "subdir/doc.pdf"
+        );
+        assert_eq!(relative_filename("subdir\\doc.pdf"), "subdir/doc.pdf");
+    }
+
+    #[tokio::test]
+    /// A repeated FIPS is recorded once per commit, with all its documents
+    async fn record_repeated_fips() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tmp.path().join("ordinance_files")).unwrap();
+        std::fs::write(tmp.path().join("jurisdictions.json"), repeated(2)).unwrap();
+        let source = Source::open(tmp.path(), crate::IO_BUFFER_SIZE, crate::PARALLEL_HASH)
+            .await
+            .unwrap();
+        let mut quality = DataQuality::default();
+        source.validate(&mut quality);
+        assert_eq!(quality.len(), 1);
+
+        let mut db = crate::sample::empty_db(&tmp.path().join("test.db"));
+        let conn = db.transaction().unwrap();
+        conn.execute("INSERT INTO bookkeeper (hash) VALUES ('a'), ('b')", [])
+            .unwrap();
+        source.record(&conn, 1).unwrap();
+        source.record(&conn, 2).unwrap();
+        conn.commit().unwrap();
+
+        let rows: Vec<(usize, String)> = db
+            .prepare("SELECT bookkeeper_lnk, documents FROM source ORDER BY bookkeeper_lnk")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<duckdb::Result<_>>()
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].0, 1);
+        assert_eq!(rows[0].1.split(',').count(), 2);
+        assert_eq!(rows[1].0, 2);
+    }
+
+    #[tokio::test]
+    /// Documents in subdirectories of ordinance_files are matched
+    async fn open_nested_document() {
+        let tmp = tempfile::tempdir().unwrap();
+        let subdir = tmp.path().join("ordinance_files").join("subdir");
+        std::fs::create_dir_all(&subdir).unwrap();
+        std::fs::write(subdir.join("doc.pdf"), b"nested document").unwrap();
+        let checksum = checksum_file(subdir.join("doc.pdf"), crate::IO_BUFFER_SIZE)
+            .await
+            .unwrap();
+
+        let content = sample::as_text()
+            .replace("\"sample_ordinance.pdf\"", "\"subdir/doc.pdf\"")
+            .replace(
+                "sha256:1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef",
+                &checksum,
+            );
+        std::fs::write(tmp.path().join("jurisdictions.json"), content).unwrap();
+
+        let source = Source::open(tmp.path(), crate::IO_BUFFER_SIZE, crate::PARALLEL_HASH)
+            .await
+            .unwrap();
+        assert!(source.unmatched.is_empty());
+
+        // Same file, but unknown to the manifest
+        std::fs::write(
+            tmp.path().join("ordinance_files").join("doc.pdf"),
+            b"nested document",
+        )
+        .unwrap();
+        let source = Source::open(tmp.path(), crate::IO_BUFFER_SIZE, crate::PARALLEL_HASH)
+            .await
+            .unwrap();
+        assert_eq!(source.unmatched.len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    /// Concurrent checksums are all correct, and in the order of the paths
+    async fn inventory_parallel() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut expected = Vec::new();
+        for i in 0..12 {
+            let dir = tmp.path().join(format!("dir-{}", i % 3));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join(format!("doc-{i:02}.pdf"));
+            let content: Vec<u8> = (0..(1000 * (i + 1))).map(|j| (j % 251) as u8).collect();
+            std::fs::write(&path, &content).unwrap();
+            expected.push((path, format!("sha256:{:x}", sha2::Sha256::digest(&content))));
+        }
+        expected.sort();
+
+        for parallel in [1, 3, 64] {
+            let files = inventory(tmp.path(), 100, parallel).await.unwrap();
+            let found: Vec<_> = files
+                .into_iter()
+                .map(|f| f.unwrap())
+                .map(|f| (f.path, f.checksum))
+                .collect();
+            assert_eq!(found, expected, "Parallel {parallel}");
+        }
+    }
+
+    #[tokio::test]
+    /// The buffer size doesn't change the checksum
+    async fn checksum_buffer_sizes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("document.pdf");
+        let content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &content).unwrap();
+
+        let small = checksum_file(&path, 1000).await.unwrap();
+        let large = checksum_file(&path, 64 * 1024).await.unwrap();
+        assert_eq!(small, large);
+        assert_eq!(
+            small,
+            format!("sha256:{:x}", sha2::Sha256::digest(&content))
+        );
+    }
+}
@@ -4,11 +4,14 @@
 //! structure and record data in the database. All the context specific
 //! for the scraper usage is defined here.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::Read;
 
 use tracing::debug;
 
+use super::duration::RunDuration;
+use super::quality::{DataQuality, Issue};
+use super::report::ParseReport;
 use crate::error::Result;
 
 #[allow(dead_code)]
@@ -21,10 +24,14 @@ use crate::error::Result;
 pub(super) struct Usage {
     #[serde(flatten)]
     pub(super) jurisdiction: HashMap<String, UsagePerModel>,
+    #[serde(skip)]
+    /// Warnings found while parsing
+    report: ParseReport,
 }
 
 #[allow(dead_code)]
 #[derive(Debug, serde::Deserialize)]
+#[serde(try_from = "RawUsagePerModel")]
 /// Scraper usage for a single model
 ///
 /// Holds the usage information for a single LLM of a single run of the
@@ -32,10 +39,42 @@ pub(super) struct Usage {
 /// such as 'data extraction' or 'document validation'. All the
 /// components are stored in the `model` field.
 pub(super) struct UsagePerModel {
+    /// Time spent on the jurisdiction, if reported
+    total_time: Option<RunDuration>,
+    model: HashMap<String, UsagePerStep>,
+}
+
+#[derive(serde::Deserialize)]
+/// Usage for a single model as given in the JSON file, see `UsagePerModel`
+///
+/// Some versions of the scraper report the time spent both in seconds
+/// (`total_time_seconds`) and as a string (`total_time`), while others
+/// only give the string.
+struct RawUsagePerModel {
+    total_time_seconds: Option<f64>,
+    total_time: Option<String>,
     #[serde(flatten)]
     model: HashMap<String, UsagePerStep>,
 }
 
+impl TryFrom<RawUsagePerModel> for UsagePerModel {
+    type Error = String;
+
+    fn try_from(raw: RawUsagePerModel) -> std::result::Result<Self, Self::Error> {
+        let total_time = match (raw.total_time_seconds, raw.total_time) {
+            (Some(seconds), _) => Some(RunDuration::from_seconds(seconds)),
+            (None, Some(value)) => Some(RunDuration::from_hms_string(&value).ok_or_else(|| {
+                format!("total_time {value:?} is not in the format H:MM:SS.ffffff")
+            })?),
+            (None, None) => None,
+        };
+        Ok(Self {
+            total_time,
+            model: raw.model,
+        })
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Debug, serde::Deserialize)]
 /// Scraper usage for a single step
@@ -57,9 +96,47 @@ pub(super) struct UsageValues {
     response_tokens: u32,
 
     #[serde(flatten)]
+    /// Any other value tracked by the scraper, such as `cache_tokens`
     extra: HashMap<String, serde_json::Value>,
 }
 
+impl UsageValues {
+    #[allow(dead_code)]
+    /// Get an extra value, such as "cache_tokens", see `extra_get()`
+    pub(super) fn extra_get(&self, key: &str) -> Option<&serde_json::Value> {
+        super::extra_get(&self.extra, key)
+    }
+
+    /// The extra values as a JSON object, if any
+    fn extra_json(&self) -> Option<String> {
+        match self.extra.is_empty() {
+            true => None,
+            false => Some(serde_json::to_string(&self.extra).unwrap()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+/// Total LLM usage of a scraper run, across all jurisdictions and models
+pub struct UsageTotals {
+    /// Number of requests to the LLMs
+    pub requests: u64,
+    /// Tokens sent in the prompts
+    pub prompt_tokens: u64,
+    /// Tokens received in the responses
+    pub response_tokens: u64,
+}
+
+impl std::fmt::Display for UsageTotals {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "this run used {} prompt tokens, {} response tokens across {} requests",
+            self.prompt_tokens, self.response_tokens, self.requests
+        )
+    }
+}
+
 impl Usage {
     /// Initialize the database for the Usage context
     pub(super) fn init_db(conn: &duckdb::Transaction) -> Result<()> {
@@ -91,9 +168,39 @@ impl Usage {
               requests INTEGER NOT NULL,
               prompt_tokens INTEGER NOT NULL,
               response_tokens INTEGER NOT NULL,
+              extra TEXT,
+              );
+            ",
+        )?;
+        Self::init_jurisdiction(conn)?;
+        Self::init_view(conn)?;
+
+        Ok(())
+    }
+
+    /// Create the `usage_jurisdiction` table, linking usage to sources
+    ///
+    /// Kept apart from `usage_event` since DuckDB can't alter a table
+    /// referenced by others, thus neither add the link to an existing
+    /// database.
+    pub(super) fn init_jurisdiction(conn: &duckdb::Transaction) -> Result<()> {
+        conn.execute_batch(
+            r"
+            CREATE TABLE IF NOT EXISTS usage_jurisdiction (
+              usage_lnk INTEGER REFERENCES usage_event(id) NOT NULL,
+              jurisdiction_lnk INTEGER REFERENCES source(id) NOT NULL,
               );
+            ",
+        )?;
 
-            CREATE VIEW usage AS
+        Ok(())
+    }
+
+    /// Create the `usage` view, joining the event, model, and step
+    pub(super) fn init_view(conn: &duckdb::Transaction) -> Result<()> {
+        conn.execute_batch(
+            r"
+            CREATE VIEW IF NOT EXISTS usage AS
               SELECT
                 usage_event.id AS usage_event_id,
                 usage_event.bookkeeper_lnk,
@@ -107,7 +214,8 @@ impl Usage {
                 usage_step.step,
                 usage_step.requests,
                 usage_step.prompt_tokens,
-                usage_step.response_tokens
+                usage_step.response_tokens,
+                usage_step.extra
               FROM usage_event
                 JOIN usage_model ON (usage_event.id=usage_model.usage_lnk)
                 JOIN usage_step ON (usage_model.id=usage_step.model_lnk);
@@ -121,6 +229,8 @@ impl Usage {
     ///
     /// # Arguments
     /// * `root`: The root directory of the scraper output.
+    /// * `tolerant`: Accept comments and trailing commas, as in a
+    ///   hand-edited file.
     ///
     /// # Returns
     /// A Usage structure with the parsed data.
@@ -129,7 +239,7 @@ impl Usage {
     /// Currently opens and parses right the way the usage data. In the future
     /// this should be changed to a lazy approach and take better advantage of
     /// been async.
-    pub(super) async fn open<P: AsRef<std::path::Path>>(root: P) -> Result<Self> {
+    pub(super) async fn open<P: AsRef<std::path::Path>>(root: P, tolerant: bool) -> Result<Self> {
         debug!("Opening Usage from {:?}", root.as_ref());
 
         let path = root.as_ref().join("usage.json");
@@ -147,6 +257,9 @@ impl Usage {
         let mut buffer = String::new();
         let _ = reader.read_to_string(&mut buffer);
 
+        if tolerant {
+            buffer = super::jsonc::normalize(&buffer);
+        }
         let usage = Self::from_json(&buffer)?;
         tracing::trace!("Usage loaded: {:?}", usage);
 
@@ -156,14 +269,86 @@ impl Usage {
     /// Parse the usage data from a JSON string
     pub(super) fn from_json(json: &str) -> Result<Self> {
         tracing::trace!("Parsing Usage as JSON");
-        let usage: Usage = serde_json::from_str(json).unwrap();
+        let (json, replaced) = super::nonfinite::to_null(json);
+        let mut usage: Usage = serde_json::from_str(&json)?;
+        for line in replaced {
+            usage.report.push(
+                "usage.json",
+                Some(line),
+                "Non-finite number taken as missing",
+            );
+        }
         Ok(usage)
     }
 
+    /// Link the usage of a commit to the jurisdictions of its sources
+    ///
+    /// A usage event is linked, in `usage_jurisdiction`, to the source of
+    /// the same commit with that full name, or, for a jurisdiction
+    /// without subdivision, named by its county and state. When several
+    /// sources match, the event is linked only once, to the first of
+    /// them. The names without a match were already registered as issues
+    /// by `validate()`.
+    pub(super) fn link_source(conn: &duckdb::Transaction, commit_id: usize) -> Result<()> {
+        debug!("Linking usage of commit {} to the sources", commit_id);
+
+        let linked = conn.execute(
+            r"
+            INSERT INTO usage_jurisdiction (usage_lnk, jurisdiction_lnk)
+            SELECT usage_event.id, MIN(source.id)
+            FROM usage_event JOIN source ON (
+              usage_event.jurisdiction = source.full_name
+              OR (source.subdivision IS NULL
+                AND usage_event.jurisdiction = source.county || ', ' || source.state))
+            WHERE usage_event.bookkeeper_lnk = ? AND source.bookkeeper_lnk = ?
+            GROUP BY usage_event.id",
+            [commit_id, commit_id],
+        )?;
+        tracing::trace!("Linked {} usage events", linked);
+
+        Ok(())
+    }
+
+    /// Warnings found while parsing
+    pub(super) fn report(&self) -> &ParseReport {
+        &self.report
+    }
+
+    /// Validate the jurisdiction names against the known `jurisdictions`
+    ///
+    /// The usage is keyed by the jurisdiction name, while the sources
+    /// identify it by county, state, and FIPS. A name that doesn't
+    /// resolve to any source points to a naming inconsistency of the
+    /// scraper, and is registered as an issue.
+    pub(super) fn validate(&self, quality: &mut DataQuality, jurisdictions: &HashSet<String>) {
+        tracing::trace!("Validating usage jurisdictions");
+
+        let mut names = self.jurisdiction.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            if !jurisdictions.contains(name) {
+                quality.push(Issue::new(
+                    "usage",
+                    "jurisdiction",
+                    name,
+                    "Usage jurisdiction doesn't match any source jurisdiction",
+                ));
+            }
+        }
+    }
+
     /// Write the usage data to the database
-    pub(super) fn write(&self, conn: &duckdb::Transaction, commit_id: usize) -> Result<()> {
+    ///
+    /// Returns the totals of what was written, i.e. the sum of the
+    /// `tracker_totals` of every model.
+    pub(super) fn write(
+        &self,
+        conn: &duckdb::Transaction,
+        commit_id: usize,
+    ) -> Result<UsageTotals> {
         tracing::trace!("Writing Usage to the database {:?}", self);
 
+        let mut totals = UsageTotals::default();
         for (jurisdiction_name, usage_by_model) in &self.jurisdiction {
             tracing::trace!("Writing usage for {:?} to the database", jurisdiction_name);
 
@@ -188,14 +373,19 @@ impl Usage {
             {
                 tracing::trace!("Writing usage for model {:?} to the database", model_name);
 
+                let model_totals = &usage_by_model.model["tracker_totals"].step[model_name];
+                totals.requests += u64::from(model_totals.requests);
+                totals.prompt_tokens += u64::from(model_totals.prompt_tokens);
+                totals.response_tokens += u64::from(model_totals.response_tokens);
+
                 let model_id: u32 = conn.query_row(
                     "INSERT INTO usage_model (usage_lnk, model, total_requests, total_prompt_tokens, total_response_tokens) VALUES (?, ?, ?, ?, ?) RETURNING id",
-                    [
-                        &jurisdiction_id.to_string(),
+                    duckdb::params![
+                        jurisdiction_id,
                         model_name,
-                        &usage_by_model.model["tracker_totals"].step[model_name].requests.to_string(),
-                        &usage_by_model.model["tracker_totals"].step[model_name].prompt_tokens.to_string(),
-                        &usage_by_model.model["tracker_totals"].step[model_name].response_tokens.to_string()
+                        model_totals.requests,
+                        model_totals.prompt_tokens,
+                        model_totals.response_tokens,
                     ],
                     |row| row.get(0)
                     ).expect("Failed to insert usage_per_jurisdiction");
@@ -209,13 +399,14 @@ impl Usage {
                     tracing::trace!("Writing usage for step {:?} to the database", step_name);
 
                     conn.execute(
-                        "INSERT INTO usage_step (model_lnk, step, requests, prompt_tokens, response_tokens) VALUES (?, ?, ?, ?, ?)",
-                        [
-                            &model_id.to_string(),
+                        "INSERT INTO usage_step (model_lnk, step, requests, prompt_tokens, response_tokens, extra) VALUES (?, ?, ?, ?, ?, ?)",
+                        duckdb::params![
+                            model_id,
                             step_name,
-                            &step.requests.to_string(),
-                            &step.prompt_tokens.to_string(),
-                            &step.response_tokens.to_string()
+                            step.requests,
+                            step.prompt_tokens,
+                            step.response_tokens,
+                            step.extra_json(),
                         ]
                         ).expect("Failed to insert usage_step");
                 }
@@ -224,7 +415,8 @@ impl Usage {
             }
         }
 
-        Ok(())
+        debug!("Usage written: {}", totals);
+        Ok(totals)
     }
 }
 
@@ -271,6 +463,7 @@ pub(crate) mod sample {
 #[cfg(test)]
 mod test_scraper_usage {
     use super::sample::as_text_v1;
+    use super::{DataQuality, RunDuration, Usage, UsageTotals};
 
     #[test]
     fn parse_json() {
@@ -291,4 +484,213 @@ mod test_scraper_usage {
             55
         );
     }
+
+    /// The sample with the given total time fields for the jurisdiction
+    fn with_total_time(fields: &str) -> String {
+        as_text_v1().replacen(
+            r#""Decatur County, Indiana": {"#,
+            &format!(r#""Decatur County, Indiana": {{ {fields}"#),
+            1,
+        )
+    }
+
+    #[test]
+    fn total_time_seconds() {
+        let json = with_total_time(r#""total_time_seconds": 294.69, "total_time": "0:04:54.69","#);
+        let usage = Usage::from_json(&json).unwrap();
+        let total_time = usage.jurisdiction["Decatur County, Indiana"].total_time;
+        assert_eq!(total_time, Some(RunDuration::from_seconds(294.69)));
+        assert!(
+            !usage.jurisdiction["Decatur County, Indiana"]
+                .model
+                .contains_key("total_time")
+        );
+    }
+
+    #[test]
+    /// Only the string form, converted into seconds
+    fn total_time_string_only() {
+        let json = with_total_time(r#""total_time": "0:04:54.5","#);
+        let usage = Usage::from_json(&json).unwrap();
+        let total_time = usage.jurisdiction["Decatur County, Indiana"].total_time;
+        assert_eq!(total_time.unwrap().as_secs_f64(), 294.5);
+    }
+
+    #[test]
+    fn total_time_missing() {
+        let usage = Usage::from_json(&as_text_v1()).unwrap();
+        assert!(
+            usage.jurisdiction["Decatur County, Indiana"]
+                .total_time
+                .is_none()
+        );
+    }
+
+    #[test]
+    /// An invalid string is an error, not a panic
+    fn total_time_invalid() {
+        let json = with_total_time(r#""total_time": "soon","#);
+        let err = Usage::from_json(&json).unwrap_err();
+        assert!(err.to_string().contains("H:MM:SS"), "{err}");
+    }
+
+    #[test]
+    /// The totals of a write match the tracker totals of the sample
+    fn write_totals() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut db = crate::sample::empty_db(&tmp.path().join("test.db"));
+        db.execute("INSERT INTO bookkeeper (hash) VALUES ('usage')", [])
+            .unwrap();
+
+        let usage = Usage::from_json(&as_text_v1()).unwrap();
+        let conn = db.transaction().unwrap();
+        let totals = usage.write(&conn, 1).unwrap();
+        conn.commit().unwrap();
+
+        assert_eq!(
+            totals,
+            UsageTotals {
+                requests: 121,
+                prompt_tokens: 186099,
+                response_tokens: 6297,
+            }
+        );
+        assert_eq!(
+            totals.to_string(),
+            "this run used 186099 prompt tokens, 6297 response tokens across 121 requests"
+        );
+    }
+
+    #[test]
+    /// Values beyond requests and tokens are preserved as JSON
+    fn extra_values() {
+        let json = as_text_v1().replacen(
+            r#""response_tokens": 1262"#,
+            r#""response_tokens": 1262, "cache_tokens": 2048"#,
+            1,
+        );
+        let usage = Usage::from_json(&json).unwrap();
+
+        let tmp = tempfile::tempdir().unwrap();
+        let mut db = crate::sample::empty_db(&tmp.path().join("test.db"));
+        db.execute("INSERT INTO bookkeeper (hash) VALUES ('usage')", [])
+            .unwrap();
+        let conn = db.transaction().unwrap();
+        usage.write(&conn, 1).unwrap();
+        conn.commit().unwrap();
+
+        let extra: String = db
+            .query_row(
+                "SELECT extra FROM usage WHERE step = 'document_location_validation'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let extra: serde_json::Value = serde_json::from_str(&extra).unwrap();
+        assert_eq!(extra["cache_tokens"], 2048);
+        let extra: Option<String> = db
+            .query_row(
+                "SELECT extra FROM usage WHERE step = 'document_content_validation'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(extra, None);
+    }
+
+    /// The sample usage with a second jurisdiction named `name`
+    fn with_jurisdiction(name: &str) -> Usage {
+        let mut json: serde_json::Value = serde_json::from_str(&as_text_v1()).unwrap();
+        let usage = json["Decatur County, Indiana"].clone();
+        json[name] = usage;
+        Usage::from_json(&json.to_string()).unwrap()
+    }
+
+    #[test]
+    /// Only the names without a source jurisdiction are issues
+    fn validate_jurisdictions() {
+        let usage = with_jurisdiction("Decatur, Indiana");
+        let jurisdictions = ["Decatur County, Indiana".to_string()].into();
+
+        let mut quality = DataQuality::default();
+        usage.validate(&mut quality, &jurisdictions);
+        let issues = quality.iter().collect::<Vec<_>>();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].component, "usage");
+        assert_eq!(issues[0].field, "jurisdiction");
+        assert_eq!(issues[0].value, "Decatur, Indiana");
+    }
+
+    #[test]
+    /// Usage is linked to the source of the same name, of the same commit
+    fn link_source() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut db = crate::sample::empty_db(&tmp.path().join("test.db"));
+        db.execute_batch(
+            r"
+            INSERT INTO bookkeeper (hash) VALUES ('usage');
+            INSERT INTO source (bookkeeper_lnk, full_name, county, state, fips)
+              VALUES (1, 'Decatur County, Indiana', 'Decatur County', 'Indiana', 18031);",
+        )
+        .unwrap();
+
+        let usage = with_jurisdiction("Decatur, Indiana");
+        let conn = db.transaction().unwrap();
+        usage.write(&conn, 1).unwrap();
+        Usage::link_source(&conn, 1).unwrap();
+        conn.commit().unwrap();
+
+        let links = db
+            .prepare(
+                r"SELECT jurisdiction, jurisdiction_lnk
+                  FROM usage_event LEFT JOIN usage_jurisdiction ON (id = usage_lnk)
+                  ORDER BY jurisdiction",
+            )
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<duckdb::Result<Vec<(String, Option<usize>)>>>()
+            .unwrap();
+        assert_eq!(
+            links,
+            [
+                ("Decatur County, Indiana".to_string(), Some(1)),
+                ("Decatur, Indiana".to_string(), None)
+            ]
+        );
+    }
+
+    #[test]
+    /// A usage event matching several sources is linked only once
+    fn link_source_once() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut db = crate::sample::empty_db(&tmp.path().join("test.db"));
+        db.execute_batch(
+            r"
+            INSERT INTO bookkeeper (hash) VALUES ('usage');
+            INSERT INTO source (bookkeeper_lnk, full_name, county, state, fips)
+              VALUES
+                (1, 'Decatur County, Indiana', 'Decatur County', 'Indiana', 18031),
+                (1, 'Decatur County, Indiana', 'Decatur County', 'Indiana', 18031);",
+        )
+        .unwrap();
+
+        let usage = with_jurisdiction("Decatur County, Indiana");
+        let conn = db.transaction().unwrap();
+        usage.write(&conn, 1).unwrap();
+        Usage::link_source(&conn, 1).unwrap();
+        conn.commit().unwrap();
+
+        let (events, links, sources): (usize, usize, usize) = db
+            .query_row(
+                r"SELECT count(DISTINCT usage_lnk), count(*), count(DISTINCT jurisdiction_lnk)
+                  FROM usage_jurisdiction",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert!(events > 0);
+        assert_eq!(links, events);
+        assert_eq!(sources, 1);
+    }
 }
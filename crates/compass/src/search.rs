@@ -0,0 +1,241 @@
+//! Full-text search of the qualitative ordinances
+//!
+//! Qualitative ordinances are summarized as long text, thus finding
+//! those about a topic, such as "noise" or "decommissioning", is a
+//! matter of searching that text. The search is ranked by relevance
+//! (BM25) with the DuckDB `fts` extension.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use tracing::{trace, warn};
+
+use crate::error::Result;
+
+#[derive(Debug, Serialize)]
+/// A qualitative ordinance matching a search
+pub struct QualitativeRecord {
+    /// Commit (bookkeeper id) that loaded it
+    pub commit_id: usize,
+    /// County, such as "Jefferson County"
+    pub county: Option<String>,
+    /// State, such as "Colorado"
+    pub state: Option<String>,
+    /// FIPS code of the jurisdiction
+    #[serde(rename = "FIPS")]
+    pub fips: Option<u64>,
+    /// Feature, such as "noise"
+    pub feature: Option<String>,
+    /// Summary of the ordinance, where the query was found
+    pub summary: Option<String>,
+    /// Year of the ordinance, if known
+    pub ord_year: Option<u32>,
+    /// Source of the ordinance, such as a URL
+    pub source: Option<String>,
+    /// Relevance to the query, the higher the better
+    pub score: f64,
+}
+
+/// Search the summaries of the qualitative ordinances
+///
+/// Returns the ordinances with a summary matching any of the words of
+/// `query`, the most relevant first. Matching is case insensitive and
+/// on the word stems, thus "setbacks" also finds "setback".
+///
+/// The database is only read. The search index is built on a temporary
+/// in-memory copy of the summaries, so it always covers the latest
+/// loads. Requires the `fts` extension, installed on the first use.
+/// Without it, such as offline, the summaries are ranked by the number
+/// of words of `query` they contain, without stemming.
+pub fn search(conn: &duckdb::Connection, query: &str) -> Result<Vec<QualitativeRecord>> {
+    trace!("Searching {:?}", query);
+
+    if query.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let summaries = conn
+        .prepare("SELECT id, summary FROM qualitative WHERE summary IS NOT NULL")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<duckdb::Result<Vec<(usize, String)>>>()?;
+    let scores = match rank_bm25(&summaries, query) {
+        Ok(scores) => scores,
+        Err(e) => {
+            warn!("Full-text search unavailable, matching words instead: {e}");
+            rank_words(&summaries, query)
+        }
+    };
+    if scores.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let ids = scores
+        .keys()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut stmt = conn.prepare(
+        r"
+        SELECT id, bookkeeper_lnk, county, state, FIPS, feature, summary,
+          ord_year, source
+        FROM qualitative
+        WHERE list_contains(string_split(?, ','), CAST(id AS VARCHAR))",
+    )?;
+    let mut matches = stmt
+        .query_map([ids], |row| {
+            let id: usize = row.get(0)?;
+            Ok(QualitativeRecord {
+                commit_id: row.get(1)?,
+                county: row.get(2)?,
+                state: row.get(3)?,
+                fips: row.get(4)?,
+                feature: row.get(5)?,
+                summary: row.get(6)?,
+                ord_year: row.get(7)?,
+                source: row.get(8)?,
+                score: scores[&id],
+            })
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+    matches.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(a.commit_id.cmp(&b.commit_id))
+            .then(a.fips.cmp(&b.fips))
+    });
+    trace!("Found {} matches", matches.len());
+
+    Ok(matches)
+}
+
+/// Relevance (BM25) of each matching summary, by its id
+///
+/// The `fts` index is built on an in-memory database, which is dropped
+/// right after.
+fn rank_bm25(summaries: &[(usize, String)], query: &str) -> Result<HashMap<usize, f64>> {
+    let index = duckdb::Connection::open_in_memory()?;
+    index.execute_batch(
+        r"
+        INSTALL fts;
+        LOAD fts;
+        CREATE TABLE summaries (id INTEGER, summary TEXT);",
+    )?;
+    {
+        let mut appender = index.appender("summaries")?;
+        for (id, summary) in summaries {
+            appender.append_row(duckdb::params![id, summary])?;
+        }
+    }
+    index.execute_batch("PRAGMA create_fts_index('summaries', 'id', 'summary');")?;
+
+    let scores = index
+        .prepare(
+            r"
+            SELECT * FROM (
+              SELECT id, fts_main_summaries.match_bm25(id, ?) AS score
+              FROM summaries
+            )
+            WHERE score IS NOT NULL",
+        )?
+        .query_map([query], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<duckdb::Result<HashMap<_, _>>>()?;
+    Ok(scores)
+}
+
+/// Number of distinct words of `query` in each matching summary, by its id
+fn rank_words(summaries: &[(usize, String)], query: &str) -> HashMap<usize, f64> {
+    let mut words = query
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+    words.sort();
+    words.dedup();
+
+    summaries
+        .iter()
+        .filter_map(|(id, summary)| {
+            let summary = summary.to_lowercase();
+            let found = words
+                .iter()
+                .filter(|w| summary.contains(w.as_str()))
+                .count();
+            (found > 0).then_some((*id, found as f64))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test_search {
+    use super::*;
+    use crate::sample::empty_db;
+
+    #[test]
+    /// A blank query matches nothing, without querying the database
+    fn blank_query() {
+        // No tables at all, thus any query would fail
+        let conn = duckdb::Connection::open_in_memory().unwrap();
+
+        assert!(search(&conn, "  ").unwrap().is_empty());
+    }
+
+    #[test]
+    /// Found with or without the `fts` extension, leaving the database as is
+    fn search_summaries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        let conn = empty_db(&db);
+        conn.execute_batch(
+            r"
+            INSERT INTO bookkeeper (hash, username) VALUES ('sample', 'test');
+            INSERT INTO qualitative (bookkeeper_lnk, county, state, FIPS, feature, summary)
+              VALUES (1, 'county-1', 'state-1', 11111, 'noise',
+                      'Noise shall not exceed 50 dBA at any occupied dwelling'),
+                     (1, 'county-1', 'state-1', 11111, 'decommissioning',
+                      'Turbines must be removed within one year of ceasing operation'),
+                     (1, 'county-2', 'state-2', 22222, 'noise',
+                      'Sound levels, including noise from blades, limited to 45 dBA');
+            ",
+        )
+        .unwrap();
+        drop(conn);
+        let conn = crate::open_readonly(&db).unwrap();
+
+        let found = search(&conn, "noise").unwrap();
+        assert_eq!(found.len(), 2);
+        assert!(found.iter().all(|r| r.feature.as_deref() == Some("noise")));
+
+        let found = search(&conn, "removed").unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].fips, Some(11111));
+        assert_eq!(found[0].feature.as_deref(), Some("decommissioning"));
+
+        assert!(search(&conn, "lighting").unwrap().is_empty());
+
+        // No index, or anything else, is stored in the database
+        let conn = crate::open_readonly(db).unwrap();
+        let schemas: usize = conn
+            .query_row(
+                "SELECT COUNT(*) FROM duckdb_schemas() WHERE schema_name LIKE 'fts_%'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(schemas, 0);
+    }
+
+    #[test]
+    /// Without `fts`, the summaries with more of the words rank first
+    fn rank_by_words() {
+        let summaries = [
+            (1, "Noise limited to 50 dBA".to_string()),
+            (2, "Turbines must be removed".to_string()),
+            (3, "Noise from turbines".to_string()),
+        ];
+
+        let scores = rank_words(&summaries, "turbines NOISE noise");
+        assert_eq!(scores.len(), 3);
+        assert_eq!(scores[&3], 2.0);
+        assert_eq!(scores[&1], 1.0);
+        assert_eq!(scores[&2], 1.0);
+    }
+}
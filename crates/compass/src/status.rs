@@ -0,0 +1,128 @@
+//! Freshness of the database
+//!
+//! An ordinance database is refreshed by loading new scraper runs. This
+//! module reports how old its newest data is, so that operators notice
+//! when it hasn't been refreshed for a while.
+
+use serde::Serialize;
+use tracing::trace;
+
+use crate::error::Result;
+
+/// Seconds in a day
+const DAY: f64 = 86_400.0;
+
+#[derive(Debug, Serialize)]
+/// Freshness of a database
+pub struct Status {
+    /// When the database file was last modified, such as
+    /// "2025-03-04 05:12:30+00", if available from the filesystem
+    pub modified: Option<String>,
+    /// When the most recent commit was loaded, if any
+    pub latest_commit: Option<String>,
+    /// Age of the most recent commit, in days, if any
+    pub age_days: Option<f64>,
+}
+
+impl Status {
+    /// Whether the newest data is older than `days`
+    ///
+    /// A database without any commit is always stale.
+    pub fn is_stale(&self, days: f64) -> bool {
+        self.age_days.is_none_or(|age| age > days)
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Database modified: {}",
+            self.modified.as_deref().unwrap_or("unknown")
+        )?;
+        match (&self.latest_commit, self.age_days) {
+            (Some(latest), Some(age)) => {
+                writeln!(f, "Latest commit: {latest}")?;
+                writeln!(f, "Newest data is {age:.1} days old")
+            }
+            _ => writeln!(f, "Latest commit: none"),
+        }
+    }
+}
+
+/// Report the freshness of a database
+///
+/// The age of the newest data is given by the most recent commit, i.e.
+/// the latest load, not by the modification of the file, which changes
+/// with any maintenance as well.
+pub fn status(conn: &duckdb::Connection) -> Result<Status> {
+    let path = crate::database_path(conn)?;
+    trace!("Checking the freshness of {:?}", path);
+
+    // An in-memory database has no file to be modified
+    let modified = path
+        .map(std::fs::metadata)
+        .transpose()?
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok());
+
+    let modified = match modified {
+        Some(t) => conn.query_row(
+            "SELECT CAST(to_timestamp(?) AS VARCHAR)",
+            [t.as_secs_f64()],
+            |row| row.get(0),
+        )?,
+        None => None,
+    };
+    let (latest_commit, age_seconds): (Option<String>, Option<f64>) = conn.query_row(
+        r"
+        SELECT CAST(MAX(created_at) AS VARCHAR),
+          date_diff('millisecond', MAX(created_at), CAST(now() AS TIMESTAMP)) / 1000.0
+        FROM bookkeeper",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    Ok(Status {
+        modified,
+        latest_commit,
+        age_days: age_seconds.map(|s| s / DAY),
+    })
+}
+
+#[cfg(test)]
+mod test_status {
+    use super::*;
+    use crate::sample::empty_db;
+
+    #[test]
+    /// The age of the newest commit, either recent or old
+    fn staleness() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        let conn = empty_db(&db);
+
+        let empty = status(&conn).unwrap();
+        assert!(empty.modified.is_some());
+        assert_eq!(empty.latest_commit, None);
+        assert!(empty.is_stale(7.0));
+
+        conn.execute_batch(
+            "INSERT INTO bookkeeper (hash, created_at)
+               VALUES ('old', CAST(now() AS TIMESTAMP) - INTERVAL 30 DAY);",
+        )
+        .unwrap();
+        let old = status(&conn).unwrap();
+        let age = old.age_days.unwrap();
+        assert!((age - 30.0).abs() < 0.1, "Age: {age}");
+        assert!(old.is_stale(7.0));
+        assert!(!old.is_stale(31.0));
+
+        conn.execute_batch("INSERT INTO bookkeeper (hash) VALUES ('recent');")
+            .unwrap();
+        let recent = status(&conn).unwrap();
+        assert!(recent.age_days.unwrap() < 0.1);
+        assert!(!recent.is_stale(7.0));
+        assert!(recent.to_string().contains("Newest data is 0.0 days old"));
+    }
+}
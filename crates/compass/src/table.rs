@@ -0,0 +1,112 @@
+//! Render query results as an aligned text table
+//!
+//! Intended for interactive use in a terminal, thus long values, such as
+//! qualitative summaries, are truncated to keep each row in a single line.
+
+use crate::error::Result;
+
+/// Maximum width of a column, in characters
+const MAX_WIDTH: usize = 40;
+
+/// Truncate a value to `MAX_WIDTH` characters, ending with an ellipsis
+fn truncate(value: &str) -> String {
+    // A single line per row, even for multi-line summaries
+    let value = value.replace(['\n', '\r'], " ");
+    if value.chars().count() <= MAX_WIDTH {
+        return value;
+    }
+    let mut truncated: String = value.chars().take(MAX_WIDTH - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Write `rows` as a bordered table with the given `headers`
+///
+/// Missing values (`None`) are left blank.
+pub(crate) fn render<W: std::io::Write>(
+    wtr: &mut W,
+    headers: &[&str],
+    rows: &[Vec<Option<String>>],
+) -> Result<()> {
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|v| truncate(v.as_deref().unwrap_or("")))
+                .collect()
+        })
+        .collect();
+
+    let widths: Vec<usize> = headers
+        .iter()
+        .enumerate()
+        .map(|(i, h)| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain([h.chars().count()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let border = widths
+        .iter()
+        .map(|w| "-".repeat(w + 2))
+        .collect::<Vec<_>>()
+        .join("+");
+    let border = format!("+{border}+");
+    let line = |values: &mut dyn Iterator<Item = &str>| {
+        let cells = values
+            .zip(&widths)
+            .map(|(v, w)| format!(" {v}{} ", " ".repeat(w - v.chars().count())))
+            .collect::<Vec<_>>()
+            .join("|");
+        format!("|{cells}|")
+    };
+
+    writeln!(wtr, "{border}")?;
+    writeln!(wtr, "{}", line(&mut headers.iter().copied()))?;
+    writeln!(wtr, "{border}")?;
+    for row in &rows {
+        writeln!(wtr, "{}", line(&mut row.iter().map(String::as_str)))?;
+    }
+    writeln!(wtr, "{border}")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_table {
+    use super::*;
+
+    #[test]
+    fn aligned() {
+        let rows = vec![
+            vec![Some("11111".to_string()), Some("structures".to_string())],
+            vec![Some("2".to_string()), None],
+        ];
+        let mut output = Vec::new();
+        render(&mut output, &["FIPS", "feature"], &rows).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "\
++-------+------------+
+| FIPS  | feature    |
++-------+------------+
+| 11111 | structures |
+| 2     |            |
++-------+------------+
+"
+        );
+    }
+
+    #[test]
+    fn truncate_long() {
+        let long = "a".repeat(100);
+        let truncated = truncate(&long);
+        assert_eq!(truncated.chars().count(), MAX_WIDTH);
+        assert!(truncated.ends_with('…'));
+        assert_eq!(truncate("multi\nline"), "multi line");
+    }
+}
@@ -0,0 +1,146 @@
+//! Verify the consistency of the loaded scraper outputs
+//!
+//! A scraper output is loaded from several files, and an incomplete one,
+//! such as a truncated ordinance CSV, still loads fine. Those problems
+//! show up as a mismatch between the jurisdictions of each file, which
+//! is what these checks look for.
+
+use serde::Serialize;
+use tracing::trace;
+
+use crate::error::Result;
+
+#[derive(Debug, PartialEq, Serialize)]
+/// A jurisdiction missing from one of the components of a commit
+pub enum Inconsistency {
+    /// A jurisdiction found by the scraper, but without any ordinance
+    MissingOrdinance {
+        /// Commit (bookkeeper id) of the load
+        commit_id: usize,
+        /// FIPS code of the jurisdiction
+        fips: u64,
+    },
+    /// A jurisdiction with ordinances, but not in the sources
+    MissingSource {
+        /// Commit (bookkeeper id) of the load
+        commit_id: usize,
+        /// FIPS code of the jurisdiction
+        fips: u64,
+    },
+}
+
+impl std::fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Inconsistency::MissingOrdinance { commit_id, fips } => write!(
+                f,
+                "commit {commit_id}: FIPS {fips} is in the sources but has no ordinance"
+            ),
+            Inconsistency::MissingSource { commit_id, fips } => write!(
+                f,
+                "commit {commit_id}: FIPS {fips} has ordinances but is not in the sources"
+            ),
+        }
+    }
+}
+
+/// Compare the jurisdictions of the sources and of the ordinances
+///
+/// Within each commit, report the jurisdictions found by the scraper
+/// (`found` in the sources) without any ordinance, and the jurisdictions
+/// with ordinances that are not in the sources. Commits without sources,
+/// such as an ordinance CSV loaded from a stream, are not checked.
+///
+/// The result is sorted by commit and FIPS code.
+pub fn consistency_check(conn: &duckdb::Connection) -> Result<Vec<Inconsistency>> {
+    trace!("Checking the consistency of the database");
+
+    let mut stmt = conn.prepare(
+        r"
+        WITH
+          sourced AS (
+            SELECT DISTINCT bookkeeper_lnk, fips FROM source WHERE found
+          ),
+          ordained AS (
+            SELECT DISTINCT bookkeeper_lnk, FIPS AS fips FROM ordinance
+            WHERE bookkeeper_lnk IN (SELECT bookkeeper_lnk FROM source)
+          )
+        SELECT * FROM (
+          SELECT 'ordinance' AS missing, * FROM (SELECT * FROM sourced EXCEPT SELECT * FROM ordained)
+          UNION ALL
+          SELECT 'source' AS missing, * FROM (SELECT * FROM ordained EXCEPT SELECT * FROM sourced)
+        )
+        ORDER BY bookkeeper_lnk, fips, missing",
+    )?;
+    let inconsistencies = stmt
+        .query_map([], |row| {
+            let missing: String = row.get(0)?;
+            let (commit_id, fips) = (row.get(1)?, row.get(2)?);
+            Ok(match missing.as_str() {
+                "ordinance" => Inconsistency::MissingOrdinance { commit_id, fips },
+                _ => Inconsistency::MissingSource { commit_id, fips },
+            })
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+    trace!("Found {} inconsistencies", inconsistencies.len());
+
+    Ok(inconsistencies)
+}
+
+#[cfg(test)]
+mod test_verify {
+    use super::*;
+    use crate::sample::empty_db;
+
+    #[test]
+    fn mismatched_jurisdictions() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        let conn = empty_db(&db);
+        conn.execute_batch(
+            r"
+            INSERT INTO bookkeeper (hash, username) VALUES ('first', 'test'), ('stream', 'test');
+            INSERT INTO source (bookkeeper_lnk, fips, found)
+              VALUES (1, 11111, true), (1, 22222, true), (1, 33333, false);
+            INSERT INTO quantitative (bookkeeper_lnk, FIPS, feature, value)
+              VALUES (1, 11111, 'structures', 500),
+                     (1, 44444, 'structures', 300),
+                     (2, 55555, 'structures', 200);
+            ",
+        )
+        .unwrap();
+
+        let inconsistencies = consistency_check(&conn).unwrap();
+        assert_eq!(
+            inconsistencies,
+            [
+                Inconsistency::MissingOrdinance {
+                    commit_id: 1,
+                    fips: 22222
+                },
+                Inconsistency::MissingSource {
+                    commit_id: 1,
+                    fips: 44444
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn consistent() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("test.db");
+        let conn = empty_db(&db);
+        conn.execute_batch(
+            r"
+            INSERT INTO bookkeeper (hash, username) VALUES ('first', 'test');
+            INSERT INTO source (bookkeeper_lnk, fips, found) VALUES (1, 11111, true);
+            INSERT INTO qualitative (bookkeeper_lnk, FIPS, feature, summary)
+              VALUES (1, 11111, 'color', 'white');
+            ",
+        )
+        .unwrap();
+
+        assert!(consistency_check(&conn).unwrap().is_empty());
+    }
+}